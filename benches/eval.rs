@@ -0,0 +1,129 @@
+//! Interpreter (`evaluate_flag`, which walks the AST through
+//! `eval_with_segments`) against the precompiled `Plan`.
+//!
+//!     cargo bench --bench eval
+
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use flagfile_lib::ast::Atom;
+use flagfile_lib::eval::{Context, EvalOptions, Segments};
+use flagfile_lib::evaluation::evaluate_flag;
+use flagfile_lib::parse_flagfile::{parse_flagfile_with_segments, Rule};
+use flagfile_lib::plan::Plan;
+use flagfile_lib::FlagMetadata;
+
+const FLAGFILE: &str = r#"
+@segment eu {
+    countryCode in (NL, DE, FR, BE, LT, LV, EE)
+}
+
+@segment paying {
+    plan in (premium, enterprise) and seats > 5
+}
+
+FF-compare {
+    tier == enterprise and seats >= 100 -> true
+    false
+}
+
+FF-regex {
+    email ~ /^[a-z0-9._%+-]+@(company|partner)\.com$/ -> true
+    false
+}
+
+FF-percentage {
+    percentage(25%, userId, checkout) -> true
+    false
+}
+
+FF-segments {
+    segment(eu) and segment(paying) -> "eu-paying"
+    segment(paying) -> "paying"
+    "free"
+}
+
+FF-many-rules {
+    lower(countryCode) == us -> 1
+    lower(countryCode) == gb -> 2
+    lower(countryCode) == de -> 3
+    lower(countryCode) == fr -> 4
+    appVersion >= 5.3.0 and not (plan == free) -> 5
+    0
+}
+"#;
+
+type Flags = (
+    HashMap<String, Vec<Rule>>,
+    HashMap<String, FlagMetadata>,
+    Segments,
+);
+
+fn load() -> Flags {
+    let parsed = parse_flagfile_with_segments(FLAGFILE).expect("parse");
+    let mut flags = HashMap::new();
+    let mut metadata = HashMap::new();
+    for fv in parsed.flags {
+        for (name, def) in fv {
+            flags.insert(name.to_string(), def.rules);
+            metadata.insert(name.to_string(), def.metadata);
+        }
+    }
+    (flags, metadata, parsed.segments)
+}
+
+fn context() -> Context<'static> {
+    HashMap::from([
+        ("tier", Atom::from("enterprise")),
+        ("seats", Atom::Number(250)),
+        ("email", Atom::from("jane.doe@company.com")),
+        ("userId", Atom::from("user-8f14e45f")),
+        ("countryCode", Atom::from("NL")),
+        ("plan", Atom::from("premium")),
+        ("appVersion", Atom::from("5.4.1")),
+    ])
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let (flags, metadata, segments) = load();
+    let plan = Plan::compile(&flags, &metadata, &segments);
+    let ctx = context();
+    let options = EvalOptions::default();
+
+    for flag in [
+        "FF-compare",
+        "FF-regex",
+        "FF-percentage",
+        "FF-segments",
+        "FF-many-rules",
+    ] {
+        let mut group = c.benchmark_group(flag);
+        group.bench_function("interpreter", |b| {
+            b.iter(|| {
+                evaluate_flag(
+                    black_box(flag),
+                    black_box(&ctx),
+                    &flags,
+                    &metadata,
+                    &segments,
+                    None,
+                )
+            })
+        });
+        group.bench_function("plan", |b| {
+            b.iter(|| plan.evaluate(black_box(flag), black_box(&ctx), None, &options))
+        });
+        group.finish();
+    }
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let (flags, metadata, segments) = load();
+    c.bench_function("compile", |b| {
+        b.iter(|| Plan::compile(black_box(&flags), &metadata, &segments))
+    });
+}
+
+criterion_group!(benches, bench_eval, bench_compile);
+criterion_main!(benches);
@@ -0,0 +1,583 @@
+//! Inputs of the conformance corpus. Expected results are not written here;
+//! `ff conformance` computes them with the Rust engine.
+//!
+//! Cases only use features every implementation is expected to support.
+//! Cases that depend on the current time set `now`, the instant `NOW()`,
+//! `ramp()` and `@active` are evaluated at.
+
+pub struct Case {
+    pub name: &'static str,
+    pub flagfile: &'static str,
+    pub env: Option<&'static str>,
+    /// RFC 3339 instant to evaluate at.
+    pub now: Option<&'static str>,
+    /// `(flag, context as a JSON object)`.
+    pub evaluations: &'static [(&'static str, &'static str)],
+}
+
+pub const CASES: &[Case] = &[
+    // ── Return values ────────────────────────────────────────────
+    Case {
+        name: "returns/literals",
+        flagfile: r#"FF-bool -> true
+FF-bool-upper -> FALSE
+FF-int -> 5000
+FF-negative -> -3
+FF-float -> 0.25
+FF-string -> "debug"
+FF-json -> json({"timeout": 30, "retries": [1, 2]})
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-bool", "{}"),
+            ("FF-bool-upper", "{}"),
+            ("FF-int", "{}"),
+            ("FF-negative", "{}"),
+            ("FF-float", "{}"),
+            ("FF-string", "{}"),
+            ("FF-json", "{}"),
+            ("FF-missing", "{}"),
+        ],
+    },
+    Case {
+        name: "returns/no-rule-matched",
+        flagfile: r#"FF-no-default {
+    plan == premium -> true
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-no-default", r#"{"plan": "premium"}"#),
+            ("FF-no-default", r#"{"plan": "free"}"#),
+        ],
+    },
+    // ── Comparisons ──────────────────────────────────────────────
+    Case {
+        name: "comparisons/equality-and-order",
+        flagfile: r#"FF-country {
+    countryCode == NL -> "nl"
+    countryCode != "US" -> "not-us"
+    "us"
+}
+
+FF-age {
+    age >= 21 -> "adult"
+    age > 12 -> "teen"
+    age < 0 -> "invalid"
+    "child"
+}
+
+FF-big-numbers {
+    createdMs > 1700000000000 -> true
+    false
+}
+
+FF-ratio {
+    ratio <= 0.5 -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-country", r#"{"countryCode": "NL"}"#),
+            ("FF-country", r#"{"countryCode": "DE"}"#),
+            ("FF-country", r#"{"countryCode": "US"}"#),
+            ("FF-age", r#"{"age": 30}"#),
+            ("FF-age", r#"{"age": 21}"#),
+            ("FF-age", r#"{"age": 13}"#),
+            ("FF-age", r#"{"age": -1}"#),
+            ("FF-age", r#"{"age": 5}"#),
+            ("FF-age", "{}"),
+            ("FF-big-numbers", r#"{"createdMs": 1735689600000}"#),
+            ("FF-big-numbers", r#"{"createdMs": 1600000000000}"#),
+            ("FF-ratio", r#"{"ratio": 0.5}"#),
+            ("FF-ratio", r#"{"ratio": 0.75}"#),
+        ],
+    },
+    Case {
+        name: "comparisons/semver-and-dates",
+        flagfile: r#"FF-sdk-upgrade {
+    appVersion >= 5.3.42 -> true
+    appVersion < 4.32.0 -> false
+    false
+}
+
+FF-date-window {
+    created > 2024-02-02 and created <= 2024-02-13 -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-sdk-upgrade", r#"{"appVersion": "5.3.42"}"#),
+            ("FF-sdk-upgrade", r#"{"appVersion": "5.10.0"}"#),
+            ("FF-sdk-upgrade", r#"{"appVersion": "5.3.9"}"#),
+            ("FF-sdk-upgrade", r#"{"appVersion": "4.31.9"}"#),
+            ("FF-date-window", r#"{"created": "2024-02-02"}"#),
+            ("FF-date-window", r#"{"created": "2024-02-10"}"#),
+            ("FF-date-window", r#"{"created": "2024-02-13"}"#),
+            ("FF-date-window", r#"{"created": "2024-02-14"}"#),
+        ],
+    },
+    Case {
+        name: "comparisons/arithmetic-and-durations",
+        flagfile: r#"FF-new-user {
+    NOW() - signupDate < 30d -> true
+    false
+}
+
+FF-cart {
+    cartTotal * 100 >= 5000 -> "free-shipping"
+    (price - discount) * qty > 100 -> "bulk"
+    qty % 2 == 0 and qty / 4 < 1 -> "pair"
+    "none"
+}
+
+FF-shipping {
+    billingCountry != var(shippingCountry) -> "abroad"
+    billingCountry != shippingCountry -> "not-the-word"
+    "same"
+}
+"#,
+        env: None,
+        now: Some("2026-12-01T00:00:00Z"),
+        evaluations: &[
+            ("FF-new-user", r#"{"signupDate": "2026-11-15"}"#),
+            ("FF-new-user", r#"{"signupDate": "2026-11-01T00:00:00Z"}"#),
+            ("FF-new-user", "{}"),
+            ("FF-cart", r#"{"cartTotal": 50}"#),
+            (
+                "FF-cart",
+                r#"{"cartTotal": 49.99, "price": 30, "discount": 5, "qty": 6}"#,
+            ),
+            ("FF-cart", r#"{"qty": 2}"#),
+            ("FF-cart", r#"{"qty": "two"}"#),
+            (
+                "FF-shipping",
+                r#"{"billingCountry": "NL", "shippingCountry": "DE"}"#,
+            ),
+            (
+                "FF-shipping",
+                r#"{"billingCountry": "NL", "shippingCountry": "NL"}"#,
+            ),
+            ("FF-shipping", r#"{"billingCountry": "shippingCountry"}"#),
+        ],
+    },
+    Case {
+        name: "comparisons/nested-context",
+        flagfile: r#"FF-enterprise-tools {
+    user.company.tier == enterprise and org["plan"] != free -> true
+    roles[0] == admin -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            (
+                "FF-enterprise-tools",
+                r#"{"user": {"company": {"tier": "enterprise"}}, "org": {"plan": "pro"}}"#,
+            ),
+            (
+                "FF-enterprise-tools",
+                r#"{"user": {"company": {"tier": "enterprise"}}, "org": {"plan": "free"}}"#,
+            ),
+            ("FF-enterprise-tools", r#"{"roles": ["admin", "viewer"]}"#),
+            ("FF-enterprise-tools", r#"{"roles": ["viewer", "admin"]}"#),
+        ],
+    },
+    Case {
+        name: "comparisons/json-strings",
+        flagfile: r#"FF-zip {
+    zip == "01234" -> true
+    false
+}
+
+FF-legacy-app {
+    app == "1.10" -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-zip", r#"{"zip": "01234"}"#),
+            ("FF-zip", r#"{"zip": 1234}"#),
+            ("FF-legacy-app", r#"{"app": "1.10"}"#),
+            ("FF-legacy-app", r#"{"app": "1.1"}"#),
+            ("FF-legacy-app", r#"{"app": 1.1}"#),
+        ],
+    },
+    // ── Logic ────────────────────────────────────────────────────
+    Case {
+        name: "logic/and-or-not-grouping",
+        flagfile: r#"FF-logic {
+    (country == NL or country == BE) and not (beta == true) -> "benelux"
+    country == DE && plan == premium -> "de-premium"
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-logic", r#"{"country": "NL", "beta": false}"#),
+            ("FF-logic", r#"{"country": "BE", "beta": true}"#),
+            ("FF-logic", r#"{"country": "DE", "plan": "premium"}"#),
+            ("FF-logic", r#"{"country": "DE", "plan": "free"}"#),
+        ],
+    },
+    // ── String matching ──────────────────────────────────────────
+    Case {
+        name: "strings/matching",
+        flagfile: r#"FF-contains {
+    lower(name) ~ nik -> true
+    false
+}
+
+FF-prefix-suffix {
+    path ^~ "/admin" -> "admin"
+    email ~$ "@company.com" -> "staff"
+    false
+}
+
+FF-regex {
+    UPPER(name) ~ /.*OLA.*/ -> true
+    name !~ /.*ola.*/ -> false
+    "fallthrough"
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-contains", r#"{"name": "Nikolajus"}"#),
+            ("FF-contains", r#"{"name": "Jonas"}"#),
+            ("FF-prefix-suffix", r#"{"path": "/admin/users"}"#),
+            (
+                "FF-prefix-suffix",
+                r#"{"path": "/home", "email": "a@company.com"}"#,
+            ),
+            (
+                "FF-prefix-suffix",
+                r#"{"path": "/home", "email": "a@example.com"}"#,
+            ),
+            ("FF-regex", r#"{"name": "Nikola"}"#),
+            ("FF-regex", r#"{"name": "Jonas"}"#),
+        ],
+    },
+    // ── Membership and null checks ───────────────────────────────
+    Case {
+        name: "membership/in-and-not-in",
+        flagfile: r#"FF-membership {
+    userId in (20, 21, 22) -> "listed"
+    model in (ms, mx, m3, my) -> "model"
+    "admin" in roles -> "admin"
+    dd not in (1, 2, 3) -> "other"
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-membership", r#"{"userId": 21}"#),
+            ("FF-membership", r#"{"model": "m3"}"#),
+            ("FF-membership", r#"{"roles": ["viewer", "admin"]}"#),
+            ("FF-membership", r#"{"dd": 4}"#),
+            ("FF-membership", r#"{"dd": 2}"#),
+        ],
+    },
+    Case {
+        name: "null/is-null",
+        flagfile: r#"FF-null-check {
+    userId is null -> "anonymous"
+    userId is not null and plan == premium -> "premium"
+    "known"
+}
+
+FF-coalesce {
+    coalesce(countryCode, region, "unknown") == "NL" -> true
+    coalesce(countryCode, region, "unknown") == "unknown" -> "unknown"
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-null-check", "{}"),
+            ("FF-null-check", r#"{"userId": null}"#),
+            ("FF-null-check", r#"{"userId": "u1", "plan": "premium"}"#),
+            ("FF-null-check", r#"{"userId": "u1", "plan": "free"}"#),
+            ("FF-coalesce", r#"{"region": "NL"}"#),
+            ("FF-coalesce", "{}"),
+            ("FF-coalesce", r#"{"countryCode": "DE", "region": "NL"}"#),
+        ],
+    },
+    // ── Percentage rollouts ──────────────────────────────────────
+    // The same vectors as `test_percentage_cross_language_vectors`.
+    Case {
+        name: "percentage/cross-language-vectors",
+        flagfile: r#"FF-test-rollout {
+    percentage(50%, userId) -> true
+    false
+}
+
+FF-new-checkout {
+    percentage(50%, userId) -> true
+    false
+}
+
+FF-zero {
+    percentage(0%, userId) -> true
+    false
+}
+
+FF-full {
+    percentage(100%, userId) -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-test-rollout", r#"{"userId": "user-123"}"#),
+            ("FF-test-rollout", r#"{"userId": "user-456"}"#),
+            ("FF-new-checkout", r#"{"userId": "user-789"}"#),
+            ("FF-zero", r#"{"userId": "user-123"}"#),
+            ("FF-full", r#"{"userId": "user-456"}"#),
+            ("FF-test-rollout", "{}"),
+        ],
+    },
+    Case {
+        name: "percentage/salted",
+        flagfile: r#"FF-test-rollout {
+    percentage(50%, userId, exp1) -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[("FF-test-rollout", r#"{"userId": "alice"}"#)],
+    },
+    Case {
+        name: "split/weighted-arms",
+        flagfile: r#"FF-test-rollout {
+    split(userId) {
+        "a": 50%
+        "b": 25%
+        "c": 25%
+    }
+    "none"
+}
+
+FF-salted {
+    split(userId, exp1) { "a": 50%, "b": 25%, "c": 25% }
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-test-rollout", r#"{"userId": "user-123"}"#),
+            ("FF-test-rollout", r#"{"userId": "user-456"}"#),
+            ("FF-test-rollout", "{}"),
+            ("FF-salted", r#"{"userId": "alice"}"#),
+        ],
+    },
+    // Windows and ramps far enough in the past or future that the
+    // expected results don't depend on when the corpus is checked.
+    Case {
+        name: "schedule/ramps-and-active-windows",
+        flagfile: r#"@active until 2020-01-01
+FF-expired -> true
+
+@active from 2020-01-01T00:00Z
+FF-launched -> true
+
+FF-test-rollout {
+    @active from 2999-01-01
+    plan == premium -> "early"
+    ramp(0% -> 60%, 2020-01-01 .. 2020-02-01, userId) -> "ramped"
+    ramp(0% -> 100%, 2999-01-01 .. 2999-02-01, userId) -> "pending"
+    "none"
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-expired", "{}"),
+            ("FF-launched", "{}"),
+            (
+                "FF-test-rollout",
+                r#"{"userId": "user-123", "plan": "premium"}"#,
+            ),
+            ("FF-test-rollout", r#"{"userId": "user-456"}"#),
+        ],
+    },
+    Case {
+        name: "schedule/timezones-and-offsets",
+        flagfile: r#"@timezone Europe/Vilnius
+
+@active from 2026-12-01
+FF-winter-sale -> true
+
+FF-banner {
+    NOW() >= 2026-11-30T23:00+01:00 -> "local-midnight"
+    NOW() >= 2026-11-30T21:00:00Z -> "almost"
+    "off"
+}
+
+FF-test-rollout {
+    ramp(0% -> 100%, 2026-11-30T22:00Z .. 2026-12-10T22:00Z, userId) -> true
+    false
+}
+"#,
+        env: None,
+        now: Some("2026-11-30T22:00:00Z"),
+        evaluations: &[
+            ("FF-winter-sale", "{}"),
+            ("FF-banner", "{}"),
+            ("FF-test-rollout", r#"{"userId": "user-123"}"#),
+        ],
+    },
+    // ── Segments ─────────────────────────────────────────────────
+    Case {
+        name: "segments/plain-and-parameterized",
+        flagfile: r#"@segment beta_users {
+    beta == true or role == developer
+}
+
+@segment in-country(c) {
+    countryCode == c
+}
+
+@segment eu-team(min) {
+    (segment(in-country, NL) or segment(in-country, "DE")) and seats >= min
+}
+
+FF-beta {
+    segment(beta_users) -> true
+    false
+}
+
+FF-eu-rollout {
+    segment(eu-team, 10) -> true
+    false
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-beta", r#"{"beta": true}"#),
+            ("FF-beta", r#"{"role": "developer"}"#),
+            ("FF-beta", r#"{"role": "viewer"}"#),
+            ("FF-eu-rollout", r#"{"countryCode": "NL", "seats": 12}"#),
+            ("FF-eu-rollout", r#"{"countryCode": "DE", "seats": 10}"#),
+            ("FF-eu-rollout", r#"{"countryCode": "DE", "seats": 9}"#),
+            ("FF-eu-rollout", r#"{"countryCode": "FR", "seats": 50}"#),
+        ],
+    },
+    // ── Environments ─────────────────────────────────────────────
+    Case {
+        name: "env/prod",
+        flagfile: ENV_FLAGFILE,
+        env: Some("prod"),
+        now: None,
+        evaluations: &[
+            ("FF-new-checkout", r#"{"country": "US", "platform": "web"}"#),
+            ("FF-new-checkout", r#"{"country": "DE", "platform": "web"}"#),
+        ],
+    },
+    Case {
+        name: "env/dev",
+        flagfile: ENV_FLAGFILE,
+        env: Some("dev"),
+        now: None,
+        evaluations: &[("FF-new-checkout", r#"{"country": "DE"}"#)],
+    },
+    Case {
+        name: "env/unset",
+        flagfile: ENV_FLAGFILE,
+        env: None,
+        now: None,
+        evaluations: &[("FF-new-checkout", r#"{"country": "US", "platform": "web"}"#)],
+    },
+    // ── Variants and prerequisites ───────────────────────────────
+    Case {
+        name: "variants/named-returns",
+        flagfile: r#"FF-checkout-experiment {
+    @variants {
+        control -> json({"steps": 4})
+        streamlined -> json({"steps": 2})
+    }
+
+    country == NL -> streamlined
+    control
+}
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-checkout-experiment", r#"{"country": "NL"}"#),
+            ("FF-checkout-experiment", r#"{"country": "DE"}"#),
+        ],
+    },
+    Case {
+        name: "requires/prerequisites",
+        flagfile: r#"FF-base {
+    plan == premium -> true
+    false
+}
+
+@requires FF-base
+FF-dependent -> true
+"#,
+        env: None,
+        now: None,
+        evaluations: &[
+            ("FF-dependent", r#"{"plan": "premium"}"#),
+            ("FF-dependent", r#"{"plan": "free"}"#),
+        ],
+    },
+    // ── Files that must not load ─────────────────────────────────
+    Case {
+        name: "errors/unclosed-block",
+        flagfile: "FF-ok -> true\n\nFF-broken {\n    plan == premium -> true\n",
+        env: None,
+        now: None,
+        evaluations: &[],
+    },
+    Case {
+        name: "errors/invalid-regex",
+        flagfile: "FF-a -> true\nFF-b {\n    email ~ /[a-z/ -> true\n    false\n}\n",
+        env: None,
+        now: None,
+        evaluations: &[],
+    },
+    Case {
+        name: "errors/declared-type-mismatch",
+        flagfile: "@type int\nFF-timeout -> true\n",
+        env: None,
+        now: None,
+        evaluations: &[],
+    },
+    Case {
+        name: "errors/segment-cycle",
+        flagfile:
+            "@segment a {\n    segment(b)\n}\n@segment b {\n    segment(a)\n}\nFF-x -> true\n",
+        env: None,
+        now: None,
+        evaluations: &[],
+    },
+];
+
+const ENV_FLAGFILE: &str = r#"FF-new-checkout {
+    @env dev -> true
+    @env prod {
+        country in (US, CA, GB) and platform == web -> true
+        false
+    }
+    false
+}
+"#;
@@ -0,0 +1,392 @@
+//! `ff conformance`: a language-neutral corpus of Flagfiles, contexts and
+//! expected results computed by the Rust engine, so that `flagfile-go`,
+//! `flagfile-ts` and other ports can be checked against it.
+//!
+//! The corpus is JSON:
+//!
+//! ```json
+//! {"version": 1, "cases": [{"name": "...", "flagfile": "...", "env": null,
+//!   "now": "2026-12-01T00:00:00Z", "parseError": null,
+//!   "evaluations": [{"flag": "FF-x", "context": {...},
+//!                    "expected": {"value": true, "variant": null,
+//!                                 "reason": "DEFAULT", "ruleIndex": 0}}]}]}
+//! ```
+//!
+//! `now` is the instant `NOW()`, `ramp()` and `@active` are evaluated at, or
+//! `null` for cases that don't depend on the time. A case whose Flagfile
+//! must be rejected has `"parseError": {"line": 3}`
+//! (`line` is `null` when the error isn't tied to a position) and no
+//! evaluations. Another implementation answers with a results file keyed by
+//! case name:
+//!
+//! ```json
+//! {"cases": {"returns/literals": {"parseError": false,
+//!                                 "results": [{"value": true, "reason": "DEFAULT"}]}}}
+//! ```
+//!
+//! `value` is always compared; `variant`, `reason`, `ruleIndex` and a parse
+//! error's `line` only when the results file includes them.
+
+mod cases;
+
+use std::process;
+
+use flagfile_lib::eval::Clock;
+use flagfile_lib::Flagfile;
+use serde_json::{json, Value};
+
+use cases::CASES;
+
+/// Bumped when the corpus or results format changes incompatibly.
+const CORPUS_VERSION: u64 = 1;
+
+/// Evaluation fields recorded as expectations. `ruleName`, `env` and
+/// `error` are left out: they are either implied by the others or worded
+/// differently by each implementation.
+const EXPECTED_FIELDS: &[&str] = &["value", "variant", "reason", "ruleIndex"];
+
+pub fn build_corpus() -> Value {
+    let cases: Vec<Value> = CASES
+        .iter()
+        .map(|case| {
+            let (parse_error, evaluations) = match Flagfile::parse_located(case.flagfile) {
+                Ok(flagfile) => {
+                    let flagfile = match case.env {
+                        Some(env) => flagfile.with_env(env),
+                        None => flagfile,
+                    };
+                    let flagfile = match case.now {
+                        Some(now) => {
+                            flagfile.with_clock(Clock::Fixed(now.parse().unwrap_or_else(|e| {
+                                panic!("case {}: invalid now {now}: {e}", case.name)
+                            })))
+                        }
+                        None => flagfile,
+                    };
+                    let evaluations: Vec<Value> = case
+                        .evaluations
+                        .iter()
+                        .map(|(flag, context)| {
+                            let context: Value =
+                                serde_json::from_str(context).unwrap_or_else(|e| {
+                                    panic!("case {}: invalid context {context}: {e}", case.name)
+                                });
+                            let detail = flagfile
+                                .eval_json(flag, &context)
+                                .unwrap_or_else(|e| panic!("case {}: {e}", case.name));
+                            let expected: serde_json::Map<String, Value> = EXPECTED_FIELDS
+                                .iter()
+                                .map(|field| (field.to_string(), detail[*field].clone()))
+                                .collect();
+                            json!({"flag": flag, "context": context, "expected": expected})
+                        })
+                        .collect();
+                    (Value::Null, evaluations)
+                }
+                Err(e) => (
+                    json!({"line": e.location.map(|(line, _)| line)}),
+                    Vec::new(),
+                ),
+            };
+            json!({
+                "name": case.name,
+                "flagfile": case.flagfile,
+                "env": case.env,
+                "now": case.now,
+                "parseError": parse_error,
+                "evaluations": evaluations,
+            })
+        })
+        .collect();
+    json!({"version": CORPUS_VERSION, "cases": cases})
+}
+
+/// Outcome of checking a results file against the corpus.
+pub struct Verification {
+    pub checks: usize,
+    /// Checks with at least one entry in `failures`.
+    pub failed: usize,
+    pub failures: Vec<String>,
+}
+
+pub fn verify(corpus: &Value, results: &Value) -> Verification {
+    let mut checks = 0;
+    let mut failed = 0;
+    let mut failures = Vec::new();
+    let empty = Vec::new();
+
+    for case in corpus["cases"].as_array().unwrap_or(&empty) {
+        let name = case["name"].as_str().unwrap_or_default();
+        let evaluations = case["evaluations"].as_array().unwrap_or(&empty);
+        let result = &results["cases"][name];
+        if result.is_null() {
+            checks += 1.max(evaluations.len());
+            failed += 1.max(evaluations.len());
+            failures.push(format!("{name}: missing from results"));
+            continue;
+        }
+
+        let got_parse_error = result["parseError"].as_bool().unwrap_or(false);
+        let expected_parse_error = &case["parseError"];
+        checks += 1;
+        if !expected_parse_error.is_null() {
+            if !got_parse_error {
+                failures.push(format!("{name}: expected a parse error, but it loaded"));
+                failed += 1;
+            } else if !result["line"].is_null()
+                && !expected_parse_error["line"].is_null()
+                && result["line"] != expected_parse_error["line"]
+            {
+                failures.push(format!(
+                    "{name}: parse error reported at line {}, expected line {}",
+                    result["line"], expected_parse_error["line"]
+                ));
+                failed += 1;
+            }
+            continue;
+        }
+        if got_parse_error {
+            failures.push(format!("{name}: unexpected parse error"));
+            failed += 1;
+            continue;
+        }
+
+        let got = result["results"].as_array().unwrap_or(&empty);
+        for (i, evaluation) in evaluations.iter().enumerate() {
+            checks += 1;
+            let before = failures.len();
+            let label = format!("{name}#{i} {}", evaluation["flag"].as_str().unwrap_or(""));
+            let Some(got) = got.get(i) else {
+                failures.push(format!("{label}: no result"));
+                failed += 1;
+                continue;
+            };
+            for field in EXPECTED_FIELDS {
+                let Some(actual) = got.get(*field) else {
+                    if *field == "value" {
+                        failures.push(format!("{label}: result has no value"));
+                    }
+                    continue;
+                };
+                let expected = &evaluation["expected"][*field];
+                if !json_eq(expected, actual) {
+                    failures.push(format!("{label}: {field} is {actual}, expected {expected}"));
+                }
+            }
+            if failures.len() > before {
+                failed += 1;
+            }
+        }
+    }
+    Verification {
+        checks,
+        failed,
+        failures,
+    }
+}
+
+/// JSON equality that treats `1` and `1.0` as the same number.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| json_eq(a, b))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .all(|(k, v)| y.get(k).is_some_and(|other| json_eq(v, other)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Print `text`, or write it to `output` and say `Wrote <what> to <path>`.
+fn emit(text: &str, output: Option<&str>, what: &str) {
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, text) {
+                eprintln!("Failed to write {}: {}", path, e);
+                process::exit(1);
+            }
+            println!("Wrote {} to {}", what, path);
+        }
+        None => print!("{}", text),
+    }
+}
+
+/// Write the corpus, or with `results_path` the report of checking those
+/// results, to `output` (stdout if `None`).
+pub fn run_conformance(output: Option<&str>, results_path: Option<&str>) {
+    let corpus = build_corpus();
+
+    let Some(results_path) = results_path else {
+        let text = format!("{}\n", serde_json::to_string_pretty(&corpus).unwrap());
+        let what = format!(
+            "{} conformance cases",
+            corpus["cases"].as_array().map_or(0, Vec::len)
+        );
+        emit(&text, output, &what);
+        return;
+    };
+
+    let results: Value = match std::fs::read_to_string(results_path) {
+        Ok(content) => match serde_json::from_str(&content) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("{} is not valid JSON: {}", results_path, e);
+                process::exit(1);
+            }
+        },
+        Err(_) => {
+            eprintln!("{} does not exist", results_path);
+            process::exit(1);
+        }
+    };
+
+    let verification = verify(&corpus, &results);
+    let mut report = String::new();
+    for failure in &verification.failures {
+        report.push_str(&format!("\u{2717} {}\n", failure));
+    }
+    let passed = verification.checks - verification.failed;
+    let summary = format!(
+        "{}/{} conformance checks passed",
+        passed, verification.checks
+    );
+    report.push_str(&format!("{}\n", summary));
+    emit(&report, output, "the conformance report");
+    if output.is_some() {
+        println!("{}", summary);
+    }
+    if !verification.failures.is_empty() {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a perfectly conforming implementation would report.
+    fn results_from(corpus: &Value) -> Value {
+        let mut cases = serde_json::Map::new();
+        for case in corpus["cases"].as_array().unwrap() {
+            let results: Vec<Value> = case["evaluations"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e["expected"].clone())
+                .collect();
+            cases.insert(
+                case["name"].as_str().unwrap().to_string(),
+                json!({
+                    "parseError": !case["parseError"].is_null(),
+                    "line": case["parseError"]["line"],
+                    "results": results,
+                }),
+            );
+        }
+        json!({"cases": cases})
+    }
+
+    #[test]
+    fn test_checked_in_corpus_is_up_to_date() {
+        let checked_in: Value =
+            serde_json::from_str(include_str!("../../../conformance/corpus.json")).unwrap();
+        assert!(
+            checked_in == build_corpus(),
+            "conformance/corpus.json is stale; regenerate it with \
+             `cargo run -p flagfile-cli -- conformance -o conformance/corpus.json`"
+        );
+    }
+
+    #[test]
+    fn test_corpus_expectations() {
+        let corpus = build_corpus();
+        let case = |name: &str| {
+            corpus["cases"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|c| c["name"] == name)
+                .unwrap()
+                .clone()
+        };
+        let vectors = case("percentage/cross-language-vectors");
+        let values: Vec<&Value> = vectors["evaluations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| &e["expected"]["value"])
+            .collect();
+        assert_eq!(
+            values,
+            [true, false, true, false, true, false]
+                .map(Value::Bool)
+                .iter()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            case("percentage/salted")["evaluations"][0]["expected"]["value"],
+            false
+        );
+        assert_eq!(
+            case("errors/invalid-regex")["parseError"],
+            json!({"line": 3})
+        );
+        assert_eq!(
+            case("errors/declared-type-mismatch")["parseError"],
+            json!({"line": null})
+        );
+        assert_eq!(
+            case("env/prod")["evaluations"][0]["expected"]["reason"],
+            "ENV_MATCH"
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_conforming_results() {
+        let corpus = build_corpus();
+        let verification = verify(&corpus, &results_from(&corpus));
+        assert!(
+            verification.failures.is_empty(),
+            "{:?}",
+            verification.failures
+        );
+        assert!(verification.checks > CASES.len());
+    }
+
+    #[test]
+    fn test_verify_reports_differences() {
+        let corpus = build_corpus();
+        let mut results = results_from(&corpus);
+        let cases = &mut results["cases"];
+        cases["returns/literals"]["results"][2]["value"] = json!(5000.0);
+        cases["returns/literals"]["results"][4]["value"] = json!(0.3);
+        cases["returns/literals"]["results"][4]["reason"] = json!("SPLIT");
+        cases["returns/literals"]["results"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("reason");
+        cases["comparisons/semver-and-dates"]["results"][1]["reason"] = json!("DEFAULT");
+        cases["errors/unclosed-block"]["parseError"] = json!(false);
+        cases["errors/invalid-regex"]["line"] = json!(4);
+        cases.as_object_mut().unwrap().remove("null/is-null");
+
+        let verification = verify(&corpus, &results);
+        assert_eq!(
+            verification.failures,
+            vec![
+                "returns/literals#4 FF-float: value is 0.3, expected 0.25",
+                "returns/literals#4 FF-float: reason is \"SPLIT\", expected \"DEFAULT\"",
+                "comparisons/semver-and-dates#1 FF-sdk-upgrade: reason is \"DEFAULT\", expected \"TARGETING_MATCH\"",
+                "null/is-null: missing from results",
+                "errors/unclosed-block: expected a parse error, but it loaded",
+                "errors/invalid-regex: parse error reported at line 4, expected line 3",
+            ]
+        );
+        // literals#4 fails once, each of null/is-null's evaluations counts
+        assert_eq!(verification.failed, 1 + 1 + 7 + 1 + 1);
+    }
+}
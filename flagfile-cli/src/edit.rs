@@ -0,0 +1,184 @@
+//! `ff set` and `ff rm`: change a flag in the file that defines it, the
+//! root Flagfile or one it `@include`s, keeping comments and formatting.
+
+use std::collections::HashSet;
+use std::process;
+
+use chrono::NaiveDate;
+use flagfile_lib::edit::{Annotation, Edit, EditError, Editor};
+use flagfile_lib::parse_flagfile::FlagReturn;
+
+/// What `ff set` was asked to change, straight from the command line.
+#[derive(Debug, Default)]
+pub struct SetOptions {
+    pub value: Option<String>,
+    pub env: Option<String>,
+    pub rule: Option<String>,
+    /// 1-based, like the rule numbers `ff eval --explain` prints.
+    pub at: Option<usize>,
+    pub percentage: Option<f64>,
+    pub in_rule: Option<usize>,
+    pub annotations: Vec<Annotation>,
+}
+
+pub fn run_set(flagfile_path: &str, flag_name: &str, options: SetOptions) {
+    if run_set_inner(flagfile_path, flag_name, options).is_err() {
+        process::exit(1);
+    }
+}
+
+fn run_set_inner(flagfile_path: &str, flag_name: &str, options: SetOptions) -> Result<(), ()> {
+    let found = find_defining_file(flagfile_path, flag_name)?;
+    let mut edits = Vec::new();
+
+    if let Some(value) = &options.value {
+        let value: FlagReturn = value.parse().map_err(|e| {
+            eprintln!("invalid value {}: {}", value, e);
+        })?;
+        if found.is_none() && options.env.is_none() {
+            edits.push(Edit::AddFlag {
+                flag: flag_name.to_string(),
+                value,
+            });
+        } else {
+            edits.push(Edit::SetDefault {
+                flag: flag_name.to_string(),
+                env: options.env.clone(),
+                value,
+            });
+        }
+    }
+    for annotation in options.annotations {
+        edits.push(Edit::SetAnnotation {
+            flag: flag_name.to_string(),
+            annotation,
+        });
+    }
+    if let Some(rule) = options.rule {
+        edits.push(Edit::InsertRule {
+            flag: flag_name.to_string(),
+            index: options.at.unwrap_or(1).saturating_sub(1),
+            rule,
+        });
+    }
+    if let Some(rate) = options.percentage {
+        edits.push(Edit::SetPercentage {
+            flag: flag_name.to_string(),
+            rule: options.in_rule.map(|n| n.saturating_sub(1)),
+            rate,
+        });
+    }
+    if edits.is_empty() {
+        eprintln!("nothing to set: pass a value, --rule, --percentage or an annotation");
+        return Err(());
+    }
+
+    let added = matches!(edits[0], Edit::AddFlag { .. });
+    let (path, mut editor) = match found {
+        Some(found) => found,
+        None => (flagfile_path.to_string(), load(flagfile_path)?),
+    };
+    for edit in &edits {
+        if let Err(e) = editor.apply(edit) {
+            report_edit_error(&path, e);
+            return Err(());
+        }
+    }
+    save(&path, &editor)?;
+    if added {
+        println!("added {} to {}", flag_name, path);
+    } else {
+        println!("updated {} in {}", flag_name, path);
+    }
+    Ok(())
+}
+
+pub fn run_rm(flagfile_path: &str, flag_name: &str, annotations: &[String]) {
+    if run_rm_inner(flagfile_path, flag_name, annotations).is_err() {
+        process::exit(1);
+    }
+}
+
+fn run_rm_inner(flagfile_path: &str, flag_name: &str, annotations: &[String]) -> Result<(), ()> {
+    let Some((path, mut editor)) = find_defining_file(flagfile_path, flag_name)? else {
+        eprintln!("flag {} not found in {}", flag_name, flagfile_path);
+        return Err(());
+    };
+    let edits: Vec<Edit> = if annotations.is_empty() {
+        vec![Edit::RemoveFlag {
+            flag: flag_name.to_string(),
+        }]
+    } else {
+        annotations
+            .iter()
+            .map(|keyword| Edit::RemoveAnnotation {
+                flag: flag_name.to_string(),
+                keyword: keyword.clone(),
+            })
+            .collect()
+    };
+    for edit in &edits {
+        if let Err(e) = editor.apply(edit) {
+            report_edit_error(&path, e);
+            return Err(());
+        }
+    }
+    save(&path, &editor)?;
+    if annotations.is_empty() {
+        println!("removed {} from {}", flag_name, path);
+    } else {
+        println!("updated {} in {}", flag_name, path);
+    }
+    Ok(())
+}
+
+/// The root Flagfile, or the first file it includes, that defines
+/// `flag_name`, loaded for editing.
+fn find_defining_file(
+    flagfile_path: &str,
+    flag_name: &str,
+) -> Result<Option<(String, Editor)>, ()> {
+    let (raw, resolved) = crate::read_flagfile_resolved(flagfile_path)?;
+    let mut files = vec![(flagfile_path.to_string(), raw)];
+    let mut seen = HashSet::from([flagfile_path.to_string()]);
+    for inc in resolved.includes {
+        let path = inc.path.display().to_string();
+        if seen.insert(path.clone()) {
+            files.push((path, inc.content));
+        }
+    }
+    Ok(files.into_iter().find_map(|(path, content)| {
+        let editor = Editor::new(content);
+        editor.has_flag(flag_name).then_some((path, editor))
+    }))
+}
+
+fn load(path: &str) -> Result<Editor, ()> {
+    Editor::load(path).map_err(|e| eprintln!("Failed to read {}: {}", path, e))
+}
+
+fn save(path: &str, editor: &Editor) -> Result<(), ()> {
+    editor
+        .save(path)
+        .map_err(|e| eprintln!("Failed to write {}: {}", path, e))
+}
+
+fn report_edit_error(path: &str, err: EditError) {
+    match err {
+        EditError::Unparsable(mut e) => {
+            e.file = Some(path.into());
+            eprintln!("{}", e.render());
+        }
+        EditError::Invalid(mut e) => {
+            e.file = Some(path.into());
+            eprintln!("edit would break {}:", path);
+            eprintln!("{}", e.render());
+        }
+        other => eprintln!("{}", other),
+    }
+}
+
+/// `--expires` takes a `YYYY-MM-DD` date, like the annotation.
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| format!("{} is not a YYYY-MM-DD date", s))
+}
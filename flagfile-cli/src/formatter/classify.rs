@@ -2,6 +2,7 @@
 ///
 /// Each source line is assigned a `LineType` that determines its indentation
 /// level and whether its content should be normalized.
+use flagfile_lib::cst::{tokenize, TokenKind};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LineType {
@@ -12,12 +13,16 @@ pub enum LineType {
     BlockCommentEnd,
     BlockCommentFull,
     Annotation,
+    /// A file-level directive: `@include` or `@timezone`.
     Include,
     FlagHeaderBlock,
     FlagHeaderShort,
     SegmentHeader,
     EnvHeaderBlock,
     EnvHeaderShort,
+    VariantsHeader,
+    /// `split(userId) {`, its arms follow one per line.
+    SplitHeader,
     ClosingBrace,
     RuleExpr,
     StaticValue,
@@ -70,8 +75,8 @@ pub fn classify_line(
         return LineType::SegmentHeader;
     }
 
-    // ── Include directive ───────────────────────────────────────────
-    if trimmed.starts_with("@include ") {
+    // ── Include and timezone directives ─────────────────────────────
+    if trimmed.starts_with("@include ") || trimmed.starts_with("@timezone ") {
         return LineType::Include;
     }
 
@@ -86,6 +91,16 @@ pub fn classify_line(
         // fallthrough – unusual, treat as annotation
     }
 
+    // ── @variants block ─────────────────────────────────────────────
+    if trimmed.starts_with("@variants") && ends_with_block_brace(trimmed) {
+        return LineType::VariantsHeader;
+    }
+
+    // ── split block ─────────────────────────────────────────────────
+    if trimmed.to_lowercase().starts_with("split") && ends_with_block_brace(trimmed) {
+        return LineType::SplitHeader;
+    }
+
     // ── Metadata annotations ────────────────────────────────────────
     if let Some(after_at) = trimmed.strip_prefix('@') {
         if after_at.starts_with("owner")
@@ -97,6 +112,7 @@ pub fn classify_line(
             || after_at.starts_with("requires")
             || after_at.starts_with("test")
             || after_at.starts_with("name")
+            || after_at.starts_with("active")
         {
             return LineType::Annotation;
         }
@@ -130,31 +146,29 @@ pub fn classify_line(
 /// Check whether the trimmed line ends with `{` as a block opener.
 ///
 /// This distinguishes `FF-flag {` (block) from `FF-flag -> json({"a":1})`
-/// (short form with JSON containing braces).
+/// (short form with JSON containing braces): a brace inside parentheses
+/// doesn't open a block.
 fn ends_with_block_brace(trimmed: &str) -> bool {
-    trimmed.trim_end().ends_with('{') && !trimmed.contains("json(")
-}
-
-/// Check whether `line` contains ` -> ` (or `->`) outside of quoted strings.
-fn contains_arrow_outside_quotes(line: &str) -> bool {
-    let mut in_single = false;
-    let mut in_double = false;
-    let bytes = line.as_bytes();
-    let len = bytes.len();
-    let mut i = 0;
-    while i < len {
-        let ch = bytes[i];
-        match ch {
-            b'\'' if !in_double => in_single = !in_single,
-            b'"' if !in_single => in_double = !in_double,
-            b'-' if !in_single && !in_double && i + 1 < len && bytes[i + 1] == b'>' => {
-                return true;
-            }
+    let mut depth = 0;
+    let mut last = None;
+    for token in tokenize(trimmed) {
+        match token.kind() {
+            kind if kind.is_trivia() => continue,
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => depth -= 1,
             _ => {}
         }
-        i += 1;
+        last = Some((token.kind(), depth));
     }
-    false
+    last == Some((TokenKind::LBrace, 0))
+}
+
+/// Check whether `line` contains `->` outside of strings, regexes and
+/// comments.
+fn contains_arrow_outside_quotes(line: &str) -> bool {
+    tokenize(line)
+        .iter()
+        .any(|token| token.kind() == TokenKind::Arrow)
 }
 
 #[cfg(test)]
@@ -224,6 +238,10 @@ mod tests {
             classify_line("@requires FF-bar", false, false),
             LineType::Annotation
         );
+        assert_eq!(
+            classify_line("@active from 2026-11-01 until 2026-12-01", false, false),
+            LineType::Annotation
+        );
     }
 
     #[test]
@@ -252,6 +270,10 @@ mod tests {
             classify_line("@include cua/Flagfile", false, false),
             LineType::Include
         );
+        assert_eq!(
+            classify_line("@timezone Europe/Vilnius", false, false),
+            LineType::Include
+        );
         // inside comments it's not a directive
         assert_eq!(
             classify_line("// @include nope.ff", false, false),
@@ -287,6 +309,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_classify_variants_header() {
+        assert_eq!(
+            classify_line("@variants {", false, false),
+            LineType::VariantsHeader
+        );
+    }
+
+    #[test]
+    fn test_classify_split_header() {
+        assert_eq!(
+            classify_line("split(userId, exp1) {", false, false),
+            LineType::SplitHeader
+        );
+        assert_eq!(
+            classify_line("split(userId) { a: 50%, b: 50% }", false, false),
+            LineType::StaticValue
+        );
+    }
+
     #[test]
     fn test_classify_closing_brace() {
         assert_eq!(classify_line("}", false, false), LineType::ClosingBrace);
@@ -326,6 +368,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_arrow_inside_regex_not_detected() {
+        assert_eq!(
+            classify_line("path ~ /a->b/", false, false),
+            LineType::StaticValue
+        );
+        assert_eq!(
+            classify_line("path ~ /a->b/ -> true", false, false),
+            LineType::RuleExpr
+        );
+    }
+
+    #[test]
+    fn test_classify_block_brace_inside_json_value() {
+        assert_eq!(
+            classify_line("FF-flag -> json({", false, false),
+            LineType::FlagHeaderShort
+        );
+        assert_eq!(
+            classify_line("@env prod {", false, false),
+            LineType::EnvHeaderBlock
+        );
+    }
+
     #[test]
     fn test_classify_docblock_start() {
         assert_eq!(
@@ -227,7 +227,11 @@ pub fn format_flagfile(input: &str) -> String {
         // ── Adjust depth AFTER output for opening braces ───────
         prev_was_open_brace = false;
         match line_type {
-            LineType::FlagHeaderBlock | LineType::SegmentHeader | LineType::EnvHeaderBlock => {
+            LineType::FlagHeaderBlock
+            | LineType::SegmentHeader
+            | LineType::EnvHeaderBlock
+            | LineType::VariantsHeader
+            | LineType::SplitHeader => {
                 depth += 1;
                 prev_was_open_brace = true;
             }
@@ -444,6 +448,29 @@ FF-flag {
         assert_eq!(format_flagfile(input), expected);
     }
 
+    #[test]
+    fn test_split_block_indented() {
+        let input = "\
+FF-flag {
+    plan == free -> \"a\"
+  split(userId) {
+  \"a\": 50%,
+        \"b\": 50%
+   }
+}
+";
+        let expected = "\
+FF-flag {
+    plan == free -> \"a\"
+    split(userId) {
+        \"a\": 50%,
+        \"b\": 50%
+    }
+}
+";
+        assert_eq!(format_flagfile(input), expected);
+    }
+
     // ── Operator normalization ─────────────────────────────────
 
     #[test]
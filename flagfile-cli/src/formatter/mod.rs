@@ -30,21 +30,9 @@ pub fn run_fmt_inner(flagfile_path: &str, check: bool, diff: bool) -> Result<(),
     let (raw, resolved) = crate::read_flagfile_resolved(flagfile_path)?;
 
     // Validate the merged content parses correctly before formatting
-    match parse_flagfile_with_segments(&resolved.content) {
-        Ok((remainder, _)) => {
-            if !remainder.trim().is_empty() {
-                let near = remainder.trim().lines().next().unwrap_or("");
-                eprintln!(
-                    "Parsing failed: unexpected content near: {}",
-                    truncate(near, 60)
-                );
-                return Err(());
-            }
-        }
-        Err(e) => {
-            eprintln!("Parsing failed: {}", e);
-            return Err(());
-        }
+    if let Err(e) = parse_flagfile_with_segments(&resolved.content) {
+        crate::report_parse_error(&resolved, e);
+        return Err(());
     }
 
     // Root file first, then each included file (deduplicated)
@@ -176,12 +164,3 @@ fn print_diff(original: &str, formatted: &str, path: &str) {
         i = hunk_end;
     }
 }
-
-/// Truncate a string for error display.
-fn truncate(s: &str, max: usize) -> &str {
-    if s.len() <= max {
-        s
-    } else {
-        &s[..max]
-    }
-}
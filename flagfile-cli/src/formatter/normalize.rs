@@ -158,6 +158,7 @@ pub fn normalize_line(trimmed: &str, line_type: &LineType) -> String {
         LineType::SegmentHeader => normalize_segment_header(trimmed),
         LineType::EnvHeaderBlock => normalize_env_header_block(trimmed),
         LineType::EnvHeaderShort => normalize_short_form(trimmed),
+        LineType::VariantsHeader | LineType::SplitHeader => normalize_env_header_block(trimmed),
         LineType::RuleExpr | LineType::Continuation => normalize_rule_line(trimmed),
         LineType::StaticValue => normalize_static_value(trimmed),
     }
@@ -185,7 +186,7 @@ fn normalize_segment_header(line: &str) -> String {
     }
 }
 
-/// `@env name {` — ensure spacing.
+/// `@env name {` or `@variants {` — ensure spacing.
 fn normalize_env_header_block(line: &str) -> String {
     if let Some(pos) = line.rfind('{') {
         let before = line[..pos].trim_end();
@@ -302,6 +303,13 @@ fn normalize_operators(expr: &str) -> String {
             return 1;
         }
 
+        // -> inside `ramp(0% -> 100%, ...)`
+        if len >= 2 && &remaining[..2] == "->" {
+            trim_trailing_space(out);
+            out.push_str(" -> ");
+            return 2;
+        }
+
         // ── Comparison operators ────────────────────────────────
         // <=
         if len >= 2 && &remaining[..2] == "<=" {
@@ -546,11 +554,13 @@ fn trim_trailing_space(out: &mut String) {
     }
 }
 
-/// Split a line at the first `->` that is outside of quoted strings.
+/// Split a line at the first `->` that is outside of quoted strings and
+/// parentheses, so `ramp(0% -> 100%, ...)` keeps its arrow.
 /// Returns `(lhs, rhs)` with the arrow removed.
 fn split_arrow_outside_quotes(line: &str) -> Option<(&str, &str)> {
     let mut in_single = false;
     let mut in_double = false;
+    let mut paren_depth: usize = 0;
     let bytes = line.as_bytes();
     let len = bytes.len();
     let mut i = 0;
@@ -559,7 +569,14 @@ fn split_arrow_outside_quotes(line: &str) -> Option<(&str, &str)> {
         match ch {
             b'\'' if !in_double => in_single = !in_single,
             b'"' if !in_single => in_double = !in_double,
-            b'-' if !in_single && !in_double && i + 1 < len && bytes[i + 1] == b'>' => {
+            b'(' if !in_single && !in_double => paren_depth += 1,
+            b')' if !in_single && !in_double => paren_depth = paren_depth.saturating_sub(1),
+            b'-' if !in_single
+                && !in_double
+                && paren_depth == 0
+                && i + 1 < len
+                && bytes[i + 1] == b'>' =>
+            {
                 return Some((&line[..i], &line[i + 2..]));
             }
             _ => {}
@@ -661,6 +678,14 @@ mod tests {
 
     // ── Comma spacing ───────────────────────────────────────────
 
+    #[test]
+    fn test_normalize_rule_line_ramp() {
+        assert_eq!(
+            normalize_rule_line("ramp(0%->100%,2026-11-01 .. 2026-11-15,userId)->true"),
+            "ramp(0% -> 100%, 2026-11-01 .. 2026-11-15, userId) -> TRUE"
+        );
+    }
+
     #[test]
     fn test_normalize_commas() {
         assert_eq!(normalize_commas("(a,b,c)"), "(a, b, c)");
@@ -0,0 +1,127 @@
+//! The `fn:` functions `ff` registers when it evaluates flags in `ff eval`,
+//! `ff test`, `ff serve` and `ff lsp`. They only compute on their arguments,
+//! so a rule can't make the server do I/O or hold a request up. Applications
+//! embedding the library register their own with `Flagfile::with_function`.
+
+use std::cmp::Ordering;
+
+use flagfile_lib::ast::Atom;
+use flagfile_lib::eval::{EvalOptions, Functions};
+
+/// `len`, `trim`, `abs`, `round`, `min` and `max`.
+pub fn builtin() -> Functions {
+    let mut functions = Functions::new();
+    functions
+        .register("len", len)
+        .register("trim", trim)
+        .register("abs", abs)
+        .register("round", round)
+        .register("min", |args: &[Atom]| extreme(args, Ordering::Less))
+        .register("max", |args: &[Atom]| extreme(args, Ordering::Greater));
+    functions
+}
+
+/// Default evaluation options with [`builtin`] registered.
+pub fn eval_options() -> EvalOptions {
+    EvalOptions {
+        functions: builtin(),
+        ..EvalOptions::default()
+    }
+}
+
+/// The first argument; a call without one gets the empty string.
+fn first(args: &[Atom]) -> Atom {
+    args.first()
+        .cloned()
+        .unwrap_or_else(|| Atom::String(String::new()))
+}
+
+/// Items in a list, characters in anything else.
+fn len(args: &[Atom]) -> Atom {
+    let len = match first(args) {
+        Atom::List(items) => items.len(),
+        other => other.to_string().chars().count(),
+    };
+    Atom::Number(len as i64)
+}
+
+fn trim(args: &[Atom]) -> Atom {
+    Atom::String(first(args).to_string().trim().to_string())
+}
+
+/// Numbers and durations lose their sign; other values are returned as is.
+fn abs(args: &[Atom]) -> Atom {
+    match first(args) {
+        Atom::Number(n) => n
+            .checked_abs()
+            .map_or(Atom::Float((n as f64).abs()), Atom::Number),
+        Atom::Float(f) => Atom::Float(f.abs()),
+        Atom::Duration(d) => Atom::Duration(d.abs()),
+        other => other,
+    }
+}
+
+/// The nearest whole number to a float, halves away from zero; other values
+/// are returned as is.
+fn round(args: &[Atom]) -> Atom {
+    match first(args) {
+        Atom::Float(f) if f.round().abs() < i64::MAX as f64 => Atom::Number(f.round() as i64),
+        other => other,
+    }
+}
+
+/// The smallest (`Less`) or largest (`Greater`) argument. One that doesn't
+/// compare with the best so far, like text against a number, is skipped.
+fn extreme(args: &[Atom], want: Ordering) -> Atom {
+    args.iter().skip(1).fold(first(args), |best, arg| {
+        if arg.partial_cmp(&best) == Some(want) {
+            arg.clone()
+        } else {
+            best
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn call(name: &str, args: &[Atom]) -> Atom {
+        builtin().get(name).expect(name)(args)
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let text = |s: &str| Atom::String(s.into());
+        assert_eq!(call("len", &[text("héllo")]), Atom::Number(5));
+        assert_eq!(
+            call("len", &[Atom::List(vec![Atom::Number(1), Atom::Number(2)])]),
+            Atom::Number(2)
+        );
+        assert_eq!(call("len", &[]), Atom::Number(0));
+        assert_eq!(call("trim", &[text("  eu ")]), text("eu"));
+        assert_eq!(call("abs", &[Atom::Number(-3)]), Atom::Number(3));
+        assert_eq!(
+            call("abs", &[Atom::Number(i64::MIN)]),
+            Atom::Float(9.223372036854776e18)
+        );
+        assert_eq!(
+            call("abs", &[Atom::Duration(TimeDelta::hours(-2))]),
+            Atom::Duration(TimeDelta::hours(2))
+        );
+        assert_eq!(call("abs", &[text("x")]), text("x"));
+        assert_eq!(call("round", &[Atom::Float(2.5)]), Atom::Number(3));
+        assert_eq!(call("round", &[Atom::Float(-2.4)]), Atom::Number(-2));
+        assert_eq!(call("round", &[Atom::Float(1e300)]), Atom::Float(1e300));
+        assert_eq!(
+            call("min", &[Atom::Number(3), Atom::Float(1.5), Atom::Number(2)]),
+            Atom::Float(1.5)
+        );
+        assert_eq!(
+            call("max", &[Atom::Number(3), text("x"), Atom::Number(7)]),
+            Atom::Number(7)
+        );
+        assert_eq!(call("max", &[]), text(""));
+    }
+}
@@ -7,10 +7,12 @@ use super::LintWarning;
 pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
     let mut warnings = Vec::new();
     let mut requires_map: HashMap<&str, &Vec<String>> = HashMap::new();
+    let mut spans = HashMap::new();
     for fv in &parsed.flags {
         for (name, def) in fv.iter() {
             if !def.metadata.requires.is_empty() {
                 requires_map.insert(*name, &def.metadata.requires);
+                spans.insert(*name, def.span);
             }
         }
     }
@@ -20,10 +22,9 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
         if !visited.contains(*flag) {
             let mut stack = HashSet::new();
             if let Some(cycle) = detect_cycle(flag, &requires_map, &mut visited, &mut stack) {
-                warnings.push(LintWarning::error(format!(
-                    "circular dependency: {}",
-                    cycle
-                )));
+                warnings.push(
+                    LintWarning::error(format!("circular dependency: {}", cycle)).at(spans[flag]),
+                );
             }
         }
     }
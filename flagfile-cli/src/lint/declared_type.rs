@@ -0,0 +1,10 @@
+use flagfile_lib::parse_flagfile::FlagDefinition;
+
+use super::LintWarning;
+
+pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
+    def.type_mismatches(name)
+        .into_iter()
+        .map(|m| LintWarning::error(m.to_string()))
+        .collect()
+}
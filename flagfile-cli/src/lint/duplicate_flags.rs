@@ -8,12 +8,11 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
     let mut warnings = Vec::new();
     let mut seen = HashSet::new();
     for fv in &parsed.flags {
-        for (name, _) in fv.iter() {
+        for (name, def) in fv.iter() {
             if !seen.insert(name) {
-                warnings.push(LintWarning::warn(format!(
-                    "{} is defined more than once",
-                    name
-                )));
+                warnings.push(
+                    LintWarning::warn(format!("{} is defined more than once", name)).at(def.span),
+                );
             }
         }
     }
@@ -6,8 +6,16 @@ use super::LintWarning;
 
 pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
     let mut warnings = Vec::new();
+    // A declared @type is checked precisely by the declared_type lint
+    if def.metadata.value_type.is_some() {
+        return warnings;
+    }
     let mut types = HashSet::new();
     collect_return_types(&def.rules, &mut types);
+    // Whole numbers alongside floats are still one numeric type
+    if types.contains("float") {
+        types.remove("integer");
+    }
     if types.len() > 1 {
         let mut sorted: Vec<&str> = types.into_iter().collect();
         sorted.sort();
@@ -24,18 +32,23 @@ fn return_type_name(ret: &FlagReturn) -> &'static str {
     match ret {
         FlagReturn::OnOff(_) => "boolean",
         FlagReturn::Integer(_) => "integer",
+        FlagReturn::Float(_) => "float",
         FlagReturn::Str(_) => "string",
         FlagReturn::Json(_) => "json",
+        FlagReturn::Variant(_, inner) => return_type_name(inner),
     }
 }
 
 fn collect_return_types(rules: &[Rule], out: &mut HashSet<&'static str>) {
     for rule in rules {
         match rule {
-            Rule::Value(ret) | Rule::BoolExpressionValue(_, ret, _) => {
+            Rule::Value(ret, _) | Rule::BoolExpressionValue(_, ret, _, _) => {
                 out.insert(return_type_name(ret));
             }
             Rule::EnvRule { rules, .. } => collect_return_types(rules, out),
+            Rule::Split { split, .. } => {
+                out.extend(split.arms.iter().map(|(ret, _)| return_type_name(ret)))
+            }
         }
     }
 }
@@ -1,6 +1,7 @@
 mod circular_deps;
 mod circular_segments;
 mod coalesce_constant_first;
+mod declared_type;
 mod deprecated;
 mod deprecated_no_expiry;
 mod duplicate_flags;
@@ -19,13 +20,15 @@ mod tautology;
 mod undefined_requires;
 mod undefined_segment;
 mod unreachable_rules;
+mod unregistered_function;
 mod unused_segments;
 
 use std::io::{self, IsTerminal};
 use std::process;
 
-use chrono::Local;
-use flagfile_lib::parse_flagfile::parse_flagfile_with_segments;
+use chrono::{Local, NaiveDate};
+use flagfile_lib::ast::Span;
+use flagfile_lib::parse_flagfile::{parse_flagfile_recovering, ParsedFlagfile};
 
 #[derive(Debug)]
 pub enum LintLevel {
@@ -37,6 +40,8 @@ pub enum LintLevel {
 pub struct LintWarning {
     pub level: LintLevel,
     pub message: String,
+    /// The flag, rule or segment the warning is about.
+    pub span: Option<Span>,
 }
 
 impl LintWarning {
@@ -44,6 +49,7 @@ impl LintWarning {
         Self {
             level: LintLevel::Warning,
             message: message.into(),
+            span: None,
         }
     }
 
@@ -51,58 +57,34 @@ impl LintWarning {
         Self {
             level: LintLevel::Error,
             message: message.into(),
+            span: None,
         }
     }
-}
 
-/// Inner lint logic that returns Ok(()) on success or Err(()) on failure.
-/// Used by both the standalone `lint` command and the combined `check` command.
-pub fn run_lint_inner(flagfile_path: &str) -> Result<(), ()> {
-    let (_raw, resolved) = crate::read_flagfile_resolved(flagfile_path)?;
-    let flagfile_content = resolved.content;
-
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Parsing failed: {}", e);
-            return Err(());
-        }
-    };
-
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Parsing failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
-        return Err(());
+    pub fn at(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
     }
+}
 
-    let today = Local::now().date_naive();
-    let use_color = io::stderr().is_terminal();
-    let warn_icon = if use_color {
-        "\x1b[33m\u{26a0}\x1b[0m"
-    } else {
-        "\u{26a0}"
-    };
-    let error_icon = if use_color {
-        "\x1b[31m\u{26a0}\x1b[0m"
-    } else {
-        "\u{26a0}"
-    };
-
+/// Every lint finding for a parsed Flagfile, global lints first. Warnings
+/// about a single flag point at it unless they found a specific rule.
+pub fn check(parsed: &ParsedFlagfile, today: NaiveDate) -> Vec<LintWarning> {
     let mut warnings: Vec<LintWarning> = Vec::new();
 
     // Global lints
-    warnings.extend(duplicate_flags::check(&parsed));
-    warnings.extend(circular_deps::check(&parsed));
-    warnings.extend(circular_segments::check(&parsed));
-    warnings.extend(unused_segments::check(&parsed));
-    warnings.extend(undefined_requires::check(&parsed));
-    warnings.extend(undefined_segment::check(&parsed));
+    warnings.extend(duplicate_flags::check(parsed));
+    warnings.extend(circular_deps::check(parsed));
+    warnings.extend(circular_segments::check(parsed));
+    warnings.extend(unused_segments::check(parsed));
+    warnings.extend(undefined_requires::check(parsed));
+    warnings.extend(undefined_segment::check(parsed));
+    warnings.extend(unregistered_function::check(parsed));
 
     // Per-flag lints
     for fv in &parsed.flags {
         for (name, def) in fv.iter() {
+            let start = warnings.len();
             warnings.extend(deprecated::check(name, def));
             warnings.extend(expired::check(name, def, today));
             warnings.extend(missing_owner::check(name, def));
@@ -111,6 +93,7 @@ pub fn run_lint_inner(flagfile_path: &str) -> Result<(), ()> {
             warnings.extend(unreachable_rules::check(name, def));
             warnings.extend(missing_default::check(name, def));
             warnings.extend(mixed_return_types::check(name, def));
+            warnings.extend(declared_type::check(name, def));
             warnings.extend(empty_flag::check(name, def));
             warnings.extend(duplicate_requires::check(name, def));
             warnings.extend(percentage_range::check(name, def));
@@ -119,24 +102,68 @@ pub fn run_lint_inner(flagfile_path: &str) -> Result<(), ()> {
             warnings.extend(redundant_function::check(name, def));
             warnings.extend(env_missing_default::check(name, def));
             warnings.extend(shadowed_env_rules::check(name, def));
+            // Point at the flag unless the lint found a specific rule
+            for w in &mut warnings[start..] {
+                w.span.get_or_insert(def.span);
+            }
         }
     }
+    warnings
+}
 
-    if warnings.is_empty() {
-        println!("{} ok, no warnings", flagfile_path);
-        Ok(())
+/// Inner lint logic that returns Ok(()) on success or Err(()) on failure.
+/// Used by both the standalone `lint` command and the combined `check` command.
+pub fn run_lint_inner(flagfile_path: &str) -> Result<(), ()> {
+    let (_raw, resolved) = crate::read_flagfile_resolved(flagfile_path)?;
+    let flagfile_content = &resolved.content;
+
+    // Lint whatever parsed, so syntax errors don't hide the other findings
+    let (parsed, syntax_errors) = parse_flagfile_recovering(flagfile_content);
+    let syntax_error_count = syntax_errors.len();
+    for e in syntax_errors {
+        crate::report_parse_error(&resolved, e);
+        eprintln!();
+    }
+
+    let today = Local::now().date_naive();
+    let use_color = io::stderr().is_terminal();
+    let warn_icon = if use_color {
+        "\x1b[33m\u{26a0}\x1b[0m"
     } else {
-        for w in &warnings {
-            let icon = match w.level {
-                LintLevel::Warning => warn_icon,
-                LintLevel::Error => error_icon,
-            };
-            eprintln!("{} {}", icon, w.message);
+        "\u{26a0}"
+    };
+    let error_icon = if use_color {
+        "\x1b[31m\u{26a0}\x1b[0m"
+    } else {
+        "\u{26a0}"
+    };
+
+    let warnings = check(&parsed, today);
+
+    if warnings.is_empty() && syntax_error_count == 0 {
+        println!("{} ok, no warnings", flagfile_path);
+        return Ok(());
+    }
+    for w in &warnings {
+        let icon = match w.level {
+            LintLevel::Warning => warn_icon,
+            LintLevel::Error => error_icon,
+        };
+        match w.span {
+            Some(span) => eprintln!("{} {}: {}", icon, resolved.position(span), w.message),
+            None => eprintln!("{} {}", icon, w.message),
         }
+    }
+    if !warnings.is_empty() {
         eprintln!();
+    }
+    if syntax_error_count > 0 {
+        eprintln!("{} syntax errors found", syntax_error_count);
+    }
+    if !warnings.is_empty() {
         eprintln!("{} warnings found", warnings.len());
-        Err(())
     }
+    Err(())
 }
 
 /// Standalone lint command entry point. Calls `run_lint_inner` and exits on failure.
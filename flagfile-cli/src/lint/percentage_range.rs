@@ -12,9 +12,14 @@ pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
 fn check_rules(name: &str, rules: &[Rule], warnings: &mut Vec<LintWarning>) {
     for rule in rules {
         match rule {
-            Rule::BoolExpressionValue(expr, _, _) => check_node(name, expr, warnings),
+            Rule::BoolExpressionValue(expr, _, _, span) => {
+                let mut found = Vec::new();
+                check_node(name, expr, &mut found);
+                warnings.extend(found.into_iter().map(|w| w.at(*span)));
+            }
             Rule::EnvRule { rules, .. } => check_rules(name, rules, warnings),
-            Rule::Value(_) => {}
+            // split weights are checked when the Flagfile is parsed
+            Rule::Value(..) | Rule::Split { .. } => {}
         }
     }
 }
@@ -30,8 +35,22 @@ fn check_node(name: &str, node: &AstNode, warnings: &mut Vec<LintWarning>) {
             }
             check_node(name, field, warnings);
         }
+        AstNode::Ramp {
+            from, to, field, ..
+        } => {
+            for rate in [from, to] {
+                if *rate < 0.0 || *rate > 100.0 {
+                    warnings.push(LintWarning::error(format!(
+                        "{}: ramp rate {}% is out of valid range (0-100)",
+                        name, rate
+                    )));
+                }
+            }
+            check_node(name, field, warnings);
+        }
         AstNode::Logic(lhs, _, rhs)
         | AstNode::Compare(lhs, _, rhs)
+        | AstNode::Arithmetic(lhs, _, rhs)
         | AstNode::Match(lhs, _, rhs)
         | AstNode::Array(lhs, _, rhs) => {
             check_node(name, lhs, warnings);
@@ -39,13 +58,13 @@ fn check_node(name: &str, node: &AstNode, warnings: &mut Vec<LintWarning>) {
         }
         AstNode::Scope { expr, .. } => check_node(name, expr, warnings),
         AstNode::Function(_, inner) => check_node(name, inner, warnings),
-        AstNode::Coalesce(nodes) => {
+        AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
             for n in nodes {
                 check_node(name, n, warnings);
             }
         }
         AstNode::NullCheck { variable, .. } => check_node(name, variable, warnings),
         AstNode::Void | AstNode::Variable(_) | AstNode::Constant(_) | AstNode::List(_) => {}
-        AstNode::Segment(_) => {}
+        AstNode::Segment(..) | AstNode::Active(_) => {}
     }
 }
@@ -12,9 +12,18 @@ pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
 fn check_rules(name: &str, rules: &[Rule], warnings: &mut Vec<LintWarning>) {
     for rule in rules {
         match rule {
-            Rule::BoolExpressionValue(expr, _, _) => check_node(name, expr, warnings),
+            Rule::BoolExpressionValue(expr, _, _, span) => {
+                let mut found = Vec::new();
+                check_node(name, expr, &mut found);
+                warnings.extend(found.into_iter().map(|w| w.at(*span)));
+            }
+            Rule::Split { split, span, .. } => {
+                let mut found = Vec::new();
+                check_node(name, &split.field, &mut found);
+                warnings.extend(found.into_iter().map(|w| w.at(*span)));
+            }
             Rule::EnvRule { rules, .. } => check_rules(name, rules, warnings),
-            Rule::Value(_) => {}
+            Rule::Value(..) => {}
         }
     }
 }
@@ -35,14 +44,17 @@ fn check_node(name: &str, node: &AstNode, warnings: &mut Vec<LintWarning>) {
         }
         AstNode::Logic(lhs, _, rhs)
         | AstNode::Compare(lhs, _, rhs)
+        | AstNode::Arithmetic(lhs, _, rhs)
         | AstNode::Match(lhs, _, rhs)
         | AstNode::Array(lhs, _, rhs) => {
             check_node(name, lhs, warnings);
             check_node(name, rhs, warnings);
         }
         AstNode::Scope { expr, .. } => check_node(name, expr, warnings),
-        AstNode::Percentage { field, .. } => check_node(name, field, warnings),
-        AstNode::Coalesce(nodes) => {
+        AstNode::Percentage { field, .. } | AstNode::Ramp { field, .. } => {
+            check_node(name, field, warnings)
+        }
+        AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
             for n in nodes {
                 check_node(name, n, warnings);
             }
@@ -52,6 +64,7 @@ fn check_node(name: &str, node: &AstNode, warnings: &mut Vec<LintWarning>) {
         | AstNode::Variable(_)
         | AstNode::Constant(_)
         | AstNode::List(_)
-        | AstNode::Segment(_) => {}
+        | AstNode::Segment(..)
+        | AstNode::Active(_) => {}
     }
 }
@@ -12,11 +12,14 @@ pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
 fn check_rules(name: &str, rules: &[Rule], warnings: &mut Vec<LintWarning>) {
     for rule in rules {
         match rule {
-            Rule::BoolExpressionValue(AstNode::Constant(Atom::Boolean(true)), _, _) => {
-                warnings.push(LintWarning::warn(format!(
-                    "{}: tautological condition (true -> ...) is always matched",
-                    name
-                )));
+            Rule::BoolExpressionValue(AstNode::Constant(Atom::Boolean(true)), _, _, span) => {
+                warnings.push(
+                    LintWarning::warn(format!(
+                        "{}: tautological condition (true -> ...) is always matched",
+                        name
+                    ))
+                    .at(*span),
+                );
             }
             Rule::EnvRule { rules, .. } => check_rules(name, rules, warnings),
             _ => {}
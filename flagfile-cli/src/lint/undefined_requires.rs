@@ -17,10 +17,13 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
         for (name, def) in fv.iter() {
             for req in &def.metadata.requires {
                 if !defined.contains(req.as_str()) {
-                    warnings.push(LintWarning::error(format!(
-                        "{}: @requires references undefined flag \"{}\"",
-                        name, req
-                    )));
+                    warnings.push(
+                        LintWarning::error(format!(
+                            "{}: @requires references undefined flag \"{}\"",
+                            name, req
+                        ))
+                        .at(def.span),
+                    );
                 }
             }
         }
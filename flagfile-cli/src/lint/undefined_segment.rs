@@ -1,13 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use flagfile_lib::ast::AstNode;
+use flagfile_lib::ast::{AstNode, Span};
 use flagfile_lib::parse_flagfile::{ParsedFlagfile, Rule};
 
 use super::LintWarning;
 
 pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
     let mut warnings = Vec::new();
-    let mut used = HashSet::new();
+    let mut used = HashMap::new();
 
     for fv in &parsed.flags {
         for (_, def) in fv.iter() {
@@ -15,54 +15,81 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
         }
     }
     // Segments can reference other segments
-    for expr in parsed.segments.values() {
-        collect_segment_refs(expr, &mut used);
+    for def in parsed.segments.values() {
+        collect_segment_refs(&def.body, def.span, &mut used);
     }
 
-    let defined: HashSet<&str> = parsed.segments.keys().map(|s| s.as_str()).collect();
-
-    for name in &used {
-        if !defined.contains(name.as_str()) {
-            warnings.push(LintWarning::error(format!(
-                "segment \"{}\" is used but never defined",
-                name
-            )));
+    let mut undefined = HashSet::new();
+    for ((name, arity), span) in &used {
+        match parsed.segments.get(name) {
+            None => {
+                if undefined.insert(name) {
+                    warnings.push(
+                        LintWarning::error(format!(
+                            "segment \"{}\" is used but never defined",
+                            name
+                        ))
+                        .at(*span),
+                    );
+                }
+            }
+            Some(def) if def.params.len() != *arity => {
+                warnings.push(
+                    LintWarning::error(format!(
+                        "segment \"{}\" takes {} argument(s) but is called with {}",
+                        name,
+                        def.params.len(),
+                        arity
+                    ))
+                    .at(*span),
+                );
+            }
+            Some(_) => {}
         }
     }
     warnings
 }
 
-fn collect_segment_refs(node: &AstNode, out: &mut HashSet<String>) {
+/// Collects `(name, argument count)` for every segment call, with the span
+/// of the first rule or segment making it.
+fn collect_segment_refs(node: &AstNode, span: Span, out: &mut HashMap<(String, usize), Span>) {
     match node {
-        AstNode::Segment(name) => {
-            out.insert(name.clone());
+        AstNode::Segment(name, args) => {
+            out.entry((name.clone(), args.len())).or_insert(span);
         }
         AstNode::Logic(lhs, _, rhs)
         | AstNode::Compare(lhs, _, rhs)
+        | AstNode::Arithmetic(lhs, _, rhs)
         | AstNode::Match(lhs, _, rhs)
         | AstNode::Array(lhs, _, rhs) => {
-            collect_segment_refs(lhs, out);
-            collect_segment_refs(rhs, out);
+            collect_segment_refs(lhs, span, out);
+            collect_segment_refs(rhs, span, out);
+        }
+        AstNode::Scope { expr, .. } => collect_segment_refs(expr, span, out),
+        AstNode::Function(_, inner) => collect_segment_refs(inner, span, out),
+        AstNode::Percentage { field, .. } | AstNode::Ramp { field, .. } => {
+            collect_segment_refs(field, span, out)
         }
-        AstNode::Scope { expr, .. } => collect_segment_refs(expr, out),
-        AstNode::Function(_, inner) => collect_segment_refs(inner, out),
-        AstNode::Percentage { field, .. } => collect_segment_refs(field, out),
-        AstNode::Coalesce(nodes) => {
+        AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
             for n in nodes {
-                collect_segment_refs(n, out);
+                collect_segment_refs(n, span, out);
             }
         }
-        AstNode::NullCheck { variable, .. } => collect_segment_refs(variable, out),
-        AstNode::Void | AstNode::Variable(_) | AstNode::Constant(_) | AstNode::List(_) => {}
+        AstNode::NullCheck { variable, .. } => collect_segment_refs(variable, span, out),
+        AstNode::Void
+        | AstNode::Variable(_)
+        | AstNode::Constant(_)
+        | AstNode::List(_)
+        | AstNode::Active(_) => {}
     }
 }
 
-fn collect_segment_refs_from_rules(rules: &[Rule], out: &mut HashSet<String>) {
+fn collect_segment_refs_from_rules(rules: &[Rule], out: &mut HashMap<(String, usize), Span>) {
     for rule in rules {
         match rule {
-            Rule::BoolExpressionValue(expr, _, _) => collect_segment_refs(expr, out),
+            Rule::BoolExpressionValue(expr, _, _, span) => collect_segment_refs(expr, *span, out),
             Rule::EnvRule { rules, .. } => collect_segment_refs_from_rules(rules, out),
-            Rule::Value(_) => {}
+            Rule::Value(..) | Rule::Split { .. } => {}
         }
     }
 }
@@ -5,12 +5,15 @@ use super::LintWarning;
 pub fn check(name: &str, def: &FlagDefinition) -> Vec<LintWarning> {
     let mut warnings = Vec::new();
     let unreachable = find_unreachable(&def.rules);
-    if !unreachable.is_empty() {
-        warnings.push(LintWarning::warn(format!(
-            "{}: {} unreachable rule(s) after catch-all",
-            name,
-            unreachable.len()
-        )));
+    if let Some(&first) = unreachable.first() {
+        warnings.push(
+            LintWarning::warn(format!(
+                "{}: {} unreachable rule(s) after catch-all",
+                name,
+                unreachable.len()
+            ))
+            .at(def.rules[first].span()),
+        );
     }
     warnings
 }
@@ -23,7 +26,7 @@ fn find_unreachable(rules: &[Rule]) -> Vec<usize> {
             unreachable.push(i);
             continue;
         }
-        if matches!(rule, Rule::Value(_)) {
+        if matches!(rule, Rule::Value(..) | Rule::Split { .. }) {
             found_catchall = true;
         }
     }
@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use flagfile_lib::ast::Span;
+use flagfile_lib::parse_flagfile::{ParsedFlagfile, Rule};
+
+use super::LintWarning;
+
+/// `fn:` calls that `ff` itself can't evaluate. They may well be registered
+/// by the application, so this is a warning rather than an error.
+pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
+    let builtin = crate::functions::builtin();
+    let mut calls = Vec::new();
+    for fv in &parsed.flags {
+        for (_, def) in fv.iter() {
+            collect_calls_from_rules(&def.rules, &mut calls);
+        }
+    }
+    for def in parsed.segments.values() {
+        calls.extend(def.body.function_calls().into_iter().map(|n| (n, def.span)));
+    }
+    calls.sort_by_key(|(_, span)| span.start);
+
+    let mut reported = HashSet::new();
+    let mut warnings = Vec::new();
+    for (name, span) in calls {
+        if !builtin.contains(name) && reported.insert(name) {
+            warnings.push(
+                LintWarning::warn(format!(
+                    "function \"fn:{}\" is not registered with ff; the application evaluating this Flagfile must provide it",
+                    name
+                ))
+                .at(span),
+            );
+        }
+    }
+    warnings
+}
+
+fn collect_calls_from_rules<'a>(rules: &'a [Rule], out: &mut Vec<(&'a str, Span)>) {
+    for rule in rules {
+        match rule {
+            Rule::BoolExpressionValue(expr, _, _, span) => {
+                out.extend(expr.function_calls().into_iter().map(|n| (n, *span)))
+            }
+            Rule::EnvRule { rules, .. } => collect_calls_from_rules(rules, out),
+            Rule::Value(..) | Rule::Split { .. } => {}
+        }
+    }
+}
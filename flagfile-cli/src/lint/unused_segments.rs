@@ -15,16 +15,19 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
         }
     }
     // Segments can reference other segments
-    for expr in parsed.segments.values() {
-        collect_refs(expr, &mut used);
+    for def in parsed.segments.values() {
+        collect_refs(&def.body, &mut used);
     }
 
-    for seg_name in parsed.segments.keys() {
+    for (seg_name, def) in parsed.segments.iter() {
         if !used.contains(seg_name) {
-            warnings.push(LintWarning::warn(format!(
-                "segment \"{}\" is defined but never used",
-                seg_name
-            )));
+            warnings.push(
+                LintWarning::warn(format!(
+                    "segment \"{}\" is defined but never used",
+                    seg_name
+                ))
+                .at(def.span),
+            );
         }
     }
     warnings
@@ -32,11 +35,12 @@ pub fn check(parsed: &ParsedFlagfile) -> Vec<LintWarning> {
 
 fn collect_refs(node: &AstNode, out: &mut HashSet<String>) {
     match node {
-        AstNode::Segment(name) => {
+        AstNode::Segment(name, _) => {
             out.insert(name.clone());
         }
         AstNode::Logic(lhs, _, rhs)
         | AstNode::Compare(lhs, _, rhs)
+        | AstNode::Arithmetic(lhs, _, rhs)
         | AstNode::Match(lhs, _, rhs)
         | AstNode::Array(lhs, _, rhs) => {
             collect_refs(lhs, out);
@@ -44,23 +48,27 @@ fn collect_refs(node: &AstNode, out: &mut HashSet<String>) {
         }
         AstNode::Scope { expr, .. } => collect_refs(expr, out),
         AstNode::Function(_, inner) => collect_refs(inner, out),
-        AstNode::Percentage { field, .. } => collect_refs(field, out),
-        AstNode::Coalesce(nodes) => {
+        AstNode::Percentage { field, .. } | AstNode::Ramp { field, .. } => collect_refs(field, out),
+        AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
             for n in nodes {
                 collect_refs(n, out);
             }
         }
         AstNode::NullCheck { variable, .. } => collect_refs(variable, out),
-        AstNode::Void | AstNode::Variable(_) | AstNode::Constant(_) | AstNode::List(_) => {}
+        AstNode::Void
+        | AstNode::Variable(_)
+        | AstNode::Constant(_)
+        | AstNode::List(_)
+        | AstNode::Active(_) => {}
     }
 }
 
 fn collect_refs_from_rules(rules: &[Rule], out: &mut HashSet<String>) {
     for rule in rules {
         match rule {
-            Rule::BoolExpressionValue(expr, _, _) => collect_refs(expr, out),
+            Rule::BoolExpressionValue(expr, ..) => collect_refs(expr, out),
             Rule::EnvRule { rules, .. } => collect_refs_from_rules(rules, out),
-            Rule::Value(_) => {}
+            Rule::Value(..) | Rule::Split { .. } => {}
         }
     }
 }
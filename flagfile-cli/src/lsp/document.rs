@@ -0,0 +1,808 @@
+//! What the language server answers about an open Flagfile. Every request
+//! resolves the document's `@include`s and parses it again: Flagfiles are
+//! small, and it keeps included files that changed on disk current.
+//!
+//! Names under the cursor are found with the lossless tokenizer in
+//! [`flagfile_lib::cst`], so names inside strings and comments don't count.
+//!
+//! Positions follow LSP: 0-based lines and UTF-16 columns. Spans from the
+//! parser are 1-based with columns in characters, in the merged content.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use flagfile_lib::ast::{AstNode, Atom, FlagMetadata, Span};
+use flagfile_lib::cst::{tokenize, Token, TokenKind};
+use flagfile_lib::eval::Context;
+use flagfile_lib::include::{resolve_includes, ResolvedFlagfile};
+use flagfile_lib::parse_flagfile::{
+    extract_test_annotations, parse_flagfile_recovering, parse_flagfile_with_segments,
+    ParsedFlagfile, Rule, TestAnnotation,
+};
+use flagfile_lib::plan::Plan;
+use serde_json::{json, Value};
+
+use crate::formatter::format_flagfile;
+use crate::lint::{self, LintLevel};
+
+/// Command run by the code lens above each inline `@test`.
+pub const RUN_TEST_COMMAND: &str = "flagfile.runTest";
+
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_MODULE: u8 = 9;
+const COMPLETION_CONSTANT: u8 = 21;
+
+/// An open document, as last synced from the editor.
+pub struct Document {
+    pub uri: String,
+    pub text: String,
+}
+
+/// A name under the cursor.
+#[derive(Debug, PartialEq)]
+enum Reference<'a> {
+    Flag(&'a str),
+    Segment(&'a str),
+}
+
+impl Document {
+    /// Syntax errors and lint warnings in this document. Findings in
+    /// included files are left to those files' own diagnostics.
+    pub fn diagnostics(&self) -> Vec<Value> {
+        let (resolved, include_error) = self.resolve();
+        let mut diagnostics = Vec::new();
+        if let Some(e) = include_error {
+            let line = self
+                .text
+                .lines()
+                .position(|l| l.trim_start().starts_with("@include"))
+                .unwrap_or(0);
+            diagnostics.push(diagnostic(line_range(&self.text, line), SEVERITY_ERROR, &e));
+        }
+
+        let (parsed, errors) = parse_flagfile_recovering(&resolved.content);
+        for err in errors {
+            let err = resolved.locate(err);
+            if err.file.is_some() {
+                continue;
+            }
+            let start = lsp_position(&self.text, err.line, err.column);
+            // underline the token the error is at
+            let token = err
+                .source_line
+                .chars()
+                .skip(err.column - 1)
+                .take_while(|c| !c.is_whitespace())
+                .count()
+                .max(1);
+            let end = lsp_position(&self.text, err.line, err.column + token);
+            let message = match err.expected_hint() {
+                Some(hint) => format!("{} ({})", err.message, hint),
+                None => err.message.clone(),
+            };
+            diagnostics.push(diagnostic(
+                json!({"start": start, "end": end}),
+                SEVERITY_ERROR,
+                &message,
+            ));
+        }
+
+        for warning in lint::check(&parsed, Local::now().date_naive()) {
+            let range = match warning.span {
+                Some(span) => match self.locate(&resolved, span) {
+                    (None, range) => range,
+                    (Some(_), _) => continue,
+                },
+                None => line_range(&self.text, 0),
+            };
+            let severity = match warning.level {
+                LintLevel::Warning => SEVERITY_WARNING,
+                LintLevel::Error => SEVERITY_ERROR,
+            };
+            diagnostics.push(diagnostic(range, severity, &warning.message));
+        }
+        diagnostics
+    }
+
+    /// Where the flag or segment under the cursor is defined.
+    pub fn definition(&self, position: &Value) -> Value {
+        let offset = offset_at(&self.text, position);
+        let Some(reference) = reference_at(&self.text, offset) else {
+            return Value::Null;
+        };
+        let (resolved, _) = self.resolve();
+        let (parsed, _) = parse_flagfile_recovering(&resolved.content);
+        let span = match reference {
+            Reference::Flag(name) => find_flag(&parsed, name).map(|(span, _)| span),
+            Reference::Segment(name) => parsed.segments.get(name).map(|def| def.span),
+        };
+        let Some(span) = span else {
+            return Value::Null;
+        };
+        let (file, range) = self.locate(&resolved, span);
+        let uri = match file {
+            Some(path) => path_to_uri(&path),
+            None => self.uri.clone(),
+        };
+        json!({"uri": uri, "range": range})
+    }
+
+    /// A flag's metadata, or a segment's signature, as markdown.
+    pub fn hover(&self, position: &Value) -> Value {
+        let offset = offset_at(&self.text, position);
+        let Some(reference) = reference_at(&self.text, offset) else {
+            return Value::Null;
+        };
+        let (resolved, _) = self.resolve();
+        let (parsed, _) = parse_flagfile_recovering(&resolved.content);
+        let markdown = match reference {
+            Reference::Flag(name) => match find_flag(&parsed, name) {
+                Some((_, metadata)) => flag_markdown(name, metadata),
+                None => return Value::Null,
+            },
+            Reference::Segment(name) => match parsed.segments.get(name) {
+                Some(def) if def.params.is_empty() => format!("**@segment {}**", name),
+                Some(def) => format!("**@segment {}({})**", name, def.params.join(", ")),
+                None => return Value::Null,
+            },
+        };
+        json!({"contents": {"kind": "markdown", "value": markdown}})
+    }
+
+    /// Flag and segment names and the context keys used by rules. Only
+    /// segments are offered inside `segment(`, and only flags after
+    /// `@requires`.
+    pub fn completion(&self, position: &Value) -> Vec<Value> {
+        let offset = offset_at(&self.text, position);
+        let line_start = self.text[..offset].rfind('\n').map_or(0, |at| at + 1);
+        let before = self.text[line_start..offset].trim_end_matches(is_word_char);
+        let in_segment_call = before
+            .trim_end()
+            .strip_suffix('(')
+            .is_some_and(|b| b.trim_end().ends_with("segment"));
+        let in_requires = before.trim_start().starts_with("@requires");
+
+        let (resolved, _) = self.resolve();
+        let (parsed, _) = parse_flagfile_recovering(&resolved.content);
+        let mut items = Vec::new();
+        if !in_segment_call {
+            let flags: BTreeMap<&str, &FlagMetadata> = parsed
+                .flags
+                .iter()
+                .flat_map(|fv| fv.iter().map(|(name, def)| (*name, &def.metadata)))
+                .collect();
+            for (name, metadata) in flags {
+                let detail = metadata.description.as_deref().unwrap_or("flag");
+                items.push(json!({"label": name, "kind": COMPLETION_CONSTANT, "detail": detail}));
+            }
+        }
+        if in_requires {
+            return items;
+        }
+        let segments: BTreeSet<&String> = parsed.segments.keys().collect();
+        for name in segments {
+            items.push(json!({"label": name, "kind": COMPLETION_MODULE, "detail": "segment"}));
+        }
+        if in_segment_call {
+            return items;
+        }
+        for key in context_keys(&parsed) {
+            items.push(json!({"label": key, "kind": COMPLETION_VARIABLE, "detail": "context key"}));
+        }
+        items
+    }
+
+    /// The `ff fmt` output as a single edit. Nothing is changed while the
+    /// document has syntax errors.
+    pub fn formatting(&self) -> Vec<Value> {
+        let (resolved, include_error) = self.resolve();
+        if include_error.is_some() || parse_flagfile_with_segments(&resolved.content).is_err() {
+            return Vec::new();
+        }
+        let formatted = format_flagfile(&self.text);
+        if formatted == self.text {
+            return Vec::new();
+        }
+        let end = json!({
+            "line": self.text.matches('\n').count(),
+            "character": self.text.rsplit('\n').next().unwrap_or("").encode_utf16().count(),
+        });
+        vec![json!({
+            "range": {"start": {"line": 0, "character": 0}, "end": end},
+            "newText": formatted,
+        })]
+    }
+
+    /// A "Run test" lens on every line with a `@test`, whether in a comment
+    /// or in a flag's annotations.
+    pub fn code_lenses(&self) -> Vec<Value> {
+        self.inline_tests()
+            .into_iter()
+            .map(|test| {
+                let line = test.line_number - 1;
+                json!({
+                    "range": {"start": {"line": line, "character": 0},
+                              "end": {"line": line, "character": 0}},
+                    "command": {
+                        "title": "\u{25b6} Run test",
+                        "command": RUN_TEST_COMMAND,
+                        "arguments": [self.uri, test.assertion],
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Evaluate a `@test` assertion against this document. `Ok` and `Err`
+    /// carry the `PASS`/`FAIL` line to show.
+    pub fn run_test(&self, assertion: &str) -> Result<String, String> {
+        let (resolved, include_error) = self.resolve();
+        if let Some(e) = include_error {
+            return Err(e);
+        }
+        let parsed = parse_flagfile_with_segments(&resolved.content)
+            .map_err(|e| resolved.locate(e).to_string())?;
+        let Some((flag_name, pairs, expected)) = crate::parse_test_line(assertion) else {
+            return Err(format!("invalid @test: {}", assertion));
+        };
+
+        let mut flags: HashMap<&str, Vec<Rule>> = HashMap::new();
+        let mut metadata: HashMap<&str, FlagMetadata> = HashMap::new();
+        for fv in &parsed.flags {
+            for (name, def) in fv.iter() {
+                flags.insert(name, def.rules.clone());
+                metadata.insert(name, def.metadata.clone());
+            }
+        }
+        let plan = Plan::compile(&flags, &metadata, &parsed.segments);
+        if plan.flag(flag_name).is_none() {
+            return Err(format!("FAIL  {} - flag not found", assertion));
+        }
+
+        let context: Context = pairs.iter().map(|(k, v)| (*k, Atom::from(*v))).collect();
+        let detail = plan.evaluate(flag_name, &context, None, &crate::functions::eval_options());
+        match detail.value {
+            Some(ref value) if crate::result_matches(value, expected) => {
+                Ok(format!("PASS  {}", assertion))
+            }
+            Some(value) => {
+                let mut message = format!("FAIL  {} - got {}", assertion, value);
+                if let Some(span) = detail.rule_span {
+                    message.push_str(&format!(", returned by {}", resolved.position(span)));
+                }
+                Err(message)
+            }
+            None => Err(format!("FAIL  {} - no rule matched", assertion)),
+        }
+    }
+
+    /// `@test`s in comments and in flag annotations, in line order.
+    fn inline_tests(&self) -> Vec<TestAnnotation> {
+        let mut tests = extract_test_annotations(&self.text);
+        for (index, line) in self.text.lines().enumerate() {
+            let Some(assertion) = line.trim().strip_prefix("@test ") else {
+                continue;
+            };
+            if !tests.iter().any(|t| t.line_number == index + 1) {
+                tests.push(TestAnnotation {
+                    assertion: assertion.trim().to_string(),
+                    line_number: index + 1,
+                });
+            }
+        }
+        tests.sort_by_key(|t| t.line_number);
+        tests
+    }
+
+    /// The document with its `@include`s expanded relative to its
+    /// directory. If they can't be, the directives are dropped and the error
+    /// is returned alongside, so the rest of the document still gets
+    /// answers.
+    fn resolve(&self) -> (ResolvedFlagfile, Option<String>) {
+        let dir = uri_to_path(&self.uri)
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| PathBuf::from("."));
+        match resolve_includes(&self.text, &dir) {
+            Ok(resolved) => (resolved, None),
+            Err(e) => {
+                // blank the directives out so line numbers stay put
+                let text: Vec<&str> = self
+                    .text
+                    .lines()
+                    .map(|l| {
+                        if l.trim_start().starts_with("@include") {
+                            ""
+                        } else {
+                            l
+                        }
+                    })
+                    .collect();
+                let resolved = resolve_includes(&text.join("\n"), &dir)
+                    .expect("content without @include always resolves");
+                (resolved, Some(e))
+            }
+        }
+    }
+
+    /// The file a span of the merged content came from (`None` for this
+    /// document) and its range there.
+    fn locate(&self, resolved: &ResolvedFlagfile, span: Span) -> (Option<PathBuf>, Value) {
+        let (file, line) = resolved.origin(span.line);
+        let text = match file {
+            Some(path) => resolved
+                .includes
+                .iter()
+                .find(|inc| inc.path == path)
+                .map_or("", |inc| inc.content.as_str()),
+            None => self.text.as_str(),
+        };
+        let start = lsp_position(text, line, span.column);
+        let (end_line, end_column) = line_column(&resolved.content, span.end);
+        let end = match resolved.origin(end_line) {
+            (end_file, end_line) if end_file == file => lsp_position(text, end_line, end_column),
+            _ => start.clone(),
+        };
+        (
+            file.map(Path::to_path_buf),
+            json!({"start": start, "end": end}),
+        )
+    }
+}
+
+fn diagnostic(range: Value, severity: u8, message: &str) -> Value {
+    json!({"range": range, "severity": severity, "source": "ff", "message": message})
+}
+
+fn find_flag<'a>(parsed: &'a ParsedFlagfile, name: &str) -> Option<(Span, &'a FlagMetadata)> {
+    parsed
+        .flags
+        .iter()
+        .find_map(|fv| fv.get(name))
+        .map(|def| (def.span, &def.metadata))
+}
+
+fn flag_markdown(name: &str, metadata: &FlagMetadata) -> String {
+    let mut out = format!("**{}**", name);
+    if let Some(description) = &metadata.description {
+        out.push_str(&format!("\n\n{}", description));
+    }
+    let mut fields = Vec::new();
+    if let Some(owner) = &metadata.owner {
+        fields.push(format!("owner: {}", owner));
+    }
+    if let Some(flag_type) = &metadata.flag_type {
+        fields.push(format!("type: {}", flag_type));
+    }
+    if let Some(value_type) = metadata.value_type {
+        fields.push(format!("returns: {}", value_type));
+    }
+    if let Some(expires) = metadata.expires {
+        fields.push(format!("expires: {}", expires.format("%Y-%m-%d")));
+    }
+    if let Some(ticket) = &metadata.ticket {
+        fields.push(format!("ticket: {}", ticket));
+    }
+    if let Some(deprecated) = &metadata.deprecated {
+        fields.push(format!("deprecated: {}", deprecated));
+    }
+    if !metadata.requires.is_empty() {
+        fields.push(format!("requires: {}", metadata.requires.join(", ")));
+    }
+    if !metadata.tests.is_empty() {
+        fields.push(format!("tests: {}", metadata.tests.len()));
+    }
+    if !fields.is_empty() {
+        out.push_str("\n\n");
+        out.push_str(
+            &fields
+                .iter()
+                .map(|f| format!("- {}", f))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+    out
+}
+
+/// Every context key a rule or segment reads, other than segment
+/// parameters.
+fn context_keys<'a>(parsed: &'a ParsedFlagfile) -> BTreeSet<&'a str> {
+    let mut keys = BTreeSet::new();
+    for fv in &parsed.flags {
+        for def in fv.values() {
+            collect_keys_from_rules(&def.rules, &mut keys);
+        }
+    }
+    for def in parsed.segments.values() {
+        let mut body = BTreeSet::new();
+        collect_keys(&def.body, &mut body);
+        keys.extend(
+            body.into_iter()
+                .filter(|k| !def.params.iter().any(|p| p == k)),
+        );
+    }
+    keys
+}
+
+fn collect_keys<'a>(node: &'a AstNode, out: &mut BTreeSet<&'a str>) {
+    match node {
+        AstNode::Variable(Atom::Variable(name)) => {
+            out.insert(name);
+        }
+        AstNode::Logic(lhs, _, rhs)
+        | AstNode::Compare(lhs, _, rhs)
+        | AstNode::Arithmetic(lhs, _, rhs)
+        | AstNode::Match(lhs, _, rhs)
+        | AstNode::Array(lhs, _, rhs) => {
+            collect_keys(lhs, out);
+            collect_keys(rhs, out);
+        }
+        AstNode::Scope { expr, .. } => collect_keys(expr, out),
+        AstNode::Function(_, inner) => collect_keys(inner, out),
+        AstNode::Percentage { field, .. } | AstNode::Ramp { field, .. } => collect_keys(field, out),
+        AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
+            for n in nodes {
+                collect_keys(n, out);
+            }
+        }
+        AstNode::NullCheck { variable, .. } => collect_keys(variable, out),
+        AstNode::Void
+        | AstNode::Variable(_)
+        | AstNode::Constant(_)
+        | AstNode::List(_)
+        | AstNode::Segment(..)
+        | AstNode::Active(_) => {}
+    }
+}
+
+fn collect_keys_from_rules<'a>(rules: &'a [Rule], out: &mut BTreeSet<&'a str>) {
+    for rule in rules {
+        match rule {
+            Rule::BoolExpressionValue(expr, ..) => collect_keys(expr, out),
+            Rule::EnvRule { rules, .. } => collect_keys_from_rules(rules, out),
+            Rule::Split { split, .. } => collect_keys(&split.field, out),
+            Rule::Value(..) => {}
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.')
+}
+
+/// The flag or segment name the cursor is in or just after: a flag name
+/// anywhere outside comments, or a segment name in `segment(...)` or after
+/// `@segment`.
+fn reference_at(text: &str, offset: usize) -> Option<Reference<'_>> {
+    let tokens: Vec<Token> = tokenize(text)
+        .into_iter()
+        .filter(|t| !t.kind().is_trivia())
+        .collect();
+    let index = tokens.iter().position(|t| {
+        t.range().contains(&offset)
+            || (t.range().end == offset
+                && matches!(t.kind(), TokenKind::FlagName | TokenKind::Word))
+    })?;
+    let before = |n: usize| index.checked_sub(n).map(|at| &tokens[at]);
+    let name = &text[tokens[index].range()];
+    match tokens[index].kind() {
+        TokenKind::FlagName => Some(Reference::Flag(name)),
+        TokenKind::Word => {
+            let in_call = before(1).is_some_and(|t| t.kind() == TokenKind::LParen)
+                && before(2).is_some_and(|t| t.text() == "segment");
+            let declared = before(1).is_some_and(|t| t.text() == "@segment");
+            (in_call || declared).then_some(Reference::Segment(name))
+        }
+        _ => None,
+    }
+}
+
+/// Byte offset of an LSP position, clamped to the end of its line.
+fn offset_at(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let mut line_start = 0;
+    for (index, line_text) in text.split_inclusive('\n').enumerate() {
+        if index == line {
+            let mut units = 0;
+            for (at, c) in line_text.char_indices() {
+                if units >= character || c == '\n' || c == '\r' {
+                    return line_start + at;
+                }
+                units += c.len_utf16();
+            }
+            return line_start + line_text.len();
+        }
+        line_start += line_text.len();
+    }
+    text.len()
+}
+
+/// LSP position of a 1-based line and character column in `text`.
+fn lsp_position(text: &str, line: usize, column: usize) -> Value {
+    let line_text = text.lines().nth(line.saturating_sub(1)).unwrap_or("");
+    let character: usize = line_text
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum();
+    json!({"line": line.saturating_sub(1), "character": character})
+}
+
+/// 1-based line and character column of a byte offset.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// The whole of a 0-based line.
+fn line_range(text: &str, line: usize) -> Value {
+    let length = text.lines().nth(line).unwrap_or("").encode_utf16().count();
+    json!({"start": {"line": line, "character": 0},
+           "end": {"line": line, "character": length}})
+}
+
+/// Path of a `file://` URI.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// `file://` URI of a path, percent-encoding everything but unreserved
+/// characters and separators.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAGFILE: &str = r#"@segment beta {
+    beta == true or role == developer
+}
+
+@owner "payments"
+@description "Premium features"
+@expires 2099-01-01
+@test FF-premium(plan=premium) == true
+FF-premium {
+    plan == premium -> true
+    segment(beta) -> true
+    false
+}
+
+// @test FF-checkout(country=DE) == false
+@requires FF-premium
+FF-checkout {
+    country in (US) and percentage(10%, userId) -> true
+    false
+}
+"#;
+
+    fn document(text: &str) -> Document {
+        Document {
+            uri: "untitled:Flagfile".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn position(line: usize, character: usize) -> Value {
+        json!({"line": line, "character": character})
+    }
+
+    #[test]
+    fn test_diagnostics_report_syntax_errors_and_lints() {
+        let doc = document(
+            "FF-a {\n    plan == pro true\n    false\n}\n\nFF-b {\n    true\n    false\n}\n",
+        );
+        let diagnostics = doc.diagnostics();
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({"start": {"line": 1, "character": 16}, "end": {"line": 1, "character": 20}})
+        );
+        assert_eq!(
+            diagnostics[0]["message"],
+            "unexpected `true` after the condition (expected `->`)"
+        );
+        let unreachable = diagnostics
+            .iter()
+            .find(|d| d["message"].as_str().unwrap().contains("unreachable"))
+            .unwrap();
+        assert_eq!(unreachable["severity"], SEVERITY_WARNING);
+        assert_eq!(unreachable["range"]["start"], position(7, 4));
+    }
+
+    #[test]
+    fn test_impossible_dates_are_syntax_errors() {
+        for (text, character) in [
+            ("FF-a {\n    d > 2026-02-30 -> true\n    false\n}\n", 12),
+            (
+                "FF-a {\n    d > 2026-02-03T25:00Z -> true\n    false\n}\n",
+                18,
+            ),
+        ] {
+            let doc = document(text);
+            let diagnostics = doc.diagnostics();
+            assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR, "{text}");
+            assert_eq!(
+                diagnostics[0]["range"]["start"],
+                position(1, character),
+                "{text}"
+            );
+            doc.hover(&position(1, 8));
+            doc.completion(&position(1, 8));
+            doc.formatting();
+            doc.code_lenses();
+        }
+    }
+
+    #[test]
+    fn test_definition_of_segments_and_required_flags() {
+        let doc = document(FLAGFILE);
+        let segment = doc.definition(&position(10, 14));
+        assert_eq!(segment["uri"], "untitled:Flagfile");
+        assert_eq!(segment["range"]["start"], position(0, 0));
+        assert_eq!(segment["range"]["end"], position(2, 1));
+
+        let flag = doc.definition(&position(15, 12));
+        assert_eq!(flag["range"]["start"], position(8, 0));
+        assert_eq!(flag["range"]["end"], position(12, 1));
+
+        assert!(doc.definition(&position(15, 0)).is_null());
+        assert!(doc.definition(&position(10, 4)).is_null());
+    }
+
+    #[test]
+    fn test_hover_shows_flag_metadata() {
+        let doc = document(FLAGFILE);
+        let hover = doc.hover(&position(15, 14));
+        assert_eq!(
+            hover["contents"]["value"],
+            "**FF-premium**\n\nPremium features\n\n- owner: payments\n- expires: 2099-01-01\n- tests: 1"
+        );
+        assert_eq!(
+            doc.hover(&position(10, 15))["contents"]["value"],
+            "**@segment beta**"
+        );
+    }
+
+    #[test]
+    fn test_completion() {
+        let doc = document(FLAGFILE);
+        let labels = |items: Vec<Value>| -> Vec<String> {
+            items
+                .iter()
+                .map(|i| i["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            labels(doc.completion(&position(10, 12))),
+            ["beta"],
+            "inside segment("
+        );
+        assert_eq!(
+            labels(doc.completion(&position(15, 13))),
+            ["FF-checkout", "FF-premium"]
+        );
+        assert_eq!(
+            labels(doc.completion(&position(9, 4))),
+            [
+                "FF-checkout",
+                "FF-premium",
+                "beta",
+                "beta",
+                "country",
+                "plan",
+                "role",
+                "userId"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_formatting_replaces_whole_document() {
+        let doc = document("FF-a {\n  plan==pro -> TRUE\n  false\n}");
+        let edits = doc.formatting();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0]["range"]["end"], position(3, 1));
+        assert_eq!(
+            edits[0]["newText"],
+            format_flagfile("FF-a {\n  plan==pro -> TRUE\n  false\n}")
+        );
+
+        let formatted = document(edits[0]["newText"].as_str().unwrap());
+        assert!(formatted.formatting().is_empty());
+        assert!(document("FF-a {\n  plan == pro true\n}")
+            .formatting()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_code_lenses_run_inline_tests() {
+        let doc = document(FLAGFILE);
+        let lenses = doc.code_lenses();
+        let lines: Vec<&Value> = lenses
+            .iter()
+            .map(|l| &l["range"]["start"]["line"])
+            .collect();
+        assert_eq!(lines, [7, 14]);
+        assert_eq!(
+            lenses[1]["command"]["arguments"],
+            json!(["untitled:Flagfile", "FF-checkout(country=DE) == false"])
+        );
+
+        assert_eq!(
+            doc.run_test("FF-premium(plan=premium) == true"),
+            Ok("PASS  FF-premium(plan=premium) == true".to_string())
+        );
+        assert_eq!(
+            doc.run_test("FF-premium(plan=free) == true"),
+            Err(
+                "FAIL  FF-premium(plan=free) == true - got false, returned by line 12, column 5"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            doc.run_test("FF-missing == true"),
+            Err("FAIL  FF-missing == true - flag not found".to_string())
+        );
+        // `fn:` calls see the same built-ins as `ff test`
+        let doc = document("FF-short-name {\n    fn:len(name) < 4 -> true\n    false\n}\n");
+        assert_eq!(
+            doc.run_test("FF-short-name(name=ada) == true"),
+            Ok("PASS  FF-short-name(name=ada) == true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_positions_count_utf16() {
+        let text = "// 😀 é\nFF-a -> true\n";
+        assert_eq!(offset_at(text, &position(0, 5)), "// 😀".len());
+        assert_eq!(offset_at(text, &position(0, 99)), "// 😀 é".len());
+        assert_eq!(offset_at(text, &position(9, 0)), text.len());
+        assert_eq!(lsp_position(text, 1, 6), position(0, 6));
+    }
+
+    #[test]
+    fn test_uris() {
+        let path = Path::new("/tmp/my flags/Flagfile");
+        assert_eq!(path_to_uri(path), "file:///tmp/my%20flags/Flagfile");
+        assert_eq!(uri_to_path(&path_to_uri(path)).as_deref(), Some(path));
+        assert_eq!(uri_to_path("untitled:Flagfile"), None);
+    }
+}
@@ -0,0 +1,284 @@
+//! `ff lsp`: a language server over stdio, built on the same parser, linter
+//! and formatter as the other commands.
+//!
+//! - diagnostics: syntax errors and `ff lint` warnings, on open and change
+//! - go to definition: `segment(name)` and `@requires FF-name`, including
+//!   into `@include`d files
+//! - hover: a flag's metadata annotations
+//! - completion: flag and segment names and the context keys rules use
+//! - formatting: `ff fmt`
+//! - code lenses: run an inline `@test` and show whether it passes
+
+mod document;
+mod transport;
+
+use std::collections::HashMap;
+use std::io;
+use std::process;
+
+use serde_json::{json, Value};
+
+use document::{Document, RUN_TEST_COMMAND};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const MESSAGE_ERROR: u8 = 1;
+const MESSAGE_INFO: u8 = 3;
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Server {
+    /// Handle one message from the client, returning what to send back:
+    /// the response to a request and any notifications it caused.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // a response to a request we never send
+            return Vec::new();
+        };
+        let params = &message["params"];
+        let mut out = Vec::new();
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "exit" => {
+                self.exit = true;
+                return out;
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(
+                    uri.to_string(),
+                    Document {
+                        uri: uri.to_string(),
+                        text: text.to_string(),
+                    },
+                );
+                out.extend(self.publish_diagnostics(uri));
+                return out;
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                // full sync: the last change holds the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                if let (Some(doc), Some(text)) = (self.documents.get_mut(uri), text) {
+                    doc.text = text.to_string();
+                }
+                out.extend(self.publish_diagnostics(uri));
+                return out;
+            }
+            "textDocument/didSave" => {
+                // included files may have changed alongside
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                out.extend(self.publish_diagnostics(uri));
+                return out;
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                out.push(notification(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                ));
+                return out;
+            }
+            "textDocument/definition" => Ok(self
+                .document(params)
+                .map_or(Value::Null, |doc| doc.definition(&params["position"]))),
+            "textDocument/hover" => Ok(self
+                .document(params)
+                .map_or(Value::Null, |doc| doc.hover(&params["position"]))),
+            "textDocument/completion" => Ok(json!(self
+                .document(params)
+                .map_or_else(Vec::new, |doc| doc.completion(&params["position"])))),
+            "textDocument/formatting" => Ok(json!(self
+                .document(params)
+                .map_or_else(Vec::new, Document::formatting))),
+            "textDocument/codeLens" => Ok(json!(self
+                .document(params)
+                .map_or_else(Vec::new, Document::code_lenses))),
+            "workspace/executeCommand" => self.execute_command(params).map(|shown| {
+                out.push(shown);
+                Value::Null
+            }),
+            _ => Err((METHOD_NOT_FOUND, format!("unhandled method {}", method))),
+        };
+
+        // notifications get no response, handled or not
+        let Some(id) = message.get("id") else {
+            return out;
+        };
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => {
+                json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+            }
+        };
+        out.insert(0, response);
+        out
+    }
+
+    fn document(&self, params: &Value) -> Option<&Document> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri)
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Option<Value> {
+        let doc = self.documents.get(uri)?;
+        Some(notification(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": doc.diagnostics()}),
+        ))
+    }
+
+    /// Run a code lens's `@test`, returning the message that reports it.
+    fn execute_command(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let command = params["command"].as_str().unwrap_or_default();
+        if command != RUN_TEST_COMMAND {
+            return Err((INVALID_PARAMS, format!("unknown command {}", command)));
+        }
+        let arguments = &params["arguments"];
+        let (Some(uri), Some(assertion)) = (arguments[0].as_str(), arguments[1].as_str()) else {
+            return Err((
+                INVALID_PARAMS,
+                format!("{} takes a document URI and a @test", RUN_TEST_COMMAND),
+            ));
+        };
+        let Some(doc) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("{} is not open", uri)));
+        };
+        let (kind, message) = match doc.run_test(assertion) {
+            Ok(message) => (MESSAGE_INFO, message),
+            Err(message) => (MESSAGE_ERROR, message),
+        };
+        Ok(notification(
+            "window/showMessage",
+            json!({"type": kind, "message": message}),
+        ))
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            // full text on every change
+            "textDocumentSync": {"openClose": true, "change": 1, "save": true},
+            "definitionProvider": true,
+            "hoverProvider": true,
+            "completionProvider": {"triggerCharacters": ["("]},
+            "documentFormattingProvider": true,
+            "codeLensProvider": {"resolveProvider": false},
+            "executeCommandProvider": {"commands": [RUN_TEST_COMMAND]},
+        },
+        "serverInfo": {"name": "ff", "version": env!("CARGO_PKG_VERSION")},
+    })
+}
+
+/// Serve LSP on stdin/stdout until the client sends `exit`.
+pub fn run_lsp() {
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+    let mut server = Server::default();
+    loop {
+        let message = match transport::read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("ff lsp: {}", e);
+                process::exit(1);
+            }
+        };
+        for reply in server.handle(&message) {
+            if let Err(e) = transport::write_message(&mut writer, &reply) {
+                eprintln!("ff lsp: {}", e);
+                process::exit(1);
+            }
+        }
+        if server.exit {
+            break;
+        }
+    }
+    // exiting without a shutdown request is an error
+    if !server.shutdown {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = Server::default();
+        let uri = "untitled:Flagfile";
+
+        let out = server.handle(&request(1, "initialize", json!({})));
+        assert_eq!(out[0]["id"], 1);
+        assert_eq!(
+            out[0]["result"]["capabilities"]["executeCommandProvider"]["commands"],
+            json!([RUN_TEST_COMMAND])
+        );
+
+        let open = notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "text": "FF-a {\n    true\n    false\n}\n"}}),
+        );
+        let out = server.handle(&open);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(out[0]["params"]["diagnostics"].as_array().unwrap().len(), 1);
+
+        let change = notification(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 2},
+                   "contentChanges": [{"text": "// @test FF-a == true\nFF-a -> true\n"}]}),
+        );
+        let out = server.handle(&change);
+        assert_eq!(out[0]["params"]["diagnostics"], json!([]));
+
+        let out = server.handle(&request(
+            2,
+            "textDocument/codeLens",
+            json!({"textDocument": {"uri": uri}}),
+        ));
+        let command = &out[0]["result"][0]["command"];
+        let out = server.handle(&request(3, "workspace/executeCommand", command.clone()));
+        assert_eq!(out[0], json!({"jsonrpc": "2.0", "id": 3, "result": null}));
+        assert_eq!(
+            out[1]["params"],
+            json!({"type": MESSAGE_INFO, "message": "PASS  FF-a == true"})
+        );
+
+        let out = server.handle(&request(4, "textDocument/rename", json!({})));
+        assert_eq!(out[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert!(server
+            .handle(&notification("$/cancelRequest", json!({})))
+            .is_empty());
+
+        server.handle(&request(5, "shutdown", Value::Null));
+        assert!(server.handle(&notification("exit", Value::Null)).is_empty());
+        assert!(server.shutdown && server.exit);
+    }
+}
@@ -0,0 +1,64 @@
+//! JSON-RPC framing for LSP over stdio: each message is a `Content-Length`
+//! header, a blank line and that many bytes of JSON.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// The next message, or `None` once the client closes the stream.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            // stray blank line between messages
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({"jsonrpc": "2.0", "id": 1, "method": "ü"})).unwrap();
+        write_message(&mut out, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+        assert!(out.starts_with(b"Content-Length: 38\r\n\r\n{"));
+
+        let mut reader = io::Cursor::new(out);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["method"], "ü");
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap()["method"],
+            "exit"
+        );
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
@@ -1,5 +1,9 @@
+mod conformance;
+mod edit;
 mod formatter;
+mod functions;
 mod lint;
+mod lsp;
 mod pull;
 mod push;
 mod push_launchdarkly;
@@ -11,13 +15,19 @@ use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::Mutex;
 
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use clap::{Parser, Subcommand};
-use flagfile_lib::ast::{Atom, FlagMetadata};
-use flagfile_lib::eval::{eval_with_segments, Context, Segments};
+use flagfile_lib::ast::{Atom, FlagMetadata, Span};
+use flagfile_lib::edit::Annotation;
+use flagfile_lib::eval::{Clock, Context, EvalOptions};
+use flagfile_lib::evaluation::Reason;
 use flagfile_lib::include::{resolve_includes_from_path, ResolvedFlagfile};
+use flagfile_lib::parse_error::ParseError;
 use flagfile_lib::parse_flagfile::{
-    extract_test_annotations, parse_flagfile_with_segments, FlagReturn, Rule, TestAnnotation,
+    extract_test_annotations, parse_flagfile_recovering, parse_flagfile_with_segments, FlagReturn,
+    Rule, TestAnnotation,
 };
+use flagfile_lib::plan::Plan;
 use ignore::WalkBuilder;
 use regex::Regex;
 
@@ -78,6 +88,10 @@ enum Command {
         /// Environment to evaluate @env rules against
         #[arg(short = 'e', long = "env")]
         env: Option<String>,
+
+        /// Evaluate as if the current time were this instant (e.g. 2026-12-01T00:00:00Z)
+        #[arg(long = "now", value_parser = parse_now)]
+        now: Option<DateTime<Utc>>,
     },
     Eval {
         /// Path to the Flagfile
@@ -88,6 +102,15 @@ enum Command {
         #[arg(short = 'e', long = "env")]
         env: Option<String>,
 
+        /// Also print the reason, matched rule and @env block
+        #[arg(long = "explain")]
+        explain: bool,
+
+        /// Fail on evaluation errors (missing variables, type mismatches,
+        /// unknown segments) instead of skipping the rule
+        #[arg(long = "strict")]
+        strict: bool,
+
         /// Flag name to evaluate (e.g. FF-my-feature)
         flag_name: String,
 
@@ -228,6 +251,99 @@ enum Command {
         #[arg(long = "diff")]
         diff: bool,
     },
+    /// Emit the cross-implementation conformance corpus, or verify another
+    /// implementation's results against it
+    Conformance {
+        /// Write the corpus, or with --verify the report, to this file
+        /// instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+
+        /// Results file produced by another implementation to check
+        #[arg(long = "verify")]
+        verify: Option<String>,
+    },
+    /// Run a language server over stdio for editor integrations
+    Lsp,
+    /// Change a flag in place, keeping comments and formatting. A flag that
+    /// doesn't exist yet is added with the given value
+    Set {
+        /// Path to the Flagfile
+        #[arg(short = 'f', long = "flagfile", default_value = "Flagfile")]
+        flagfile: String,
+
+        /// Flag name to change (e.g. FF-my-feature)
+        flag_name: String,
+
+        /// Value returned when no rule matches (e.g. true, 42, '"text"', 'json({...})')
+        value: Option<String>,
+
+        /// Set the value for this @env instead of the default
+        #[arg(short = 'e', long = "env", requires = "value")]
+        env: Option<String>,
+
+        /// Insert a rule (e.g. "plan == premium -> true")
+        #[arg(long = "rule")]
+        rule: Option<String>,
+
+        /// Position of the inserted rule, counting from 1 [default: 1]
+        #[arg(long = "at", requires = "rule")]
+        at: Option<usize>,
+
+        /// Change the rate of the flag's percentage() rollout
+        #[arg(long = "percentage")]
+        percentage: Option<f64>,
+
+        /// Rule holding the percentage() to change, counting from 1, when
+        /// the flag has several
+        #[arg(long = "in-rule", requires = "percentage")]
+        in_rule: Option<usize>,
+
+        /// Set @owner
+        #[arg(long = "owner")]
+        owner: Option<String>,
+
+        /// Set @description
+        #[arg(long = "description")]
+        description: Option<String>,
+
+        /// Set @ticket
+        #[arg(long = "ticket")]
+        ticket: Option<String>,
+
+        /// Set @expires (YYYY-MM-DD)
+        #[arg(long = "expires", value_parser = edit::parse_date)]
+        expires: Option<chrono::NaiveDate>,
+
+        /// Set @type (bool, int, float, string, json, or a flag kind like release)
+        #[arg(long = "type")]
+        flag_type: Option<String>,
+
+        /// Set @deprecated
+        #[arg(long = "deprecated")]
+        deprecated: Option<String>,
+
+        /// Add @requires for this flag
+        #[arg(long = "requires")]
+        requires: Option<String>,
+
+        /// Add an inline @test (e.g. "FF-my-feature(plan=premium) == true")
+        #[arg(long = "test")]
+        test: Option<String>,
+    },
+    /// Remove a flag with its annotations, or only some of its annotations
+    Rm {
+        /// Path to the Flagfile
+        #[arg(short = 'f', long = "flagfile", default_value = "Flagfile")]
+        flagfile: String,
+
+        /// Flag name to remove (e.g. FF-my-feature)
+        flag_name: String,
+
+        /// Remove only this annotation (e.g. owner); repeatable
+        #[arg(long = "annotation")]
+        annotations: Vec<String>,
+    },
 }
 
 /// Parse a test line like: FF-name(key=val,key=val) == EXPECTED
@@ -324,70 +440,6 @@ fn parse_kv_pair(s: &str) -> Option<(&str, &str)> {
     Some((&s[..eq_pos], &s[eq_pos + 1..]))
 }
 
-/// Evaluate a flag's rules with an optional environment for @env rules
-pub(crate) fn evaluate_rules_with_env(
-    rules: &[Rule],
-    context: &Context,
-    flag_name: Option<&str>,
-    segments: &Segments,
-    env: Option<&str>,
-) -> Option<FlagReturn> {
-    for rule in rules {
-        match rule {
-            Rule::BoolExpressionValue(expr, return_val, _) => {
-                if let Ok(true) = eval_with_segments(expr, context, flag_name, segments) {
-                    return Some(return_val.clone());
-                }
-            }
-            Rule::Value(return_val) => {
-                return Some(return_val.clone());
-            }
-            Rule::EnvRule {
-                env: rule_env,
-                rules: sub_rules,
-            } => {
-                if env == Some(rule_env.as_str()) {
-                    let result =
-                        evaluate_rules_with_env(sub_rules, context, flag_name, segments, env);
-                    if result.is_some() {
-                        return result;
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
-/// Evaluate a flag checking @requires dependencies first.
-/// If any required flag doesn't evaluate to true, returns None.
-pub(crate) fn evaluate_flag_with_env(
-    flag_name: &str,
-    context: &Context,
-    all_flags: &HashMap<&str, Vec<Rule>>,
-    metadata: &HashMap<&str, FlagMetadata>,
-    segments: &Segments,
-    env: Option<&str>,
-) -> Option<FlagReturn> {
-    // Check @requires prerequisites
-    if let Some(meta) = metadata.get(flag_name) {
-        for req in &meta.requires {
-            match all_flags.get(req.as_str()) {
-                None => return None, // required flag doesn't exist
-                Some(req_rules) => {
-                    match evaluate_rules_with_env(req_rules, context, Some(req), segments, env) {
-                        Some(FlagReturn::OnOff(true)) => {} // prerequisite satisfied
-                        _ => return None,                   // prerequisite not met
-                    }
-                }
-            }
-        }
-    }
-
-    let rules = all_flags.get(flag_name)?;
-    evaluate_rules_with_env(rules, context, Some(flag_name), segments, env)
-}
-
 /// Compare evaluation result with expected string
 fn result_matches(result: &FlagReturn, expected: &str) -> bool {
     match result {
@@ -407,6 +459,9 @@ fn result_matches(result: &FlagReturn, expected: &str) -> bool {
             }
         }
         FlagReturn::Integer(val) => expected.parse::<i64>() == Ok(*val),
+        FlagReturn::Float(val) => expected.parse::<f64>() == Ok(*val),
+        // A variant matches either by its name or by its underlying value
+        FlagReturn::Variant(name, inner) => name == expected || result_matches(inner, expected),
         FlagReturn::Str(val) => {
             // Strip surrounding quotes if present
             let expected_str = expected
@@ -567,28 +622,36 @@ pub(crate) fn read_flagfile_resolved(
     }
 }
 
+/// Print a parse error of merged content against the file and line it came
+/// from.
+pub(crate) fn report_parse_error(resolved: &ResolvedFlagfile, err: ParseError) {
+    eprintln!("{}", resolved.locate(err).render());
+}
+
+/// Print every error from [`parse_flagfile_recovering`] followed by a count.
+pub(crate) fn report_parse_errors(resolved: &ResolvedFlagfile, errors: Vec<ParseError>) {
+    let count = errors.len();
+    for err in errors {
+        report_parse_error(resolved, err);
+        eprintln!();
+    }
+    eprintln!("{} syntax errors found", count);
+}
+
 fn run_list(flagfile_path: &str, show_description: bool) {
     let Ok((_raw, resolved)) = read_flagfile_resolved(flagfile_path) else {
         process::exit(1);
     };
-    let flagfile_content = resolved.content;
+    let flagfile_content = &resolved.content;
 
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
+    let parsed = match parse_flagfile_with_segments(flagfile_content) {
+        Ok(parsed) => parsed,
         Err(e) => {
-            eprintln!("Parsing failed: {}", e);
+            report_parse_error(&resolved, e);
             process::exit(1);
         }
     };
 
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Parsing failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
-        process::exit(1);
-    }
-
     for fv in &parsed.flags {
         for (name, def) in fv.iter() {
             if show_description {
@@ -620,7 +683,7 @@ fn run_check(flagfile_path: &str, testfile_path: &str, env: Option<&str>) {
 
     println!();
     println!("=== test ===");
-    if run_tests_inner(flagfile_path, testfile_path, env).is_err() {
+    if run_tests_inner(flagfile_path, testfile_path, env, Clock::System).is_err() {
         failed = true;
     }
 
@@ -633,21 +696,29 @@ fn run_check(flagfile_path: &str, testfile_path: &str, env: Option<&str>) {
 /// Used by both the standalone `validate` command and the combined `check` command.
 fn run_validate_inner(flagfile_path: &str) -> Result<(), ()> {
     let (_raw, resolved) = read_flagfile_resolved(flagfile_path)?;
-    let flagfile_content = resolved.content;
+    let flagfile_content = &resolved.content;
 
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Parsing failed: {}", e);
-            return Err(());
+    let (parsed, errors) = parse_flagfile_recovering(flagfile_content);
+    if !errors.is_empty() {
+        report_parse_errors(&resolved, errors);
+        return Err(());
+    }
+
+    let mismatches = parsed.type_mismatches();
+    if !mismatches.is_empty() {
+        for m in &mismatches {
+            eprintln!("✗ {}", m);
         }
-    };
+        return Err(());
+    }
 
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Parsing failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
+    if let Some(cycle) = parsed.segment_cycle() {
+        eprintln!("✗ {}", cycle);
+        return Err(());
+    }
+
+    if let Some(mismatch) = parsed.segment_arity_mismatch() {
+        eprintln!("✗ {}", mismatch);
         return Err(());
     }
 
@@ -703,10 +774,12 @@ fn run_validate(flagfile_path: &str) {
 /// Runs individual test assertion lines against a merged flag map,
 /// accumulating pass/fail counts across test sources.
 struct AssertionRunner<'a> {
-    flags: &'a HashMap<&'a str, Vec<Rule>>,
-    metadata: &'a HashMap<&'a str, FlagMetadata>,
-    segments: &'a Segments,
+    plan: &'a Plan,
     env: Option<&'a str>,
+    options: EvalOptions,
+    /// Maps rule and flag spans back to their files for failure reports.
+    resolved: &'a ResolvedFlagfile,
+    flag_spans: &'a HashMap<&'a str, Span>,
     pass_label: &'a str,
     fail_label: &'a str,
     passed: usize,
@@ -727,32 +800,37 @@ impl AssertionRunner<'_> {
 
         let context: Context = pairs.iter().map(|(k, v)| (*k, Atom::from(*v))).collect();
 
-        if !self.flags.contains_key(flag_name) {
+        if self.plan.flag(flag_name).is_none() {
             println!("{}  {} - flag not found{}", self.fail_label, line, suffix);
             self.failed += 1;
             return;
         }
 
-        let result = evaluate_flag_with_env(
-            flag_name,
-            &context,
-            self.flags,
-            self.metadata,
-            self.segments,
-            self.env,
-        );
+        let detail = self
+            .plan
+            .evaluate(flag_name, &context, self.env, &self.options);
 
-        match result {
+        match detail.value {
             Some(ref ret) if result_matches(ret, expected) => {
                 println!("{}  {}{}", self.pass_label, line, suffix);
                 self.passed += 1;
             }
             Some(_) => {
                 println!("{}  {}{}", self.fail_label, line, suffix);
+                if let Some(span) = detail.rule_span {
+                    println!("      returned by {}", self.resolved.position(span));
+                }
                 self.failed += 1;
             }
             None => {
                 println!("{}  {} - no rule matched{}", self.fail_label, line, suffix);
+                if let Some(span) = self.flag_spans.get(flag_name) {
+                    println!(
+                        "      {} is at {}",
+                        flag_name,
+                        self.resolved.position(*span)
+                    );
+                }
                 self.failed += 1;
             }
         }
@@ -768,13 +846,9 @@ impl AssertionRunner<'_> {
         }
     }
 
-    fn run_annotations(&mut self, annotations: &[TestAnnotation], with_line_numbers: bool) {
+    fn run_annotations(&mut self, annotations: &[TestAnnotation]) {
         for annotation in annotations {
-            let suffix = if with_line_numbers {
-                format!(" (line {})", annotation.line_number)
-            } else {
-                String::new()
-            };
+            let suffix = format!(" (line {})", annotation.line_number);
             self.run_line(&annotation.assertion, &suffix, "Invalid @test annotation");
         }
     }
@@ -782,7 +856,12 @@ impl AssertionRunner<'_> {
 
 /// Inner test logic that returns Ok(()) on success or Err(()) on failure.
 /// Used by both the standalone `test` command and the combined `check` command.
-fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>) -> Result<(), ()> {
+fn run_tests_inner(
+    flagfile_path: &str,
+    testfile_path: &str,
+    env: Option<&str>,
+    clock: Clock,
+) -> Result<(), ()> {
     let use_color = io::stdout().is_terminal();
     let pass_label = if use_color {
         "\x1b[32mPASS\x1b[0m"
@@ -797,39 +876,33 @@ fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>)
 
     // 1. Read Flagfile and resolve @include directives
     let (raw_content, resolved) = read_flagfile_resolved(flagfile_path)?;
-    let flagfile_content = resolved.content;
+    let flagfile_content = &resolved.content;
 
     // 2. Parse merged Flagfile
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
-        Err(_) => {
-            eprintln!("Flagfile parsing failed");
+    let parsed = match parse_flagfile_with_segments(flagfile_content) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            report_parse_error(&resolved, e);
             return Err(());
         }
     };
 
-    if !remainder.trim().is_empty() {
-        eprintln!("Flagfile parsing failed");
-        return Err(());
-    }
-
     // Merge all FlagValue entries into a single map and collect @test annotations from metadata
     let mut flags: HashMap<&str, Vec<Rule>> = HashMap::new();
     let mut metadata: HashMap<&str, FlagMetadata> = HashMap::new();
-    let mut annotation_tests: Vec<TestAnnotation> = Vec::new();
+    let mut flag_spans: HashMap<&str, Span> = HashMap::new();
+    let mut annotation_tests: Vec<(&str, Span)> = Vec::new();
     for fv in &parsed.flags {
         for (name, def) in fv.iter() {
             for test_assertion in &def.metadata.tests {
-                annotation_tests.push(TestAnnotation {
-                    assertion: test_assertion.clone(),
-                    line_number: 0,
-                });
+                annotation_tests.push((test_assertion, def.span));
             }
             flags.insert(name, def.rules.clone());
             metadata.insert(name, def.metadata.clone());
+            flag_spans.insert(name, def.span);
         }
     }
-    let segments = &parsed.segments;
+    let plan = Plan::compile(&flags, &metadata, &parsed.segments);
 
     // Extract inline @test annotations from the raw root file so line
     // numbers refer to that file, not the merged content
@@ -869,10 +942,14 @@ fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>)
     };
 
     let mut runner = AssertionRunner {
-        flags: &flags,
-        metadata: &metadata,
-        segments,
+        plan: &plan,
         env,
+        options: EvalOptions {
+            clock,
+            ..functions::eval_options()
+        },
+        resolved: &resolved,
+        flag_spans: &flag_spans,
         pass_label,
         fail_label,
         passed: 0,
@@ -898,7 +975,7 @@ fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>)
     // 5. Run inline @test annotations (from comments in the root file)
     if !inline_tests.is_empty() {
         section!("--- inline @test ({}) ---", flagfile_path);
-        runner.run_annotations(&inline_tests, true);
+        runner.run_annotations(&inline_tests);
     }
 
     // 6. Run sibling `.tests` files of included files
@@ -910,13 +987,16 @@ fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>)
     // 7. Run inline @test annotations from included files
     for (path, annotations) in &included_inline_tests {
         section!("--- inline @test ({}) ---", path.display());
-        runner.run_annotations(annotations, true);
+        runner.run_annotations(annotations);
     }
 
     // 8. Run @test annotations from flag metadata (merged across includes)
     if !annotation_tests.is_empty() {
         section!("--- @test annotations ({}) ---", flagfile_path);
-        runner.run_annotations(&annotation_tests, false);
+        for (assertion, span) in &annotation_tests {
+            let suffix = format!(" ({})", resolved.position(*span));
+            runner.run_line(assertion, &suffix, "Invalid @test annotation");
+        }
     }
 
     // 9. Summary
@@ -933,34 +1013,57 @@ fn run_tests_inner(flagfile_path: &str, testfile_path: &str, env: Option<&str>)
     }
 }
 
-fn run_tests(flagfile_path: &str, testfile_path: &str, env: Option<&str>) {
-    if run_tests_inner(flagfile_path, testfile_path, env).is_err() {
+fn run_tests(
+    flagfile_path: &str,
+    testfile_path: &str,
+    env: Option<&str>,
+    now: Option<DateTime<Utc>>,
+) {
+    let clock = now.map_or(Clock::System, Clock::Fixed);
+    if run_tests_inner(flagfile_path, testfile_path, env, clock).is_err() {
         process::exit(1);
     }
 }
 
-fn run_eval(flagfile_path: &str, flag_name: &str, context_args: &[String], env: Option<&str>) {
+/// Parses `--now`: an RFC 3339 timestamp, or a datetime or date without an
+/// offset, which is read as UTC.
+fn parse_now(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .map(|dt| dt.and_utc())
+        .ok_or_else(|| format!("{} is not a datetime like 2026-12-01T00:00:00Z", s))
+}
+
+fn run_eval(
+    flagfile_path: &str,
+    flag_name: &str,
+    context_args: &[String],
+    env: Option<&str>,
+    explain: bool,
+    strict: bool,
+) {
     let Ok((_raw, resolved)) = read_flagfile_resolved(flagfile_path) else {
         process::exit(1);
     };
-    let flagfile_content = resolved.content;
+    let flagfile_content = &resolved.content;
 
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
+    let parsed = match parse_flagfile_with_segments(flagfile_content) {
+        Ok(parsed) => parsed,
         Err(e) => {
-            eprintln!("Parsing failed: {}", e);
+            report_parse_error(&resolved, e);
             process::exit(1);
         }
     };
 
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Parsing failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
-        process::exit(1);
-    }
-
     let mut flags: HashMap<&str, Vec<Rule>> = HashMap::new();
     let mut metadata: HashMap<&str, FlagMetadata> = HashMap::new();
     for fv in &parsed.flags {
@@ -983,23 +1086,64 @@ fn run_eval(flagfile_path: &str, flag_name: &str, context_args: &[String], env:
         })
         .collect();
 
-    match evaluate_flag_with_env(
-        flag_name,
-        &context,
-        &flags,
-        &metadata,
-        &parsed.segments,
-        env,
-    ) {
+    let options = EvalOptions {
+        strict,
+        ..functions::eval_options()
+    };
+    let plan = Plan::compile(&flags, &metadata, &parsed.segments);
+    let detail = plan.evaluate(flag_name, &context, env, &options);
+
+    match detail.value {
         Some(FlagReturn::OnOff(val)) => println!("{}", val),
         Some(FlagReturn::Json(val)) => println!("{}", val),
         Some(FlagReturn::Integer(val)) => println!("{}", val),
+        Some(FlagReturn::Float(val)) => println!("{}", val),
         Some(FlagReturn::Str(val)) => println!("{}", val),
+        Some(FlagReturn::Variant(name, val)) => println!("{} ({})", String::from(*val), name),
         None => {
-            eprintln!("No rule matched for '{}'", flag_name);
+            match detail.reason {
+                Reason::PrerequisiteFailed => {
+                    eprintln!("Prerequisite (@requires) not met for '{}'", flag_name)
+                }
+                Reason::Disabled => {
+                    eprintln!("'{}' is outside its @active window", flag_name)
+                }
+                Reason::Error if strict => match (detail.rule_index, detail.rule_span) {
+                    (Some(idx), Some(span)) => eprintln!(
+                        "Evaluation of '{}' failed at rule #{} ({})",
+                        flag_name,
+                        idx + 1,
+                        resolved.position(span)
+                    ),
+                    (Some(idx), None) => {
+                        eprintln!("Evaluation of '{}' failed at rule #{}", flag_name, idx + 1)
+                    }
+                    (None, _) => eprintln!("Evaluation of '{}' failed", flag_name),
+                },
+                _ => eprintln!("No rule matched for '{}'", flag_name),
+            }
+            if let Some(err) = &detail.error {
+                eprintln!("  error: {}", err);
+            }
             process::exit(1);
         }
     }
+
+    if explain {
+        println!("reason: {}", detail.reason);
+        if let Some(idx) = detail.rule_index {
+            match &detail.rule_name {
+                Some(name) => println!("rule:   #{} \"{}\"", idx + 1, name),
+                None => println!("rule:   #{}", idx + 1),
+            }
+        }
+        if let Some(env) = &detail.env {
+            println!("env:    {}", env);
+        }
+        if let Some(span) = detail.rule_span {
+            println!("at:     {}", resolved.position(span));
+        }
+    }
 }
 
 fn run_find(
@@ -1205,22 +1349,15 @@ fn run_find_unused(path: &str, flagfile_path: &str) {
         }
     };
 
-    let (remainder, parsed) = match parse_flagfile_with_segments(&flagfile_content) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Parsing failed: {}", e);
+    let parsed = match parse_flagfile_with_segments(&flagfile_content) {
+        Ok(parsed) => parsed,
+        Err(mut e) => {
+            e.file = Some(PathBuf::from(flagfile_path));
+            eprintln!("{}", e.render());
             process::exit(1);
         }
     };
 
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Parsing failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
-        process::exit(1);
-    }
-
     let mut defined_flags: Vec<String> = Vec::new();
     for fv in &parsed.flags {
         for (name, _) in fv.iter() {
@@ -1373,13 +1510,23 @@ async fn main() {
             flagfile,
             testfile,
             env,
-        } => run_tests(&flagfile, &testfile, env.as_deref()),
+            now,
+        } => run_tests(&flagfile, &testfile, env.as_deref(), now),
         Command::Eval {
             flagfile,
             env,
+            explain,
+            strict,
             flag_name,
             context,
-        } => run_eval(&flagfile, &flag_name, &context, env.as_deref()),
+        } => run_eval(
+            &flagfile,
+            &flag_name,
+            &context,
+            env.as_deref(),
+            explain,
+            strict,
+        ),
         Command::Find {
             path,
             search,
@@ -1469,5 +1616,53 @@ async fn main() {
             check,
             diff,
         } => formatter::run_fmt(&flagfile, check, diff),
+        Command::Conformance { output, verify } => {
+            conformance::run_conformance(output.as_deref(), verify.as_deref())
+        }
+        Command::Lsp => lsp::run_lsp(),
+        Command::Set {
+            flagfile,
+            flag_name,
+            value,
+            env,
+            rule,
+            at,
+            percentage,
+            in_rule,
+            owner,
+            description,
+            ticket,
+            expires,
+            flag_type,
+            deprecated,
+            requires,
+            test,
+        } => {
+            let annotations = [
+                owner.map(Annotation::Owner),
+                description.map(Annotation::Description),
+                ticket.map(Annotation::Ticket),
+                expires.map(Annotation::Expires),
+                flag_type.map(Annotation::Type),
+                deprecated.map(Annotation::Deprecated),
+                requires.map(Annotation::Requires),
+                test.map(Annotation::Test),
+            ];
+            let options = edit::SetOptions {
+                value,
+                env,
+                rule,
+                at,
+                percentage,
+                in_rule,
+                annotations: annotations.into_iter().flatten().collect(),
+            };
+            edit::run_set(&flagfile, &flag_name, options)
+        }
+        Command::Rm {
+            flagfile,
+            flag_name,
+            annotations,
+        } => edit::run_rm(&flagfile, &flag_name, &annotations),
     }
 }
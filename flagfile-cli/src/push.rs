@@ -75,23 +75,18 @@ pub async fn run_push(
 
     // 1. Read local Flagfile, resolving @include directives — the remote
     // serves a self-contained flagfile, so includes are inlined here.
-    let content = match crate::read_flagfile_resolved(flagfile_path) {
-        Ok((_raw, resolved)) => resolved.content,
+    let resolved = match crate::read_flagfile_resolved(flagfile_path) {
+        Ok((_raw, resolved)) => resolved,
         Err(()) => process::exit(1),
     };
 
     // 2. Validate syntax locally (fail fast)
-    match flagfile_lib::parse_flagfile::parse_flagfile_with_segments(&content) {
-        Ok((rest, _)) if rest.trim().is_empty() => {}
-        Ok(_) => {
-            eprintln!("Validation failed: unparsed trailing content");
-            process::exit(1);
-        }
-        Err(e) => {
-            eprintln!("Validation failed: {}", e);
-            process::exit(1);
-        }
+    if let Err(e) = flagfile_lib::parse_flagfile::parse_flagfile_with_segments(&resolved.content) {
+        eprintln!("Validation failed:");
+        crate::report_parse_error(&resolved, e);
+        process::exit(1);
     }
+    let content = resolved.content;
 
     // 3. Build URL
     let url = match &namespace {
@@ -138,15 +138,11 @@ async fn push(
     // Read + parse the Flagfile, resolving any @include directives so the
     // merged flag set is pushed.
     let (_raw, resolved) = crate::read_flagfile_resolved(flagfile_path)?;
-    let content = resolved.content;
-    let (rest, mut parsed) = flagfile_lib::parse_flagfile::parse_flagfile_with_segments(&content)
+    let content = &resolved.content;
+    let mut parsed = flagfile_lib::parse_flagfile::parse_flagfile_with_segments(content)
         .map_err(|e| {
-        eprintln!("Validation failed: {}", e);
+        eprintln!("Validation failed: {}", resolved.locate(e));
     })?;
-    if !rest.trim().is_empty() {
-        eprintln!("Validation failed: unparsed trailing content");
-        return Err(());
-    }
 
     // Optionally narrow to an explicit set of flags (--flags a,b,c). Filtering
     // here (before transpile) means unrelated, untranspilable flags don't block
@@ -202,7 +198,15 @@ async fn push(
                 errors.len()
             );
             for e in &errors {
-                eprintln!("  - {:?}", e);
+                let span = parsed
+                    .flags
+                    .iter()
+                    .find_map(|fv| fv.get(e.flag()))
+                    .map(|def| def.span);
+                match span {
+                    Some(span) => eprintln!("  - {}: {}", resolved.position(span), e),
+                    None => eprintln!("  - {}", e),
+                }
             }
             return Err(());
         }
@@ -719,7 +723,7 @@ mod tests {
 
     /// Transpile a one-flag Flagfile (single env `_` -> "production") to an LdFlag.
     fn one_flag(src: &str) -> LdFlag {
-        let (_, parsed) = parse_flagfile_with_segments(src).expect("parse");
+        let parsed = parse_flagfile_with_segments(src).expect("parse");
         let cfg = TranspileConfig {
             project_key: "p".into(),
             env_keys: BTreeMap::from([("_".into(), "production".into())]),
@@ -23,8 +23,8 @@ use self::config::{FfServerConfig, SidecarConfig, StorageBackend};
 use self::metrics::{handle_health_check, handle_metrics, handle_readyz, track_metrics};
 use self::ofrep::{handle_ofrep_bulk, handle_ofrep_single};
 use self::routes::{
-    handle_eval, handle_events, handle_flagfile, handle_flagfile_hash, handle_health,
-    handle_put_flagfile,
+    handle_eval, handle_eval_post, handle_events, handle_flagfile, handle_flagfile_hash,
+    handle_health, handle_put_flagfile,
 };
 use self::sse::SseBroadcaster;
 use self::state::{AppState, ParsedNamespace};
@@ -141,13 +141,7 @@ async fn run_serve_single_tenant(
     let mut namespaces = HashMap::new();
     namespaces.insert(
         ROOT_NAMESPACE.to_string(),
-        ParsedNamespace {
-            flagfile_content,
-            flags,
-            metadata,
-            segments,
-            env: env.clone(),
-        },
+        ParsedNamespace::new(flagfile_content, flags, metadata, segments, env.clone()),
     );
 
     let broadcaster = Arc::new(SseBroadcaster::new());
@@ -158,6 +152,7 @@ async fn run_serve_single_tenant(
         broadcaster: Arc::clone(&broadcaster),
         persistent_store: None,
         multi_tenant: false,
+        eval_options: crate::functions::eval_options(),
         raft_handle: std::sync::OnceLock::new(),
         raft_transport: std::sync::OnceLock::new(),
     });
@@ -242,13 +237,7 @@ async fn run_serve_multi_tenant(
                 if let Some((flags, metadata, segments)) = parse_flags(&content) {
                     namespaces.insert(
                         ns_key.clone(),
-                        ParsedNamespace {
-                            flagfile_content: content,
-                            flags,
-                            metadata,
-                            segments,
-                            env: env_arg.clone(),
-                        },
+                        ParsedNamespace::new(content, flags, metadata, segments, env_arg.clone()),
                     );
                 }
             }
@@ -271,6 +260,7 @@ async fn run_serve_multi_tenant(
         broadcaster: Arc::clone(&broadcaster),
         persistent_store: Some(Arc::clone(&persistent_store)),
         multi_tenant: true,
+        eval_options: crate::functions::eval_options(),
         raft_handle: std::sync::OnceLock::new(),
         raft_transport: std::sync::OnceLock::new(),
     });
@@ -361,13 +351,13 @@ async fn run_serve_multi_tenant(
                                         {
                                             ns_map.insert(
                                                 ns_key.clone(),
-                                                ParsedNamespace {
-                                                    flagfile_content: content,
+                                                ParsedNamespace::new(
+                                                    content,
                                                     flags,
                                                     metadata,
                                                     segments,
-                                                    env: env_arg.clone(),
-                                                },
+                                                    env_arg.clone(),
+                                                ),
                                             );
                                             // Update metrics for the bootstrapped namespace.
                                             metrics::metrics()
@@ -500,7 +490,10 @@ fn build_single_tenant_router(state: Arc<AppState>) -> Router {
         .route("/flagfile", get(handle_flagfile).put(handle_put_flagfile))
         .route("/flagfile/hash", get(handle_flagfile_hash))
         .route("/events", get(handle_events))
-        .route("/v1/eval/{flag_name}", get(handle_eval))
+        .route(
+            "/v1/eval/{flag_name}",
+            get(handle_eval).post(handle_eval_post),
+        )
         .route("/ofrep/v1/evaluate/flags/{key}", post(handle_ofrep_single))
         .route("/ofrep/v1/evaluate/flags", post(handle_ofrep_bulk))
         .route("/metrics", get(handle_metrics))
@@ -515,7 +508,10 @@ fn build_multi_tenant_router(state: Arc<AppState>) -> Router {
         .route("/flagfile", get(handle_flagfile).put(handle_put_flagfile))
         .route("/flagfile/hash", get(handle_flagfile_hash))
         .route("/events", get(handle_events))
-        .route("/v1/eval/{flag_name}", get(handle_eval))
+        .route(
+            "/v1/eval/{flag_name}",
+            get(handle_eval).post(handle_eval_post),
+        )
         .route("/ofrep/v1/evaluate/flags/{key}", post(handle_ofrep_single))
         .route("/ofrep/v1/evaluate/flags", post(handle_ofrep_bulk));
 
@@ -530,7 +526,10 @@ fn build_multi_tenant_router(state: Arc<AppState>) -> Router {
             get(handle_flagfile_hash_ns),
         )
         .route("/ns/{namespace}/events", get(handle_events_ns))
-        .route("/ns/{namespace}/v1/eval/{flag_name}", get(handle_eval_ns));
+        .route(
+            "/ns/{namespace}/v1/eval/{flag_name}",
+            get(handle_eval_ns).post(handle_eval_post_ns),
+        );
 
     // Observability (no auth)
     let obs_routes = Router::new()
@@ -608,6 +607,7 @@ async fn run_serve_sidecar(
         broadcaster: Arc::clone(&broadcaster),
         persistent_store: None,
         multi_tenant: false,
+        eval_options: crate::functions::eval_options(),
         raft_handle: std::sync::OnceLock::new(),
         raft_transport: std::sync::OnceLock::new(),
     });
@@ -661,7 +661,10 @@ fn build_sidecar_router(state: Arc<AppState>) -> Router {
         )
         .route("/flagfile/hash", get(handle_flagfile_hash))
         .route("/events", get(handle_events))
-        .route("/v1/eval/{flag_name}", get(handle_eval))
+        .route(
+            "/v1/eval/{flag_name}",
+            get(handle_eval).post(handle_eval_post),
+        )
         .route("/ofrep/v1/evaluate/flags/{key}", post(handle_ofrep_single))
         .route("/ofrep/v1/evaluate/flags", post(handle_ofrep_bulk))
         .route("/readyz", get(sidecar::handle_sidecar_readyz))
@@ -720,6 +723,20 @@ async fn handle_eval_ns(
     handle_eval(state, axum::extract::Path(params), query, headers).await
 }
 
+async fn handle_eval_post_ns(
+    state: axum::extract::State<Arc<AppState>>,
+    axum::extract::Path((namespace, flag_name)): axum::extract::Path<(String, String)>,
+    query: axum::extract::Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    body: axum::Json<routes::EvalRequest>,
+) -> axum::response::Response {
+    let params = routes::EvalParams {
+        flag_name,
+        namespace: Some(namespace),
+    };
+    handle_eval_post(state, axum::extract::Path(params), query, headers, body).await
+}
+
 // ── File watcher (new state format) ─────────────────────────
 
 async fn watch_flagfile_new(
@@ -781,13 +798,7 @@ async fn watch_flagfile_new(
                     let mut namespaces = state.namespaces.write().await;
                     namespaces.insert(
                         ROOT_NAMESPACE.to_string(),
-                        ParsedNamespace {
-                            flagfile_content: content,
-                            flags,
-                            metadata,
-                            segments,
-                            env: env.clone(),
-                        },
+                        ParsedNamespace::new(content, flags, metadata, segments, env.clone()),
                     );
                     drop(namespaces);
 
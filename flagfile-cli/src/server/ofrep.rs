@@ -8,10 +8,10 @@ use axum::response::{IntoResponse, Response};
 use axum::Json;
 use flagfile_lib::ast::Atom;
 use flagfile_lib::eval::Context;
+use flagfile_lib::evaluation::EvaluationDetail;
 use flagfile_lib::parse_flagfile::FlagReturn;
 
 use super::metrics::metrics;
-use super::routes::evaluate_flag_with_reason;
 use super::state::AppState;
 use super::store::ROOT_NAMESPACE;
 
@@ -43,18 +43,12 @@ pub struct OFREPBulkResponse {
     flags: Vec<serde_json::Value>,
 }
 
-/// Convert OFREP context (JSON values) to flagfile Context (string-based Atoms).
-fn build_context_from_ofrep(raw: &HashMap<String, serde_json::Value>) -> HashMap<String, String> {
+/// Convert an OFREP (or `/v1/eval` JSON) context into a flagfile Context,
+/// keeping numbers, booleans, arrays and nested objects typed. `null`
+/// attributes are left out.
+pub fn build_context_from_json(raw: &HashMap<String, serde_json::Value>) -> Context<'_> {
     raw.iter()
-        .map(|(k, v)| {
-            let s = match v {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Number(n) => n.to_string(),
-                other => other.to_string(),
-            };
-            (k.clone(), s)
-        })
+        .filter_map(|(k, v)| Some((k.as_str(), Atom::from_json(v)?)))
         .collect()
 }
 
@@ -81,6 +75,13 @@ fn flag_return_to_ofrep(key: &str, ret: &FlagReturn, reason: &str) -> OFREPEvalS
             value: serde_json::json!(*val),
             metadata: serde_json::json!({}),
         },
+        FlagReturn::Float(val) => OFREPEvalSuccess {
+            key: key.to_string(),
+            reason: reason.to_string(),
+            variant: val.to_string(),
+            value: serde_json::json!(*val),
+            metadata: serde_json::json!({}),
+        },
         FlagReturn::Str(val) => OFREPEvalSuccess {
             key: key.to_string(),
             reason: reason.to_string(),
@@ -88,6 +89,38 @@ fn flag_return_to_ofrep(key: &str, ret: &FlagReturn, reason: &str) -> OFREPEvalS
             value: serde_json::Value::String(val.clone()),
             metadata: serde_json::json!({}),
         },
+        FlagReturn::Variant(name, inner) => OFREPEvalSuccess {
+            variant: name.clone(),
+            ..flag_return_to_ofrep(key, inner, reason)
+        },
+    }
+}
+
+/// Build the OFREP response for an evaluation. When no rule produced a value
+/// the flag resolves to `false`, with the reason explaining why.
+fn detail_to_ofrep(key: &str, detail: &EvaluationDetail) -> OFREPEvalSuccess {
+    let Some(ret) = &detail.value else {
+        return OFREPEvalSuccess {
+            key: key.to_string(),
+            reason: detail.reason.as_str().to_string(),
+            variant: "false".to_string(),
+            value: serde_json::Value::Bool(false),
+            metadata: serde_json::json!({}),
+        };
+    };
+    let mut metadata = serde_json::Map::new();
+    if let Some(idx) = detail.rule_index {
+        metadata.insert("ruleIndex".into(), serde_json::json!(idx));
+    }
+    if let Some(name) = &detail.rule_name {
+        metadata.insert("ruleName".into(), serde_json::json!(name));
+    }
+    if let Some(env) = &detail.env {
+        metadata.insert("env".into(), serde_json::json!(env));
+    }
+    OFREPEvalSuccess {
+        metadata: serde_json::Value::Object(metadata),
+        ..flag_return_to_ofrep(key, ret, detail.reason.as_str())
     }
 }
 
@@ -141,25 +174,15 @@ pub async fn handle_ofrep_single(
             .into_response();
     }
 
-    let string_ctx = body
+    let context = body
         .context
         .as_ref()
-        .map(build_context_from_ofrep)
+        .map(build_context_from_json)
         .unwrap_or_default();
 
-    let context: Context = string_ctx
-        .iter()
-        .map(|(k, v)| (k.as_str(), Atom::from(v.as_str())))
-        .collect();
-
-    let result = evaluate_flag_with_reason(
-        &key,
-        &context,
-        &ns.flags,
-        &ns.metadata,
-        &ns.segments,
-        ns.env.as_deref(),
-    );
+    let detail = ns
+        .plan
+        .evaluate(&key, &context, ns.env.as_deref(), &state.eval_options);
 
     let m = metrics();
     m.eval_total
@@ -169,22 +192,7 @@ pub async fn handle_ofrep_single(
         .with_label_values(&[ROOT_NAMESPACE])
         .observe(start.elapsed().as_secs_f64());
 
-    match result {
-        Some((ret, reason)) => {
-            let success = flag_return_to_ofrep(&key, &ret, reason);
-            (StatusCode::OK, Json(success)).into_response()
-        }
-        None => {
-            let success = OFREPEvalSuccess {
-                key: key.clone(),
-                reason: "DEFAULT".to_string(),
-                variant: "false".to_string(),
-                value: serde_json::Value::Bool(false),
-                metadata: serde_json::json!({}),
-            };
-            (StatusCode::OK, Json(success)).into_response()
-        }
-    }
+    (StatusCode::OK, Json(detail_to_ofrep(&key, &detail))).into_response()
 }
 
 pub async fn handle_ofrep_bulk(
@@ -200,36 +208,18 @@ pub async fn handle_ofrep_bulk(
         }
     };
 
-    let string_ctx = body
+    let context = body
         .context
         .as_ref()
-        .map(build_context_from_ofrep)
+        .map(build_context_from_json)
         .unwrap_or_default();
 
-    let context: Context = string_ctx
-        .iter()
-        .map(|(k, v)| (k.as_str(), Atom::from(v.as_str())))
-        .collect();
-
     let mut flags = Vec::new();
     for key in ns.flags.keys() {
-        let result = match evaluate_flag_with_reason(
-            key,
-            &context,
-            &ns.flags,
-            &ns.metadata,
-            &ns.segments,
-            ns.env.as_deref(),
-        ) {
-            Some((ret, reason)) => flag_return_to_ofrep(key, &ret, reason),
-            None => OFREPEvalSuccess {
-                key: key.clone(),
-                reason: "DEFAULT".to_string(),
-                variant: "false".to_string(),
-                value: serde_json::Value::Bool(false),
-                metadata: serde_json::json!({}),
-            },
-        };
+        let detail = ns
+            .plan
+            .evaluate(key, &context, ns.env.as_deref(), &state.eval_options);
+        let result = detail_to_ofrep(key, &detail);
         metrics()
             .eval_total
             .with_label_values(&[ROOT_NAMESPACE, key])
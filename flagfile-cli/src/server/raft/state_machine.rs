@@ -45,13 +45,7 @@ impl RaftStateMachine {
                         let mut ns_map = self.state.namespaces.write().await;
                         ns_map.insert(
                             namespace.clone(),
-                            ParsedNamespace {
-                                flagfile_content: content_str,
-                                flags,
-                                metadata,
-                                segments,
-                                env,
-                            },
+                            ParsedNamespace::new(content_str, flags, metadata, segments, env),
                         );
                     }
                 }
@@ -93,13 +87,7 @@ impl RaftStateMachine {
                     if let Some((flags, metadata, segments)) = parse_flags(&content) {
                         ns_map.insert(
                             ns_key,
-                            ParsedNamespace {
-                                flagfile_content: content,
-                                flags,
-                                metadata,
-                                segments,
-                                env: None,
-                            },
+                            ParsedNamespace::new(content, flags, metadata, segments, None),
                         );
                     }
                 }
@@ -7,11 +7,12 @@ use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use flagfile_lib::ast::{Atom, FlagMetadata};
-use flagfile_lib::eval::{eval_with_segments, Context, Segments};
+use flagfile_lib::eval::Context;
 use flagfile_lib::parse_flagfile::{parse_flagfile_with_segments, FlagReturn, Rule};
 use sha1::{Digest, Sha1};
 
 use super::metrics::metrics;
+use super::ofrep::build_context_from_json;
 
 use super::auth::{check_token, extract_bearer_token, forbidden, unauthorized, TokenPermission};
 use super::sse::{create_sse_stream, FlagUpdateEvent};
@@ -121,8 +122,8 @@ pub async fn handle_put_flagfile(
 
     // Validate syntax
     let body_for_parse = body.clone();
-    let (remainder, parsed) = match parse_flagfile_with_segments(&body_for_parse) {
-        Ok(result) => result,
+    let parsed = match parse_flagfile_with_segments(&body_for_parse) {
+        Ok(parsed) => parsed,
         Err(e) => {
             metrics()
                 .push_total
@@ -130,23 +131,41 @@ pub async fn handle_put_flagfile(
                 .inc();
             return (
                 StatusCode::UNPROCESSABLE_ENTITY,
-                Json(serde_json::json!({"error": format!("parse error: {}", e)})),
+                Json(serde_json::json!({
+                    "error": format!("parse error: {}", e),
+                    "parseError": e.to_json(),
+                })),
             )
                 .into_response();
         }
     };
 
-    if !remainder.trim().is_empty() {
+    let mismatches = parsed.type_mismatches();
+    if !mismatches.is_empty() {
         metrics()
             .push_total
             .with_label_values(&[ns_key, "error"])
             .inc();
+        let errors: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
         return (
             StatusCode::UNPROCESSABLE_ENTITY,
-            Json(serde_json::json!({
-                "error": format!("unexpected content near: {}",
-                    remainder.trim().lines().next().unwrap_or(""))
-            })),
+            Json(serde_json::json!({"error": "type check failed", "details": errors})),
+        )
+            .into_response();
+    }
+
+    let segment_error = parsed
+        .segment_cycle()
+        .map(|cycle| cycle.to_string())
+        .or_else(|| parsed.segment_arity_mismatch().map(|m| m.to_string()));
+    if let Some(error) = segment_error {
+        metrics()
+            .push_total
+            .with_label_values(&[ns_key, "error"])
+            .inc();
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({"error": error})),
         )
             .into_response();
     }
@@ -315,13 +334,7 @@ pub async fn handle_put_flagfile(
         let mut namespaces = state.namespaces.write().await;
         namespaces.insert(
             ns_key.to_string(),
-            ParsedNamespace {
-                flagfile_content: body,
-                flags,
-                metadata: metadata_map,
-                segments: parsed.segments,
-                env,
-            },
+            ParsedNamespace::new(body, flags, metadata_map, parsed.segments, env),
         );
     }
 
@@ -399,13 +412,59 @@ pub async fn handle_events(
     .into_response()
 }
 
-// ── GET /v1/eval/{flag} or /ns/{ns}/v1/eval/{flag} ──────────
+// ── GET/POST /v1/eval/{flag} or /ns/{ns}/v1/eval/{flag} ─────
 
+/// Context comes from the query string: `?tier=gold&seats=12`.
 pub async fn handle_eval(
     State(state): State<Arc<AppState>>,
     Path(params): Path<EvalParams>,
     Query(query): Query<HashMap<String, String>>,
     headers: HeaderMap,
+) -> Response {
+    let context: Context = query
+        .iter()
+        .filter(|(k, _)| k.as_str() != "ff_output")
+        .map(|(k, v)| (k.as_str(), Atom::from(v.as_str())))
+        .collect();
+    eval_flag(&state, &params, &headers, &context, is_plain(&query)).await
+}
+
+/// JSON body for `POST /v1/eval/{flag}`.
+#[derive(serde::Deserialize)]
+pub struct EvalRequest {
+    pub context: Option<HashMap<String, serde_json::Value>>,
+}
+
+/// Context comes from a JSON body, `{"context": {"user": {"tier": "gold"}}}`,
+/// so numbers, booleans and nested objects keep their type.
+pub async fn handle_eval_post(
+    State(state): State<Arc<AppState>>,
+    Path(params): Path<EvalParams>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    Json(body): Json<EvalRequest>,
+) -> Response {
+    let context = body
+        .context
+        .as_ref()
+        .map(build_context_from_json)
+        .unwrap_or_default();
+    eval_flag(&state, &params, &headers, &context, is_plain(&query)).await
+}
+
+fn is_plain(query: &HashMap<String, String>) -> bool {
+    query
+        .get("ff_output")
+        .map(|v| v == "plain")
+        .unwrap_or(false)
+}
+
+async fn eval_flag(
+    state: &AppState,
+    params: &EvalParams,
+    headers: &HeaderMap,
+    context: &Context<'_>,
+    plain: bool,
 ) -> Response {
     let start = Instant::now();
     let ns_key = params.namespace.as_deref().unwrap_or(ROOT_NAMESPACE);
@@ -415,7 +474,7 @@ pub async fn handle_eval(
         Some(c) => c,
         None => return forbidden(),
     };
-    let token = get_token(&headers);
+    let token = get_token(headers);
     if state.multi_tenant && !check_token(&ns_config, token.as_deref(), TokenPermission::Read) {
         return unauthorized();
     }
@@ -433,11 +492,6 @@ pub async fn handle_eval(
         }
     };
 
-    let plain = query
-        .get("ff_output")
-        .map(|v| v == "plain")
-        .unwrap_or(false);
-
     if !ns.flags.contains_key(flag_name.as_str()) {
         let m = metrics();
         m.eval_total.with_label_values(&[ns_key, flag_name]).inc();
@@ -455,20 +509,9 @@ pub async fn handle_eval(
             .into_response();
     }
 
-    let context: Context = query
-        .iter()
-        .filter(|(k, _)| k.as_str() != "ff_output")
-        .map(|(k, v)| (k.as_str(), Atom::from(v.as_str())))
-        .collect();
-
-    let result = evaluate_flag_with_reason(
-        flag_name,
-        &context,
-        &ns.flags,
-        &ns.metadata,
-        &ns.segments,
-        ns.env.as_deref(),
-    );
+    let detail = ns
+        .plan
+        .evaluate(flag_name, context, ns.env.as_deref(), &state.eval_options);
 
     let m = metrics();
     m.eval_total.with_label_values(&[ns_key, flag_name]).inc();
@@ -476,8 +519,8 @@ pub async fn handle_eval(
         .with_label_values(&[ns_key])
         .observe(start.elapsed().as_secs_f64());
 
-    match result {
-        Some((val, _reason)) => format_flag_response(flag_name, &val, plain),
+    match detail.value {
+        Some(val) => format_flag_response(flag_name, &val, plain),
         None => {
             m.eval_errors.with_label_values(&[ns_key]).inc();
             if plain {
@@ -485,7 +528,11 @@ pub async fn handle_eval(
             } else {
                 (
                     StatusCode::UNPROCESSABLE_ENTITY,
-                    Json(serde_json::json!({"error": "no rule matched", "flag": flag_name})),
+                    Json(serde_json::json!({
+                        "error": "no rule matched",
+                        "flag": flag_name,
+                        "reason": detail.reason.as_str(),
+                    })),
                 )
                     .into_response()
             }
@@ -533,9 +580,9 @@ fn format_flag_response(flag_name: &str, val: &FlagReturn, plain: bool) -> Respo
             )
                 .into_response()
         }
-        FlagReturn::Str(v) => {
+        FlagReturn::Float(v) => {
             if plain {
-                return (StatusCode::OK, v.clone()).into_response();
+                return (StatusCode::OK, v.to_string()).into_response();
             }
             (
                 StatusCode::OK,
@@ -543,75 +590,33 @@ fn format_flag_response(flag_name: &str, val: &FlagReturn, plain: bool) -> Respo
             )
                 .into_response()
         }
-    }
-}
-
-// ── Evaluation helpers ──────────────────────────────────────
-
-/// Evaluate rules and return the result along with a reason string.
-pub fn evaluate_rules_with_reason(
-    rules: &[Rule],
-    context: &Context,
-    flag_name: Option<&str>,
-    segments: &Segments,
-    env: Option<&str>,
-) -> Option<(FlagReturn, &'static str)> {
-    for rule in rules {
-        match rule {
-            Rule::BoolExpressionValue(expr, return_val, _) => {
-                if let Ok(true) = eval_with_segments(expr, context, flag_name, segments) {
-                    return Some((return_val.clone(), "TARGETING_MATCH"));
-                }
-            }
-            Rule::Value(return_val) => {
-                return Some((return_val.clone(), "DEFAULT"));
-            }
-            Rule::EnvRule {
-                env: rule_env,
-                rules: sub_rules,
-            } => {
-                if env == Some(rule_env.as_str()) {
-                    let result =
-                        evaluate_rules_with_reason(sub_rules, context, flag_name, segments, env);
-                    if result.is_some() {
-                        return result;
-                    }
-                }
+        FlagReturn::Str(v) => {
+            if plain {
+                return (StatusCode::OK, v.clone()).into_response();
             }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({"flag": flag_name, "value": v})),
+            )
+                .into_response()
         }
-    }
-    None
-}
-
-/// Evaluate a flag checking @requires dependencies first.
-pub fn evaluate_flag_with_reason(
-    flag_name: &str,
-    context: &Context,
-    all_flags: &HashMap<String, Vec<Rule>>,
-    metadata: &HashMap<String, FlagMetadata>,
-    segments: &Segments,
-    env: Option<&str>,
-) -> Option<(FlagReturn, &'static str)> {
-    if let Some(meta) = metadata.get(flag_name) {
-        for req in &meta.requires {
-            match all_flags.get(req.as_str()) {
-                None => return None,
-                Some(req_rules) => {
-                    match evaluate_rules_with_reason(
-                        req_rules,
-                        context,
-                        Some(req.as_str()),
-                        segments,
-                        env,
-                    ) {
-                        Some((FlagReturn::OnOff(true), _)) => {}
-                        _ => return None,
-                    }
-                }
+        FlagReturn::Variant(name, inner) => {
+            if plain {
+                return format_flag_response(flag_name, inner, plain);
             }
+            let value = match inner.value() {
+                FlagReturn::OnOff(v) => serde_json::json!(v),
+                FlagReturn::Json(v) => v.clone(),
+                FlagReturn::Integer(v) => serde_json::json!(v),
+                FlagReturn::Float(v) => serde_json::json!(v),
+                FlagReturn::Str(v) => serde_json::json!(v),
+                FlagReturn::Variant(..) => unreachable!("value() looks through variants"),
+            };
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({"flag": flag_name, "value": value, "variant": name})),
+            )
+                .into_response()
         }
     }
-
-    let rules = all_flags.get(flag_name)?;
-    evaluate_rules_with_reason(rules, context, Some(flag_name), segments, env)
 }
@@ -67,13 +67,7 @@ pub async fn fetch_and_update(
     let mut namespaces = state.namespaces.write().await;
     namespaces.insert(
         ROOT_NAMESPACE.to_string(),
-        ParsedNamespace {
-            flagfile_content: content,
-            flags,
-            metadata,
-            segments,
-            env: None,
-        },
+        ParsedNamespace::new(content, flags, metadata, segments, None),
     );
     drop(namespaces);
 
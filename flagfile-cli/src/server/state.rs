@@ -2,8 +2,9 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use flagfile_lib::ast::FlagMetadata;
-use flagfile_lib::eval::Segments;
+use flagfile_lib::eval::{EvalOptions, Segments};
 use flagfile_lib::parse_flagfile::Rule;
+use flagfile_lib::plan::Plan;
 use tokio::sync::RwLock;
 
 use super::config::{FfServerConfig, NamespaceConfig};
@@ -20,9 +21,34 @@ pub type ParsedFlags = (
 pub struct ParsedNamespace {
     pub flagfile_content: String,
     pub flags: HashMap<String, Vec<Rule>>,
+    /// Kept next to `plan` for handlers that need the parsed form.
+    #[allow(dead_code)]
     pub metadata: HashMap<String, FlagMetadata>,
+    #[allow(dead_code)]
     pub segments: Segments,
     pub env: Option<String>,
+    /// Compiled flags, segments and `@requires`; used for evaluation.
+    pub plan: Plan,
+}
+
+impl ParsedNamespace {
+    pub fn new(
+        flagfile_content: String,
+        flags: HashMap<String, Vec<Rule>>,
+        metadata: HashMap<String, FlagMetadata>,
+        segments: Segments,
+        env: Option<String>,
+    ) -> Self {
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        Self {
+            flagfile_content,
+            flags,
+            metadata,
+            segments,
+            env,
+            plan,
+        }
+    }
 }
 
 /// Shared application state for the HTTP server.
@@ -43,6 +69,9 @@ pub struct AppState {
     pub persistent_store: Option<Arc<dyn store::FlagStore + Send + Sync>>,
     /// Whether the server is running in multi-tenant mode.
     pub multi_tenant: bool,
+    /// How flags are evaluated, with the fixed set of `fn:` functions in
+    /// [`crate::functions::builtin`].
+    pub eval_options: EvalOptions,
     /// Raft consensus handle (cluster mode only).
     pub raft_handle: std::sync::OnceLock<super::raft::node::RaftHandle>,
     /// Raft gRPC transport for forwarding writes to the leader.
@@ -6,19 +6,29 @@ pub fn parse_flags(content: &str) -> Option<ParsedFlags> {
     use flagfile_lib::ast::FlagMetadata;
     use flagfile_lib::parse_flagfile::{parse_flagfile_with_segments, Rule};
 
-    let (remainder, parsed) = match parse_flagfile_with_segments(content) {
-        Ok(result) => result,
+    let parsed = match parse_flagfile_with_segments(content) {
+        Ok(parsed) => parsed,
         Err(e) => {
             eprintln!("Warning: reload parse error: {}", e);
             return None;
         }
     };
 
-    if !remainder.trim().is_empty() {
-        eprintln!(
-            "Warning: reload failed: unexpected content near: {}",
-            remainder.trim().lines().next().unwrap_or("")
-        );
+    let mismatches = parsed.type_mismatches();
+    if !mismatches.is_empty() {
+        for m in &mismatches {
+            eprintln!("Warning: reload failed: {}", m);
+        }
+        return None;
+    }
+
+    if let Some(cycle) = parsed.segment_cycle() {
+        eprintln!("Warning: reload failed: {}", cycle);
+        return None;
+    }
+
+    if let Some(mismatch) = parsed.segment_arity_mismatch() {
+        eprintln!("Warning: reload failed: {}", mismatch);
         return None;
     }
 
@@ -42,6 +42,27 @@ fn test_validate_fails_on_missing_include() {
     assert!(stderr(&out).contains("nope.ff"), "stderr: {}", stderr(&out));
 }
 
+#[test]
+fn test_validate_reports_syntax_errors_in_included_file() {
+    let flagfile = fixture("syntax/Flagfile");
+    let out = ff(&["validate", "-f", &flagfile.display().to_string()]);
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(
+        err.contains("error: unexpected `true` after the condition"),
+        "stderr: {err}"
+    );
+    assert!(err.contains("broken.ff:2:21"), "stderr: {err}");
+    assert!(
+        err.contains("2 |     plan == premium true"),
+        "stderr: {err}"
+    );
+    assert!(err.contains("= expected `->`"), "stderr: {err}");
+    // the error in the root file after the include is reported too
+    assert!(err.contains("syntax/Flagfile:7:16"), "stderr: {err}");
+    assert!(err.contains("2 syntax errors found"), "stderr: {err}");
+}
+
 // ── lint ───────────────────────────────────────────────────
 
 #[test]
@@ -56,6 +77,37 @@ fn test_lint_passes_with_includes() {
     );
 }
 
+#[test]
+fn test_lint_reports_syntax_errors_in_included_file() {
+    let flagfile = fixture("syntax/Flagfile");
+    let out = ff(&["lint", "-f", &flagfile.display().to_string()]);
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(err.contains("broken.ff:2:21"), "stderr: {err}");
+    assert!(err.contains("syntax/Flagfile:7:16"), "stderr: {err}");
+    assert!(err.contains("2 syntax errors found"), "stderr: {err}");
+}
+
+#[test]
+fn test_lint_warnings_point_at_included_file() {
+    let flagfile = fixture("lint/Flagfile");
+    let out = ff(&["lint", "-f", &flagfile.display().to_string()]);
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(
+        err.contains("lint/rules.ff:5:5: FF-shadowed: 1 unreachable rule(s)"),
+        "stderr: {err}"
+    );
+    assert!(
+        err.contains("lint/rules.ff:2:1: FF-shadowed: no default case"),
+        "stderr: {err}"
+    );
+    assert!(
+        err.contains("lint/rules.ff:11:5: FF-split-shadowed: 1 unreachable rule(s)"),
+        "stderr: {err}"
+    );
+}
+
 #[test]
 fn test_lint_fails_on_missing_include() {
     let flagfile = fixture("missing/Flagfile");
@@ -109,7 +161,13 @@ fn test_test_fails_on_failing_included_assertion() {
         &testfile.display().to_string(),
     ]);
     assert!(!out.status.success(), "stdout: {}", stdout(&out));
-    assert!(stdout(&out).contains("FAIL"), "stdout: {}", stdout(&out));
+    let output = stdout(&out);
+    assert!(output.contains("FAIL"), "stdout: {output}");
+    // the failure points at the rule that produced the value
+    assert!(
+        output.contains("returned by") && output.contains("sub/Flagfile:3:5"),
+        "stdout: {output}"
+    );
 }
 
 #[test]
@@ -130,6 +188,33 @@ fn test_test_fails_on_escaping_include() {
 
 // ── check ──────────────────────────────────────────────────
 
+#[test]
+fn test_test_now_evaluates_included_schedule_in_root_timezone() {
+    let flagfile = fixture("schedule/Flagfile").display().to_string();
+    let testfile = fixture("schedule/Flagfile.tests").display().to_string();
+    let run = |now: &str| ff(&["test", "-f", &flagfile, "-t", &testfile, "--now", now]);
+
+    // midnight in Vilnius is 22:00 UTC the day before
+    let out = run("2026-11-30T22:30:00Z");
+    assert!(out.status.success(), "stdout: {}", stdout(&out));
+
+    let out = run("2026-12-01T00:30:00+03:00");
+    assert!(!out.status.success());
+    assert!(
+        stdout(&out).contains("FAIL  FF-winter-sale == true - no rule matched"),
+        "stdout: {}",
+        stdout(&out)
+    );
+
+    let out = run("tomorrow");
+    assert!(!out.status.success());
+    assert!(
+        stderr(&out).contains("tomorrow is not a datetime"),
+        "stderr: {}",
+        stderr(&out)
+    );
+}
+
 #[test]
 fn test_check_passes_with_includes() {
     let flagfile = fixture("withtests/Flagfile");
@@ -177,6 +262,25 @@ fn test_eval_works_with_includes() {
     );
 }
 
+#[test]
+fn test_eval_explain_points_at_rule_in_included_file() {
+    let flagfile = fixture("withtests/Flagfile");
+    let out = ff(&[
+        "eval",
+        "--explain",
+        "-f",
+        &flagfile.display().to_string(),
+        "FF-included-feature",
+        "countryCode=nl",
+    ]);
+    assert!(out.status.success(), "stderr: {}", stderr(&out));
+    let output = stdout(&out);
+    assert!(
+        output.contains("withtests/sub/Flagfile:3:5"),
+        "stdout: {output}"
+    );
+}
+
 #[test]
 fn test_list_shows_included_flags() {
     let flagfile = fixture("withtests/Flagfile");
@@ -254,6 +358,54 @@ fn test_fmt_formats_included_files_in_place() {
     );
 }
 
+// ── set / rm ───────────────────────────────────────────────
+
+#[test]
+fn test_set_and_rm_edit_the_file_defining_the_flag() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("set_include_write");
+    let sub = dir.join("sub");
+    std::fs::create_dir_all(&sub).unwrap();
+    let root = dir.join("Flagfile");
+    std::fs::write(&root, "FF-root -> true\n\n@include sub/Flagfile\n").unwrap();
+    let inc = sub.join("Flagfile");
+    std::fs::write(
+        &inc,
+        "// rollout\nFF-included {\n    percentage(10%, userId) -> true // ramping\n    false\n}\n",
+    )
+    .unwrap();
+    let f = root.display().to_string();
+
+    let out = ff(&["set", "-f", &f, "FF-included", "--percentage", "50"]);
+    assert!(out.status.success(), "stderr: {}", stderr(&out));
+    assert!(stdout(&out).contains("updated FF-included in"));
+    let out = ff(&["set", "-f", &f, "FF-included", "true", "--owner", "growth"]);
+    assert!(out.status.success(), "stderr: {}", stderr(&out));
+    assert_eq!(
+        std::fs::read_to_string(&inc).unwrap(),
+        "// rollout\n@owner \"growth\"\nFF-included {\n    percentage(50%, userId) -> true // ramping\n    true\n}\n"
+    );
+
+    let out = ff(&["set", "-f", &f, "FF-new", "42"]);
+    assert!(out.status.success(), "stderr: {}", stderr(&out));
+    let out = ff(&["rm", "-f", &f, "FF-root"]);
+    assert!(out.status.success(), "stderr: {}", stderr(&out));
+    assert_eq!(
+        std::fs::read_to_string(&root).unwrap(),
+        "@include sub/Flagfile\n\nFF-new -> 42\n"
+    );
+
+    let out = ff(&["set", "-f", &f, "FF-included", "--rule", "plan == -> true"]);
+    assert!(!out.status.success());
+    assert!(
+        stderr(&out).contains("edit would break"),
+        "stderr: {}",
+        stderr(&out)
+    );
+    let out = ff(&["rm", "-f", &f, "FF-missing"]);
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("flag FF-missing not found"));
+}
+
 // ── push ───────────────────────────────────────────────────
 // Both push modes must resolve includes; a missing include fails during
 // local validation, before any network I/O happens.
@@ -294,3 +446,69 @@ fn test_push_remote_fails_on_missing_include() {
     assert!(!out.status.success());
     assert!(stderr(&out).contains("nope.ff"), "stderr: {}", stderr(&out));
 }
+
+// ── lsp ────────────────────────────────────────────────────
+
+/// Send `messages` to `ff lsp` and collect everything it answers.
+fn lsp_session(messages: &[serde_json::Value]) -> (bool, Vec<serde_json::Value>) {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ff"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run ff binary");
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        let body = message.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+    drop(stdin);
+    let out = child.wait_with_output().unwrap();
+
+    let mut replies = Vec::new();
+    let mut rest = stdout(&out);
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let length: usize = header["Content-Length: ".len()..].parse().unwrap();
+        replies.push(serde_json::from_str(&body[..length]).unwrap());
+        rest = body[length..].to_string();
+    }
+    (out.status.success(), replies)
+}
+
+#[test]
+fn test_lsp_definition_jumps_into_included_file() {
+    use serde_json::json;
+
+    let dir = fixture("lint").canonicalize().unwrap();
+    let uri = format!("file://{}", dir.join("Flagfile").display());
+    let text = "FF-root -> true\n\n@include rules.ff\n\n@requires FF-shadowed\nFF-next -> true\n";
+    let (success, replies) = lsp_session(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen",
+               "params": {"textDocument": {"uri": uri, "text": text}}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/definition",
+               "params": {"textDocument": {"uri": uri}, "position": {"line": 4, "character": 12}}}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ]);
+    assert!(success);
+
+    // the unreachable rule is in rules.ff, not in the open document
+    assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+
+    assert_eq!(replies[2]["id"], 2);
+    let location = &replies[2]["result"];
+    assert_eq!(
+        location["uri"],
+        format!("file://{}", dir.join("rules.ff").display())
+    );
+    assert_eq!(
+        location["range"]["start"],
+        json!({"line": 1, "character": 0})
+    );
+    assert_eq!(location["range"]["end"], json!({"line": 5, "character": 1}));
+}
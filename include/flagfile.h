@@ -0,0 +1,63 @@
+#ifndef FLAGFILE_H
+#define FLAGFILE_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An opaque handle to a parsed Flagfile. Create one with
+// [`ff_flagfile_new`], evaluate flags with [`ff_flagfile_evaluate`] and
+// release it with [`ff_flagfile_free`].
+typedef struct Flagfile Flagfile;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Parse Flagfile content into a handle. `env` selects the environment for
+// `@env` rules and may be NULL. Returns NULL on failure.
+//
+// # Safety
+// `content` must be a NUL-terminated string; `env` must be NULL or one.
+Flagfile *ff_flagfile_new(const char *content, const char *env);
+
+// Free a handle from [`ff_flagfile_new`]. NULL is a no-op.
+//
+// # Safety
+// `handle` must be NULL or a handle that hasn't been freed yet.
+void ff_flagfile_free(Flagfile *handle);
+
+// Evaluate a flag. `context_json` is a JSON object, or NULL for an empty
+// context. Returns a JSON object
+// `{"value", "variant", "reason", "ruleIndex", "ruleName", "env", "error"}`
+// to be freed with [`ff_string_free`], or NULL on failure. An unknown flag
+// is not a failure: it returns `"reason": "FLAG_NOT_FOUND"`.
+//
+// # Safety
+// `handle` must come from [`ff_flagfile_new`]; `flag` must be a
+// NUL-terminated string; `context_json` must be NULL or one.
+char *ff_flagfile_evaluate(const Flagfile *handle, const char *flag, const char *context_json);
+
+// Free a string returned by the library. NULL is a no-op.
+//
+// # Safety
+// `s` must be NULL or a string returned by this library that hasn't been
+// freed yet.
+void ff_string_free(char *s);
+
+// The error message from the last failed call on this thread, or NULL.
+// The string is owned by the library and valid until the next call on the
+// same thread.
+const char *ff_last_error(void);
+
+// The library version, e.g. `"0.4.16"`. The string is static.
+const char *ff_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FLAGFILE_H */
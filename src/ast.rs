@@ -1,23 +1,68 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
 use core::fmt;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::parse::parse_atom;
 
 #[derive(Debug, Clone)]
 pub enum Atom {
     String(String),
-    Number(i32),
+    Number(i64),
     Float(f64),
     Boolean(bool),
     Variable(String),
     Date(NaiveDate),
-    DateTime(NaiveDateTime),
+    /// An instant. Literals with `Z` or an offset are converted to UTC, those
+    /// without are read in the Flagfile's `@timezone`.
+    DateTime(DateTime<Utc>),
     Semver(u32, u32, u32),
-    Regex(String),
+    /// A length of time such as `30d` or `1h30m`.
+    Duration(TimeDelta),
+    /// Compiled when the Flagfile is parsed; see [`crate::parse::compile_regex`].
+    Regex(Regex),
     List(Vec<Atom>),
+    /// Nested context value, read in rules as `user.company.tier` or `org["plan"]`.
+    Object(BTreeMap<String, Atom>),
     // Timestamp(i64)
 }
 
+/// How instants are written back: ISO 8601 in UTC, like the literals.
+pub(crate) const UTC_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// A date compared with an instant stands for its midnight UTC.
+pub(crate) fn midnight(date: &NaiveDate) -> DateTime<Utc> {
+    date.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+/// Write a duration the way it is written in a Flagfile, largest unit first:
+/// `1d12h`, `-90s`, `0s`.
+fn write_duration(f: &mut fmt::Formatter<'_>, d: TimeDelta) -> fmt::Result {
+    if d.is_zero() {
+        return write!(f, "0s");
+    }
+    if d < TimeDelta::zero() {
+        write!(f, "-")?;
+    }
+    let mut ms = d.num_milliseconds().unsigned_abs();
+    for (unit, len) in [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("m", 60_000),
+        ("s", 1_000),
+    ] {
+        if ms >= len {
+            write!(f, "{}{unit}", ms / len)?;
+            ms %= len;
+        }
+    }
+    if ms > 0 {
+        write!(f, "{ms}ms")?;
+    }
+    Ok(())
+}
+
 /// Try to interpret a float as semver components (e.g. 5.4 → (5, 4, 0)).
 fn float_to_semver(f: f64) -> Option<(u32, u32, u32)> {
     let s = format!("{}", f);
@@ -31,6 +76,23 @@ fn float_to_semver(f: f64) -> Option<(u32, u32, u32)> {
     }
 }
 
+/// `s` read as a date, datetime or version, if it is one. JSON contexts send
+/// those as strings.
+pub(crate) fn parse_typed(s: &str) -> Option<Atom> {
+    match parse_atom(s) {
+        Ok(("", atom @ (Atom::Date(_) | Atom::DateTime(_) | Atom::Semver(..)))) => Some(atom),
+        _ => None,
+    }
+}
+
+/// `s` read with [`parse_typed`] when `like` is a date, datetime or version;
+/// other strings are only ever compared as text.
+fn parse_like(s: &str, like: &Atom) -> Option<Atom> {
+    matches!(like, Atom::Date(_) | Atom::DateTime(_) | Atom::Semver(..))
+        .then(|| parse_typed(s))
+        .flatten()
+}
+
 impl PartialEq<Atom> for Atom {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -44,11 +106,12 @@ impl PartialEq<Atom> for Atom {
             (Atom::Date(d1), Atom::Date(d2)) => d1 == d2,
             (Atom::DateTime(t1), Atom::DateTime(t2)) => t1 == t2,
             (Atom::DateTime(dt), Atom::Date(d)) | (Atom::Date(d), Atom::DateTime(dt)) => {
-                d.and_hms_opt(0, 0, 0) == Some(*dt)
+                midnight(d) == *dt
             }
             (Atom::Semver(a1, b1, c1), Atom::Semver(a2, b2, c2)) => {
                 a1 == a2 && b1 == b2 && c1 == c2
             }
+            (Atom::Duration(d1), Atom::Duration(d2)) => d1 == d2,
             (Atom::Semver(a, b, c), Atom::Float(f)) | (Atom::Float(f), Atom::Semver(a, b, c)) => {
                 if let Some((maj, min, patch)) = float_to_semver(*f) {
                     *a == maj && *b == min && *c == patch
@@ -57,16 +120,16 @@ impl PartialEq<Atom> for Atom {
                 }
             }
             (Atom::Semver(a, b, c), Atom::Number(n)) | (Atom::Number(n), Atom::Semver(a, b, c)) => {
-                if *n >= 0 {
-                    *a == *n as u32 && *b == 0 && *c == 0
-                } else {
-                    false
-                }
+                u32::try_from(*n) == Ok(*a) && *b == 0 && *c == 0
             }
-            (Atom::Regex(r1), Atom::Regex(r2)) => r1 == r2,
+            (Atom::Regex(r1), Atom::Regex(r2)) => r1.as_str() == r2.as_str(),
             (Atom::List(l1), Atom::List(l2)) => {
                 l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(a, b)| a == b)
             }
+            (Atom::Object(o1), Atom::Object(o2)) => o1 == o2,
+            (Atom::String(s), typed) | (typed, Atom::String(s)) => {
+                parse_like(s, typed).is_some_and(|parsed| &parsed == typed)
+            }
             _ => false,
         }
     }
@@ -74,22 +137,25 @@ impl PartialEq<Atom> for Atom {
 
 impl PartialOrd for Atom {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if let Atom::String(s) = self {
+            return parse_like(s, other)?.partial_cmp(other);
+        }
+        if let Atom::String(s) = other {
+            return self.partial_cmp(&parse_like(s, self)?);
+        }
         match self {
             Atom::Number(v) => match other {
                 Atom::Number(v2) => Some(v.cmp(v2)),
-                Atom::Float(v2) => f64::from(*v).partial_cmp(v2),
+                Atom::Float(v2) => (*v as f64).partial_cmp(v2),
                 Atom::Semver(a2, b2, c2) => {
-                    if *v < 0 {
-                        return None;
-                    }
-                    let maj = *v as u32;
+                    let maj = u32::try_from(*v).ok()?;
                     Some(maj.cmp(a2).then(0u32.cmp(b2)).then(0u32.cmp(c2)))
                 }
                 _ => None,
             },
             Atom::Float(v) => match other {
                 Atom::Float(v2) => v.partial_cmp(v2),
-                Atom::Number(v2) => v.partial_cmp(&f64::from(*v2)),
+                Atom::Number(v2) => v.partial_cmp(&(*v2 as f64)),
                 Atom::Semver(a2, b2, c2) => {
                     let (maj, min, patch) = float_to_semver(*v)?;
                     Some(maj.cmp(a2).then(min.cmp(b2)).then(patch.cmp(c2)))
@@ -98,18 +164,16 @@ impl PartialOrd for Atom {
             },
             Atom::Date(v) => match other {
                 Atom::Date(v2) => v.partial_cmp(v2),
-                Atom::DateTime(dt) => {
-                    let self_dt = v.and_hms_opt(0, 0, 0)?;
-                    self_dt.partial_cmp(dt)
-                }
+                Atom::DateTime(dt) => midnight(v).partial_cmp(dt),
                 _ => None,
             },
             Atom::DateTime(v) => match other {
                 Atom::DateTime(v2) => v.partial_cmp(v2),
-                Atom::Date(d) => {
-                    let d_dt = d.and_hms_opt(0, 0, 0)?;
-                    v.partial_cmp(&d_dt)
-                }
+                Atom::Date(d) => v.partial_cmp(&midnight(d)),
+                _ => None,
+            },
+            Atom::Duration(v) => match other {
+                Atom::Duration(v2) => v.partial_cmp(v2),
                 _ => None,
             },
             Atom::Semver(a1, b1, c1) => match other {
@@ -119,10 +183,7 @@ impl PartialOrd for Atom {
                     Some(a1.cmp(&maj).then(b1.cmp(&min)).then(c1.cmp(&patch)))
                 }
                 Atom::Number(n) => {
-                    if *n < 0 {
-                        return None;
-                    }
-                    let maj = *n as u32;
+                    let maj = u32::try_from(*n).ok()?;
                     Some(a1.cmp(&maj).then(b1.cmp(&0).then(c1.cmp(&0))))
                 }
                 _ => None,
@@ -141,14 +202,20 @@ impl fmt::Display for Atom {
             Atom::Boolean(bool) => write!(f, "{bool}"),
             Atom::Variable(var) => write!(f, "{var}"),
             Atom::Date(var) => write!(f, "{var}"),
-            Atom::DateTime(var) => write!(f, "{var}"),
+            Atom::DateTime(var) => write!(f, "{}", var.format(UTC_FORMAT)),
             Atom::Semver(major, minor, patch) => write!(f, "{major}.{minor}.{patch}"),
-            Atom::Regex(p) => write!(f, "/{p}/"),
+            Atom::Duration(d) => write_duration(f, *d),
+            Atom::Regex(p) => write!(f, "/{}/", p.as_str()),
             Atom::List(items) => {
                 let strings: Vec<std::string::String> =
                     items.iter().map(|a| a.to_string()).collect();
                 write!(f, "[{}]", strings.join(", "))
             }
+            Atom::Object(fields) => {
+                let strings: Vec<std::string::String> =
+                    fields.iter().map(|(k, v)| format!("{k}: {v}")).collect();
+                write!(f, "{{{}}}", strings.join(", "))
+            }
         }
     }
 }
@@ -181,15 +248,40 @@ impl<'a> From<&'a str> for Atom {
             }
             return Atom::List(items);
         }
-        let res = parse_atom(val);
-        if let Ok((_i, out)) = res {
-            return out;
+        // Only take the parsed atom if it covers the whole value, so that
+        // `user-123` stays a string rather than becoming the variable `user`
+        match parse_atom(val) {
+            Ok((rest, out)) if rest.trim().is_empty() => out,
+            _ => Atom::String(val.into()),
         }
-        Atom::String(val.into())
     }
 }
 
 impl Atom {
+    /// Convert a JSON context value, keeping its type: numbers stay numbers,
+    /// booleans stay booleans, strings stay strings and objects become
+    /// [`Atom::Object`]. A string compared with a date or version literal is
+    /// read as one. `null` is `None`, the same as an unset attribute.
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        use serde_json::Value;
+        Some(match value {
+            Value::Null => return None,
+            Value::Bool(b) => Atom::Boolean(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Atom::Number(i),
+                None => Atom::Float(n.as_f64()?),
+            },
+            Value::String(s) => Atom::String(s.clone()),
+            Value::Array(items) => Atom::List(items.iter().filter_map(Atom::from_json).collect()),
+            Value::Object(fields) => Atom::Object(
+                fields
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), Atom::from_json(v)?)))
+                    .collect(),
+            ),
+        })
+    }
+
     /// Parse a single value (not an array). Strips surrounding quotes.
     fn from_single(val: &str) -> Self {
         let val = val.trim();
@@ -197,11 +289,10 @@ impl Atom {
             .strip_prefix('"')
             .and_then(|s| s.strip_suffix('"'))
             .unwrap_or(val);
-        let res = parse_atom(unquoted);
-        if let Ok((_i, out)) = res {
-            return out;
+        match parse_atom(unquoted) {
+            Ok((rest, out)) if rest.trim().is_empty() => out,
+            _ => Atom::String(unquoted.into()),
         }
-        Atom::String(unquoted.into())
     }
 }
 
@@ -264,6 +355,27 @@ impl fmt::Display for ComparisonOp {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl fmt::Display for ArithOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithOp::Add => write!(f, "+"),
+            ArithOp::Sub => write!(f, "-"),
+            ArithOp::Mul => write!(f, "*"),
+            ArithOp::Div => write!(f, "/"),
+            ArithOp::Rem => write!(f, "%"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum LogicOp {
     And,
@@ -301,6 +413,9 @@ pub enum AstNode {
     Constant(Atom),
     List(Vec<Atom>),
     Compare(Box<AstNode>, ComparisonOp, Box<AstNode>),
+    /// `NOW() - signupDate` or `cartTotal * 100`: a computed operand of a
+    /// comparison. Bare words inside it are context variables.
+    Arithmetic(Box<AstNode>, ArithOp, Box<AstNode>),
     Match(Box<AstNode>, MatchOp, Box<AstNode>),
     Array(Box<AstNode>, ArrayOp, Box<AstNode>),
     Logic(Box<AstNode>, LogicOp, Box<AstNode>),
@@ -314,11 +429,27 @@ pub enum AstNode {
         salt: Option<String>,
     },
     Coalesce(Vec<AstNode>),
-    Segment(String),
+    /// `segment(name)` or, for a parameterized segment, `segment(name, arg, ...)`.
+    Segment(String, Vec<Atom>),
     NullCheck {
         variable: Box<AstNode>,
         is_null: bool,
     },
+    /// `ramp(0% -> 100%, start .. end, field)`: a `percentage()` whose rate
+    /// moves linearly from `from` to `to` between `start` and `end`.
+    Ramp {
+        from: f64,
+        to: f64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        field: Box<AstNode>,
+        salt: Option<String>,
+    },
+    /// A rule's `@active` window: true while the current time is inside it.
+    Active(ActiveWindow),
+    /// `fn:region(ip)`: a function registered by the host application, see
+    /// [`crate::eval::Functions`]. Bare words in the arguments are variables.
+    Call(String, Vec<AstNode>),
 }
 
 impl AstNode {
@@ -330,6 +461,251 @@ impl AstNode {
             _ => None,
         }
     }
+
+    /// Replace bare words naming a parameter with the bound value.
+    fn bind(&self, bindings: &HashMap<&str, &Atom>) -> AstNode {
+        let atom = |a: &Atom| match a {
+            Atom::Variable(v) => bindings
+                .get(v.as_str())
+                .map_or_else(|| a.clone(), |b| (*b).clone()),
+            other => other.clone(),
+        };
+        let node = |n: &AstNode| Box::new(n.bind(bindings));
+        match self {
+            AstNode::Constant(a) => AstNode::Constant(atom(a)),
+            AstNode::List(items) => AstNode::List(items.iter().map(atom).collect()),
+            AstNode::Segment(name, args) => {
+                AstNode::Segment(name.clone(), args.iter().map(atom).collect())
+            }
+            AstNode::Function(f, inner) => AstNode::Function(f.clone(), node(inner)),
+            AstNode::Compare(l, op, r) => AstNode::Compare(node(l), op.clone(), node(r)),
+            // Arithmetic and function arguments read bare words as
+            // variables, so a parameter there is a `Variable` rather than a
+            // `Constant`.
+            AstNode::Arithmetic(l, op, r) => AstNode::Arithmetic(
+                Box::new(l.bind_operand(bindings)),
+                *op,
+                Box::new(r.bind_operand(bindings)),
+            ),
+            AstNode::Call(name, args) => AstNode::Call(
+                name.clone(),
+                args.iter().map(|a| a.bind_operand(bindings)).collect(),
+            ),
+            AstNode::Match(l, op, r) => AstNode::Match(node(l), op.clone(), node(r)),
+            AstNode::Array(l, op, r) => AstNode::Array(node(l), op.clone(), node(r)),
+            AstNode::Logic(l, op, r) => AstNode::Logic(node(l), op.clone(), node(r)),
+            AstNode::Scope { expr, negate } => AstNode::Scope {
+                expr: node(expr),
+                negate: *negate,
+            },
+            AstNode::Coalesce(args) => {
+                AstNode::Coalesce(args.iter().map(|a| a.bind(bindings)).collect())
+            }
+            // Context variables (`Variable`), percentage and ramp fields and
+            // null checks name context keys, not values.
+            AstNode::Void
+            | AstNode::Variable(_)
+            | AstNode::Percentage { .. }
+            | AstNode::Ramp { .. }
+            | AstNode::NullCheck { .. }
+            | AstNode::Active(_) => self.clone(),
+        }
+    }
+
+    /// [`Self::bind`] for a value read like arithmetic, where a bare word is
+    /// a variable.
+    fn bind_operand(&self, bindings: &HashMap<&str, &Atom>) -> AstNode {
+        match self {
+            AstNode::Variable(Atom::Variable(v)) => match bindings.get(v.as_str()) {
+                Some(bound) => AstNode::Constant((*bound).clone()),
+                None => self.clone(),
+            },
+            other => other.bind(bindings),
+        }
+    }
+
+    /// Names of the segments this expression references, in source order.
+    pub fn segment_refs(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        self.walk(&mut |node| {
+            if let AstNode::Segment(name, _) = node {
+                out.push(name.as_str());
+            }
+        });
+        out
+    }
+
+    /// Name and argument count of every `segment(...)` call, in source order.
+    pub fn segment_calls(&self) -> Vec<(&str, usize)> {
+        let mut out = Vec::new();
+        self.walk(&mut |node| {
+            if let AstNode::Segment(name, args) = node {
+                out.push((name.as_str(), args.len()));
+            }
+        });
+        out
+    }
+
+    /// Names of the `fn:` functions this expression calls, in source order.
+    pub fn function_calls(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        self.walk(&mut |node| {
+            if let AstNode::Call(name, _) = node {
+                out.push(name.as_str());
+            }
+        });
+        out
+    }
+
+    /// Visit this node and everything below it, parents first.
+    fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a AstNode)) {
+        visit(self);
+        match self {
+            AstNode::Logic(lhs, _, rhs)
+            | AstNode::Compare(lhs, _, rhs)
+            | AstNode::Arithmetic(lhs, _, rhs)
+            | AstNode::Match(lhs, _, rhs)
+            | AstNode::Array(lhs, _, rhs) => {
+                lhs.walk(visit);
+                rhs.walk(visit);
+            }
+            AstNode::Scope { expr, .. } => expr.walk(visit),
+            AstNode::Function(_, inner) => inner.walk(visit),
+            AstNode::Percentage { field, .. } | AstNode::Ramp { field, .. } => field.walk(visit),
+            AstNode::Coalesce(nodes) | AstNode::Call(_, nodes) => {
+                nodes.iter().for_each(|n| n.walk(visit))
+            }
+            AstNode::NullCheck { variable, .. } => variable.walk(visit),
+            AstNode::Void
+            | AstNode::Variable(_)
+            | AstNode::Constant(_)
+            | AstNode::List(_)
+            | AstNode::Segment(..)
+            | AstNode::Active(_) => {}
+        }
+    }
+}
+
+/// `@active from 2026-11-01T00:00Z until 2026-11-15T00:00Z` on a flag or a
+/// rule. Either end may be left out; `until` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveWindow {
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl ActiveWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| at >= from) && self.until.is_none_or(|until| at < until)
+    }
+}
+
+impl fmt::Display for ActiveWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        if let Some(from) = self.from {
+            write!(f, "from {}", from.format(UTC_FORMAT))?;
+            sep = " ";
+        }
+        if let Some(until) = self.until {
+            write!(f, "{sep}until {}", until.format(UTC_FORMAT))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a flag, rule or segment sits in the parsed content: the byte range
+/// `start..end` and the 1-based line and column `start` is on. Content that
+/// went through [`crate::include::resolve_includes`] is the merged text; map
+/// it back with [`crate::include::ResolvedFlagfile::position`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A `@segment` definition. Parameters are bound to the call's arguments
+/// wherever the body uses them as a value, e.g. the `c` in `countryCode == c`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentDef {
+    pub params: Vec<String>,
+    pub body: AstNode,
+    /// From `@segment` to the closing brace.
+    pub span: Span,
+}
+
+impl From<AstNode> for SegmentDef {
+    fn from(body: AstNode) -> Self {
+        SegmentDef {
+            params: Vec::new(),
+            body,
+            span: Span::default(),
+        }
+    }
+}
+
+impl SegmentDef {
+    /// The body with `args` bound to the parameters.
+    pub fn instantiate(&self, name: &str, args: &[Atom]) -> Result<Cow<'_, AstNode>, String> {
+        if args.len() != self.params.len() {
+            return Err(format!(
+                "segment '{}' takes {} argument{}, got {}",
+                name,
+                self.params.len(),
+                if self.params.len() == 1 { "" } else { "s" },
+                args.len()
+            ));
+        }
+        if args.is_empty() {
+            return Ok(Cow::Borrowed(&self.body));
+        }
+        let bindings: HashMap<&str, &Atom> =
+            self.params.iter().map(String::as_str).zip(args).collect();
+        Ok(Cow::Owned(self.body.bind(&bindings)))
+    }
+}
+
+/// Value type a flag declares with `@type bool|int|float|string|json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+    Json,
+}
+
+impl ValueType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bool" => Some(ValueType::Bool),
+            "int" => Some(ValueType::Int),
+            "float" => Some(ValueType::Float),
+            "string" => Some(ValueType::String),
+            "json" => Some(ValueType::Json),
+            _ => None,
+        }
+    }
+
+    /// Whether a flag declared with this type may return `found`. Integers
+    /// are valid `float` values.
+    pub fn accepts(self, found: ValueType) -> bool {
+        self == found || (self == ValueType::Float && found == ValueType::Int)
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Bool => write!(f, "bool"),
+            ValueType::Int => write!(f, "int"),
+            ValueType::Float => write!(f, "float"),
+            ValueType::String => write!(f, "string"),
+            ValueType::Json => write!(f, "json"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -338,8 +714,35 @@ pub struct FlagMetadata {
     pub expires: Option<NaiveDate>,
     pub ticket: Option<String>,
     pub description: Option<String>,
+    /// Flag kind such as `release`, `experiment`, `ops` or `permission`.
     pub flag_type: Option<String>,
+    /// Declared value type (`@type bool|int|float|string|json`).
+    pub value_type: Option<ValueType>,
     pub deprecated: Option<String>,
     pub requires: Vec<String>,
     pub tests: Vec<String>,
+    /// Outside this window the flag is disabled and serves no value.
+    pub active: Option<ActiveWindow>,
+}
+
+impl FlagMetadata {
+    /// The annotations as a JSON object for non-Rust callers. `expires` is an
+    /// ISO `YYYY-MM-DD` date, the `active` window ends are ISO datetimes.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "owner": self.owner,
+            "expires": self.expires.map(|d| d.format("%Y-%m-%d").to_string()),
+            "ticket": self.ticket,
+            "description": self.description,
+            "type": self.flag_type,
+            "valueType": self.value_type.map(|t| t.to_string()),
+            "deprecated": self.deprecated,
+            "requires": self.requires,
+            "tests": self.tests,
+            "active": self.active.map(|w| serde_json::json!({
+                "from": w.from.map(|t| t.format(UTC_FORMAT).to_string()),
+                "until": w.until.map(|t| t.format(UTC_FORMAT).to_string()),
+            })),
+        })
+    }
 }
@@ -0,0 +1,948 @@
+//! Lossless concrete syntax tree for Flagfiles.
+//!
+//! [`crate::parse_flagfile`] builds the AST that evaluation needs and drops
+//! everything else. The CST keeps every byte: comments, whitespace and even
+//! text that doesn't parse end up in some token, so `parse(source).text()`
+//! is always `source`. Tools that rewrite part of a Flagfile and must leave
+//! the rest alone — the formatter, refactorings, the language server — build
+//! on it.
+//!
+//! ```
+//! use flagfile_lib::cst::{self, NodeKind};
+//!
+//! let source = "// checkout\n@owner \"web\"\nFF-new-ui {\n    plan == pro -> true\n    false\n}\n";
+//! let root = cst::parse(source);
+//! assert_eq!(root.text(), source);
+//!
+//! let flag = root.nodes().next().unwrap();
+//! assert_eq!(flag.kind(), NodeKind::Flag);
+//! assert_eq!(flag.name(), Some("FF-new-ui"));
+//! assert_eq!(flag.text(), "@owner \"web\"\nFF-new-ui {\n    plan == pro -> true\n    false\n}");
+//! ```
+//!
+//! The tree is forgiving rather than validating: it follows the shape of
+//! the source and leaves checking to the parser. Text that fits nowhere is
+//! kept in [`NodeKind::Error`] nodes.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Spaces and tabs.
+    Whitespace,
+    /// `\n` or `\r\n`.
+    Newline,
+    /// `// ...` up to the end of the line.
+    LineComment,
+    /// `/* ... */`, or up to the end of the input when unterminated.
+    BlockComment,
+    /// `@owner`, `@env`, `@segment` and the other annotations.
+    Annotation,
+    /// A word starting with `FF-` or `FF_`.
+    FlagName,
+    /// Identifiers, keywords, numbers, dates, versions and percentages.
+    Word,
+    /// `"..."` or `'...'`, or up to the end of the line when unterminated.
+    String,
+    /// `/pattern/` after a match operator.
+    Regex,
+    /// `->`
+    Arrow,
+    /// Comparison, match and logic operators such as `==`, `!~` or `&&`.
+    Operator,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    /// Any other character.
+    Punct,
+}
+
+impl TokenKind {
+    /// Whitespace, newlines and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace
+                | TokenKind::Newline
+                | TokenKind::LineComment
+                | TokenKind::BlockComment
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    kind: TokenKind,
+    text: String,
+    offset: usize,
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte range in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole file.
+    Root,
+    /// A flag with the annotations above it: `FF-name -> value` or
+    /// `FF-name { ... }`.
+    Flag,
+    /// `@segment name(params) { condition }`.
+    Segment,
+    /// One annotation line, such as `@owner "team"`, `@include path` or a
+    /// rule's `@name "..."`.
+    Annotation,
+    /// `{ ... }` of a flag, an `@env` rule, `@variants` or a segment.
+    Block,
+    /// `@variants { ... }` at the top of a flag's block.
+    Variants,
+    /// A rule in a block: `condition -> value`, a bare value, or `@env name`
+    /// followed by `-> value` or a block.
+    Rule,
+    /// The expression of a rule or segment.
+    Condition,
+    /// A returned value: `true`, `42`, `"text"`, `json(...)` or a variant.
+    Value,
+    /// Text that fits nowhere else.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+impl Element {
+    fn is_trivia(&self) -> bool {
+        matches!(self, Element::Token(t) if t.kind.is_trivia())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    kind: NodeKind,
+    children: Vec<Element>,
+}
+
+impl Node {
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn children(&self) -> &[Element] {
+        &self.children
+    }
+
+    /// Child nodes, skipping tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Child tokens, skipping nodes.
+    pub fn child_tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Token(token) => Some(token),
+            Element::Node(_) => None,
+        })
+    }
+
+    /// Every token under this node, in source order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut out = Vec::new();
+        self.collect_tokens(&mut out);
+        out
+    }
+
+    fn collect_tokens<'a>(&'a self, out: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                Element::Token(token) => out.push(token),
+                Element::Node(node) => node.collect_tokens(out),
+            }
+        }
+    }
+
+    /// Every node under this one, parents before their children.
+    pub fn descendants(&self) -> Vec<&Node> {
+        let mut out = Vec::new();
+        for node in self.nodes() {
+            out.push(node);
+            out.extend(node.descendants());
+        }
+        out
+    }
+
+    /// The source text this node covers.
+    pub fn text(&self) -> String {
+        self.tokens().into_iter().map(Token::text).collect()
+    }
+
+    /// Byte range in the source; empty for an empty file.
+    pub fn range(&self) -> Range<usize> {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.offset..last.range().end,
+            _ => 0..0,
+        }
+    }
+
+    /// A flag's or segment's name.
+    pub fn name(&self) -> Option<&str> {
+        let wanted = match self.kind {
+            NodeKind::Flag => TokenKind::FlagName,
+            NodeKind::Segment => TokenKind::Word,
+            _ => return None,
+        };
+        self.child_tokens()
+            .find(|t| t.kind == wanted)
+            .map(Token::text)
+    }
+
+    /// An annotation's keyword, e.g. `@owner`, or `@env` for an `@env`
+    /// rule.
+    pub fn annotation(&self) -> Option<&str> {
+        match self.kind {
+            NodeKind::Annotation | NodeKind::Rule => {
+                self.children.first().and_then(|child| match child {
+                    Element::Token(t) if t.kind == TokenKind::Annotation => Some(t.text()),
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The innermost node whose range contains `offset`.
+    pub fn node_at(&self, offset: usize) -> &Node {
+        self.nodes()
+            .find(|node| node.range().contains(&offset))
+            .map_or(self, |node| node.node_at(offset))
+    }
+}
+
+/// Split `source` into tokens. Concatenating their text gives `source`
+/// back.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    let mut after_match_op = false;
+    while offset < source.len() {
+        let rest = &source[offset..];
+        let (kind, len) = next_token(rest, after_match_op);
+        if !kind.is_trivia() {
+            after_match_op = kind == TokenKind::Operator && rest[..len].contains('~');
+        }
+        tokens.push(Token {
+            kind,
+            text: rest[..len].to_string(),
+            offset,
+        });
+        offset += len;
+    }
+    tokens
+}
+
+/// Longest first, so `!~$` isn't read as `!~` followed by `$`.
+const OPERATORS: &[&str] = &[
+    "!^~", "!~$", "==", "!=", "<>", "<=", ">=", "&&", "||", "!~", "^~", "~$", "<", ">", "~", "=",
+    "!",
+];
+
+/// Kind and byte length of the token `s` starts with. A `/` after a match
+/// operator starts a regex.
+fn next_token(s: &str, regex_allowed: bool) -> (TokenKind, usize) {
+    let c = s.chars().next().expect("next_token on empty input");
+    match c {
+        ' ' | '\t' => (
+            TokenKind::Whitespace,
+            s.find(|c| c != ' ' && c != '\t').unwrap_or(s.len()),
+        ),
+        '\n' => (TokenKind::Newline, 1),
+        '\r' if s[1..].starts_with('\n') => (TokenKind::Newline, 2),
+        '/' if s.starts_with("//") => (TokenKind::LineComment, line_len(s)),
+        '/' if s.starts_with("/*") => (
+            TokenKind::BlockComment,
+            s[2..].find("*/").map_or(s.len(), |at| at + 4),
+        ),
+        '/' if regex_allowed => (TokenKind::Regex, quoted_len(s, '/')),
+        '"' | '\'' => (TokenKind::String, quoted_len(s, c)),
+        '@' => match word_len(&s[1..]) {
+            0 => (TokenKind::Punct, 1),
+            len => (TokenKind::Annotation, len + 1),
+        },
+        '-' if s.starts_with("->") => (TokenKind::Arrow, 2),
+        '{' => (TokenKind::LBrace, 1),
+        '}' => (TokenKind::RBrace, 1),
+        '(' => (TokenKind::LParen, 1),
+        ')' => (TokenKind::RParen, 1),
+        '[' => (TokenKind::LBracket, 1),
+        ']' => (TokenKind::RBracket, 1),
+        ',' => (TokenKind::Comma, 1),
+        c if c.is_alphanumeric() || c == '_' => {
+            let len = word_len(s);
+            if s.starts_with("FF-") || s.starts_with("FF_") {
+                (TokenKind::FlagName, len)
+            } else {
+                (TokenKind::Word, len)
+            }
+        }
+        _ => match OPERATORS.iter().find(|op| s.starts_with(*op)) {
+            Some(op) => (TokenKind::Operator, op.len()),
+            None => (TokenKind::Punct, c.len_utf8()),
+        },
+    }
+}
+
+/// Length of the word `s` starts with: letters, digits, `_`, `.` and `%`,
+/// `-` unless it starts `->`, and `:` between digits (`10:30`).
+fn word_len(s: &str) -> usize {
+    let mut chars = s.char_indices().peekable();
+    let mut prev = None;
+    let mut len = 0;
+    while let Some((at, c)) = chars.next() {
+        let next = chars.peek().map(|(_, n)| *n);
+        let continues = match c {
+            c if c.is_alphanumeric() => true,
+            '_' => true,
+            '.' | '%' | '-' => at > 0 && !(c == '-' && next == Some('>')),
+            ':' => {
+                prev.is_some_and(|p: char| p.is_ascii_digit())
+                    && next.is_some_and(|n| n.is_ascii_digit())
+            }
+            _ => false,
+        };
+        if !continues {
+            break;
+        }
+        prev = Some(c);
+        len = at + c.len_utf8();
+    }
+    len
+}
+
+/// Length of the rest of the line, without its line ending.
+fn line_len(s: &str) -> usize {
+    let end = s.find('\n').unwrap_or(s.len());
+    if s[..end].ends_with('\r') {
+        end - 1
+    } else {
+        end
+    }
+}
+
+/// Length of text between `quote`s, or up to the end of the line when the
+/// closing one is missing.
+fn quoted_len(s: &str, quote: char) -> usize {
+    let line = line_len(s);
+    match s[1..line].find(quote) {
+        Some(at) => at + 2,
+        None => line,
+    }
+}
+
+/// Build the tree for `source`.
+pub fn parse(source: &str) -> Node {
+    let mut builder = Builder {
+        tokens: tokenize(source),
+        pos: 0,
+    };
+    builder.root()
+}
+
+/// Where a run of tokens stops, other than at a closing bracket it didn't
+/// open or the end of the input.
+#[derive(Clone, Copy, PartialEq)]
+enum Until {
+    /// The end of the line.
+    LineEnd,
+    /// A rule's `->`, or the end of a line that doesn't continue onto the
+    /// next.
+    Arrow,
+    /// Only a closing bracket.
+    Closer,
+}
+
+struct Builder {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Builder {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Element {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        Element::Token(token)
+    }
+
+    /// The next token that isn't trivia, from `pos` on.
+    fn significant_from(&self, pos: usize) -> Option<&Token> {
+        self.tokens[pos.min(self.tokens.len())..]
+            .iter()
+            .find(|t| !t.kind.is_trivia())
+    }
+
+    fn next_significant(&self) -> Option<&Token> {
+        self.significant_from(self.pos)
+    }
+
+    fn trivia(&mut self, out: &mut Vec<Element>) {
+        while self.peek().is_some_and(|t| t.kind.is_trivia()) {
+            out.push(self.bump());
+        }
+    }
+
+    fn root(&mut self) -> Node {
+        let mut children = Vec::new();
+        while let Some(token) = self.peek() {
+            match (token.kind, token.text.as_str()) {
+                (kind, _) if kind.is_trivia() => children.push(self.bump()),
+                (TokenKind::Annotation, "@segment") => self.segment(&mut children),
+                (TokenKind::Annotation, "@include") => {
+                    self.run(NodeKind::Annotation, Until::LineEnd, &mut children)
+                }
+                (TokenKind::Annotation, _) => self.annotated_flag(&mut children),
+                (TokenKind::FlagName, _) => self.flag(Vec::new(), &mut children),
+                _ => self.run(NodeKind::Error, Until::LineEnd, &mut children),
+            }
+        }
+        Node {
+            kind: NodeKind::Root,
+            children,
+        }
+    }
+
+    /// Annotation lines and the flag they belong to. When no flag follows
+    /// they stay on their own.
+    fn annotated_flag(&mut self, out: &mut Vec<Element>) {
+        let mut children = Vec::new();
+        while let Some(token) = self.peek() {
+            match (token.kind, token.text.as_str()) {
+                (kind, _) if kind.is_trivia() => children.push(self.bump()),
+                (TokenKind::Annotation, "@segment" | "@include") => break,
+                (TokenKind::Annotation, _) => {
+                    self.run(NodeKind::Annotation, Until::LineEnd, &mut children)
+                }
+                _ => break,
+            }
+        }
+        if self.peek().is_some_and(|t| t.kind == TokenKind::FlagName) {
+            self.flag(children, out);
+        } else {
+            out.extend(children);
+        }
+    }
+
+    /// A flag from its name on; `children` holds its annotations.
+    fn flag(&mut self, mut children: Vec<Element>, out: &mut Vec<Element>) {
+        children.push(self.bump());
+        match self.next_significant().map(|t| t.kind) {
+            Some(TokenKind::Arrow) => {
+                self.trivia(&mut children);
+                children.push(self.bump());
+                self.trivia(&mut children);
+                if self.next_significant().is_some() {
+                    self.run(NodeKind::Value, Until::LineEnd, &mut children);
+                }
+            }
+            Some(TokenKind::LBrace) => {
+                self.trivia(&mut children);
+                self.rules_block(&mut children);
+            }
+            _ => {}
+        }
+        let trailing = split_trailing_trivia(&mut children);
+        out.push(Element::Node(Node {
+            kind: NodeKind::Flag,
+            children,
+        }));
+        out.extend(trailing);
+    }
+
+    /// `{`, rules, `}`.
+    fn rules_block(&mut self, out: &mut Vec<Element>) {
+        let mut children = vec![self.bump()];
+        while let Some(token) = self.peek() {
+            match (token.kind, token.text.as_str()) {
+                (kind, _) if kind.is_trivia() => children.push(self.bump()),
+                (TokenKind::RBrace, _) => {
+                    children.push(self.bump());
+                    break;
+                }
+                (TokenKind::Annotation, "@env") => self.env_rule(&mut children),
+                (TokenKind::Annotation, "@variants") => self.variants(&mut children),
+                (TokenKind::Annotation, _) => {
+                    self.run(NodeKind::Annotation, Until::LineEnd, &mut children)
+                }
+                _ => self.rule(&mut children),
+            }
+        }
+        out.push(Element::Node(Node {
+            kind: NodeKind::Block,
+            children,
+        }));
+    }
+
+    /// `condition -> value` or a bare value.
+    fn rule(&mut self, out: &mut Vec<Element>) {
+        let mut children = Vec::new();
+        if self.peek().is_some_and(|t| t.kind != TokenKind::Arrow) {
+            self.run(NodeKind::Condition, Until::Arrow, &mut children);
+        }
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::Arrow) {
+            self.trivia(&mut children);
+            children.push(self.bump());
+            self.trivia(&mut children);
+            if self.next_significant().is_some_and(|t| !is_closer(t.kind)) {
+                self.run(NodeKind::Value, Until::LineEnd, &mut children);
+            }
+        } else if let Some(Element::Node(node)) = children.first_mut() {
+            // no `->`: what looked like a condition is the value
+            node.kind = NodeKind::Value;
+        }
+        let trailing = split_trailing_trivia(&mut children);
+        out.push(Element::Node(Node {
+            kind: NodeKind::Rule,
+            children,
+        }));
+        out.extend(trailing);
+    }
+
+    /// `@env name -> value` or `@env name { rules }`.
+    fn env_rule(&mut self, out: &mut Vec<Element>) {
+        let mut children = vec![self.bump()];
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::Word) {
+            self.trivia(&mut children);
+            children.push(self.bump());
+        }
+        match self.next_significant().map(|t| t.kind) {
+            Some(TokenKind::Arrow) => {
+                self.trivia(&mut children);
+                children.push(self.bump());
+                self.trivia(&mut children);
+                if self.next_significant().is_some_and(|t| !is_closer(t.kind)) {
+                    self.run(NodeKind::Value, Until::LineEnd, &mut children);
+                }
+            }
+            Some(TokenKind::LBrace) => {
+                self.trivia(&mut children);
+                self.rules_block(&mut children);
+            }
+            _ => {}
+        }
+        let trailing = split_trailing_trivia(&mut children);
+        out.push(Element::Node(Node {
+            kind: NodeKind::Rule,
+            children,
+        }));
+        out.extend(trailing);
+    }
+
+    /// `@variants { name -> value ... }`.
+    fn variants(&mut self, out: &mut Vec<Element>) {
+        let mut children = vec![self.bump()];
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::LBrace) {
+            self.trivia(&mut children);
+            self.rules_block(&mut children);
+        }
+        out.push(Element::Node(Node {
+            kind: NodeKind::Variants,
+            children,
+        }));
+    }
+
+    /// `@segment name(params) { condition }`.
+    fn segment(&mut self, out: &mut Vec<Element>) {
+        let mut children = vec![self.bump()];
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::Word) {
+            self.trivia(&mut children);
+            children.push(self.bump());
+        }
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::LParen) {
+            self.trivia(&mut children);
+            while let Some(token) = self.peek() {
+                let close = token.kind == TokenKind::RParen;
+                if matches!(token.kind, TokenKind::LBrace | TokenKind::RBrace) {
+                    break;
+                }
+                children.push(self.bump());
+                if close {
+                    break;
+                }
+            }
+        }
+        if self.next_significant().map(|t| t.kind) == Some(TokenKind::LBrace) {
+            self.trivia(&mut children);
+            let mut block = vec![self.bump()];
+            self.trivia(&mut block);
+            if self.peek().is_some_and(|t| t.kind != TokenKind::RBrace) {
+                self.run(NodeKind::Condition, Until::Closer, &mut block);
+            }
+            self.trivia(&mut block);
+            if self.peek().is_some_and(|t| t.kind == TokenKind::RBrace) {
+                block.push(self.bump());
+            }
+            children.push(Element::Node(Node {
+                kind: NodeKind::Block,
+                children: block,
+            }));
+        }
+        out.push(Element::Node(Node {
+            kind: NodeKind::Segment,
+            children,
+        }));
+    }
+
+    /// A `kind` node from the next token up to where `until` says, always
+    /// taking at least one token. Trailing trivia is left to the parent.
+    fn run(&mut self, kind: NodeKind, until: Until, out: &mut Vec<Element>) {
+        let mut children = vec![self.bump()];
+        let mut depth = match children[0] {
+            Element::Token(ref t) if is_opener(t.kind) => 1,
+            _ => 0,
+        };
+        while let Some(token) = self.peek() {
+            if depth == 0 {
+                let stop = match token.kind {
+                    k if is_closer(k) => true,
+                    TokenKind::Arrow => until == Until::Arrow,
+                    TokenKind::Newline => match until {
+                        Until::LineEnd => true,
+                        Until::Arrow => !self.continues(&children),
+                        Until::Closer => false,
+                    },
+                    _ => false,
+                };
+                if stop {
+                    break;
+                }
+            }
+            if is_opener(token.kind) {
+                depth += 1;
+            } else if is_closer(token.kind) {
+                depth -= 1;
+            }
+            children.push(self.bump());
+        }
+        let trailing = split_trailing_trivia(&mut children);
+        out.push(Element::Node(Node { kind, children }));
+        out.extend(trailing);
+    }
+
+    /// Whether a condition goes on past the newline at `pos`: it ends in an
+    /// operator, or the next line starts with one or with the `->`.
+    fn continues(&self, children: &[Element]) -> bool {
+        let last = children.iter().rev().find_map(|child| match child {
+            Element::Token(t) if !t.kind.is_trivia() => Some(t),
+            _ => None,
+        });
+        if last.is_some_and(|t| t.kind == TokenKind::Operator || is_logic_word(t)) {
+            return true;
+        }
+        self.next_significant().is_some_and(|t| {
+            matches!(t.kind, TokenKind::Operator | TokenKind::Arrow) || is_logic_word(t)
+        })
+    }
+}
+
+fn is_logic_word(token: &Token) -> bool {
+    token.kind == TokenKind::Word
+        && ["and", "or", "not", "in"]
+            .iter()
+            .any(|w| token.text.eq_ignore_ascii_case(w))
+}
+
+fn is_opener(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LBrace | TokenKind::LParen | TokenKind::LBracket
+    )
+}
+
+fn is_closer(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RBrace | TokenKind::RParen | TokenKind::RBracket
+    )
+}
+
+/// Remove and return the trivia at the end of `children`.
+fn split_trailing_trivia(children: &mut Vec<Element>) -> Vec<Element> {
+    let keep = children
+        .iter()
+        .rposition(|child| !child.is_trivia())
+        .map_or(0, |at| at + 1);
+    children.split_off(keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kinds and texts of the tokens in `source`, without whitespace.
+    fn kinds(source: &str) -> Vec<(TokenKind, String)> {
+        tokenize(source)
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    fn owned(expected: &[(TokenKind, &str)]) -> Vec<(TokenKind, String)> {
+        expected.iter().map(|(k, t)| (*k, t.to_string())).collect()
+    }
+
+    /// `kind:text` of the node and each descendant, indented by depth.
+    fn outline(node: &Node, depth: usize, out: &mut Vec<String>) {
+        out.push(format!(
+            "{}{:?} {:?}",
+            "  ".repeat(depth),
+            node.kind,
+            node.text()
+        ));
+        for child in node.nodes() {
+            outline(child, depth + 1, out);
+        }
+    }
+
+    fn tree(source: &str) -> Vec<String> {
+        let root = parse(source);
+        assert_eq!(root.text(), source);
+        let mut out = Vec::new();
+        for node in root.nodes() {
+            outline(node, 0, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("FF-a->true // on\n@owner \"x\"\r\nemail ~ /a.*b/ and v >= 1.2.3"),
+            owned(&[
+                (FlagName, "FF-a"),
+                (Arrow, "->"),
+                (Word, "true"),
+                (LineComment, "// on"),
+                (Newline, "\n"),
+                (Annotation, "@owner"),
+                (String, "\"x\""),
+                (Newline, "\r\n"),
+                (Word, "email"),
+                (Operator, "~"),
+                (Regex, "/a.*b/"),
+                (Word, "and"),
+                (Word, "v"),
+                (Operator, ">="),
+                (Word, "1.2.3"),
+            ])
+        );
+        assert_eq!(
+            kinds("percentage(25%, id) now() > 2025-01-01T10:30:00Z a: 1 x !~$ 'y"),
+            owned(&[
+                (Word, "percentage"),
+                (LParen, "("),
+                (Word, "25%"),
+                (Comma, ","),
+                (Word, "id"),
+                (RParen, ")"),
+                (Word, "now"),
+                (LParen, "("),
+                (RParen, ")"),
+                (Operator, ">"),
+                (Word, "2025-01-01T10:30:00Z"),
+                (Word, "a"),
+                (Punct, ":"),
+                (Word, "1"),
+                (Word, "x"),
+                (Operator, "!~$"),
+                (String, "'y"),
+            ])
+        );
+        assert_eq!(
+            kinds("/* a\n b */ x / 2 @"),
+            owned(&[
+                (BlockComment, "/* a\n b */"),
+                (Word, "x"),
+                (Punct, "/"),
+                (Word, "2"),
+                (Punct, "@"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_flags_rules_and_segments() {
+        let source = r#"// header
+@segment eu(country) {
+    country in (DE, FR)
+        or x == 1
+}
+
+@owner "web" // team
+// @test FF-a == true
+@description "A"
+FF-a {
+    @variants {
+        on -> true
+    }
+    // @name first
+    plan == pro
+      and beta == true -> on
+    @env prod {
+        segment(eu, DE) -> json({
+            "a": 1
+        })
+    }
+    @env dev -> false
+    false
+}
+
+FF-b -> 42 // answer
+@include more.ff
+"#;
+        assert_eq!(
+            tree(source),
+            [
+                "Segment \"@segment eu(country) {\\n    country in (DE, FR)\\n        or x == 1\\n}\"",
+                "  Block \"{\\n    country in (DE, FR)\\n        or x == 1\\n}\"",
+                "    Condition \"country in (DE, FR)\\n        or x == 1\"",
+                "Flag \"@owner \\\"web\\\" // team\\n// @test FF-a == true\\n@description \\\"A\\\"\\nFF-a {\\n    @variants {\\n        on -> true\\n    }\\n    // @name first\\n    plan == pro\\n      and beta == true -> on\\n    @env prod {\\n        segment(eu, DE) -> json({\\n            \\\"a\\\": 1\\n        })\\n    }\\n    @env dev -> false\\n    false\\n}\"",
+                "  Annotation \"@owner \\\"web\\\"\"",
+                "  Annotation \"@description \\\"A\\\"\"",
+                "  Block \"{\\n    @variants {\\n        on -> true\\n    }\\n    // @name first\\n    plan == pro\\n      and beta == true -> on\\n    @env prod {\\n        segment(eu, DE) -> json({\\n            \\\"a\\\": 1\\n        })\\n    }\\n    @env dev -> false\\n    false\\n}\"",
+                "    Variants \"@variants {\\n        on -> true\\n    }\"",
+                "      Block \"{\\n        on -> true\\n    }\"",
+                "        Rule \"on -> true\"",
+                "          Condition \"on\"",
+                "          Value \"true\"",
+                "    Rule \"plan == pro\\n      and beta == true -> on\"",
+                "      Condition \"plan == pro\\n      and beta == true\"",
+                "      Value \"on\"",
+                "    Rule \"@env prod {\\n        segment(eu, DE) -> json({\\n            \\\"a\\\": 1\\n        })\\n    }\"",
+                "      Block \"{\\n        segment(eu, DE) -> json({\\n            \\\"a\\\": 1\\n        })\\n    }\"",
+                "        Rule \"segment(eu, DE) -> json({\\n            \\\"a\\\": 1\\n        })\"",
+                "          Condition \"segment(eu, DE)\"",
+                "          Value \"json({\\n            \\\"a\\\": 1\\n        })\"",
+                "    Rule \"@env dev -> false\"",
+                "      Value \"false\"",
+                "    Rule \"false\"",
+                "      Value \"false\"",
+                "Flag \"FF-b -> 42\"",
+                "  Value \"42\"",
+                "Annotation \"@include more.ff\"",
+            ]
+        );
+
+        let root = parse(source);
+        let names: Vec<_> = root.nodes().filter_map(Node::name).collect();
+        assert_eq!(names, ["eu", "FF-a", "FF-b"]);
+        let rules: Vec<_> = root
+            .descendants()
+            .into_iter()
+            .filter_map(Node::annotation)
+            .collect();
+        assert_eq!(
+            rules,
+            ["@owner", "@description", "@env", "@env", "@include"]
+        );
+
+        let offset = source.find("beta == true").unwrap();
+        let node = root.node_at(offset);
+        assert_eq!(node.kind(), NodeKind::Condition);
+        assert_eq!(&source[node.range()], node.text());
+    }
+
+    #[test]
+    fn test_static_value_then_rule() {
+        assert_eq!(
+            tree("FF-a {\n    true\n    a == b -> false\n}"),
+            [
+                "Flag \"FF-a {\\n    true\\n    a == b -> false\\n}\"",
+                "  Block \"{\\n    true\\n    a == b -> false\\n}\"",
+                "    Rule \"true\"",
+                "      Value \"true\"",
+                "    Rule \"a == b -> false\"",
+                "      Condition \"a == b\"",
+                "      Value \"false\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_leftover_text_is_kept() {
+        assert_eq!(
+            tree("} oops\n@owner \"x\"\n\n@segment s {\nFF-x {\n  a ->"),
+            [
+                "Error \"} oops\"",
+                "Annotation \"@owner \\\"x\\\"\"",
+                "Segment \"@segment s {\\nFF-x {\\n  a ->\"",
+                "  Block \"{\\nFF-x {\\n  a ->\"",
+                "    Condition \"FF-x {\\n  a ->\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let sources = [
+            include_str!("../Flagfile"),
+            include_str!("../Flagfile.example"),
+            "",
+            "\r\n\t \n",
+            "FF-a { \"unterminated\n}\n/* never closed",
+            "@@@ ((( ]]] }}} -> -> FF- ~ / é 😀",
+        ];
+        for source in sources {
+            assert_eq!(parse(source).text(), source);
+            let joined: String = tokenize(source).iter().map(Token::text).collect();
+            assert_eq!(joined, source);
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_parser_on_flags_and_segments() {
+        for source in [
+            include_str!("../Flagfile"),
+            include_str!("../Flagfile.example"),
+        ] {
+            let parsed = crate::parse_flagfile::parse_flagfile_with_segments(source).unwrap();
+            let mut expected: Vec<&str> = parsed
+                .flags
+                .iter()
+                .flat_map(|fv| fv.keys().copied())
+                .chain(parsed.segments.keys().map(String::as_str))
+                .collect();
+            let root = parse(source);
+            let mut names: Vec<&str> = root.nodes().filter_map(Node::name).collect();
+            expected.sort();
+            names.sort();
+            assert_eq!(names, expected);
+            assert!(root.descendants().iter().all(|n| n.kind != NodeKind::Error));
+        }
+    }
+}
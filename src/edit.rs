@@ -0,0 +1,966 @@
+//! Typed edits to a Flagfile's source. Only the text an edit is about
+//! changes: comments, blank lines and the layout of everything else are
+//! kept byte for byte. Edits find their place through the [`crate::cst`]
+//! and the result must parse before it's accepted.
+//!
+//! ```
+//! use flagfile_lib::edit::{Edit, Editor};
+//! use flagfile_lib::FlagReturn;
+//!
+//! let mut editor = Editor::new("// new checkout\nFF-checkout -> false\n");
+//! editor
+//!     .apply(&Edit::SetDefault {
+//!         flag: "FF-checkout".into(),
+//!         env: None,
+//!         value: FlagReturn::OnOff(true),
+//!     })
+//!     .unwrap();
+//! assert_eq!(editor.source(), "// new checkout\nFF-checkout -> true\n");
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+
+use chrono::NaiveDate;
+
+use crate::cst::{self, Node, NodeKind, Token, TokenKind};
+use crate::parse_error::ParseError;
+use crate::parse_flagfile::{parse_flagfile_with_segments, FlagReturn, TypeMismatch};
+
+/// Indentation of rules in a block that's created from `FF-name -> value`.
+const INDENT: &str = "    ";
+
+/// One change to a flag.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// Append `FF-name -> value` to the end of the file.
+    AddFlag { flag: String, value: FlagReturn },
+    /// Remove a flag with its annotations and the comment lines directly
+    /// above it.
+    RemoveFlag { flag: String },
+    /// Set what the flag returns when no rule matches: the value of
+    /// `FF-name -> value`, or the bare value ending its block, which is
+    /// added when missing. With `env`, the same inside the flag's `@env`
+    /// rule, which is added as the first rule when missing.
+    SetDefault {
+        flag: String,
+        env: Option<String>,
+        value: FlagReturn,
+    },
+    /// Insert a rule, such as `plan == pro -> true` or `@env prod -> false`,
+    /// before the 0-based rule `index`; past the last rule it's appended.
+    /// `FF-name -> value` becomes a block with the value as its default.
+    InsertRule {
+        flag: String,
+        index: usize,
+        rule: String,
+    },
+    /// Change the rate of the flag's `percentage(...)`, or of the one in
+    /// the 0-based rule `rule` when the flag has several.
+    SetPercentage {
+        flag: String,
+        rule: Option<usize>,
+        rate: f64,
+    },
+    /// Add an annotation above the flag name, replacing an existing one of
+    /// the same kind. `@requires` and `@test` can repeat, so they're only
+    /// added when the same line isn't there yet.
+    SetAnnotation {
+        flag: String,
+        annotation: Annotation,
+    },
+    /// Remove every annotation of a flag with this keyword, e.g. `owner`
+    /// or `@owner`.
+    RemoveAnnotation { flag: String, keyword: String },
+}
+
+impl Edit {
+    /// The flag the edit is about.
+    pub fn flag(&self) -> &str {
+        match self {
+            Edit::AddFlag { flag, .. }
+            | Edit::RemoveFlag { flag }
+            | Edit::SetDefault { flag, .. }
+            | Edit::InsertRule { flag, .. }
+            | Edit::SetPercentage { flag, .. }
+            | Edit::SetAnnotation { flag, .. }
+            | Edit::RemoveAnnotation { flag, .. } => flag,
+        }
+    }
+}
+
+/// A flag annotation [`Edit::SetAnnotation`] writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Annotation {
+    Owner(String),
+    Description(String),
+    Ticket(String),
+    Deprecated(String),
+    Expires(NaiveDate),
+    Type(String),
+    Requires(String),
+    Test(String),
+}
+
+impl Annotation {
+    /// The keyword, e.g. `@owner`.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Annotation::Owner(_) => "@owner",
+            Annotation::Description(_) => "@description",
+            Annotation::Ticket(_) => "@ticket",
+            Annotation::Deprecated(_) => "@deprecated",
+            Annotation::Expires(_) => "@expires",
+            Annotation::Type(_) => "@type",
+            Annotation::Requires(_) => "@requires",
+            Annotation::Test(_) => "@test",
+        }
+    }
+
+    fn repeats(&self) -> bool {
+        matches!(self, Annotation::Requires(_) | Annotation::Test(_))
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = self.keyword();
+        match self {
+            Annotation::Owner(s)
+            | Annotation::Description(s)
+            | Annotation::Ticket(s)
+            | Annotation::Deprecated(s) => write!(f, "{} {}", keyword, quote(s)),
+            Annotation::Expires(date) => write!(f, "{} {}", keyword, date.format("%Y-%m-%d")),
+            Annotation::Type(s) | Annotation::Requires(s) | Annotation::Test(s) => {
+                write!(f, "{} {}", keyword, s)
+            }
+        }
+    }
+}
+
+/// `s` in double quotes, or single ones when it contains a double quote.
+fn quote(s: &str) -> String {
+    if s.contains('"') {
+        format!("'{}'", s)
+    } else {
+        format!("\"{}\"", s)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EditError {
+    FlagNotFound(String),
+    FlagExists(String),
+    RuleNotFound {
+        flag: String,
+        index: usize,
+    },
+    NoPercentage {
+        flag: String,
+    },
+    /// The flag has several `percentage(...)` and no rule was picked.
+    AmbiguousPercentage {
+        flag: String,
+        count: usize,
+    },
+    InvalidRate(f64),
+    AnnotationNotFound {
+        flag: String,
+        keyword: String,
+    },
+    /// The Flagfile doesn't parse, so it's not edited.
+    Unparsable(ParseError),
+    /// The edit would leave a Flagfile that doesn't parse.
+    Invalid(ParseError),
+    /// The edit would return a value of the wrong type for the flag's
+    /// `@type`.
+    TypeMismatch(TypeMismatch),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::FlagNotFound(flag) => write!(f, "flag {} not found", flag),
+            EditError::FlagExists(flag) => write!(f, "flag {} already exists", flag),
+            EditError::RuleNotFound { flag, index } => {
+                write!(f, "{} has no rule #{}", flag, index + 1)
+            }
+            EditError::NoPercentage { flag } => {
+                write!(f, "{} has no percentage() rollout", flag)
+            }
+            EditError::AmbiguousPercentage { flag, count } => write!(
+                f,
+                "{} has {} percentage() rollouts, pick the rule to change",
+                flag, count
+            ),
+            EditError::InvalidRate(rate) => {
+                write!(f, "percentage {} is not between 0 and 100", rate)
+            }
+            EditError::AnnotationNotFound { flag, keyword } => {
+                write!(f, "{} has no {} annotation", flag, keyword)
+            }
+            EditError::Unparsable(e) => write!(f, "Flagfile doesn't parse: {}", e),
+            EditError::Invalid(e) => write!(f, "edit would break the Flagfile: {}", e),
+            EditError::TypeMismatch(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// A Flagfile's source and the edits applied to it so far.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    source: String,
+}
+
+impl Editor {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        std::fs::read_to_string(path).map(Self::new)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, &self.source)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the source defines `flag` itself, not through `@include`.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        find_flag(&cst::parse(&self.source), flag).is_some()
+    }
+
+    /// Apply `edit`, or leave the source as it was and say why not.
+    pub fn apply(&mut self, edit: &Edit) -> Result<(), EditError> {
+        if let Err(e) = check(&self.source) {
+            return Err(EditError::Unparsable(e));
+        }
+        let changes = changes(&self.source, edit)?;
+        let mut edited = self.source.clone();
+        // back to front, so earlier ranges stay put
+        for (range, text) in changes.into_iter().rev() {
+            edited.replace_range(range, &text);
+        }
+        let parsed = without_includes(&edited);
+        let parsed = parse_flagfile_with_segments(&parsed).map_err(EditError::Invalid)?;
+        if let Some(mismatch) = parsed
+            .type_mismatches()
+            .into_iter()
+            .find(|m| m.flag == edit.flag())
+        {
+            return Err(EditError::TypeMismatch(mismatch));
+        }
+        self.source = edited;
+        Ok(())
+    }
+}
+
+/// Replacement text for byte ranges of the source, in source order.
+type Change = (Range<usize>, String);
+
+fn changes(source: &str, edit: &Edit) -> Result<Vec<Change>, EditError> {
+    let root = cst::parse(source);
+    if let Edit::AddFlag { flag, value } = edit {
+        if find_flag(&root, flag).is_some() {
+            return Err(EditError::FlagExists(flag.clone()));
+        }
+        return Ok(vec![add_flag(source, flag, value)]);
+    }
+    let name = edit.flag();
+    let flag = find_flag(&root, name).ok_or_else(|| EditError::FlagNotFound(name.to_string()))?;
+
+    match edit {
+        Edit::AddFlag { .. } => unreachable!("handled above"),
+        Edit::RemoveFlag { .. } => Ok(vec![remove_flag(source, flag)]),
+        Edit::SetDefault { env, value, .. } => {
+            let value = value.to_string();
+            let Some(env) = env else {
+                return Ok(set_default(source, flag, &value));
+            };
+            match env_rule(flag, env) {
+                Some(rule) => Ok(set_default(source, rule, &value)),
+                None => Ok(insert_rule(
+                    source,
+                    flag,
+                    0,
+                    &format!("@env {} -> {}", env, value),
+                )),
+            }
+        }
+        Edit::InsertRule { index, rule, .. } => Ok(insert_rule(source, flag, *index, rule.trim())),
+        Edit::SetPercentage { rule, rate, .. } => set_percentage(flag, name, *rule, *rate),
+        Edit::SetAnnotation { annotation, .. } => Ok(set_annotation(source, flag, annotation)),
+        Edit::RemoveAnnotation { keyword, .. } => {
+            let keyword = format!("@{}", keyword.trim_start_matches('@'));
+            let lines: Vec<Change> = flag
+                .nodes()
+                .filter(|node| node.kind() == NodeKind::Annotation)
+                .filter(|node| node.annotation() == Some(keyword.as_str()))
+                .map(|node| (whole_lines(source, node.range()), String::new()))
+                .collect();
+            if lines.is_empty() {
+                return Err(EditError::AnnotationNotFound {
+                    flag: name.to_string(),
+                    keyword,
+                });
+            }
+            Ok(lines)
+        }
+    }
+}
+
+fn find_flag<'a>(root: &'a Node, name: &str) -> Option<&'a Node> {
+    root.nodes()
+        .find(|node| node.kind() == NodeKind::Flag && node.name() == Some(name))
+}
+
+fn child(node: &Node, kind: NodeKind) -> Option<&Node> {
+    node.nodes().find(|n| n.kind() == kind)
+}
+
+fn flag_name(flag: &Node) -> &Token {
+    flag.child_tokens()
+        .find(|t| t.kind() == TokenKind::FlagName)
+        .expect("a flag node starts at its name")
+}
+
+/// The rules of a flag's or `@env` rule's block, each with where it starts
+/// counting the annotations, like `@name`, directly above it.
+fn rules(node: &Node) -> Vec<(usize, &Node)> {
+    let Some(block) = child(node, NodeKind::Block) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    let mut annotated_from = None;
+    for node in block.nodes() {
+        match node.kind() {
+            NodeKind::Annotation => {
+                annotated_from.get_or_insert(node.range().start);
+            }
+            NodeKind::Rule => {
+                out.push((annotated_from.take().unwrap_or(node.range().start), node));
+            }
+            _ => annotated_from = None,
+        }
+    }
+    out
+}
+
+fn env_rule<'a>(flag: &'a Node, env: &str) -> Option<&'a Node> {
+    rules(flag).into_iter().map(|(_, rule)| rule).find(|rule| {
+        rule.annotation() == Some("@env")
+            && rule
+                .child_tokens()
+                .find(|t| t.kind() == TokenKind::Word)
+                .is_some_and(|t| t.text() == env)
+    })
+}
+
+/// A bare value with no condition, ending a block.
+fn is_default(rule: &Node) -> bool {
+    rule.annotation().is_none()
+        && rule
+            .nodes()
+            .next()
+            .is_some_and(|n| n.kind() == NodeKind::Value)
+}
+
+fn add_flag(source: &str, flag: &str, value: &FlagReturn) -> Change {
+    let nl = newline(source);
+    let separator = if source.trim().is_empty() || source.ends_with(&nl.repeat(2)) {
+        String::new()
+    } else if source.ends_with('\n') {
+        nl.to_string()
+    } else {
+        nl.repeat(2)
+    };
+    let end = source.len();
+    (
+        end..end,
+        format!("{}{} -> {}{}", separator, flag, value, nl),
+    )
+}
+
+/// The flag, the comment lines directly above it and, when it sits between
+/// blank lines, one of them.
+fn remove_flag(source: &str, flag: &Node) -> Change {
+    let mut range = whole_lines(source, flag.range());
+    range.start = comments_above(source, range.start);
+    let blank_above =
+        range.start == 0 || source[..range.start].ends_with(&newline(source).repeat(2));
+    let rest = &source[range.end..];
+    if blank_above {
+        if let Some(blank) = ["\n", "\r\n"].iter().find(|nl| rest.starts_with(*nl)) {
+            range.end += blank.len();
+        } else if rest.is_empty() && range.start > 0 {
+            // last in the file: drop the blank line before it instead
+            range.start -= newline(source).len();
+        }
+    }
+    (range, String::new())
+}
+
+/// `node` is a flag or an `@env` rule.
+fn set_default(source: &str, node: &Node, value: &str) -> Vec<Change> {
+    if let Some(current) = child(node, NodeKind::Value) {
+        return vec![(current.range(), value.to_string())];
+    }
+    let rules = rules(node);
+    let Some((_, last)) = rules.last() else {
+        return Vec::new();
+    };
+    if is_default(last) {
+        return vec![(last.range(), value.to_string())];
+    }
+    let end = last.range().end;
+    let indent = indentation(source, last.range().start);
+    vec![(end..end, format!("{}{}{}", newline(source), indent, value))]
+}
+
+fn insert_rule(source: &str, flag: &Node, index: usize, rule: &str) -> Vec<Change> {
+    let nl = newline(source);
+    if let Some(value) = child(flag, NodeKind::Value) {
+        // FF-name -> value: make it a block
+        let arrow = flag
+            .child_tokens()
+            .find(|t| t.kind() == TokenKind::Arrow)
+            .expect("a flag's value follows its arrow");
+        let indent = indentation(source, flag_name(flag).range().start);
+        let value = value.text();
+        let (first, second) = if index == 0 {
+            (rule, value.as_str())
+        } else {
+            (value.as_str(), rule)
+        };
+        let block = format!(
+            "{{{nl}{indent}{INDENT}{}{nl}{indent}{INDENT}{}{nl}{indent}}}",
+            first, second
+        );
+        return vec![(arrow.range().start..flag.range().end, block)];
+    }
+    let rules = rules(flag);
+    match (rules.get(index), rules.last()) {
+        (Some(&(start, _)), _) => {
+            let indent = indentation(source, start);
+            if line_start(source, start) + indent.len() != start {
+                // shares its line with what comes before
+                return vec![(start..start, format!("{} ", rule))];
+            }
+            // above the comments that describe the rule
+            let at = comments_above(source, line_start(source, start));
+            vec![(at..at, format!("{}{}{}", indent, rule, nl))]
+        }
+        (None, Some((_, last))) => {
+            let end = last.range().end;
+            let indent = indentation(source, last.range().start);
+            vec![(end..end, format!("{}{}{}", nl, indent, rule))]
+        }
+        (None, None) => Vec::new(),
+    }
+}
+
+fn set_percentage(
+    flag: &Node,
+    name: &str,
+    rule: Option<usize>,
+    rate: f64,
+) -> Result<Vec<Change>, EditError> {
+    if !(0.0..=100.0).contains(&rate) {
+        return Err(EditError::InvalidRate(rate));
+    }
+    let scope = match rule {
+        Some(index) => rules(flag)
+            .get(index)
+            .map(|&(_, rule)| rule)
+            .ok_or_else(|| EditError::RuleNotFound {
+                flag: name.to_string(),
+                index,
+            })?,
+        None => flag,
+    };
+    let tokens: Vec<&Token> = scope
+        .tokens()
+        .into_iter()
+        .filter(|t| !t.kind().is_trivia())
+        .collect();
+    // percentage ( 25%
+    let rates: Vec<&Token> = tokens
+        .windows(3)
+        .filter(|w| {
+            w[0].kind() == TokenKind::Word
+                && w[0].text().eq_ignore_ascii_case("percentage")
+                && w[1].kind() == TokenKind::LParen
+                && w[2].kind() == TokenKind::Word
+        })
+        .map(|w| w[2])
+        .collect();
+    match rates.as_slice() {
+        [] => Err(EditError::NoPercentage {
+            flag: name.to_string(),
+        }),
+        [current] => {
+            let suffix = if current.text().ends_with('%') {
+                "%"
+            } else {
+                ""
+            };
+            let rate = if rate.fract() == 0.0 {
+                format!("{:.0}", rate)
+            } else {
+                rate.to_string()
+            };
+            Ok(vec![(current.range(), format!("{}{}", rate, suffix))])
+        }
+        several => Err(EditError::AmbiguousPercentage {
+            flag: name.to_string(),
+            count: several.len(),
+        }),
+    }
+}
+
+fn set_annotation(source: &str, flag: &Node, annotation: &Annotation) -> Vec<Change> {
+    let line = annotation.to_string();
+    let mut existing = flag
+        .nodes()
+        .filter(|node| node.kind() == NodeKind::Annotation)
+        .filter(|node| node.annotation() == Some(annotation.keyword()));
+    if annotation.repeats() {
+        if existing.any(|node| node.text().trim_end() == line) {
+            return Vec::new();
+        }
+    } else if let Some(current) = existing.next() {
+        return vec![(current.range(), line)];
+    }
+    let at = flag_name(flag).range().start;
+    let indent = indentation(source, at);
+    vec![(at..at, format!("{}{}{}", line, newline(source), indent))]
+}
+
+/// Where the run of `//` comment lines directly above the line starting at
+/// `start` begins, or `start` when there are none.
+fn comments_above(source: &str, mut start: usize) -> usize {
+    while start > 0 {
+        let above = line_start(source, start - 1);
+        if !source[above..start].trim_start().starts_with("//") {
+            break;
+        }
+        start = above;
+    }
+    start
+}
+
+/// The line ending `source` uses: `\r\n` if it has any, `\n` otherwise.
+fn newline(source: &str) -> &'static str {
+    if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Byte offset where the line holding `offset` starts.
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |at| at + 1)
+}
+
+/// The spaces and tabs the line holding `offset` starts with.
+fn indentation(source: &str, offset: usize) -> &str {
+    let line = &source[line_start(source, offset)..];
+    let text = line.trim_start_matches([' ', '\t']);
+    &line[..line.len() - text.len()]
+}
+
+/// `range` widened to the start of its first line and past the line
+/// ending of its last.
+fn whole_lines(source: &str, range: Range<usize>) -> Range<usize> {
+    let end = source[range.end..]
+        .find('\n')
+        .map_or(source.len(), |at| range.end + at + 1);
+    line_start(source, range.start)..end
+}
+
+/// Parse `source` on its own. Lines of the root Flagfile can be `@include`
+/// directives, which only resolving includes understands.
+fn check(source: &str) -> Result<(), ParseError> {
+    parse_flagfile_with_segments(&without_includes(source)).map(|_| ())
+}
+
+/// `source` with `@include` lines emptied, keeping line numbers.
+fn without_includes(source: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            if line.trim_start().starts_with("@include") {
+                &line[line.trim_end_matches(['\r', '\n']).len()..]
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(source: &str, edit: Edit) -> String {
+        let mut editor = Editor::new(source);
+        editor.apply(&edit).unwrap();
+        editor.source().to_string()
+    }
+
+    fn edit_err(source: &str, edit: Edit) -> EditError {
+        let mut editor = Editor::new(source);
+        let err = editor.apply(&edit).unwrap_err();
+        assert_eq!(editor.source(), source, "a failed edit changed the source");
+        err
+    }
+
+    const BLOCK: &str = "\
+// checkout rollout
+@owner \"payments\"
+FF-checkout {
+    // pro plans first
+    plan == pro -> true // always
+    percentage(25%, userId) -> true
+    false
+}
+";
+
+    #[test]
+    fn test_set_default() {
+        assert_eq!(
+            edit(
+                "FF-a -> false // off for now\n",
+                Edit::SetDefault {
+                    flag: "FF-a".into(),
+                    env: None,
+                    value: FlagReturn::OnOff(true),
+                }
+            ),
+            "FF-a -> true // off for now\n"
+        );
+        assert_eq!(
+            edit(
+                BLOCK,
+                Edit::SetDefault {
+                    flag: "FF-checkout".into(),
+                    env: None,
+                    value: FlagReturn::OnOff(true),
+                }
+            ),
+            BLOCK.replace("    false\n", "    true\n")
+        );
+        // no default yet
+        assert_eq!(
+            edit(
+                "FF-a {\n  plan == pro -> 2\n}\n",
+                Edit::SetDefault {
+                    flag: "FF-a".into(),
+                    env: None,
+                    value: FlagReturn::Integer(1),
+                }
+            ),
+            "FF-a {\n  plan == pro -> 2\n  1\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_set_env_default() {
+        let source = "FF-a {\n    @env dev -> true\n    @env prod {\n        plan == pro -> true\n        false\n    }\n    false\n}\n";
+        let set = |env: &str| Edit::SetDefault {
+            flag: "FF-a".into(),
+            env: Some(env.into()),
+            value: FlagReturn::Str("on".into()),
+        };
+        assert_eq!(
+            edit(source, set("dev")),
+            source.replace("@env dev -> true", "@env dev -> \"on\"")
+        );
+        assert_eq!(
+            edit(source, set("prod")),
+            source.replace("        false", "        \"on\"")
+        );
+        assert_eq!(
+            edit("FF-a -> false\n", set("stage")),
+            "FF-a {\n    @env stage -> \"on\"\n    false\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_rule() {
+        let insert = |index| Edit::InsertRule {
+            flag: "FF-checkout".into(),
+            index,
+            rule: "country == NL -> true".into(),
+        };
+        // before the comment and rule it describes
+        assert_eq!(
+            edit(BLOCK, insert(0)),
+            BLOCK.replace(
+                "    // pro plans first\n",
+                "    country == NL -> true\n    // pro plans first\n"
+            )
+        );
+        assert_eq!(
+            edit(BLOCK, insert(2)),
+            BLOCK.replace("    false\n", "    country == NL -> true\n    false\n")
+        );
+        assert_eq!(
+            edit(BLOCK, insert(9)),
+            BLOCK.replace("    false\n", "    false\n    country == NL -> true\n")
+        );
+        assert_eq!(
+            edit("@owner \"a\"\nFF-checkout -> false\n", insert(0)),
+            "@owner \"a\"\nFF-checkout {\n    country == NL -> true\n    false\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_rule_before_named_rule() {
+        let source = "FF-a {\n    @name \"pro\"\n    plan == pro -> true\n    false\n}\n";
+        assert_eq!(
+            edit(
+                source,
+                Edit::InsertRule {
+                    flag: "FF-a".into(),
+                    index: 0,
+                    rule: "beta == true -> true".into(),
+                }
+            ),
+            "FF-a {\n    beta == true -> true\n    @name \"pro\"\n    plan == pro -> true\n    false\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_set_percentage() {
+        let set = |rule, rate| Edit::SetPercentage {
+            flag: "FF-checkout".into(),
+            rule,
+            rate,
+        };
+        assert_eq!(edit(BLOCK, set(None, 50.0)), BLOCK.replace("25%", "50%"));
+        assert_eq!(
+            edit(BLOCK, set(Some(1), 12.5)),
+            BLOCK.replace("25%", "12.5%")
+        );
+        assert!(matches!(
+            edit_err(BLOCK, set(Some(0), 50.0)),
+            EditError::NoPercentage { .. }
+        ));
+        assert!(matches!(
+            edit_err(BLOCK, set(Some(5), 50.0)),
+            EditError::RuleNotFound { index: 5, .. }
+        ));
+        assert!(matches!(
+            edit_err(BLOCK, set(None, 101.0)),
+            EditError::InvalidRate(_)
+        ));
+        let two = BLOCK.replace("plan == pro", "percentage(5%, orgId) and plan == pro");
+        assert!(matches!(
+            edit_err(&two, set(None, 50.0)),
+            EditError::AmbiguousPercentage { count: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_set_annotation() {
+        let set = |annotation| Edit::SetAnnotation {
+            flag: "FF-checkout".into(),
+            annotation,
+        };
+        assert_eq!(
+            edit(BLOCK, set(Annotation::Owner("growth".into()))),
+            BLOCK.replace("\"payments\"", "\"growth\"")
+        );
+        let expires = NaiveDate::from_ymd_opt(2030, 1, 31).unwrap();
+        assert_eq!(
+            edit(BLOCK, set(Annotation::Expires(expires))),
+            BLOCK.replace("FF-checkout {", "@expires 2030-01-31\nFF-checkout {")
+        );
+        assert_eq!(
+            edit(BLOCK, set(Annotation::Description("say \"hi\"".into()))),
+            BLOCK.replace("FF-checkout {", "@description 'say \"hi\"'\nFF-checkout {")
+        );
+        let test = "@test FF-checkout(plan=pro) == true\nFF-checkout -> true\n";
+        let add = set(Annotation::Test("FF-checkout(plan=pro) == true".into()));
+        assert_eq!(edit(test, add), test);
+    }
+
+    #[test]
+    fn test_remove_annotation() {
+        assert_eq!(
+            edit(
+                BLOCK,
+                Edit::RemoveAnnotation {
+                    flag: "FF-checkout".into(),
+                    keyword: "owner".into(),
+                }
+            ),
+            BLOCK.replace("@owner \"payments\"\n", "")
+        );
+        assert!(matches!(
+            edit_err(
+                BLOCK,
+                Edit::RemoveAnnotation {
+                    flag: "FF-checkout".into(),
+                    keyword: "@ticket".into(),
+                }
+            ),
+            EditError::AnnotationNotFound { .. }
+        ));
+    }
+
+    #[test]
+    fn test_remove_flag() {
+        let source = format!("// ─── flags ───\n\nFF-a -> true\n\n{}\nFF-b -> 1\n", BLOCK);
+        let remove = |flag: &str| Edit::RemoveFlag { flag: flag.into() };
+        assert_eq!(
+            edit(&source, remove("FF-checkout")),
+            "// ─── flags ───\n\nFF-a -> true\n\nFF-b -> 1\n"
+        );
+        assert_eq!(
+            edit(&source, remove("FF-a")),
+            format!("// ─── flags ───\n\n{}\nFF-b -> 1\n", BLOCK)
+        );
+        assert_eq!(
+            edit(&source, remove("FF-b")),
+            format!("// ─── flags ───\n\nFF-a -> true\n\n{}", BLOCK)
+        );
+        assert!(matches!(
+            edit_err(&source, remove("FF-c")),
+            EditError::FlagNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_edits_keep_crlf_line_endings() {
+        let crlf = |text: &str| text.replace('\n', "\r\n");
+        let source = format!(
+            "FF-a -> true\n\n{}\nFF-b {{\n  plan == pro -> 2\n}}\n",
+            BLOCK
+        );
+        let edits = [
+            Edit::SetDefault {
+                flag: "FF-b".into(),
+                env: None,
+                value: FlagReturn::Integer(1),
+            },
+            Edit::InsertRule {
+                flag: "FF-checkout".into(),
+                index: 0,
+                rule: "country == NL -> true".into(),
+            },
+            Edit::InsertRule {
+                flag: "FF-checkout".into(),
+                index: 9,
+                rule: "country == NL -> true".into(),
+            },
+            Edit::InsertRule {
+                flag: "FF-a".into(),
+                index: 0,
+                rule: "country == NL -> true".into(),
+            },
+            Edit::SetAnnotation {
+                flag: "FF-checkout".into(),
+                annotation: Annotation::Owner("growth".into()),
+            },
+            Edit::SetAnnotation {
+                flag: "FF-a".into(),
+                annotation: Annotation::Owner("growth".into()),
+            },
+            Edit::AddFlag {
+                flag: "FF-c".into(),
+                value: FlagReturn::OnOff(false),
+            },
+            Edit::RemoveFlag {
+                flag: "FF-a".into(),
+            },
+            Edit::RemoveFlag {
+                flag: "FF-b".into(),
+            },
+        ];
+        for e in edits {
+            let expected = crlf(&edit(&source, e.clone()));
+            assert_eq!(edit(&crlf(&source), e), expected);
+        }
+    }
+
+    #[test]
+    fn test_add_flag() {
+        let add = |flag: &str| Edit::AddFlag {
+            flag: flag.into(),
+            value: FlagReturn::Float(0.5),
+        };
+        assert_eq!(edit("", add("FF-a")), "FF-a -> 0.5\n");
+        assert_eq!(
+            edit("FF-b -> true\n", add("FF-a")),
+            "FF-b -> true\n\nFF-a -> 0.5\n"
+        );
+        assert!(matches!(
+            edit_err("FF-a -> true\n", add("FF-a")),
+            EditError::FlagExists(_)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_broken_results() {
+        assert!(matches!(
+            edit_err(
+                BLOCK,
+                Edit::InsertRule {
+                    flag: "FF-checkout".into(),
+                    index: 0,
+                    rule: "plan == -> true".into(),
+                }
+            ),
+            EditError::Invalid(_)
+        ));
+        assert!(matches!(
+            edit_err(
+                "@type bool\nFF-a -> true\n",
+                Edit::SetDefault {
+                    flag: "FF-a".into(),
+                    env: None,
+                    value: FlagReturn::Integer(3),
+                }
+            ),
+            EditError::TypeMismatch(_)
+        ));
+        assert!(matches!(
+            edit_err(
+                "FF-a {\n",
+                Edit::RemoveFlag {
+                    flag: "FF-a".into()
+                }
+            ),
+            EditError::Unparsable(_)
+        ));
+    }
+
+    #[test]
+    fn test_keeps_includes() {
+        let source = "@include \"flags/*.ff\"\n\nFF-a -> true\n";
+        assert_eq!(
+            edit(
+                source,
+                Edit::SetDefault {
+                    flag: "FF-a".into(),
+                    env: None,
+                    value: FlagReturn::OnOff(false),
+                }
+            ),
+            source.replace("true", "false")
+        );
+    }
+}
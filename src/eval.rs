@@ -1,31 +1,55 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
-use chrono::Local;
-
-use regex::Regex;
+use chrono::{DateTime, TimeDelta, Utc};
 
 use sha1::{Digest, Sha1};
 
-use crate::ast::{ArrayOp, AstNode, Atom, ComparisonOp, FnCall, LogicOp, MatchOp};
+use crate::ast::{
+    midnight, parse_typed, ArithOp, ArrayOp, AstNode, Atom, ComparisonOp, FnCall, LogicOp, MatchOp,
+    SegmentDef,
+};
+use crate::parse::{parse_path, PathSegment};
+use crate::parse_flagfile::{FlagReturn, Split};
 
-pub type Segments = HashMap<String, AstNode>;
+pub type Segments = HashMap<String, SegmentDef>;
 
 pub type Context<'a> = HashMap<&'a str, Atom>;
 
+/// Look up an attribute. A name that is not itself a key is read as a path
+/// into nested values: `user.company.tier`, `org["plan"]` or `items[0]`.
+pub fn resolve<'a>(context: &'a Context, name: &str) -> Option<&'a Atom> {
+    if let Some(value) = context.get(name) {
+        return Some(value);
+    }
+    let (root, path) = parse_path(name)?;
+    if path.is_empty() {
+        return None;
+    }
+    path.into_iter()
+        .try_fold(context.get(root)?, |value, step| match (value, step) {
+            (Atom::Object(fields), PathSegment::Key(key)) => fields.get(key),
+            (Atom::List(items), PathSegment::Index(idx)) => items.get(idx),
+            _ => None,
+        })
+}
+
 fn get_variable_value_from_context<'a>(
     variable: &'a AstNode,
     context: &'a Context,
+    clock: &Clock,
 ) -> Option<Atom> {
     let res = match variable {
-        AstNode::Variable(Atom::Variable(v)) => context.get(v.as_str()),
-        AstNode::Constant(Atom::Variable(v)) => context.get(v.as_str()),
+        AstNode::Variable(Atom::Variable(v)) => resolve(context, v),
+        AstNode::Constant(Atom::Variable(v)) => resolve(context, v),
         AstNode::Function(op, v) => {
             match op {
                 FnCall::Now => {
-                    return Some(Atom::DateTime(Local::now().naive_local()));
+                    return Some(Atom::DateTime(clock.now()));
                 }
                 _ => {
-                    let value = get_variable_value_from_context(v, context);
+                    let value = get_variable_value_from_context(v, context, clock);
                     if let Some(v) = value {
                         let vv = match op {
                             FnCall::Upper => Atom::String(v.to_string().to_uppercase()),
@@ -42,7 +66,7 @@ fn get_variable_value_from_context<'a>(
             for arg in args {
                 match arg {
                     AstNode::Variable(Atom::Variable(v)) => {
-                        if let Some(val) = context.get(v.as_str()) {
+                        if let Some(val) = resolve(context, v) {
                             return Some(val.clone());
                         }
                     }
@@ -59,209 +83,607 @@ fn get_variable_value_from_context<'a>(
     res.cloned()
 }
 
-pub fn eval_with_segments<'a>(
+/// Default limit on how deeply `segment(...)` references may nest.
+pub const DEFAULT_MAX_SEGMENT_DEPTH: usize = 32;
+
+/// Why an expression could not be evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// A variable the expression needs is not in the context.
+    MissingVariable(String),
+    /// Operands that cannot be compared, e.g. `"abc" > 5`.
+    TypeMismatch(String),
+    UnknownSegment(String),
+    /// A `fn:` function that is not registered in [`EvalOptions::functions`].
+    UnknownFunction(String),
+    /// Segment references nested deeper than [`EvalOptions::max_segment_depth`].
+    RecursionLimit(usize),
+    /// An AST shape the evaluator cannot handle.
+    InvalidExpression(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::MissingVariable(v) => write!(f, "variable '{v}' is not set in the context"),
+            EvalError::TypeMismatch(msg) => write!(f, "type mismatch: {msg}"),
+            EvalError::UnknownSegment(name) => write!(f, "unknown segment '{name}'"),
+            EvalError::UnknownFunction(name) => write!(f, "unknown function 'fn:{name}'"),
+            EvalError::RecursionLimit(depth) => {
+                write!(f, "segment references nested deeper than {depth}")
+            }
+            EvalError::InvalidExpression(msg) => write!(f, "invalid expression: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Where `NOW()`, `ramp()` and `@active` windows get the current time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Clock {
+    #[default]
+    System,
+    /// Always this instant, to evaluate time-dependent rules
+    /// deterministically.
+    Fixed(DateTime<Utc>),
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Fixed(at) => *at,
+        }
+    }
+}
+
+/// A host function, called from rules as `fn:name(args...)`.
+pub type HostFn = dyn Fn(&[Atom]) -> Atom + Send + Sync;
+
+/// Functions the host application provides to rules, for targeting on data
+/// only it has, such as a geo lookup: `fn:region(ip) == "eu"`. Arguments are
+/// evaluated before the call; one that can't be, like a missing variable,
+/// fails the condition without calling the function.
+#[derive(Clone, Default)]
+pub struct Functions(HashMap<String, Arc<HostFn>>);
+
+impl Functions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `f` callable as `fn:name`, replacing any function already
+    /// registered under that name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        f: impl Fn(&[Atom]) -> Atom + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.0.insert(name.into(), Arc::new(f));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HostFn> {
+        self.0.get(name).map(Arc::as_ref)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Registered names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.0.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl fmt::Debug for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// Controls how expressions are evaluated.
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    /// Surface errors (missing variables, type mismatches, unknown segments)
+    /// instead of treating the failing condition as `false`.
+    pub strict: bool,
+    pub max_segment_depth: usize,
+    pub clock: Clock,
+    /// What `fn:name(...)` in a rule calls.
+    pub functions: Functions,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            max_segment_depth: DEFAULT_MAX_SEGMENT_DEPTH,
+            clock: Clock::System,
+            functions: Functions::default(),
+        }
+    }
+}
+
+impl EvalOptions {
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Self::default()
+        }
+    }
+}
+
+pub fn eval_with_segments(
     expr: &AstNode,
     context: &Context,
     flag_name: Option<&str>,
     segments: &Segments,
-) -> Result<bool, &'a str> {
-    eval_impl(expr, context, flag_name, Some(segments))
+) -> Result<bool, EvalError> {
+    eval_impl(
+        expr,
+        context,
+        flag_name,
+        Some(segments),
+        &EvalOptions::default(),
+        &mut None,
+        0,
+    )
+}
+
+pub fn eval(expr: &AstNode, context: &Context, flag_name: Option<&str>) -> Result<bool, EvalError> {
+    eval_impl(
+        expr,
+        context,
+        flag_name,
+        None,
+        &EvalOptions::default(),
+        &mut None,
+        0,
+    )
+}
+
+/// Evaluate with explicit options. In lenient mode (the default) a condition
+/// that fails to evaluate is `false`; only [`EvalError::RecursionLimit`] is
+/// returned. In strict mode every error is returned to the caller.
+pub fn eval_with_options(
+    expr: &AstNode,
+    context: &Context,
+    flag_name: Option<&str>,
+    segments: Option<&Segments>,
+    options: &EvalOptions,
+) -> Result<bool, EvalError> {
+    eval_impl(expr, context, flag_name, segments, options, &mut None, 0)
 }
 
-pub fn eval<'a>(
+/// [`eval_with_options`], also keeping in `skipped` the first error that
+/// lenient mode counted as `false`, so the rule loop can report it.
+pub(crate) fn eval_noting_errors(
     expr: &AstNode,
     context: &Context,
     flag_name: Option<&str>,
-) -> Result<bool, &'a str> {
-    eval_impl(expr, context, flag_name, None)
+    segments: Option<&Segments>,
+    options: &EvalOptions,
+    skipped: &mut Option<EvalError>,
+) -> Result<bool, EvalError> {
+    eval_impl(expr, context, flag_name, segments, options, skipped, 0)
+}
+
+/// Human-readable name of the value an expression reads, for error messages.
+pub(crate) fn describe_operand(node: &AstNode) -> String {
+    match node {
+        AstNode::Variable(atom) | AstNode::Constant(atom) => atom.to_string(),
+        AstNode::Function(FnCall::Upper, inner) => format!("upper({})", describe_operand(inner)),
+        AstNode::Function(FnCall::Lower, inner) => format!("lower({})", describe_operand(inner)),
+        AstNode::Function(FnCall::Now, _) => "now()".to_string(),
+        AstNode::Coalesce(args) => {
+            let args: Vec<String> = args.iter().map(describe_operand).collect();
+            format!("coalesce({})", args.join(", "))
+        }
+        AstNode::Arithmetic(lhs, op, rhs) => {
+            let side = |node: &AstNode| match node {
+                AstNode::Arithmetic(..) => format!("({})", describe_operand(node)),
+                other => describe_operand(other),
+            };
+            format!("{} {op} {}", side(lhs), side(rhs))
+        }
+        AstNode::Call(name, args) => {
+            let args: Vec<String> = args.iter().map(describe_operand).collect();
+            format!("fn:{name}({})", args.join(", "))
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+fn lookup(node: &AstNode, context: &Context, options: &EvalOptions) -> Result<Atom, EvalError> {
+    if let AstNode::Call(name, args) = node {
+        return call(name, args, context, options);
+    }
+    get_variable_value_from_context(node, context, &options.clock)
+        .ok_or_else(|| EvalError::MissingVariable(describe_operand(node)))
+}
+
+/// Call a registered `fn:` function with its arguments' values.
+fn call(
+    name: &str,
+    args: &[AstNode],
+    context: &Context,
+    options: &EvalOptions,
+) -> Result<Atom, EvalError> {
+    let f = options
+        .functions
+        .get(name)
+        .ok_or_else(|| EvalError::UnknownFunction(name.to_string()))?;
+    let args = args
+        .iter()
+        .map(|arg| operand_value(arg, context, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(f(&args))
+}
+
+/// One side of a comparison: a literal as written, where a bare word is
+/// text, or a value read from the context or computed from it.
+fn operand_value(
+    node: &AstNode,
+    context: &Context,
+    options: &EvalOptions,
+) -> Result<Atom, EvalError> {
+    match node {
+        AstNode::Constant(atom) => Ok(atom.clone()),
+        AstNode::Arithmetic(lhs, op, rhs) => arithmetic(
+            &operand_value(lhs, context, options)?,
+            *op,
+            &operand_value(rhs, context, options)?,
+        ),
+        AstNode::Variable(_) | AstNode::Function(..) | AstNode::Coalesce(_) | AstNode::Call(..) => {
+            lookup(node, context, options)
+        }
+        other => Err(EvalError::InvalidExpression(format!(
+            "{:?} is not a value",
+            other
+        ))),
+    }
+}
+
+/// `lhs op rhs` on numbers, dates and durations. Whole numbers stay whole
+/// unless a division leaves a remainder; two instants differ by a duration,
+/// and a duration moves an instant. A date is its midnight UTC.
+pub(crate) fn arithmetic(lhs: &Atom, op: ArithOp, rhs: &Atom) -> Result<Atom, EvalError> {
+    use ArithOp::*;
+
+    let is_zero = match rhs {
+        Atom::Number(n) => *n == 0,
+        Atom::Float(f) => *f == 0.0,
+        Atom::Duration(d) => d.is_zero(),
+        _ => false,
+    };
+    if matches!(op, Div | Rem) && is_zero {
+        return Err(EvalError::InvalidExpression(format!(
+            "division by zero in {lhs} {op} {rhs}"
+        )));
+    }
+    let instant = |atom: &Atom| match atom {
+        Atom::DateTime(dt) => Some(*dt),
+        Atom::Date(d) => Some(midnight(d)),
+        Atom::String(s) => match parse_typed(s)? {
+            Atom::DateTime(dt) => Some(dt),
+            Atom::Date(d) => Some(midnight(&d)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let float = |atom: &Atom| match atom {
+        Atom::Number(n) => Some(*n as f64),
+        Atom::Float(f) => Some(*f),
+        _ => None,
+    };
+    let scale = |d: &TimeDelta, by: f64| {
+        let ms = (d.num_milliseconds() as f64 * by).round();
+        (ms.abs() < i64::MAX as f64)
+            .then(|| TimeDelta::try_milliseconds(ms as i64))
+            .flatten()
+            .map(Atom::Duration)
+    };
+
+    let result = match (lhs, op, rhs) {
+        (Atom::Number(a), Add, Atom::Number(b)) => a.checked_add(*b).map(Atom::Number),
+        (Atom::Number(a), Sub, Atom::Number(b)) => a.checked_sub(*b).map(Atom::Number),
+        (Atom::Number(a), Mul, Atom::Number(b)) => a.checked_mul(*b).map(Atom::Number),
+        (Atom::Number(a), Rem, Atom::Number(b)) => a.checked_rem(*b).map(Atom::Number),
+        // `i64::MIN / -1` has no remainder but overflows, like `*` can
+        (Atom::Number(a), Div, Atom::Number(b)) if a.checked_rem(*b).is_none_or(|r| r == 0) => {
+            a.checked_div(*b).map(Atom::Number)
+        }
+        (Atom::Number(_) | Atom::Float(_), _, Atom::Number(_) | Atom::Float(_)) => {
+            let (a, b) = (
+                float(lhs).unwrap_or_default(),
+                float(rhs).unwrap_or_default(),
+            );
+            let value = match op {
+                Add => a + b,
+                Sub => a - b,
+                Mul => a * b,
+                Div => a / b,
+                Rem => a % b,
+            };
+            value.is_finite().then_some(Atom::Float(value))
+        }
+        (Atom::Duration(a), Add, Atom::Duration(b)) => a.checked_add(b).map(Atom::Duration),
+        (Atom::Duration(a), Sub, Atom::Duration(b)) => a.checked_sub(b).map(Atom::Duration),
+        (Atom::Duration(a), Div, Atom::Duration(b)) => Some(Atom::Float(
+            a.num_milliseconds() as f64 / b.num_milliseconds() as f64,
+        )),
+        (Atom::Duration(d), Mul, n) | (n, Mul, Atom::Duration(d)) if float(n).is_some() => {
+            scale(d, float(n).unwrap_or_default())
+        }
+        (Atom::Duration(d), Div, n) if float(n).is_some() => {
+            scale(d, 1.0 / float(n).unwrap_or_default())
+        }
+        (Atom::Duration(d), Add, at) | (at, Add, Atom::Duration(d)) if instant(at).is_some() => {
+            instant(at)
+                .and_then(|at| at.checked_add_signed(*d))
+                .map(Atom::DateTime)
+        }
+        (at, Sub, Atom::Duration(d)) if instant(at).is_some() => instant(at)
+            .and_then(|at| at.checked_sub_signed(*d))
+            .map(Atom::DateTime),
+        (a, Sub, b) if instant(a).is_some() && instant(b).is_some() => instant(a)
+            .zip(instant(b))
+            .map(|(a, b)| Atom::Duration(a - b)),
+        _ => {
+            return Err(EvalError::TypeMismatch(format!(
+                "cannot compute {lhs} {op} {rhs}"
+            )))
+        }
+    };
+    result.ok_or_else(|| EvalError::InvalidExpression(format!("{lhs} {op} {rhs} is out of range")))
+}
+
+pub(crate) fn compare(left: &Atom, op: &ComparisonOp, right: &Atom) -> Result<bool, EvalError> {
+    use std::cmp::Ordering;
+
+    let ord = match op {
+        ComparisonOp::Eq => return Ok(left == right),
+        ComparisonOp::NotEq => return Ok(left != right),
+        _ => left.partial_cmp(right).ok_or_else(|| {
+            EvalError::TypeMismatch(format!("cannot compare {left} {op} {right}"))
+        })?,
+    };
+    Ok(match op {
+        ComparisonOp::More => ord == Ordering::Greater,
+        ComparisonOp::MoreEq => ord != Ordering::Less,
+        ComparisonOp::Less => ord == Ordering::Less,
+        ComparisonOp::LessEq => ord != Ordering::Greater,
+        ComparisonOp::Eq | ComparisonOp::NotEq => unreachable!(),
+    })
+}
+
+/// The rate, in percent, a `ramp()` is at: `from` until `start`, `to` from
+/// `end` on and linear in between.
+pub(crate) fn ramp_rate(
+    from: f64,
+    to: f64,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    at: DateTime<Utc>,
+) -> f64 {
+    if at <= start {
+        return from;
+    }
+    if at >= end {
+        return to;
+    }
+    let elapsed = (at - start).num_milliseconds() as f64;
+    let total = (end - start).num_milliseconds() as f64;
+    from + (to - from) * elapsed / total
+}
+
+/// Bucket out of 100 000 for `percentage()`, `ramp()` and `split()`: the
+/// first 15 hex digits of the SHA-1 of `flag.key` or `flag.salt.key`.
+fn bucket(flag_name: Option<&str>, salt: Option<&str>, key: &Atom) -> u64 {
+    let flag = flag_name.unwrap_or("unknown");
+    let input = match salt {
+        Some(s) => format!("{}.{}.{}", flag, s, key),
+        None => format!("{}.{}", flag, key),
+    };
+
+    let mut hasher = Sha1::new();
+    hasher.update(input.as_bytes());
+    let hex = format!("{:x}", hasher.finalize());
+    u64::from_str_radix(&hex[..15], 16).unwrap_or(0) % 100_000
+}
+
+/// The arm of `split` the context's bucket falls in.
+pub fn eval_split<'a>(
+    split: &'a Split,
+    context: &Context,
+    flag_name: Option<&str>,
+) -> Result<&'a FlagReturn, EvalError> {
+    let key = lookup(&split.field, context, &EvalOptions::default())?;
+    Ok(split.arm(bucket(flag_name, split.salt.as_deref(), &key)))
 }
 
-fn eval_impl<'a>(
+fn eval_impl(
     expr: &AstNode,
     context: &Context,
     flag_name: Option<&str>,
     segments: Option<&Segments>,
-) -> Result<bool, &'a str> {
+    options: &EvalOptions,
+    skipped: &mut Option<EvalError>,
+    depth: usize,
+) -> Result<bool, EvalError> {
+    let clock = &options.clock;
     let result = match expr {
         // true || false
-        AstNode::Constant(var) => {
-            let mut result = false;
-            if let Atom::Boolean(v) = var {
-                result = *v;
-            }
-            if let Atom::Variable(_v) = var {
-                let context_val = get_variable_value_from_context(expr, context);
-                if let Some(Atom::Boolean(inner)) = context_val {
-                    result = inner;
-                }
-            }
-            result
-        }
+        AstNode::Constant(var) => match var {
+            Atom::Boolean(v) => Ok(*v),
+            Atom::Variable(_) => match lookup(expr, context, options) {
+                Ok(Atom::Boolean(inner)) => Ok(inner),
+                Ok(other) => Err(EvalError::TypeMismatch(format!(
+                    "{} is {other}, not a boolean",
+                    describe_operand(expr)
+                ))),
+                Err(e) => Err(e),
+            },
+            other => Err(EvalError::TypeMismatch(format!("{other} is not a boolean"))),
+        },
         // a == 3
         // a < 3
-        AstNode::Compare(var, op, val) => {
-            let context_val = get_variable_value_from_context(var, context);
-            let val_content = match val.as_ref() {
-                AstNode::Constant(a) => Some(a),
-                _ => None,
-            }
-            .unwrap();
-
-            if let Some(c_val) = &context_val {
-                match op {
-                    ComparisonOp::More => c_val > val_content,
-                    ComparisonOp::MoreEq => c_val >= val_content,
-                    ComparisonOp::Less => c_val < val_content,
-                    ComparisonOp::LessEq => c_val <= val_content,
-                    ComparisonOp::Eq => c_val == val_content,
-                    ComparisonOp::NotEq => c_val != val_content,
-                }
-            } else {
-                false
-            }
-        }
+        // a - b > 3d
+        AstNode::Compare(lhs, op, rhs) => operand_value(lhs, context, options)
+            .and_then(|l| compare(&l, op, &operand_value(rhs, context, options)?)),
         // x in (1, 2, 3) OR "value" in variable
         AstNode::Array(left_expr, op, right_expr) => {
             // Case 1: variable in (literal_list)
             if let AstNode::List(vec_list) = right_expr.as_ref() {
-                let var_value = get_variable_value_from_context(left_expr, context);
-                if let Some(search_value) = &var_value {
+                lookup(left_expr, context, options).map(|search_value| {
+                    let found = vec_list.iter().any(|i| &search_value == i);
                     match op {
-                        ArrayOp::In => {
-                            for i in vec_list.iter() {
-                                if search_value == i {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        ArrayOp::NotIn => {
-                            let found = vec_list.iter().any(|i| search_value == i);
-                            return Ok(!found);
-                        }
+                        ArrayOp::In => found,
+                        ArrayOp::NotIn => !found,
                     }
-                }
-                false
+                })
             }
             // Case 2: "literal" in variable (variable resolves to List in context)
             else {
                 let search_value = match left_expr.as_ref() {
                     AstNode::Constant(atom) if !matches!(atom, Atom::Variable(_)) => {
-                        Some(atom.clone())
+                        Ok(atom.clone())
                     }
-                    _ => get_variable_value_from_context(left_expr, context),
+                    _ => lookup(left_expr, context, options),
                 };
-                let list_value = get_variable_value_from_context(right_expr, context);
-                if let (Some(needle), Some(Atom::List(items))) = (&search_value, &list_value) {
-                    match op {
-                        ArrayOp::In => items.iter().any(|item| needle == item),
-                        ArrayOp::NotIn => !items.iter().any(|item| needle == item),
+                search_value.and_then(|needle| match lookup(right_expr, context, options)? {
+                    Atom::List(items) => {
+                        let found = items.iter().any(|item| &needle == item);
+                        Ok(match op {
+                            ArrayOp::In => found,
+                            ArrayOp::NotIn => !found,
+                        })
                     }
-                } else {
-                    false
-                }
+                    other => Err(EvalError::TypeMismatch(format!(
+                        "{} is {other}, not a list",
+                        describe_operand(right_expr)
+                    ))),
+                })
             }
         }
-        AstNode::Match(var, op, rhs) => {
-            let context_val = get_variable_value_from_context(var, context);
-            if let Some(c_val) = &context_val {
+        AstNode::Match(var, op, rhs) => match rhs.as_ref() {
+            AstNode::Constant(rhs_atom) => lookup(var, context, options).and_then(|c_val| {
                 let haystack = c_val.to_string();
-                let rhs_atom = match rhs.as_ref() {
-                    AstNode::Constant(a) => a,
-                    _ => return Ok(false),
-                };
                 let needle = match rhs_atom {
-                    Atom::Regex(pattern) => {
-                        let re_matched = match Regex::new(pattern) {
-                            Ok(re) => re.is_match(&haystack),
-                            Err(_) => false,
+                    Atom::Regex(re) => {
+                        let re_matched = re.is_match(&haystack);
+                        return match op {
+                            MatchOp::Contains => Ok(re_matched),
+                            MatchOp::NotContains => Ok(!re_matched),
+                            other => Err(EvalError::TypeMismatch(format!(
+                                "regex {rhs_atom} only supports ~ and !~, not {other}"
+                            ))),
                         };
-                        return Ok(match op {
-                            MatchOp::Contains => re_matched,
-                            MatchOp::NotContains => !re_matched,
-                            _ => false,
-                        });
                     }
                     other => other.to_string(),
                 };
-                match op {
+                Ok(match op {
                     MatchOp::Contains => haystack.contains(&needle),
                     MatchOp::NotContains => !haystack.contains(&needle),
                     MatchOp::StartsWith => haystack.starts_with(&needle),
                     MatchOp::NotStartsWith => !haystack.starts_with(&needle),
                     MatchOp::EndsWith => haystack.ends_with(&needle),
                     MatchOp::NotEndsWith => !haystack.ends_with(&needle),
-                }
-            } else {
-                false
-            }
-        }
+                })
+            }),
+            other => Err(EvalError::InvalidExpression(format!(
+                "right side of match must be a constant, got {:?}",
+                other
+            ))),
+        },
         AstNode::Logic(expr1, op, expr2) => {
-            let expr1_eval = eval_impl(expr1, context, flag_name, segments).unwrap();
-            let expr2_eval = eval_impl(expr2, context, flag_name, segments).unwrap();
-            match op {
-                LogicOp::And => expr1_eval && expr2_eval,
-                LogicOp::Or => expr1_eval || expr2_eval,
-            }
+            let lhs = eval_impl(expr1, context, flag_name, segments, options, skipped, depth)?;
+            return match (op, lhs) {
+                (LogicOp::And, false) => Ok(false),
+                (LogicOp::Or, true) => Ok(true),
+                _ => eval_impl(expr2, context, flag_name, segments, options, skipped, depth),
+            };
         }
         AstNode::Scope { expr, negate } => {
-            let res = eval_impl(expr, context, flag_name, segments).unwrap();
-            match negate {
-                true => !res,
-                false => res,
-            }
+            let res = eval_impl(expr, context, flag_name, segments, options, skipped, depth)?;
+            return Ok(res != *negate);
         }
-        AstNode::Segment(name) => {
-            if let Some(segs) = segments {
-                if let Some(seg_expr) = segs.get(name.as_str()) {
-                    eval_impl(seg_expr, context, flag_name, segments).unwrap_or(false)
-                } else {
-                    false
+        AstNode::Segment(name, args) => match segments.and_then(|segs| segs.get(name.as_str())) {
+            Some(def) => match def.instantiate(name, args) {
+                Ok(body) => {
+                    if depth >= options.max_segment_depth {
+                        return Err(EvalError::RecursionLimit(options.max_segment_depth));
+                    }
+                    return eval_impl(
+                        &body,
+                        context,
+                        flag_name,
+                        segments,
+                        options,
+                        skipped,
+                        depth + 1,
+                    );
                 }
-            } else {
-                false
-            }
-        }
-        AstNode::NullCheck { variable, is_null } => {
-            let val = get_variable_value_from_context(variable, context);
-            if *is_null {
-                val.is_none()
-            } else {
-                val.is_some()
-            }
-        }
-        AstNode::Percentage { rate, field, salt } => {
-            let bucket_key = get_variable_value_from_context(field, context);
-            let bucket_key_str = match bucket_key {
-                Some(v) => v.to_string(),
-                None => return Ok(false),
-            };
-
-            let flag = flag_name.unwrap_or("unknown");
-
-            let input = match salt {
-                Some(s) => format!("{}.{}.{}", flag, s, bucket_key_str),
-                None => format!("{}.{}", flag, bucket_key_str),
-            };
-
-            let mut hasher = Sha1::new();
-            hasher.update(input.as_bytes());
-            let hash = hasher.finalize();
-            let hex = format!("{:x}", hash);
-
-            let substr = &hex[..15];
-            let value = u64::from_str_radix(substr, 16).unwrap_or(0);
-            let bucket = value % 100_000;
-            let threshold = (rate * 1000.0) as u64;
-            bucket < threshold
-        }
-        _ => false,
+                Err(msg) => Err(EvalError::InvalidExpression(msg)),
+            },
+            None => Err(EvalError::UnknownSegment(name.clone())),
+        },
+        AstNode::NullCheck { variable, is_null } => match lookup(variable, context, options) {
+            Ok(_) => Ok(!*is_null),
+            Err(EvalError::MissingVariable(_)) => Ok(*is_null),
+            Err(e) => Err(e),
+        },
+        AstNode::Percentage { rate, field, salt } => lookup(field, context, options)
+            .map(|key| bucket(flag_name, salt.as_deref(), &key) < (rate * 1000.0) as u64),
+        AstNode::Ramp {
+            from,
+            to,
+            start,
+            end,
+            field,
+            salt,
+        } => lookup(field, context, options).map(|key| {
+            let rate = ramp_rate(*from, *to, *start, *end, clock.now());
+            bucket(flag_name, salt.as_deref(), &key) < (rate * 1000.0) as u64
+        }),
+        AstNode::Active(window) => Ok(window.contains(clock.now())),
+        // fn:entitled(accountId, "sso")
+        AstNode::Call(..) => lookup(expr, context, options).and_then(|value| match value {
+            Atom::Boolean(b) => Ok(b),
+            other => Err(EvalError::TypeMismatch(format!(
+                "{} is {other}, not a boolean",
+                describe_operand(expr)
+            ))),
+        }),
+        other => Err(EvalError::InvalidExpression(format!(
+            "{:?} is not a condition",
+            other
+        ))),
     };
-    Ok(result)
+    // Lenient mode keeps the historical behaviour: a condition that can't be
+    // evaluated is simply false. The first such error is kept for the caller.
+    match result {
+        Err(e) if !options.strict => {
+            skipped.get_or_insert(e);
+            Ok(false)
+        }
+        other => other,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{ast::Atom, parse::parse};
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::{
+        ast::{ActiveWindow, Atom, Span},
+        parse::parse,
+    };
 
     use super::*;
 
@@ -403,6 +825,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_comparison_expr_eval() {
         let context = HashMap::from([
             ("a", Atom::Number(3)),
@@ -635,7 +1058,9 @@ mod tests {
     fn testing_datetime_comparison_evaluation() {
         use chrono::NaiveDateTime;
 
-        let dt = NaiveDateTime::parse_from_str("2025-06-15T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let dt = NaiveDateTime::parse_from_str("2025-06-15T12:00:00", "%Y-%m-%dT%H:%M:%S")
+            .unwrap()
+            .and_utc();
 
         // DateTime > DateTime
         let (_i, expr) = parse("ts > 2025-06-15T09:00:00Z").unwrap();
@@ -666,8 +1091,9 @@ mod tests {
         );
 
         // DateTime outside range
-        let late_dt =
-            NaiveDateTime::parse_from_str("2025-06-15T20:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let late_dt = NaiveDateTime::parse_from_str("2025-06-15T20:00:00", "%Y-%m-%dT%H:%M:%S")
+            .unwrap()
+            .and_utc();
         assert_eq!(
             false,
             eval(
@@ -691,7 +1117,9 @@ mod tests {
         use chrono::NaiveDateTime;
 
         // DateTime compared with Date (Date treated as midnight)
-        let dt = NaiveDateTime::parse_from_str("2025-06-15T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let dt = NaiveDateTime::parse_from_str("2025-06-15T12:00:00", "%Y-%m-%dT%H:%M:%S")
+            .unwrap()
+            .and_utc();
         let (_i, expr) = parse("ts > 2025-06-15").unwrap();
         assert_eq!(
             true,
@@ -699,8 +1127,9 @@ mod tests {
         );
 
         // DateTime at midnight == Date
-        let midnight =
-            NaiveDateTime::parse_from_str("2025-06-15T00:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let midnight = NaiveDateTime::parse_from_str("2025-06-15T00:00:00", "%Y-%m-%dT%H:%M:%S")
+            .unwrap()
+            .and_utc();
         let (_i, expr) = parse("ts == 2025-06-15").unwrap();
         assert_eq!(
             true,
@@ -1180,6 +1609,109 @@ mod tests {
         assert_eq!(r1, r2);
     }
 
+    #[test]
+    fn test_split_uses_percentage_buckets() {
+        // Same vectors as below: user-123 is in bucket 46118, user-456 in
+        // 69367 and alice, salted with exp1, in 77285.
+        let split = |salt: Option<&str>| Split {
+            field: AstNode::Variable(Atom::Variable("userId".into())),
+            salt: salt.map(str::to_string),
+            arms: vec![
+                (FlagReturn::Str("a".into()), 50.0),
+                (FlagReturn::Str("b".into()), 25.0),
+                (FlagReturn::Str("c".into()), 25.0),
+            ],
+        };
+        let arm = |split: &Split, user: &str| {
+            let ctx = HashMap::from([("userId", Atom::String(user.into()))]);
+            eval_split(split, &ctx, Some("FF-test-rollout"))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(arm(&split(None), "user-123"), "\"a\"");
+        assert_eq!(arm(&split(None), "user-456"), "\"b\"");
+        assert_eq!(arm(&split(Some("exp1")), "alice"), "\"c\"");
+        assert!(matches!(
+            eval_split(&split(None), &HashMap::new(), Some("FF-test-rollout")),
+            Err(EvalError::MissingVariable(_))
+        ));
+    }
+
+    #[test]
+    fn test_ramp_rate_is_linear_between_start_and_end() {
+        let at = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+                .unwrap()
+                .and_utc()
+        };
+        let (start, end) = (at("2026-11-01T00:00"), at("2026-11-11T00:00"));
+        let rate = |now: &str| ramp_rate(10.0, 60.0, start, end, at(now));
+        assert_eq!(rate("2026-10-01T00:00"), 10.0);
+        assert_eq!(rate("2026-11-01T00:00"), 10.0);
+        assert_eq!(rate("2026-11-02T00:00"), 15.0);
+        assert_eq!(rate("2026-11-06T00:00"), 35.0);
+        assert_eq!(rate("2026-11-11T00:00"), 60.0);
+        assert_eq!(rate("2027-01-01T00:00"), 60.0);
+    }
+
+    #[test]
+    fn test_ramp_and_active_windows() {
+        let ctx = HashMap::from([("userId", Atom::String("user-456".into()))]);
+        let holds = |expr: &str| {
+            let (_, e) = parse(expr).unwrap();
+            eval(&e, &ctx, Some("FF-test-rollout")).unwrap()
+        };
+        // a finished ramp stays at its end rate, one not started yet at its start
+        assert!(holds("ramp(0% -> 100%, 2020-01-01 .. 2020-02-01, userId)"));
+        assert!(!holds("ramp(0% -> 100%, 2999-01-01 .. 2999-02-01, userId)"));
+        // user-456 is in bucket 69367
+        assert!(!holds("ramp(0% -> 60%, 2020-01-01 .. 2020-02-01, userId)"));
+        assert!(holds("ramp(0% -> 70%, 2020-01-01 .. 2020-02-01, userId)"));
+
+        let window = |from, until| AstNode::Active(ActiveWindow { from, until });
+        let at = |s: &str| {
+            Some(
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+                    .unwrap()
+                    .and_utc(),
+            )
+        };
+        let active = |node: AstNode| eval(&node, &ctx, None).unwrap();
+        assert!(active(window(at("2020-01-01T00:00"), None)));
+        assert!(active(window(None, at("2999-01-01T00:00"))));
+        assert!(!active(window(None, at("2020-01-01T00:00"))));
+        assert!(!active(window(
+            at("2999-01-01T00:00"),
+            at("2999-02-01T00:00")
+        )));
+    }
+
+    #[test]
+    fn test_fixed_clock() {
+        let ctx = HashMap::from([("userId", Atom::String("user-456".into()))]);
+        let holds_at = |expr: &str, now: &str| {
+            let (_, e) = parse(expr).unwrap();
+            let options = EvalOptions {
+                clock: Clock::Fixed(now.parse().unwrap()),
+                ..EvalOptions::default()
+            };
+            eval_with_options(&e, &ctx, Some("FF-test-rollout"), None, &options).unwrap()
+        };
+        assert!(holds_at(
+            "NOW() > 2026-12-01T00:00:00Z",
+            "2026-12-01T00:00:01Z"
+        ));
+        assert!(!holds_at(
+            "NOW() > 2026-12-01T00:00:00Z",
+            "2026-11-30T23:59:59Z"
+        ));
+        assert!(!holds_at("NOW() < 2026-12-01", "2026-12-01T00:00:00Z"));
+        // user-456 is in bucket 69367: in at 70%, out at 60%
+        let ramp = "ramp(0% -> 100%, 2026-12-01T00:00Z .. 2026-12-11T00:00Z, userId)";
+        assert!(!holds_at(ramp, "2026-12-07T00:00:00Z"));
+        assert!(holds_at(ramp, "2026-12-08T00:00:00Z"));
+    }
+
     #[test]
     fn test_percentage_cross_language_vectors() {
         // These test vectors MUST produce identical results in TypeScript.
@@ -1478,12 +2010,121 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_64_bit_numbers() {
+        let ctx = HashMap::from([
+            ("createdMs", Atom::Number(1_735_689_600_000)),
+            ("accountId", Atom::from("9007199254740993")),
+        ]);
+        let check = |expr: &str| eval(&parse(expr).unwrap().1, &ctx, None);
+        assert_eq!(Ok(true), check("createdMs > 1700000000000"));
+        assert_eq!(Ok(true), check("accountId == 9007199254740993"));
+        assert_eq!(Ok(false), check("accountId == 9007199254740992"));
+        assert_eq!(Ok(true), check("accountId in (1, 9007199254740993)"));
+        // a number that doesn't fit a semver component never equals one
+        assert_eq!(Atom::Number(4_294_967_301) == Atom::Semver(5, 0, 0), false);
+    }
+
+    #[test]
+    fn test_context_strings_are_not_truncated() {
+        // Context values are parsed like literals, but only when the whole
+        // value is one: `user-123` must not become the variable `user`
+        assert!(matches!(Atom::from("user-123"), Atom::String(s) if s == "user-123"));
+        assert!(matches!(Atom::from("a@company.com"), Atom::String(s) if s == "a@company.com"));
+        assert!(matches!(Atom::from("5.3.42"), Atom::Semver(5, 3, 42)));
+        assert!(
+            matches!(Atom::from("[u-1, u-2]"), Atom::List(l) if l.len() == 2 && l[1] == Atom::String("u-2".into()))
+        );
+
+        let (_, expr) = parse("percentage(50%, userId)").unwrap();
+        let ctx = HashMap::from([("userId", Atom::from("user-123"))]);
+        assert_eq!(eval(&expr, &ctx, Some("FF-test-rollout")), Ok(true));
+        let (_, expr) = parse(r#"email ~$ "@company.com""#).unwrap();
+        let ctx = HashMap::from([("email", Atom::from("a@company.com"))]);
+        assert_eq!(eval(&expr, &ctx, None), Ok(true));
+    }
+
+    // ── Nested context ────────────────────────────────────────────
+
+    #[test]
+    fn test_nested_context_paths() {
+        let user = serde_json::json!({
+            "company": {"tier": "gold", "seats": 120, "beta": true},
+            "roles": ["admin", "dev"],
+            "manager": null
+        });
+        let ctx = HashMap::from([
+            ("user", Atom::from_json(&user).unwrap()),
+            ("org.plan", Atom::from("flat")),
+        ]);
+        let check = |expr: &str| eval(&parse(expr).unwrap().1, &ctx, None);
+        assert_eq!(Ok(true), check("user.company.tier == gold"));
+        assert_eq!(Ok(true), check("user[\"company\"]['seats'] > 100"));
+        assert_eq!(Ok(true), check("user.company.beta == true"));
+        assert_eq!(Ok(true), check("user.roles[1] == dev"));
+        assert_eq!(Ok(true), check("\"admin\" in user.roles"));
+        assert_eq!(Ok(true), check("upper(user.company.tier) == GOLD"));
+        assert_eq!(Ok(true), check("user.company.beta"));
+        assert_eq!(Ok(false), check("not user.company.beta"));
+        // null and missing attributes behave the same
+        assert_eq!(Ok(true), check("user.manager is null"));
+        assert_eq!(Ok(true), check("user.company.size is null"));
+        assert_eq!(Ok(false), check("user.roles[5] == dev"));
+        // a flat key that contains a dot is found as is
+        assert_eq!(Ok(true), check("org.plan == flat"));
+        assert_eq!(
+            eval_with_options(
+                &parse("user.company.size > 5").unwrap().1,
+                &ctx,
+                None,
+                None,
+                &EvalOptions::strict(),
+            ),
+            Err(EvalError::MissingVariable("user.company.size".into()))
+        );
+    }
+
+    #[test]
+    fn test_json_strings_stay_strings() {
+        let user = serde_json::json!({"zip": "01234", "app": "1.10"});
+        let user = Atom::from_json(&user).unwrap();
+        let Atom::Object(fields) = &user else {
+            panic!("expected an object, got {user:?}");
+        };
+        assert_eq!(fields["zip"], Atom::String("01234".into()));
+        assert_eq!(fields["app"], Atom::String("1.10".into()));
+        let ctx = HashMap::from([("user", user)]);
+        let check = |expr: &str| eval(&parse(expr).unwrap().1, &ctx, None);
+        assert_eq!(Ok(true), check(r#"user.zip == "01234""#));
+        assert_eq!(Ok(true), check(r#"user.app == "1.10""#));
+        assert_eq!(Ok(false), check(r#"user.app == "1.1""#));
+        assert_eq!(Ok(false), check("user.zip == 1234"));
+        // compared with a version or date literal, a string is read as one
+        let ctx = HashMap::from([
+            (
+                "appVersion",
+                Atom::from_json(&serde_json::json!("5.10.0")).unwrap(),
+            ),
+            (
+                "created",
+                Atom::from_json(&serde_json::json!("2024-02-10")).unwrap(),
+            ),
+        ]);
+        let check = |expr: &str| eval(&parse(expr).unwrap().1, &ctx, None);
+        assert_eq!(Ok(true), check("appVersion > 5.3.42"));
+        assert_eq!(Ok(true), check("appVersion == 5.10.0"));
+        assert_eq!(
+            Ok(true),
+            check("created > 2024-02-02 and created <= 2024-02-13")
+        );
+    }
+
     // ── Segment tests ─────────────────────────────────────────────
 
     #[test]
     fn test_segment_eval_true() {
         let seg_expr = parse("plan == premium").unwrap().1;
-        let segments = HashMap::from([("premium_users".to_string(), seg_expr)]);
+        let segments = HashMap::from([("premium_users".to_string(), seg_expr.into())]);
         let ctx = HashMap::from([("plan", Atom::String("premium".into()))]);
         assert_eq!(
             true,
@@ -1500,7 +2141,7 @@ mod tests {
     #[test]
     fn test_segment_eval_false() {
         let seg_expr = parse("plan == premium").unwrap().1;
-        let segments = HashMap::from([("premium_users".to_string(), seg_expr)]);
+        let segments = HashMap::from([("premium_users".to_string(), seg_expr.into())]);
         let ctx = HashMap::from([("plan", Atom::String("free".into()))]);
         assert_eq!(
             false,
@@ -1533,7 +2174,7 @@ mod tests {
     #[test]
     fn test_segment_in_logic_expr() {
         let seg_expr = parse("country == US").unwrap().1;
-        let segments = HashMap::from([("us_users".to_string(), seg_expr)]);
+        let segments = HashMap::from([("us_users".to_string(), seg_expr.into())]);
         let ctx = HashMap::from([
             ("country", Atom::String("US".into())),
             ("plan", Atom::String("premium".into())),
@@ -1550,6 +2191,178 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_arithmetic() {
+        let at = |s: &str| Atom::DateTime(s.parse().unwrap());
+        let days = |n: i64| Atom::Duration(TimeDelta::days(n));
+        let cases = [
+            (
+                Atom::Number(7),
+                ArithOp::Div,
+                Atom::Number(2),
+                Atom::Float(3.5),
+            ),
+            (
+                Atom::Number(8),
+                ArithOp::Div,
+                Atom::Number(2),
+                Atom::Number(4),
+            ),
+            (
+                Atom::Number(7),
+                ArithOp::Rem,
+                Atom::Number(2),
+                Atom::Number(1),
+            ),
+            (
+                Atom::Float(0.5),
+                ArithOp::Add,
+                Atom::Number(2),
+                Atom::Float(2.5),
+            ),
+            (
+                at("2026-12-01T00:00:00Z"),
+                ArithOp::Sub,
+                Atom::Date(NaiveDate::from_ymd_opt(2026, 11, 1).unwrap()),
+                days(30),
+            ),
+            (
+                at("2026-12-01T00:00:00Z"),
+                ArithOp::Add,
+                Atom::Duration(TimeDelta::hours(36)),
+                at("2026-12-02T12:00:00Z"),
+            ),
+            (Atom::Number(2), ArithOp::Mul, days(3), days(6)),
+            (
+                days(3),
+                ArithOp::Div,
+                Atom::Number(2),
+                Atom::Duration(TimeDelta::hours(36)),
+            ),
+            (days(3), ArithOp::Div, days(2), Atom::Float(1.5)),
+        ];
+        for (lhs, op, rhs, expected) in cases {
+            assert_eq!(arithmetic(&lhs, op, &rhs), Ok(expected), "{lhs} {op} {rhs}");
+        }
+        assert_eq!(
+            arithmetic(&Atom::Number(1), ArithOp::Div, &Atom::Number(0)),
+            Err(EvalError::InvalidExpression(
+                "division by zero in 1 / 0".into()
+            ))
+        );
+        assert_eq!(
+            arithmetic(&Atom::Number(i64::MAX), ArithOp::Add, &Atom::Number(1)),
+            Err(EvalError::InvalidExpression(format!(
+                "{} + 1 is out of range",
+                i64::MAX
+            )))
+        );
+        for op in [ArithOp::Mul, ArithOp::Div, ArithOp::Rem] {
+            assert_eq!(
+                arithmetic(&Atom::Number(i64::MIN), op, &Atom::Number(-1)),
+                Err(EvalError::InvalidExpression(format!(
+                    "{} {op} -1 is out of range",
+                    i64::MIN
+                ))),
+                "{op}"
+            );
+        }
+        assert_eq!(
+            arithmetic(&Atom::String("NL".into()), ArithOp::Mul, &days(1)),
+            Err(EvalError::TypeMismatch("cannot compute NL * 1d".into()))
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_comparisons() {
+        let ctx = HashMap::from([
+            ("cartTotal", Atom::Float(49.99)),
+            ("qty", Atom::Number(3)),
+            ("signupDate", Atom::from("2026-11-20")),
+            ("billingCountry", Atom::String("NL".into())),
+            ("shippingCountry", Atom::String("DE".into())),
+        ]);
+        let options = EvalOptions {
+            clock: Clock::Fixed("2026-12-01T00:00:00Z".parse().unwrap()),
+            ..EvalOptions::strict()
+        };
+        let check = |expr: &str| {
+            let (_, e) = parse(expr).unwrap();
+            eval_with_options(&e, &ctx, None, None, &options)
+        };
+        assert_eq!(check("NOW() - signupDate < 30d"), Ok(true));
+        assert_eq!(check("NOW() - signupDate < 1w"), Ok(false));
+        assert_eq!(check("cartTotal * qty >= 100"), Ok(true));
+        assert_eq!(check("cartTotal * 100 >= 5000"), Ok(false));
+        assert_eq!(check("qty % 2 == 1 and qty / 3 == 1"), Ok(true));
+        assert_eq!(check("billingCountry != var(shippingCountry)"), Ok(true));
+        assert_eq!(check("billingCountry == var(billingCountry)"), Ok(true));
+        assert_eq!(
+            check("cartTotal * missing > 1"),
+            Err(EvalError::MissingVariable("missing".into()))
+        );
+        assert_eq!(
+            check("billingCountry == var(missing)"),
+            Err(EvalError::MissingVariable("missing".into()))
+        );
+        assert_eq!(
+            check("billingCountry + 1 > 1"),
+            Err(EvalError::TypeMismatch("cannot compute NL + 1".into()))
+        );
+    }
+
+    #[test]
+    fn test_parameterized_segment() {
+        let in_country = SegmentDef {
+            params: vec!["c".into()],
+            body: parse("countryCode == c").unwrap().1,
+            span: Span::default(),
+        };
+        let eu = SegmentDef {
+            params: vec!["min".into()],
+            body: parse(
+                "(segment(in-country, NL) or segment(in-country, \"DE\")) and seats >= min",
+            )
+            .unwrap()
+            .1,
+            span: Span::default(),
+        };
+        let segments = HashMap::from([
+            ("in-country".to_string(), in_country),
+            ("eu-team".to_string(), eu),
+        ]);
+        let ctx = HashMap::from([
+            ("countryCode", Atom::String("DE".into())),
+            ("seats", Atom::Number(10)),
+        ]);
+        let check = |expr: &str| eval_with_segments(&parse(expr).unwrap().1, &ctx, None, &segments);
+        assert_eq!(Ok(true), check("segment(in-country, DE)"));
+        assert_eq!(Ok(false), check("segment(in-country, 'NL')"));
+        assert_eq!(Ok(true), check("segment(eu-team, 5)"));
+        assert_eq!(Ok(false), check("segment(eu-team, 50)"));
+        // a parameter is only bound in value positions
+        assert_eq!(Ok(false), check("segment(in-country, countryCode)"));
+        // arithmetic reads bare words as variables, parameters included
+        let per_seat = SegmentDef {
+            params: vec!["price".into()],
+            body: parse("seats * price >= 100").unwrap().1,
+            span: Span::default(),
+        };
+        let spend = HashMap::from([("spend".to_string(), per_seat)]);
+        let spends = |expr: &str| eval_with_segments(&parse(expr).unwrap().1, &ctx, None, &spend);
+        assert_eq!(Ok(true), spends("segment(spend, 10)"));
+        assert_eq!(Ok(false), spends("segment(spend, 9)"));
+
+        // wrong number of arguments
+        assert_eq!(Ok(false), check("segment(in-country)"));
+        assert_eq!(
+            strict("segment(in-country, NL, DE)", &ctx, &segments),
+            Err(EvalError::InvalidExpression(
+                "segment 'in-country' takes 1 argument, got 2".into()
+            ))
+        );
+    }
+
     #[test]
     fn test_segment_without_segments_returns_false() {
         // eval (without segments) should return false for segment() calls
@@ -1563,4 +2376,127 @@ mod tests {
             .unwrap()
         );
     }
+
+    // ── Strict mode ───────────────────────────────────────────────
+
+    fn strict(expr: &str, ctx: &Context, segments: &Segments) -> Result<bool, EvalError> {
+        eval_with_options(
+            &parse(expr).unwrap().1,
+            ctx,
+            None,
+            Some(segments),
+            &EvalOptions::strict(),
+        )
+    }
+
+    #[test]
+    fn test_strict_missing_variable() {
+        let segments = Segments::new();
+        assert_eq!(
+            strict("age > 18", &HashMap::new(), &segments),
+            Err(EvalError::MissingVariable("age".into()))
+        );
+        // lenient mode treats the failing comparison as false, so `not` flips it
+        let expr = parse("not (age > 18)").unwrap().1;
+        assert_eq!(Ok(true), eval(&expr, &HashMap::new(), None));
+        // null checks never need the variable
+        assert_eq!(Ok(true), strict("age is null", &HashMap::new(), &segments));
+    }
+
+    #[test]
+    fn test_strict_type_mismatch() {
+        let ctx = HashMap::from([("plan", Atom::String("premium".into()))]);
+        let segments = Segments::new();
+        assert!(matches!(
+            strict("plan > 5", &ctx, &segments),
+            Err(EvalError::TypeMismatch(_))
+        ));
+        // equality across types is just "not equal"
+        assert_eq!(Ok(false), strict("plan == 5", &ctx, &segments));
+    }
+
+    #[test]
+    fn test_strict_unknown_segment() {
+        assert_eq!(
+            strict("segment(nonexistent)", &HashMap::new(), &Segments::new()),
+            Err(EvalError::UnknownSegment("nonexistent".into()))
+        );
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut functions = Functions::new();
+        functions
+            .register("region", |args: &[Atom]| match args {
+                [Atom::String(ip)] if ip.starts_with("81.") => Atom::String("eu".into()),
+                _ => Atom::String("us".into()),
+            })
+            .register("double", |args: &[Atom]| match args {
+                [Atom::Number(n)] => Atom::Number(n * 2),
+                _ => Atom::Number(0),
+            })
+            .register("beta", |args: &[Atom]| {
+                Atom::Boolean(matches!(args, [Atom::Number(id)] if id % 2 == 0))
+            });
+        let options = EvalOptions {
+            functions,
+            ..EvalOptions::default()
+        };
+        let strict = EvalOptions {
+            strict: true,
+            ..options.clone()
+        };
+        let ctx = HashMap::from([
+            ("ip", Atom::String("81.2.69.160".into())),
+            ("userId", Atom::Number(42)),
+        ]);
+        let run = |expr: &str, options: &EvalOptions| {
+            eval_with_options(&parse(expr).unwrap().1, &ctx, None, None, options)
+        };
+
+        assert_eq!(run("fn:region(ip) == \"eu\"", &options), Ok(true));
+        assert_eq!(
+            run("fn:region(ip) == eu and fn:beta(userId)", &options),
+            Ok(true)
+        );
+        assert_eq!(run("fn:region(ip) in (us, uk)", &options), Ok(false));
+        assert_eq!(run("fn:double(userId + 1) - 6 == 80", &options), Ok(true));
+        assert_eq!(run("userId * 2 == fn:double(userId)", &options), Ok(true));
+        assert_eq!(run("fn:region(ip) is not null", &options), Ok(true));
+
+        // a missing argument fails the condition before the call
+        assert_eq!(
+            run("fn:region(country) == eu", &strict),
+            Err(EvalError::MissingVariable("country".into()))
+        );
+        assert_eq!(run("fn:region(country) is null", &strict), Ok(true));
+        assert_eq!(
+            run("fn:geo(ip) == eu", &strict),
+            Err(EvalError::UnknownFunction("geo".into()))
+        );
+        assert_eq!(run("fn:geo(ip) == eu", &options), Ok(false));
+        assert_eq!(run("fn:geo(ip)", &options), Ok(false));
+        assert_eq!(
+            run("fn:region(ip)", &strict),
+            Err(EvalError::TypeMismatch(
+                "fn:region(ip) is eu, not a boolean".into()
+            ))
+        );
+        assert_eq!(
+            format!("{:?}", strict.functions),
+            r#"{"beta", "double", "region"}"#
+        );
+    }
+
+    #[test]
+    fn test_self_referencing_segment_hits_depth_limit() {
+        let segments =
+            HashMap::from([("loop".to_string(), parse("segment(loop)").unwrap().1.into())]);
+        let expr = parse("segment(loop)").unwrap().1;
+        // returned even in lenient mode, instead of overflowing the stack
+        assert_eq!(
+            eval_with_segments(&expr, &HashMap::new(), None, &segments),
+            Err(EvalError::RecursionLimit(DEFAULT_MAX_SEGMENT_DEPTH))
+        );
+    }
 }
@@ -0,0 +1,592 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::ast::{FlagMetadata, Span};
+use crate::eval::{eval_noting_errors, eval_split, Context, EvalError, EvalOptions, Segments};
+use crate::parse_flagfile::{FlagReturn, Rule};
+
+/// Why an evaluation produced the value it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// A rule condition matched.
+    TargetingMatch,
+    /// The flag's fallthrough value was returned.
+    Default,
+    /// A rule inside the active `@env` block matched.
+    EnvMatch,
+    /// A `split()` rule picked the value by bucketing the context.
+    Split,
+    /// A `@requires` prerequisite is missing or did not evaluate to `true`.
+    PrerequisiteFailed,
+    /// The flag is outside its `@active` window.
+    Disabled,
+    FlagNotFound,
+    /// Every rule was tried and none produced a value.
+    NoRuleMatched,
+    /// No value was produced and at least one rule failed to evaluate.
+    Error,
+}
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::TargetingMatch => "TARGETING_MATCH",
+            Reason::Default => "DEFAULT",
+            Reason::EnvMatch => "ENV_MATCH",
+            Reason::Split => "SPLIT",
+            Reason::PrerequisiteFailed => "PREREQUISITE_FAILED",
+            Reason::Disabled => "DISABLED",
+            Reason::FlagNotFound => "FLAG_NOT_FOUND",
+            Reason::NoRuleMatched => "NO_RULE_MATCHED",
+            Reason::Error => "ERROR",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Result of evaluating a flag, with enough detail to explain it.
+#[derive(Debug, Clone)]
+pub struct EvaluationDetail {
+    /// `None` when no rule produced a value (see `reason`).
+    pub value: Option<FlagReturn>,
+    pub reason: Reason,
+    /// Index of the matched rule within its block: the flag body, or the
+    /// `@env` block named in `env`.
+    pub rule_index: Option<usize>,
+    /// The `@env` block the matched rule came from.
+    pub env: Option<String>,
+    /// The rule's `@name`, if it has one.
+    pub rule_name: Option<String>,
+    /// Where the rule is in the parsed content.
+    pub rule_span: Option<Span>,
+    /// First evaluation error hit while trying rules, if any.
+    pub error: Option<EvalError>,
+}
+
+impl EvaluationDetail {
+    pub(crate) fn no_value(reason: Reason) -> Self {
+        Self {
+            value: None,
+            reason,
+            rule_index: None,
+            env: None,
+            rule_name: None,
+            rule_span: None,
+            error: None,
+        }
+    }
+
+    /// The detail as a JSON object for non-Rust callers:
+    /// `{"value", "variant", "reason", "ruleIndex", "ruleName", "env", "error"}`,
+    /// with `null` for anything that doesn't apply.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "value": self.value.as_ref().map(FlagReturn::to_json),
+            "variant": self.value.as_ref().and_then(FlagReturn::variant_name),
+            "reason": self.reason.as_str(),
+            "ruleIndex": self.rule_index,
+            "ruleName": self.rule_name,
+            "env": self.env,
+            "error": self.error.as_ref().map(EvalError::to_string),
+        })
+    }
+}
+
+/// Evaluate a flag by name, checking its `@requires` prerequisites first.
+///
+/// This is the single evaluation path shared by [`crate::ff`], the CLI and the
+/// server; the flag maps may be keyed by `String` or `&str`.
+pub fn evaluate_flag<K>(
+    flag_name: &str,
+    context: &Context,
+    flags: &HashMap<K, Vec<Rule>>,
+    metadata: &HashMap<K, FlagMetadata>,
+    segments: &Segments,
+    env: Option<&str>,
+) -> EvaluationDetail
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    evaluate_flag_with_options(
+        flag_name,
+        context,
+        flags,
+        metadata,
+        segments,
+        env,
+        &EvalOptions::default(),
+    )
+}
+
+/// [`evaluate_flag`] with explicit [`EvalOptions`]. In strict mode the first
+/// rule that fails to evaluate stops evaluation with [`Reason::Error`].
+pub fn evaluate_flag_with_options<K>(
+    flag_name: &str,
+    context: &Context,
+    flags: &HashMap<K, Vec<Rule>>,
+    metadata: &HashMap<K, FlagMetadata>,
+    segments: &Segments,
+    env: Option<&str>,
+    options: &EvalOptions,
+) -> EvaluationDetail
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    let Some(rules) = flags.get(flag_name) else {
+        return EvaluationDetail::no_value(Reason::FlagNotFound);
+    };
+
+    let now = options.clock.now();
+    let active = |name: &str| {
+        metadata
+            .get(name)
+            .and_then(|m| m.active)
+            .is_none_or(|w| w.contains(now))
+    };
+    if let Some(meta) = metadata.get(flag_name) {
+        if !active(flag_name) {
+            return EvaluationDetail::no_value(Reason::Disabled);
+        }
+        for req in &meta.requires {
+            let satisfied = active(req)
+                && flags.get(req.as_str()).is_some_and(|req_rules| {
+                    matches!(
+                        evaluate_rules_with_options(
+                            req_rules,
+                            context,
+                            Some(req),
+                            segments,
+                            env,
+                            options
+                        )
+                        .value
+                        .as_ref()
+                        .map(FlagReturn::value),
+                        Some(FlagReturn::OnOff(true))
+                    )
+                });
+            if !satisfied {
+                return EvaluationDetail::no_value(Reason::PrerequisiteFailed);
+            }
+        }
+    }
+
+    evaluate_rules_with_options(rules, context, Some(flag_name), segments, env, options)
+}
+
+/// Evaluate a rule list top to bottom; the first matching rule wins.
+pub fn evaluate_rules(
+    rules: &[Rule],
+    context: &Context,
+    flag_name: Option<&str>,
+    segments: &Segments,
+    env: Option<&str>,
+) -> EvaluationDetail {
+    evaluate_rules_with_options(
+        rules,
+        context,
+        flag_name,
+        segments,
+        env,
+        &EvalOptions::default(),
+    )
+}
+
+/// [`evaluate_rules`] with explicit [`EvalOptions`].
+pub fn evaluate_rules_with_options(
+    rules: &[Rule],
+    context: &Context,
+    flag_name: Option<&str>,
+    segments: &Segments,
+    env: Option<&str>,
+    options: &EvalOptions,
+) -> EvaluationDetail {
+    let mut error = None;
+    let block = Block {
+        context,
+        flag_name,
+        segments,
+        env,
+        options,
+    };
+    if let Some(detail) = block.evaluate(rules, None, &mut error) {
+        return detail;
+    }
+    let reason = if error.is_some() {
+        Reason::Error
+    } else {
+        Reason::NoRuleMatched
+    };
+    EvaluationDetail {
+        error,
+        ..EvaluationDetail::no_value(reason)
+    }
+}
+
+/// Inputs shared by every rule list of a single flag evaluation.
+struct Block<'a> {
+    context: &'a Context<'a>,
+    flag_name: Option<&'a str>,
+    segments: &'a Segments,
+    env: Option<&'a str>,
+    options: &'a EvalOptions,
+}
+
+impl Block<'_> {
+    fn evaluate(
+        &self,
+        rules: &[Rule],
+        block_env: Option<&str>,
+        error: &mut Option<EvalError>,
+    ) -> Option<EvaluationDetail> {
+        let matched = |idx: usize,
+                       rule: &Rule,
+                       value: &FlagReturn,
+                       reason: Reason,
+                       error: &Option<EvalError>| {
+            let reason = if block_env.is_some() {
+                Reason::EnvMatch
+            } else {
+                reason
+            };
+            let name = match rule {
+                Rule::BoolExpressionValue(_, _, name, _) | Rule::Split { name, .. } => name.clone(),
+                _ => None,
+            };
+            EvaluationDetail {
+                value: Some(value.clone()),
+                reason,
+                rule_index: Some(idx),
+                env: block_env.map(str::to_string),
+                rule_name: name,
+                rule_span: Some(rule.span()),
+                error: error.clone(),
+            }
+        };
+
+        for (idx, rule) in rules.iter().enumerate() {
+            match rule {
+                Rule::BoolExpressionValue(expr, return_val, name, span) => {
+                    match eval_noting_errors(
+                        expr,
+                        self.context,
+                        self.flag_name,
+                        Some(self.segments),
+                        self.options,
+                        error,
+                    ) {
+                        Ok(true) => {
+                            return Some(matched(
+                                idx,
+                                rule,
+                                return_val,
+                                Reason::TargetingMatch,
+                                error,
+                            ))
+                        }
+                        Ok(false) => {}
+                        Err(e) if self.options.strict => {
+                            return Some(EvaluationDetail {
+                                rule_index: Some(idx),
+                                env: block_env.map(str::to_string),
+                                rule_name: name.clone(),
+                                rule_span: Some(*span),
+                                error: Some(e),
+                                ..EvaluationDetail::no_value(Reason::Error)
+                            });
+                        }
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                Rule::Value(return_val, _) => {
+                    return Some(matched(idx, rule, return_val, Reason::Default, error));
+                }
+                Rule::Split { split, name, span } => {
+                    match eval_split(split, self.context, self.flag_name) {
+                        Ok(arm) => return Some(matched(idx, rule, arm, Reason::Split, error)),
+                        Err(e) if self.options.strict => {
+                            return Some(EvaluationDetail {
+                                rule_index: Some(idx),
+                                env: block_env.map(str::to_string),
+                                rule_name: name.clone(),
+                                rule_span: Some(*span),
+                                error: Some(e),
+                                ..EvaluationDetail::no_value(Reason::Error)
+                            });
+                        }
+                        // Like a condition that can't be evaluated, a split
+                        // without its field is skipped.
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                Rule::EnvRule {
+                    env: rule_env,
+                    rules: sub_rules,
+                    ..
+                } => {
+                    if self.env == Some(rule_env.as_str()) {
+                        let result = self.evaluate(sub_rules, Some(rule_env), error);
+                        if result.is_some() {
+                            return result;
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Atom;
+    use crate::parse_flagfile::parse_flagfile_with_segments;
+
+    fn load(content: &str) -> (HashMap<String, Vec<Rule>>, HashMap<String, FlagMetadata>) {
+        let parsed = parse_flagfile_with_segments(content).unwrap();
+        let mut flags = HashMap::new();
+        let mut metadata = HashMap::new();
+        for fv in parsed.flags {
+            for (name, def) in fv {
+                flags.insert(name.to_string(), def.rules);
+                metadata.insert(name.to_string(), def.metadata);
+            }
+        }
+        (flags, metadata)
+    }
+
+    const FLAGS: &str = r#"
+FF-base -> true
+
+@requires FF-base
+FF-checkout {
+    @env prod {
+        @name "prod beta"
+        beta == true -> true
+    }
+    @name "premium"
+    plan == premium -> true
+    false
+}
+
+@requires FF-missing
+FF-orphan -> true
+
+FF-only-rules {
+    plan == premium -> true
+}
+"#;
+
+    #[test]
+    fn test_detail_targeting_match_with_rule_name() {
+        let (flags, metadata) = load(FLAGS);
+        let ctx: Context = HashMap::from([("plan", Atom::String("premium".into()))]);
+        let d = evaluate_flag(
+            "FF-checkout",
+            &ctx,
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert!(matches!(d.value, Some(FlagReturn::OnOff(true))));
+        assert_eq!(d.reason, Reason::TargetingMatch);
+        assert_eq!(d.rule_index, Some(1));
+        assert_eq!(d.rule_name.as_deref(), Some("premium"));
+        assert_eq!(d.env, None);
+        let span = d.rule_span.unwrap();
+        assert_eq!((span.line, span.column), (11, 5));
+    }
+
+    #[test]
+    fn test_detail_default() {
+        let (flags, metadata) = load(FLAGS);
+        let d = evaluate_flag(
+            "FF-checkout",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert!(matches!(d.value, Some(FlagReturn::OnOff(false))));
+        assert_eq!(d.reason, Reason::Default);
+        assert_eq!(d.rule_index, Some(2));
+        assert_eq!(d.rule_name, None);
+    }
+
+    #[test]
+    fn test_detail_keeps_first_lenient_error() {
+        let (flags, metadata) = load("FF-adult {\n    age > 18 -> true\n    false\n}\n");
+        let d = evaluate_flag(
+            "FF-adult",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert_eq!(d.reason, Reason::Default);
+        assert_eq!(d.error, Some(EvalError::MissingVariable("age".into())));
+
+        let (flags, metadata) = load(FLAGS);
+        let d = evaluate_flag(
+            "FF-only-rules",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert_eq!(d.reason, Reason::Error);
+        assert_eq!(d.error, Some(EvalError::MissingVariable("plan".into())));
+    }
+
+    #[test]
+    fn test_detail_env_match() {
+        let (flags, metadata) = load(FLAGS);
+        let ctx: Context = HashMap::from([("beta", Atom::Boolean(true))]);
+        let d = evaluate_flag(
+            "FF-checkout",
+            &ctx,
+            &flags,
+            &metadata,
+            &Segments::new(),
+            Some("prod"),
+        );
+        assert_eq!(d.reason, Reason::EnvMatch);
+        assert_eq!(d.env.as_deref(), Some("prod"));
+        assert_eq!(d.rule_index, Some(0));
+        assert_eq!(d.rule_name.as_deref(), Some("prod beta"));
+        assert_eq!(d.rule_span.map(|s| s.line), Some(8));
+    }
+
+    #[test]
+    fn test_detail_flag_not_found_and_prerequisite_failed() {
+        let (flags, metadata) = load(FLAGS);
+        let segments = Segments::new();
+        let d = evaluate_flag(
+            "FF-nope",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &segments,
+            None,
+        );
+        assert_eq!(d.reason, Reason::FlagNotFound);
+        let d = evaluate_flag(
+            "FF-orphan",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &segments,
+            None,
+        );
+        assert_eq!(d.reason, Reason::PrerequisiteFailed);
+        assert!(d.value.is_none());
+    }
+
+    #[test]
+    fn test_detail_no_rule_matched() {
+        let (flags, metadata) = load(FLAGS);
+        let ctx: Context = HashMap::from([("plan", Atom::String("free".into()))]);
+        let d = evaluate_flag(
+            "FF-only-rules",
+            &ctx,
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert_eq!(d.reason, Reason::NoRuleMatched);
+        assert!(d.value.is_none());
+    }
+
+    #[test]
+    fn test_detail_disabled_outside_active_window() {
+        let (flags, metadata) = load(
+            "@active until 2020-01-01\nFF-old-promo -> true\n\n@active from 2020-01-01\nFF-launched -> true\n\n@requires FF-old-promo\nFF-promo-banner -> true\n",
+        );
+        let eval = |name: &str| {
+            evaluate_flag(
+                name,
+                &Context::new(),
+                &flags,
+                &metadata,
+                &Segments::new(),
+                None,
+            )
+        };
+        let d = eval("FF-old-promo");
+        assert_eq!(d.reason, Reason::Disabled);
+        assert!(d.value.is_none());
+        assert_eq!(eval("FF-launched").reason, Reason::Default);
+        // a disabled prerequisite is not met
+        assert_eq!(eval("FF-promo-banner").reason, Reason::PrerequisiteFailed);
+    }
+
+    #[test]
+    fn test_detail_split() {
+        let (flags, metadata) = load(
+            "FF-test-rollout {\n    @name \"layout test\"\n    split(userId) { \"a\": 50%, \"b\": 25%, \"c\": 25% }\n    \"none\"\n}",
+        );
+        // user-456 is in bucket 69367 for this flag, see `eval`.
+        let ctx: Context = HashMap::from([("userId", Atom::String("user-456".into()))]);
+        let d = evaluate_flag(
+            "FF-test-rollout",
+            &ctx,
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert!(matches!(d.value, Some(FlagReturn::Str(ref s)) if s == "b"));
+        assert_eq!(d.reason, Reason::Split);
+        assert_eq!(d.rule_index, Some(0));
+        assert_eq!(d.rule_name.as_deref(), Some("layout test"));
+
+        // Without the field the split is skipped, like a failing condition.
+        let d = evaluate_flag(
+            "FF-test-rollout",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert_eq!(d.reason, Reason::Default);
+        assert_eq!(d.rule_index, Some(1));
+    }
+
+    #[test]
+    fn test_detail_str_keyed_maps() {
+        let (flags, metadata) = load(FLAGS);
+        let flags: HashMap<&str, Vec<Rule>> =
+            flags.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        let metadata: HashMap<&str, FlagMetadata> = metadata
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+        let d = evaluate_flag(
+            "FF-base",
+            &Context::new(),
+            &flags,
+            &metadata,
+            &Segments::new(),
+            None,
+        );
+        assert_eq!(d.reason, Reason::Default);
+    }
+}
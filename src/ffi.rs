@@ -0,0 +1,256 @@
+//! C ABI for embedding the evaluator in other runtimes (.NET, Python, Go via
+//! cgo, ...). Link against the `cdylib` and include `include/flagfile.h`,
+//! which is generated from this module with `cbindgen` (see `cbindgen.toml`).
+//!
+//! Contexts and results cross the boundary as JSON strings. Functions that
+//! can fail return `NULL` and record a message readable with
+//! [`ff_last_error`]; strings returned by the library are freed with
+//! [`ff_string_free`].
+#![allow(unsafe_code)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use serde_json::Value;
+
+use crate::flagfile::Flagfile;
+
+/// An opaque handle to a parsed Flagfile. Create one with
+/// [`ff_flagfile_new`], evaluate flags with [`ff_flagfile_evaluate`] and
+/// release it with [`ff_flagfile_free`].
+pub struct FlagfileHandle(Flagfile);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    // interior NULs would truncate the message on the C side
+    let message = message.into().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// Run `body`, turning a panic into a recorded error and `fallback` so it
+/// never unwinds into the caller.
+fn guard<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        set_last_error(format!("internal error: {message}"));
+        fallback
+    })
+}
+
+/// Borrow a C string argument, recording an error if it's NULL or not UTF-8.
+///
+/// # Safety
+/// `ptr` must be NULL or point to a NUL-terminated string that outlives `'a`.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Option<&'a str> {
+    if ptr.is_null() {
+        set_last_error(format!("{name} is NULL"));
+        return None;
+    }
+    match CStr::from_ptr(ptr).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{name} is not valid UTF-8"));
+            None
+        }
+    }
+}
+
+/// Parse Flagfile content into a handle. `env` selects the environment for
+/// `@env` rules and may be NULL. Returns NULL on failure.
+///
+/// # Safety
+/// `content` must be a NUL-terminated string; `env` must be NULL or one.
+#[no_mangle]
+pub unsafe extern "C" fn ff_flagfile_new(
+    content: *const c_char,
+    env: *const c_char,
+) -> *mut FlagfileHandle {
+    clear_last_error();
+    guard(ptr::null_mut(), || {
+        let Some(content) = str_arg(content, "content") else {
+            return ptr::null_mut();
+        };
+        let env = if env.is_null() {
+            None
+        } else {
+            match str_arg(env, "env") {
+                Some(env) => Some(env),
+                None => return ptr::null_mut(),
+            }
+        };
+        match Flagfile::parse(content) {
+            Ok(flagfile) => {
+                let flagfile = match env {
+                    Some(env) => flagfile.with_env(env),
+                    None => flagfile,
+                };
+                Box::into_raw(Box::new(FlagfileHandle(flagfile)))
+            }
+            Err(e) => {
+                set_last_error(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Free a handle from [`ff_flagfile_new`]. NULL is a no-op.
+///
+/// # Safety
+/// `handle` must be NULL or a handle that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ff_flagfile_free(handle: *mut FlagfileHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Evaluate a flag. `context_json` is a JSON object, or NULL for an empty
+/// context. Returns a JSON object
+/// `{"value", "variant", "reason", "ruleIndex", "ruleName", "env", "error"}`
+/// to be freed with [`ff_string_free`], or NULL on failure. An unknown flag
+/// is not a failure: it returns `"reason": "FLAG_NOT_FOUND"`.
+///
+/// # Safety
+/// `handle` must come from [`ff_flagfile_new`]; `flag` must be a
+/// NUL-terminated string; `context_json` must be NULL or one.
+#[no_mangle]
+pub unsafe extern "C" fn ff_flagfile_evaluate(
+    handle: *const FlagfileHandle,
+    flag: *const c_char,
+    context_json: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    guard(ptr::null_mut(), || {
+        let Some(FlagfileHandle(flagfile)) = handle.as_ref() else {
+            set_last_error("handle is NULL");
+            return ptr::null_mut();
+        };
+        let Some(flag) = str_arg(flag, "flag") else {
+            return ptr::null_mut();
+        };
+        let context = if context_json.is_null() {
+            Value::Null
+        } else {
+            let Some(text) = str_arg(context_json, "context_json") else {
+                return ptr::null_mut();
+            };
+            match serde_json::from_str(text) {
+                Ok(value) => value,
+                Err(e) => {
+                    set_last_error(format!("invalid context JSON: {e}"));
+                    return ptr::null_mut();
+                }
+            }
+        };
+        match flagfile.eval_json(flag, &context) {
+            Ok(detail) => CString::new(detail.to_string())
+                .map(CString::into_raw)
+                .unwrap_or(ptr::null_mut()),
+            Err(e) => {
+                set_last_error(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Free a string returned by the library. NULL is a no-op.
+///
+/// # Safety
+/// `s` must be NULL or a string returned by this library that hasn't been
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ff_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// The error message from the last failed call on this thread, or NULL.
+/// The string is owned by the library and valid until the next call on the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn ff_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// The library version, e.g. `"0.4.16"`. The string is static.
+#[no_mangle]
+pub extern "C" fn ff_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let err = ff_last_error();
+        (!err.is_null()).then(|| unsafe { CStr::from_ptr(err) }.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_null_arguments_set_last_error() {
+        unsafe {
+            assert!(ff_flagfile_new(ptr::null(), ptr::null()).is_null());
+            assert_eq!(last_error().as_deref(), Some("content is NULL"));
+            assert!(ff_flagfile_evaluate(ptr::null(), c"FF-x".as_ptr(), ptr::null()).is_null());
+            assert_eq!(last_error().as_deref(), Some("handle is NULL"));
+            ff_flagfile_free(ptr::null_mut());
+            ff_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_context_errors() {
+        unsafe {
+            let handle = ff_flagfile_new(c"FF-x -> true".as_ptr(), ptr::null());
+            assert!(!handle.is_null());
+            assert!(last_error().is_none());
+
+            let out = ff_flagfile_evaluate(handle, c"FF-x".as_ptr(), c"{not json".as_ptr());
+            assert!(out.is_null());
+            assert!(last_error().unwrap().starts_with("invalid context JSON"));
+
+            let out = ff_flagfile_evaluate(handle, c"FF-x".as_ptr(), c"[1]".as_ptr());
+            assert!(out.is_null());
+            assert_eq!(
+                last_error().as_deref(),
+                Some("context must be a JSON object")
+            );
+            ff_flagfile_free(handle);
+        }
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        let out = guard(ptr::null_mut::<c_char>(), || panic!("boom"));
+        assert!(out.is_null());
+        assert_eq!(last_error().as_deref(), Some("internal error: boom"));
+        let out = guard(ptr::null_mut::<c_char>(), || {
+            panic!("{} {}", "formatted", 1)
+        });
+        assert!(out.is_null());
+        assert_eq!(last_error().as_deref(), Some("internal error: formatted 1"));
+    }
+
+    #[test]
+    fn test_version() {
+        let version = unsafe { CStr::from_ptr(ff_version()) };
+        assert_eq!(version.to_str(), Ok(env!("CARGO_PKG_VERSION")));
+    }
+}
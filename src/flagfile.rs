@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::ast::{Atom, FlagMetadata};
+use crate::eval::{Clock, Context, EvalOptions, Segments};
+use crate::evaluation::EvaluationDetail;
+use crate::parse_flagfile::{self, FlagReturn, Rule};
+use crate::plan::Plan;
+
+/// Why a Flagfile failed to load, with the 1-based `(line, column)` of the
+/// offending text when it is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub message: String,
+    pub location: Option<(usize, usize)>,
+}
+
+impl LoadError {
+    fn unlocated(message: String) -> Self {
+        Self {
+            message,
+            location: None,
+        }
+    }
+}
+
+/// A parsed Flagfile that owns its flags, segments and environment.
+///
+/// Unlike the global [`crate::init`]/[`crate::ff`] pair, any number of these
+/// can live side by side — one per tenant, or an old and a new revision.
+///
+/// ```
+/// use flagfile_lib::{Context, Flagfile, FlagReturn};
+///
+/// let flags = Flagfile::parse("FF-new-ui -> true").unwrap().with_env("prod");
+/// assert!(matches!(flags.eval("FF-new-ui", &Context::new()), Some(FlagReturn::OnOff(true))));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Flagfile {
+    /// Flag names in the order they appear in the source.
+    names: Vec<String>,
+    flags: HashMap<String, Vec<Rule>>,
+    metadata: HashMap<String, FlagMetadata>,
+    segments: Segments,
+    env: Option<String>,
+    options: EvalOptions,
+    /// Compiled from `flags` and `segments` at parse time.
+    plan: Plan,
+}
+
+impl Flagfile {
+    /// Parse Flagfile content. `@include` directives are not resolved here;
+    /// use [`Flagfile::from_path`] for that.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        Self::parse_located(content).map_err(|e| e.message)
+    }
+
+    /// Like [`Flagfile::parse`] but keeps where a syntax error occurred.
+    pub fn parse_located(content: &str) -> Result<Self, LoadError> {
+        let parsed =
+            parse_flagfile::parse_flagfile_with_segments(content).map_err(|e| LoadError {
+                message: format!("Failed to parse Flagfile: {}", e),
+                location: Some((e.line, e.column)),
+            })?;
+        let mismatches = parsed.type_mismatches();
+        if !mismatches.is_empty() {
+            let msgs: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+            return Err(LoadError::unlocated(format!(
+                "Flagfile type check failed: {}",
+                msgs.join("; ")
+            )));
+        }
+        if let Some(cycle) = parsed.segment_cycle() {
+            return Err(LoadError::unlocated(format!(
+                "Flagfile segment check failed: {}",
+                cycle
+            )));
+        }
+        if let Some(mismatch) = parsed.segment_arity_mismatch() {
+            return Err(LoadError {
+                message: format!("Flagfile segment check failed: {}", mismatch),
+                location: Some((mismatch.span.line, mismatch.span.column)),
+            });
+        }
+
+        let mut flagfile = Flagfile {
+            segments: parsed.segments,
+            ..Flagfile::default()
+        };
+        for fv in parsed.flags {
+            for (name, def) in fv {
+                if !flagfile.flags.contains_key(name) {
+                    flagfile.names.push(name.to_string());
+                }
+                flagfile.flags.insert(name.to_string(), def.rules);
+                flagfile.metadata.insert(name.to_string(), def.metadata);
+            }
+        }
+        flagfile.plan = Plan::compile(&flagfile.flags, &flagfile.metadata, &flagfile.segments);
+        Ok(flagfile)
+    }
+
+    /// Read and parse a Flagfile, resolving `@include` directives relative
+    /// to its directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        let resolved = crate::include::resolve_includes_from_path(path.as_ref())?;
+        Self::parse(&resolved.content)
+    }
+
+    /// Set the environment that `@env` rules are evaluated against.
+    pub fn with_env(mut self, env: impl Into<String>) -> Self {
+        self.env = Some(env.into());
+        self
+    }
+
+    pub fn env(&self) -> Option<&str> {
+        self.env.as_deref()
+    }
+
+    /// Set how conditions are evaluated, e.g. [`EvalOptions::strict`] to
+    /// report evaluation errors instead of skipping the failing rule.
+    pub fn with_options(mut self, options: EvalOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set where `NOW()`, `ramp()` and `@active` read the current time, e.g.
+    /// [`Clock::Fixed`] to evaluate time-dependent rules deterministically.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.options.clock = clock;
+        self
+    }
+
+    /// Register a host function that rules call as `fn:name(...)`; see
+    /// [`crate::eval::Functions`].
+    pub fn with_function(
+        mut self,
+        name: impl Into<String>,
+        f: impl Fn(&[Atom]) -> Atom + Send + Sync + 'static,
+    ) -> Self {
+        self.options.functions.register(name, f);
+        self
+    }
+
+    /// Evaluate a flag. Returns `None` if the flag doesn't exist, a
+    /// prerequisite isn't met, or no rule matched.
+    pub fn eval(&self, flag_name: &str, context: &Context) -> Option<FlagReturn> {
+        self.eval_detail(flag_name, context).value
+    }
+
+    /// Evaluate a flag and explain the result.
+    pub fn eval_detail(&self, flag_name: &str, context: &Context) -> EvaluationDetail {
+        self.plan
+            .evaluate(flag_name, context, self.env.as_deref(), &self.options)
+    }
+
+    /// Evaluate a flag against a JSON context and return the detail as JSON
+    /// (see [`EvaluationDetail::to_json`]). The context is an object, whose
+    /// nested objects rules reach with dotted paths like `user.plan`, or
+    /// `null` for an empty context.
+    pub fn eval_json(&self, flag_name: &str, context: &Value) -> Result<Value, String> {
+        let attrs: HashMap<String, Atom> = match context {
+            Value::Null => HashMap::new(),
+            Value::Object(fields) => fields
+                .iter()
+                .filter_map(|(k, v)| Some((k.clone(), Atom::from_json(v)?)))
+                .collect(),
+            _ => return Err("context must be a JSON object".to_string()),
+        };
+        let ctx: Context = attrs.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        Ok(self.eval_detail(flag_name, &ctx).to_json())
+    }
+
+    /// Metadata annotations for a flag, or `None` if it doesn't exist.
+    pub fn metadata(&self, flag_name: &str) -> Option<&FlagMetadata> {
+        self.metadata.get(flag_name)
+    }
+
+    /// The rules of a flag, or `None` if it doesn't exist.
+    pub fn rules(&self, flag_name: &str) -> Option<&[Rule]> {
+        self.flags.get(flag_name).map(Vec::as_slice)
+    }
+
+    /// Flag names in source order.
+    pub fn flags(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    pub fn contains(&self, flag_name: &str) -> bool {
+        self.flags.contains_key(flag_name)
+    }
+
+    pub fn segments(&self) -> &Segments {
+        &self.segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = r#"
+@owner "checkout-team"
+FF-checkout {
+    @env prod -> false
+    plan == premium -> true
+    false
+}
+
+FF-banner -> "hero"
+"#;
+
+    #[test]
+    fn test_parse_and_iterate_in_source_order() {
+        let ff = Flagfile::parse(CONTENT).unwrap();
+        assert_eq!(
+            ff.flags().collect::<Vec<_>>(),
+            vec!["FF-checkout", "FF-banner"]
+        );
+        assert!(ff.contains("FF-banner"));
+        assert_eq!(ff.rules("FF-checkout").map(<[Rule]>::len), Some(3));
+        assert_eq!(
+            ff.metadata("FF-checkout").and_then(|m| m.owner.as_deref()),
+            Some("checkout-team")
+        );
+        assert!(ff.metadata("FF-missing").is_none());
+    }
+
+    #[test]
+    fn test_instances_are_independent() {
+        let ctx: Context = HashMap::from([("plan", Atom::String("premium".into()))]);
+        let dev = Flagfile::parse(CONTENT).unwrap();
+        let prod = dev.clone().with_env("prod");
+        assert!(matches!(
+            dev.eval("FF-checkout", &ctx),
+            Some(FlagReturn::OnOff(true))
+        ));
+        assert!(matches!(
+            prod.eval("FF-checkout", &ctx),
+            Some(FlagReturn::OnOff(false))
+        ));
+        assert_eq!(prod.env(), Some("prod"));
+        assert!(dev.eval("FF-missing", &ctx).is_none());
+    }
+
+    #[test]
+    fn test_strict_options() {
+        let ff = Flagfile::parse("FF-age {\n    age > 18 -> true\n    false\n}").unwrap();
+        let ctx = Context::new();
+        assert!(matches!(
+            ff.eval("FF-age", &ctx),
+            Some(FlagReturn::OnOff(false))
+        ));
+        let strict = ff.with_options(EvalOptions::strict());
+        let detail = strict.eval_detail("FF-age", &ctx);
+        assert_eq!(detail.reason, crate::evaluation::Reason::Error);
+        assert_eq!(detail.rule_index, Some(0));
+        assert!(detail.value.is_none());
+    }
+
+    #[test]
+    fn test_nested_boolean_condition() {
+        let ff = Flagfile::parse("FF-beta {\n    user.beta -> true\n    false\n}").unwrap();
+        let eval = |context: serde_json::Value| {
+            ff.eval_json("FF-beta", &context).unwrap()["value"].clone()
+        };
+        assert_eq!(eval(serde_json::json!({"user": {"beta": true}})), true);
+        assert_eq!(eval(serde_json::json!({"user": {"beta": false}})), false);
+        assert_eq!(eval(serde_json::json!({"user": {}})), false);
+    }
+
+    #[test]
+    fn test_fixed_clock() {
+        let ff = Flagfile::parse(
+            "@timezone Europe/Vilnius\nFF-sale {\n    NOW() > 2026-12-01T00:00 -> true\n    false\n}",
+        )
+        .unwrap();
+        let ctx = Context::new();
+        let at = |s: &str| Clock::Fixed(s.parse().unwrap());
+        // Midnight in Vilnius is 22:00 UTC the day before.
+        let before = ff.clone().with_clock(at("2026-11-30T21:59:59Z"));
+        let after = ff.with_clock(at("2026-11-30T22:00:01Z"));
+        assert!(matches!(
+            before.eval("FF-sale", &ctx),
+            Some(FlagReturn::OnOff(false))
+        ));
+        assert!(matches!(
+            after.eval("FF-sale", &ctx),
+            Some(FlagReturn::OnOff(true))
+        ));
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let ff = Flagfile::parse(
+            "FF-eu-banner {\n    fn:region(ip) == \"eu\" -> true\n    false\n}\n\
+             FF-sso {\n    fn:entitled(accountId, \"sso\") -> true\n    false\n}",
+        )
+        .unwrap();
+        let ctx: Context = HashMap::from([
+            ("ip", Atom::String("81.2.69.160".into())),
+            ("accountId", Atom::Number(7)),
+        ]);
+        // Not registered: the rule is skipped
+        assert!(matches!(
+            ff.eval("FF-eu-banner", &ctx),
+            Some(FlagReturn::OnOff(false))
+        ));
+
+        let ff = ff
+            .with_function("region", |args: &[Atom]| {
+                let eu = matches!(args, [Atom::String(ip)] if ip.starts_with("81."));
+                Atom::String(if eu { "eu" } else { "us" }.into())
+            })
+            .with_function("entitled", |args: &[Atom]| {
+                Atom::Boolean(matches!(args, [Atom::Number(7), Atom::String(f)] if f == "sso"))
+            });
+        assert!(matches!(
+            ff.eval("FF-eu-banner", &ctx),
+            Some(FlagReturn::OnOff(true))
+        ));
+        assert!(matches!(
+            ff.eval("FF-sso", &ctx),
+            Some(FlagReturn::OnOff(true))
+        ));
+        assert!(matches!(
+            ff.eval("FF-sso", &Context::new()),
+            Some(FlagReturn::OnOff(false))
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Flagfile::parse("FF-ok -> true\n@include \"other.ff\"").unwrap_err();
+        assert!(err.contains("@include directives are only resolved"));
+        assert!(Flagfile::parse("@type int\nFF-x -> true").is_err());
+        let err = Flagfile::parse(
+            "@segment a {\n    segment(b)\n}\n@segment b {\n    segment(a)\n}\nFF-x -> true",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Flagfile segment check failed: circular segment dependency: a -> b -> a"
+        );
+        let err = Flagfile::parse_located(
+            "@segment in-country(c) {\n    country == c\n}\nFF-x {\n    segment(in-country, NL, DE) -> true\n    false\n}",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Flagfile segment check failed: segment 'in-country' takes 1 argument, called with 2 at line 5"
+        );
+        assert_eq!(err.location.map(|(line, _)| line), Some(5));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let err = Flagfile::parse_located("FF-ok -> true\n\n  FF-bad {{\n").unwrap_err();
+        assert_eq!(err.location, Some((3, 11)));
+        assert!(
+            err.message
+                .contains("unexpected `{` where a rule was expected"),
+            "{}",
+            err.message
+        );
+        let err = Flagfile::parse_located("@type int\nFF-x -> true").unwrap_err();
+        assert_eq!(err.location, None);
+    }
+}
@@ -9,6 +9,9 @@
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
+use crate::ast::Span;
+use crate::parse_error::ParseError;
+
 /// A single file pulled in via `@include`, in depth-first inclusion order.
 #[derive(Debug, Clone)]
 pub struct IncludedFile {
@@ -26,6 +29,65 @@ pub struct ResolvedFlagfile {
     pub content: String,
     /// All included files in depth-first inclusion order.
     pub includes: Vec<IncludedFile>,
+    /// The root file, when the content was read from a path.
+    root: Option<PathBuf>,
+    /// Where each line of `content` came from. Empty when nothing was
+    /// included, in which case every line is the root file's own.
+    origins: Vec<LineOrigin>,
+}
+
+/// A line of merged content: `include` indexes `includes` (`None` for the
+/// root file) and `line` is 1-based within that file.
+#[derive(Debug, Clone, Copy)]
+struct LineOrigin {
+    include: Option<usize>,
+    line: usize,
+}
+
+impl ResolvedFlagfile {
+    /// The file and 1-based line in it that `line` of the merged content
+    /// came from. The file is `None` for the root when it wasn't read from
+    /// a path.
+    pub fn origin(&self, line: usize) -> (Option<&Path>, usize) {
+        let root = self.root.as_deref();
+        match self.origins.get(line.wrapping_sub(1)) {
+            Some(LineOrigin {
+                include: Some(index),
+                line,
+            }) => (Some(self.includes[*index].path.as_path()), *line),
+            Some(LineOrigin {
+                include: None,
+                line,
+            }) => (root, *line),
+            None => (root, line),
+        }
+    }
+
+    /// `path:line:column` of a span of the merged content in the file it
+    /// came from, or `line N, column M` when that file has no path.
+    pub fn position(&self, span: Span) -> String {
+        match self.origin(span.line) {
+            (Some(file), line) => format!("{}:{}:{}", file.display(), line, span.column),
+            (None, line) => format!("line {}, column {}", line, span.column),
+        }
+    }
+
+    /// Point an error in the merged content at the file and line it came
+    /// from.
+    pub fn locate(&self, mut err: ParseError) -> ParseError {
+        let (file, line) = self.origin(err.line);
+        err.file = file.map(Path::to_path_buf);
+        err.line = line;
+        err
+    }
+}
+
+/// What [`expand`] accumulates.
+#[derive(Default)]
+struct Expansion {
+    content: String,
+    includes: Vec<IncludedFile>,
+    origins: Vec<LineOrigin>,
 }
 
 /// Expands `@include` directives in `content`, resolving paths relative to
@@ -35,25 +97,28 @@ pub fn resolve_includes(content: &str, base_dir: &Path) -> Result<ResolvedFlagfi
         return Ok(ResolvedFlagfile {
             content: content.to_string(),
             includes: Vec::new(),
+            root: None,
+            origins: Vec::new(),
         });
     }
     let root = fs::canonicalize(base_dir)
         .map_err(|_| format!("could not resolve directory '{}'", base_dir.display()))?;
-    let mut includes = Vec::new();
-    let mut out = String::new();
+    let mut out = Expansion::default();
     let mut stack: Vec<PathBuf> = Vec::new();
     expand(
         content,
+        None,
         base_dir,
         &root,
         &base_dir.display().to_string(),
         &mut stack,
-        &mut includes,
         &mut out,
     )?;
     Ok(ResolvedFlagfile {
-        content: out,
-        includes,
+        content: out.content,
+        includes: out.includes,
+        root: None,
+        origins: out.origins,
     })
 }
 
@@ -66,6 +131,8 @@ pub fn resolve_includes_from_path(path: &Path) -> Result<ResolvedFlagfile, Strin
         return Ok(ResolvedFlagfile {
             content,
             includes: Vec::new(),
+            root: Some(path.to_path_buf()),
+            origins: Vec::new(),
         });
     }
     let dir = parent_dir(path);
@@ -73,21 +140,22 @@ pub fn resolve_includes_from_path(path: &Path) -> Result<ResolvedFlagfile, Strin
         .map_err(|_| format!("could not resolve directory '{}'", dir.display()))?;
     let canonical_self =
         fs::canonicalize(path).map_err(|_| format!("could not resolve '{}'", path.display()))?;
-    let mut includes = Vec::new();
-    let mut out = String::new();
+    let mut out = Expansion::default();
     let mut stack = vec![canonical_self];
     expand(
         &content,
+        None,
         &dir,
         &root,
         &path.display().to_string(),
         &mut stack,
-        &mut includes,
         &mut out,
     )?;
     Ok(ResolvedFlagfile {
-        content: out,
-        includes,
+        content: out.content,
+        includes: out.includes,
+        root: Some(path.to_path_buf()),
+        origins: out.origins,
     })
 }
 
@@ -105,23 +173,27 @@ fn parent_dir(path: &Path) -> PathBuf {
     }
 }
 
-/// Recursively expands `content` into `out`, tracking comment state so
-/// `@include` inside `//` and `/* */` comments is left verbatim.
+/// Recursively expands `content` — the root file's or `includes[current]`'s
+/// — into `out`, tracking comment state so `@include` inside `//` and
+/// `/* */` comments is left verbatim.
 fn expand(
     content: &str,
+    current: Option<usize>,
     dir: &Path,
     root: &Path,
     includer: &str,
     stack: &mut Vec<PathBuf>,
-    includes: &mut Vec<IncludedFile>,
-    out: &mut String,
+    out: &mut Expansion,
 ) -> Result<(), String> {
     let mut in_block_comment = false;
-    for line in content.lines() {
+    for (number, line) in content.lines().enumerate() {
+        let origin = LineOrigin {
+            include: current,
+            line: number + 1,
+        };
         let trimmed = line.trim();
         if in_block_comment {
-            out.push_str(line);
-            out.push('\n');
+            out.push_line(line, origin);
             if trimmed.contains("*/") {
                 in_block_comment = false;
             }
@@ -136,8 +208,7 @@ fn expand(
             parse_include_line(line)
         };
         let Some(raw_path) = include_path else {
-            out.push_str(line);
-            out.push('\n');
+            out.push_line(line, origin);
             continue;
         };
 
@@ -166,18 +237,18 @@ fn expand(
         }
         let file_content = fs::read_to_string(&joined)
             .map_err(|_| format!("could not read included file '{}'", joined.display()))?;
-        includes.push(IncludedFile {
+        out.includes.push(IncludedFile {
             path: joined.clone(),
             content: file_content.clone(),
         });
         stack.push(canonical);
         expand(
             &file_content,
+            Some(out.includes.len() - 1),
             &parent_dir(&joined),
             root,
             &joined.display().to_string(),
             stack,
-            includes,
             out,
         )?;
         stack.pop();
@@ -185,6 +256,14 @@ fn expand(
     Ok(())
 }
 
+impl Expansion {
+    fn push_line(&mut self, line: &str, origin: LineOrigin) {
+        self.content.push_str(line);
+        self.content.push('\n');
+        self.origins.push(origin);
+    }
+}
+
 /// Returns the include path if the line is an `@include` directive.
 fn parse_include_line(line: &str) -> Option<&str> {
     let rest = line.trim().strip_prefix("@include")?;
@@ -1,27 +1,32 @@
-use std::collections::HashMap;
 use std::sync::{OnceLock, RwLock};
 
-use wasm_bindgen::prelude::wasm_bindgen;
-
 pub mod ast;
 pub mod builder;
+pub mod cst;
+pub mod edit;
 pub mod eval;
+pub mod evaluation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+pub mod flagfile;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod include;
 pub mod parse;
+pub mod parse_error;
 pub mod parse_flagfile;
+pub mod plan;
 pub mod transpile;
+pub mod wasm;
 
-pub use ast::FlagMetadata;
+pub use ast::{FlagMetadata, ValueType};
 pub use eval::{Context, Segments};
+pub use evaluation::{EvaluationDetail, Reason};
+pub use flagfile::{Flagfile, LoadError};
 pub use parse_flagfile::{
     extract_test_annotations, FlagDefinition, FlagReturn, ParsedFlagfile, Rule, TestAnnotation,
 };
 
-static FLAGS: OnceLock<RwLock<HashMap<String, Vec<Rule>>>> = OnceLock::new();
-static METADATA: OnceLock<RwLock<HashMap<String, FlagMetadata>>> = OnceLock::new();
-static SEGMENTS: OnceLock<RwLock<Segments>> = OnceLock::new();
-static ENVIRONMENT: OnceLock<RwLock<Option<String>>> = OnceLock::new();
+static FLAGFILE: OnceLock<RwLock<Flagfile>> = OnceLock::new();
 
 /// Returns a builder for configuring flagfile initialization.
 /// Without any chaining, behaves identically to the previous `init()`.
@@ -71,46 +76,16 @@ pub fn init_with_env(env: &str) {
 /// Parse content and store in global state. Returns an error on parse failure
 /// instead of panicking, making it safe for background reloads (e.g. SSE).
 pub(crate) fn parse_and_store(content: &str, env: Option<String>) -> Result<(), String> {
-    let (remainder, parsed) = parse_flagfile::parse_flagfile_with_segments(content)
-        .map_err(|e| format!("Failed to parse Flagfile: {}", e))?;
-    if !remainder.trim().is_empty() {
-        let near = remainder.trim().lines().next().unwrap_or("");
-        let hint = if near.starts_with("@include") {
-            " (@include directives are only resolved when loading from a file path — use init().file(...) or the ff CLI)"
-        } else {
-            ""
-        };
-        return Err(format!(
-            "Flagfile parsing failed: unexpected content near: {}{}",
-            near, hint
-        ));
-    }
-    let mut flags: HashMap<String, Vec<Rule>> = HashMap::new();
-    let mut metadata_map: HashMap<String, FlagMetadata> = HashMap::new();
-    for fv in parsed.flags {
-        for (name, def) in fv {
-            flags.insert(name.to_string(), def.rules);
-            metadata_map.insert(name.to_string(), def.metadata);
-        }
+    let mut flagfile = Flagfile::parse(content)?;
+    if let Some(env) = env {
+        flagfile = flagfile.with_env(env);
     }
     // Use get_or_init so the OnceLock is created on first call, then
-    // subsequent calls (SSE reloads) just update the inner RwLock value.
-    *FLAGS
-        .get_or_init(|| RwLock::new(HashMap::new()))
+    // subsequent calls (SSE reloads) just replace the inner value.
+    *FLAGFILE
+        .get_or_init(|| RwLock::new(Flagfile::default()))
         .write()
-        .unwrap() = flags;
-    *METADATA
-        .get_or_init(|| RwLock::new(HashMap::new()))
-        .write()
-        .unwrap() = metadata_map;
-    *SEGMENTS
-        .get_or_init(|| RwLock::new(Segments::new()))
-        .write()
-        .unwrap() = parsed.segments;
-    *ENVIRONMENT
-        .get_or_init(|| RwLock::new(None))
-        .write()
-        .unwrap() = env;
+        .unwrap() = flagfile;
     Ok(())
 }
 
@@ -125,57 +100,20 @@ pub(crate) fn init_from_str_inner(content: &str, env: Option<String>) {
 ///
 /// Panics if [`init`] or [`init_from_str`] has not been called.
 pub fn ff(flag_name: &str, context: &Context) -> Option<FlagReturn> {
-    let flags_guard = FLAGS
-        .get()
-        .expect("flagfile_lib::init() must be called before ff()")
-        .read()
-        .unwrap();
-    let metadata_guard = METADATA
-        .get()
-        .expect("flagfile_lib::init() must be called before ff()")
-        .read()
-        .unwrap();
-    let segments_guard = SEGMENTS
-        .get()
-        .expect("flagfile_lib::init() must be called before ff()")
-        .read()
-        .unwrap();
-    let env_guard = ENVIRONMENT
+    ff_detail(flag_name, context).value
+}
+
+/// Like [`ff`] but explains the result: the reason, the matched rule's index
+/// and name, and the `@env` block it came from.
+///
+/// Panics if [`init`] or [`init_from_str`] has not been called.
+pub fn ff_detail(flag_name: &str, context: &Context) -> EvaluationDetail {
+    FLAGFILE
         .get()
         .expect("flagfile_lib::init() must be called before ff()")
         .read()
-        .unwrap();
-    let current_env = env_guard.as_deref();
-
-    // Check @requires prerequisites
-    if let Some(meta) = metadata_guard.get(flag_name) {
-        for req in &meta.requires {
-            match flags_guard.get(req.as_str()) {
-                None => return None, // required flag doesn't exist
-                Some(req_rules) => {
-                    match evaluate_rules(
-                        req_rules,
-                        context,
-                        Some(req),
-                        &segments_guard,
-                        current_env,
-                    ) {
-                        Some(FlagReturn::OnOff(true)) => {} // prerequisite satisfied
-                        _ => return None,                   // prerequisite not met
-                    }
-                }
-            }
-        }
-    }
-
-    let rules = flags_guard.get(flag_name)?;
-    evaluate_rules(
-        rules,
-        context,
-        Some(flag_name),
-        &segments_guard,
-        current_env,
-    )
+        .unwrap()
+        .eval_detail(flag_name, context)
 }
 
 /// Returns the metadata annotations for a flag, if any.
@@ -184,60 +122,20 @@ pub fn ff(flag_name: &str, context: &Context) -> Option<FlagReturn> {
 ///
 /// Panics if [`init`] or [`init_from_str`] has not been called.
 pub fn ff_metadata(flag_name: &str) -> Option<FlagMetadata> {
-    let metadata = METADATA
+    FLAGFILE
         .get()
         .expect("flagfile_lib::init() must be called before ff_metadata()")
         .read()
-        .unwrap();
-    metadata.get(flag_name).cloned()
-}
-
-fn evaluate_rules(
-    rules: &[Rule],
-    context: &Context,
-    flag_name: Option<&str>,
-    segments: &Segments,
-    env: Option<&str>,
-) -> Option<FlagReturn> {
-    for rule in rules {
-        match rule {
-            Rule::BoolExpressionValue(expr, return_val, _) => {
-                if let Ok(true) = eval::eval_with_segments(expr, context, flag_name, segments) {
-                    return Some(return_val.clone());
-                }
-            }
-            Rule::Value(return_val) => {
-                return Some(return_val.clone());
-            }
-            Rule::EnvRule {
-                env: rule_env,
-                rules: sub_rules,
-            } => {
-                if env == Some(rule_env.as_str()) {
-                    let result = evaluate_rules(sub_rules, context, flag_name, segments, env);
-                    if result.is_some() {
-                        return result;
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
-#[wasm_bindgen]
-pub fn parse_wasm(i: &str) -> String {
-    let Ok((_i, tree)) = parse::parse(i) else {
-        todo!()
-    };
-    let b = format!("{:?}", tree);
-    b.to_string()
+        .unwrap()
+        .metadata(flag_name)
+        .cloned()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ast::Atom;
+    use std::collections::HashMap;
 
     #[test]
     fn test_evaluate_rules_bool_on() {
@@ -245,7 +143,8 @@ mod tests {
         let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
         let def = &fvs[0]["FF-test-flag"];
         let ctx = Context::new();
-        let result = evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None);
+        let result =
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value;
         assert!(matches!(result, Some(FlagReturn::OnOff(true))));
     }
 
@@ -255,7 +154,8 @@ mod tests {
         let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
         let def = &fvs[0]["FF-disabled"];
         let ctx = Context::new();
-        let result = evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None);
+        let result =
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value;
         assert!(matches!(result, Some(FlagReturn::OnOff(false))));
     }
 
@@ -271,14 +171,14 @@ mod tests {
         // matching context
         let ctx: Context = HashMap::from([("plan", Atom::String("premium".to_string()))]);
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value,
             Some(FlagReturn::OnOff(true))
         ));
 
         // non-matching context falls through to default
         let ctx: Context = HashMap::from([("plan", Atom::String("free".to_string()))]);
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value,
             Some(FlagReturn::OnOff(false))
         ));
     }
@@ -289,7 +189,8 @@ mod tests {
         let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
         let def = &fvs[0]["FF-config"];
         let ctx = Context::new();
-        let result = evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None);
+        let result =
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value;
         assert!(matches!(result, Some(FlagReturn::Json(_))));
     }
 
@@ -300,18 +201,45 @@ mod tests {
         let def = &fvs[0]["FF-timeout"];
         let ctx = Context::new();
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value,
             Some(FlagReturn::Integer(5000))
         ));
     }
 
+    #[test]
+    fn test_parse_and_store_rejects_declared_type_mismatch() {
+        let err = parse_and_store("@type bool\nFF-retry-count -> 3", None).unwrap_err();
+        assert!(err.contains("FF-retry-count: rule returns int 3 but flag is declared @type bool"));
+    }
+
+    #[test]
+    fn test_evaluate_rules_variant_return() {
+        let content = r#"FF-checkout {
+    @variants {
+        control -> json({"steps": 4})
+        express -> json({"steps": 1})
+    }
+    plan == premium -> express
+    control
+}"#;
+        let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
+        let def = &fvs[0]["FF-checkout"];
+        let ctx: Context = HashMap::from([("plan", Atom::String("premium".to_string()))]);
+        let result = evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None)
+            .value
+            .unwrap();
+        assert_eq!(result.variant_name(), Some("express"));
+        assert!(matches!(result.value(), FlagReturn::Json(v) if v["steps"] == 1));
+    }
+
     #[test]
     fn test_evaluate_rules_string_return() {
         let content = r#"FF-level -> "debug""#;
         let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
         let def = &fvs[0]["FF-level"];
         let ctx = Context::new();
-        let result = evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None);
+        let result =
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value;
         assert!(matches!(result, Some(FlagReturn::Str(ref s)) if s == "debug"));
     }
 
@@ -323,7 +251,11 @@ mod tests {
         let (_, fvs) = parse_flagfile::parse_flagfile(content).unwrap();
         let def = &fvs[0]["FF-strict"];
         let ctx: Context = HashMap::from([("plan", Atom::String("free".to_string()))]);
-        assert!(evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).is_none());
+        assert!(
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None)
+                .value
+                .is_none()
+        );
     }
 
     #[test]
@@ -352,21 +284,34 @@ mod tests {
 
         // With env=dev, should return true
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")).value,
             Some(FlagReturn::OnOff(true))
         ));
 
         // With env=prod, should return false
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod"))
+                .value,
             Some(FlagReturn::OnOff(false))
         ));
 
         // With env=stage (no match), should return None
-        assert!(evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("stage")).is_none());
+        assert!(evaluation::evaluate_rules(
+            &def.rules,
+            &ctx,
+            None,
+            &Segments::new(),
+            Some("stage")
+        )
+        .value
+        .is_none());
 
         // With no env, should skip @env rules and return None
-        assert!(evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).is_none());
+        assert!(
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None)
+                .value
+                .is_none()
+        );
     }
 
     #[test]
@@ -384,26 +329,28 @@ mod tests {
 
         // env=prod, plan=premium -> true (from sub-rule match)
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod"))
+                .value,
             Some(FlagReturn::OnOff(true))
         ));
 
         // env=prod, plan=free -> false (from sub-rule default)
         let ctx: Context = HashMap::from([("plan", Atom::String("free".to_string()))]);
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod"))
+                .value,
             Some(FlagReturn::OnOff(false))
         ));
 
         // env=dev -> skip @env prod, fall through to true
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")).value,
             Some(FlagReturn::OnOff(true))
         ));
 
         // No env -> skip @env rules, fall through to true
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), None).value,
             Some(FlagReturn::OnOff(true))
         ));
     }
@@ -420,15 +367,17 @@ mod tests {
         let ctx = Context::new();
 
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("dev")).value,
             Some(FlagReturn::OnOff(true))
         ));
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("stage")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("stage"))
+                .value,
             Some(FlagReturn::OnOff(true))
         ));
         assert!(matches!(
-            evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod")),
+            evaluation::evaluate_rules(&def.rules, &ctx, None, &Segments::new(), Some("prod"))
+                .value,
             Some(FlagReturn::OnOff(false))
         ));
     }
@@ -438,7 +387,7 @@ mod tests {
         // This test can only run once per process due to OnceLock.
         // If other tests already called init, this will panic, so we
         // guard it.
-        if FLAGS.get().is_some() {
+        if FLAGFILE.get().is_some() {
             return;
         }
         let content = r#"FF-hello -> true
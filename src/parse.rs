@@ -1,16 +1,24 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use std::cell::Cell;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
 use nom::{
     branch::alt,
     bytes::complete::{tag, tag_no_case, take_until},
+    character::complete::multispace1,
     character::complete::{alpha1, alphanumeric1, char, digit1, multispace0},
-    combinator::{cut, map, opt, recognize},
+    combinator::{all_consuming, cut, map, map_opt, map_res, not, opt, recognize, verify},
     error::ParseError,
-    multi::{many0, many0_count, separated_list0},
-    sequence::{delimited, pair, tuple},
+    multi::{many0, many0_count, many1, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
 
-use crate::ast::{ArrayOp, AstNode, Atom, ComparisonOp, FnCall, LogicOp, MatchOp};
+use regex::{Regex, RegexBuilder};
+
+use crate::ast::{
+    ActiveWindow, ArithOp, ArrayOp, AstNode, Atom, ComparisonOp, FnCall, LogicOp, MatchOp,
+};
 
 /// Took from nom recipes
 pub fn ws<'a, F, O, E: ParseError<&'a str>>(
@@ -24,11 +32,11 @@ where
 
 fn parse_number(i: &str) -> IResult<&str, Atom> {
     let parser = recognize(pair(opt(tag("-")), digit1));
-    map(parser, |num: &str| Atom::Number(num.parse().unwrap()))(i)
+    map_res(parser, |num: &str| num.parse().map(Atom::Number))(i)
 }
 
 /// modified original double parser to always have "." for floats
-fn parse_float(i: &str) -> IResult<&str, Atom> {
+pub(crate) fn parse_float(i: &str) -> IResult<&str, Atom> {
     let parser = recognize(tuple((
         opt(alt((char('+'), char('-')))),
         alt((
@@ -56,12 +64,56 @@ pub fn parse_boolean(i: &str) -> IResult<&str, Atom> {
 fn parse_date(i: &str) -> IResult<&str, Atom> {
     let parser = recognize(tuple((digit1, char('-'), digit1, char('-'), digit1)));
 
-    map(parser, |date_str: &str| {
-        let dt = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").expect("Invalid date format");
-        Atom::Date(dt)
+    // `2026-02-30` has the shape of a date but isn't one
+    map_res(parser, |date_str: &str| {
+        NaiveDate::parse_from_str(date_str, "%Y-%m-%d").map(Atom::Date)
     })(i)
 }
 
+thread_local! {
+    static TIMEZONE: Cell<Tz> = const { Cell::new(Tz::UTC) };
+}
+
+/// Run `f` with datetimes that have no offset read in `tz`, as the
+/// Flagfile parser does for a file's `@timezone`. Outside it they are UTC.
+pub(crate) fn with_timezone<T>(tz: Tz, f: impl FnOnce() -> T) -> T {
+    let previous = TIMEZONE.with(|cell| cell.replace(tz));
+    let out = f();
+    TIMEZONE.with(|cell| cell.set(previous));
+    out
+}
+
+/// `local` as wall-clock time in the current `@timezone`. A time clocks go
+/// back over is the earlier instant; one they skip is read an hour later.
+fn from_local(local: NaiveDateTime) -> DateTime<Utc> {
+    let tz = TIMEZONE.with(Cell::get);
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(local + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map_or_else(|| local.and_utc(), |dt| dt.with_timezone(&Utc))
+}
+
+/// `Z`, `+02:00` or `-05:30` after a datetime.
+fn parse_utc_offset(i: &str) -> IResult<&str, FixedOffset> {
+    alt((
+        map(char('Z'), |_| FixedOffset::east_opt(0).unwrap()),
+        map_opt(
+            tuple((alt((char('+'), char('-'))), digit1, char(':'), digit1)),
+            |(sign, hours, _, minutes): (char, &str, char, &str)| {
+                let (hours, minutes) = (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?);
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let seconds = (hours * 60 + minutes) * 60;
+                FixedOffset::east_opt(if sign == '-' { -seconds } else { seconds })
+            },
+        ),
+    ))(i)
+}
+
 fn parse_datetime(i: &str) -> IResult<&str, Atom> {
     let parser = recognize(tuple((
         digit1,
@@ -73,17 +125,78 @@ fn parse_datetime(i: &str) -> IResult<&str, Atom> {
         digit1,
         char(':'),
         digit1,
-        char(':'),
-        digit1,
-        opt(char('Z')),
+        opt(pair(char(':'), digit1)),
     )));
+    map_res(
+        pair(parser, opt(parse_utc_offset)),
+        |(dt_str, offset): (&str, Option<FixedOffset>)| {
+            // seconds may be left out: `2026-11-01T00:00Z`
+            let format = if dt_str.matches(':').count() == 2 {
+                "%Y-%m-%dT%H:%M:%S"
+            } else {
+                "%Y-%m-%dT%H:%M"
+            };
+            let local = NaiveDateTime::parse_from_str(dt_str, format)?;
+            let dt = match offset {
+                Some(offset) => (local - offset).and_utc(),
+                None => from_local(local),
+            };
+            Ok::<_, chrono::ParseError>(Atom::DateTime(dt))
+        },
+    )(i)
+}
 
-    map(parser, |dt_str: &str| {
-        let clean = dt_str.strip_suffix('Z').unwrap_or(dt_str);
-        let dt = NaiveDateTime::parse_from_str(clean, "%Y-%m-%dT%H:%M:%S")
-            .expect("Invalid datetime format");
-        Atom::DateTime(dt)
-    })(i)
+/// A date or datetime literal as a point in time; a date is its midnight
+/// in the current `@timezone`.
+pub(crate) fn parse_instant(i: &str) -> IResult<&str, DateTime<Utc>> {
+    let (rest, atom) = alt((parse_datetime, parse_date))(i)?;
+    match atom {
+        Atom::DateTime(dt) => Ok((rest, dt)),
+        Atom::Date(d) => Ok((rest, from_local(d.and_time(chrono::NaiveTime::MIN)))),
+        _ => unreachable!(),
+    }
+}
+
+/// `from <instant>`, `until <instant>` or both, as in `@active`.
+pub(crate) fn parse_active_window(i: &str) -> IResult<&str, ActiveWindow> {
+    let (rest, from) = opt(preceded(ws(tag_no_case("from")), ws(parse_instant)))(i)?;
+    let (rest, until) = opt(preceded(ws(tag_no_case("until")), ws(parse_instant)))(rest)?;
+    if from.is_none() && until.is_none() {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Tag,
+        )));
+    }
+    Ok((rest, ActiveWindow { from, until }))
+}
+
+/// `30d`, `12h` or `1h30m`: counts followed by `w`, `d`, `h`, `m`, `s` or
+/// `ms`.
+fn parse_duration(i: &str) -> IResult<&str, Atom> {
+    let unit = alt((
+        map(tag("ms"), |_| TimeDelta::milliseconds(1)),
+        map(char('w'), |_| TimeDelta::weeks(1)),
+        map(char('d'), |_| TimeDelta::days(1)),
+        map(char('h'), |_| TimeDelta::hours(1)),
+        map(char('m'), |_| TimeDelta::minutes(1)),
+        map(char('s'), |_| TimeDelta::seconds(1)),
+    ));
+    let part = map_opt(pair(digit1, unit), |(count, unit): (&str, TimeDelta)| {
+        unit.checked_mul(count.parse().ok()?)
+    });
+    let (rest, parts) = many1(part)(i)?;
+    let total = parts
+        .into_iter()
+        .try_fold(TimeDelta::zero(), |acc, part| acc.checked_add(&part));
+    match total {
+        Some(d) if !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') => {
+            Ok((rest, Atom::Duration(d)))
+        }
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Verify,
+        ))),
+    }
 }
 
 fn parse_semver(i: &str) -> IResult<&str, Atom> {
@@ -107,11 +220,55 @@ fn parse_string(i: &str) -> IResult<&str, Atom> {
     map(parser, |s: &str| Atom::String(s.to_string()))(i)
 }
 
-fn parse_variable(i: &str) -> IResult<&str, Atom> {
-    let parser = recognize(pair(
+pub(crate) fn parse_identifier(i: &str) -> IResult<&str, &str> {
+    recognize(pair(
         alt((alpha1, tag("_"))),
         many0_count(alt((alphanumeric1, tag("_")))),
+    ))(i)
+}
+
+fn parse_variable(i: &str) -> IResult<&str, Atom> {
+    map(parse_identifier, |v: &str| Atom::Variable(v.to_string()))(i)
+}
+
+/// One step into a nested context value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathSegment<'a> {
+    /// `.tier` or `["tier"]`
+    Key(&'a str),
+    /// `[0]`
+    Index(usize),
+}
+
+fn parse_path_segment(i: &str) -> IResult<&str, PathSegment<'_>> {
+    let quoted = alt((
+        delimited(char('"'), take_until("\""), char('"')),
+        delimited(char('\''), take_until("'"), char('\'')),
     ));
+    let index = map_res(digit1, str::parse::<usize>);
+    alt((
+        map(preceded(char('.'), parse_identifier), PathSegment::Key),
+        delimited(
+            char('['),
+            alt((
+                map(quoted, PathSegment::Key),
+                map(index, PathSegment::Index),
+            )),
+            char(']'),
+        ),
+    ))(i)
+}
+
+/// Split `user.company["tier"]` into its root and the steps below it.
+pub(crate) fn parse_path(name: &str) -> Option<(&str, Vec<PathSegment<'_>>)> {
+    all_consuming(pair(parse_identifier, many0(parse_path_segment)))(name)
+        .ok()
+        .map(|(_, path)| path)
+}
+
+/// A context attribute, optionally a path into a nested value.
+fn parse_variable_path(i: &str) -> IResult<&str, Atom> {
+    let parser = recognize(pair(parse_identifier, many0_count(parse_path_segment)));
     map(parser, |v: &str| Atom::Variable(v.to_string()))(i)
 }
 
@@ -155,7 +312,7 @@ fn parse_list(i: &str) -> IResult<&str, AstNode> {
     map(parser, AstNode::List)(i)
 }
 fn parse_variable_node(i: &str) -> IResult<&str, AstNode> {
-    map(parse_variable, AstNode::Variable)(i)
+    map(parse_variable_path, AstNode::Variable)(i)
 }
 
 fn parse_variable_node_modifier(i: &str) -> IResult<&str, AstNode> {
@@ -168,11 +325,33 @@ fn parse_variable_node_modifier(i: &str) -> IResult<&str, AstNode> {
     })(i)
 }
 
+/// `var(shippingCountry)`: a context variable where a bare word would be
+/// text, on the right of a comparison.
+fn parse_var_reference(i: &str) -> IResult<&str, AstNode> {
+    let (i, _) = tag_no_case("var")(i)?;
+    delimited(char('('), ws(parse_variable_node), char(')'))(i)
+}
+
+/// `fn:region(ip)`: a function the host application registers. Any name is
+/// accepted here; arguments read like arithmetic, so bare words are variables.
+fn parse_function_call(i: &str) -> IResult<&str, AstNode> {
+    let (i, _) = tag("fn:")(i)?;
+    let (i, name) = parse_identifier(i)?;
+    let (i, args) = delimited(
+        pair(char('('), multispace0),
+        separated_list0(ws(char(',')), parse_arith),
+        pair(multispace0, char(')')),
+    )(i)?;
+    Ok((i, AstNode::Call(name.to_string(), args)))
+}
+
 fn parse_variable_node_or_modified(i: &str) -> IResult<&str, AstNode> {
     alt((
+        parse_function_call,
         parse_coalesce,
         parse_nullary_function,
         parse_variable_node_modifier,
+        parse_var_reference,
         parse_variable_node,
     ))(i)
 }
@@ -181,6 +360,17 @@ fn parse_constant(i: &str) -> IResult<&str, AstNode> {
     map(parse_atom, AstNode::Constant)(i)
 }
 
+/// A condition on its own: a literal, or a boolean attribute that may sit
+/// inside a nested value (`user.beta`).
+fn parse_bare_condition(i: &str) -> IResult<&str, AstNode> {
+    alt((
+        verify(parse_constant, |node| {
+            !matches!(node, AstNode::Constant(Atom::Variable(_)))
+        }),
+        map(parse_variable_path, AstNode::Constant),
+    ))(i)
+}
+
 fn parse_array_op(i: &str) -> IResult<&str, ArrayOp> {
     alt((
         map(tag_no_case("not in"), |_| ArrayOp::NotIn),
@@ -228,9 +418,10 @@ fn parse_segment_call(i: &str) -> IResult<&str, AstNode> {
     let (i, _) = char('(')(i)?;
     let (i, _) = multispace0(i)?;
     let (i, name) = parse_segment_name(i)?;
+    let (i, args) = many0(preceded(ws(char(',')), parse_atom))(i)?;
     let (i, _) = multispace0(i)?;
     let (i, _) = char(')')(i)?;
-    Ok((i, AstNode::Segment(name.to_string())))
+    Ok((i, AstNode::Segment(name.to_string(), args)))
 }
 
 fn parse_nullary_function(i: &str) -> IResult<&str, AstNode> {
@@ -249,22 +440,139 @@ fn parse_array_expr(i: &str) -> IResult<&str, AstNode> {
     })(i)
 }
 
+fn parse_add_op(i: &str) -> IResult<&str, ArithOp> {
+    alt((
+        map(ws(char('+')), |_| ArithOp::Add),
+        // `-` needs a space before it, so an unquoted `user-123` is an error
+        // rather than `user` minus 123, and `->` is the arrow.
+        map(
+            delimited(
+                multispace1,
+                terminated(char('-'), not(char('>'))),
+                multispace0,
+            ),
+            |_| ArithOp::Sub,
+        ),
+    ))(i)
+}
+
+fn parse_mul_op(i: &str) -> IResult<&str, ArithOp> {
+    alt((
+        map(char('*'), |_| ArithOp::Mul),
+        map(char('/'), |_| ArithOp::Div),
+        map(char('%'), |_| ArithOp::Rem),
+    ))(i)
+}
+
+/// An operand of arithmetic. Bare words are variables here, not text.
+fn parse_arith_operand(i: &str) -> IResult<&str, AstNode> {
+    alt((
+        delimited(
+            pair(char('('), multispace0),
+            parse_arith,
+            pair(multispace0, char(')')),
+        ),
+        map(
+            alt((
+                parse_datetime,
+                parse_date,
+                parse_duration,
+                parse_string,
+                parse_float,
+                parse_number,
+            )),
+            AstNode::Constant,
+        ),
+        parse_variable_node_or_modified,
+    ))(i)
+}
+
+fn parse_arith_term(i: &str) -> IResult<&str, AstNode> {
+    let (i, mut head) = parse_arith_operand(i)?;
+    let (i, tail) = many0(pair(ws(parse_mul_op), parse_arith_operand))(i)?;
+    for (op, operand) in tail {
+        head = AstNode::Arithmetic(Box::new(head), op, Box::new(operand));
+    }
+    Ok((i, head))
+}
+
+/// `+` and `-` bind looser than `*`, `/` and `%`; all are left-associative.
+fn parse_arith(i: &str) -> IResult<&str, AstNode> {
+    let (i, mut head) = parse_arith_term(i)?;
+    let (i, tail) = many0(pair(parse_add_op, parse_arith_term))(i)?;
+    for (op, term) in tail {
+        head = AstNode::Arithmetic(Box::new(head), op, Box::new(term));
+    }
+    Ok((i, head))
+}
+
+/// Arithmetic with at least one operator.
+fn parse_computed(i: &str) -> IResult<&str, AstNode> {
+    verify(parse_arith, |node| matches!(node, AstNode::Arithmetic(..)))(i)
+}
+
+/// The right side of a comparison: a literal, where a bare word is text, or
+/// a value computed from the context.
+fn parse_compare_rhs(i: &str) -> IResult<&str, AstNode> {
+    alt((
+        parse_computed,
+        map(parse_duration, AstNode::Constant),
+        parse_coalesce,
+        parse_nullary_function,
+        parse_variable_node_modifier,
+        parse_var_reference,
+        parse_function_call,
+        parse_constant,
+    ))(i)
+}
+
 fn parse_compare_expr(i: &str) -> IResult<&str, AstNode> {
     let parser = tuple((
-        parse_variable_node_or_modified,
+        alt((parse_computed, parse_variable_node_or_modified)),
         ws(parse_comparison_op),
-        parse_constant,
+        parse_compare_rhs,
     ));
     map(parser, |(var, op, val)| {
         AstNode::Compare(Box::new(var), op, Box::new(val))
     })(i)
 }
 
+/// Longest regex literal accepted, in bytes.
+pub const MAX_REGEX_LEN: usize = 1024;
+/// Upper bound on the compiled program and lazy DFA cache of a regex literal.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const REGEX_NEST_LIMIT: u32 = 32;
+
+/// Compile a regex literal with the limits applied to Flagfile patterns.
+///
+/// The `regex` crate matches in linear time, so there is no catastrophic
+/// backtracking; the limits bound how much memory a pattern like
+/// `(a{1000}){1000}` may take to compile.
+pub fn compile_regex(pattern: &str) -> Result<Regex, String> {
+    if pattern.len() > MAX_REGEX_LEN {
+        return Err(format!(
+            "pattern is {} bytes, longer than the {} byte limit",
+            pattern.len(),
+            MAX_REGEX_LEN
+        ));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .nest_limit(REGEX_NEST_LIMIT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// A pattern that doesn't compile is a hard failure pointing at the pattern,
+/// so it is reported instead of being skipped as unparsed content.
 fn parse_regex_literal(i: &str) -> IResult<&str, Atom> {
     let (i, _) = tag("/")(i)?;
-    let (i, pattern) = take_until("/")(i)?;
-    let (i, _) = tag("/")(i)?;
-    Ok((i, Atom::Regex(pattern.to_string())))
+    let (rest, pattern) = take_until("/")(i)?;
+    let re = compile_regex(pattern)
+        .map_err(|_| nom::Err::Failure(nom::error::Error::new(i, nom::error::ErrorKind::Verify)))?;
+    let (rest, _) = tag("/")(rest)?;
+    Ok((rest, Atom::Regex(re)))
 }
 
 fn parse_match_op(i: &str) -> IResult<&str, MatchOp> {
@@ -355,11 +663,8 @@ fn parse_percentage_salt(i: &str) -> IResult<&str, String> {
     Ok((i, salt_val.to_string()))
 }
 
-fn parse_percentage(i: &str) -> IResult<&str, AstNode> {
-    let (i, _) = tag_no_case("percentage")(i)?;
-    let (i, _) = ws(char('('))(i)?;
-
-    // Parse rate: number followed by '%'
+/// A rate such as `25%` or `0.5%`.
+fn parse_rate(i: &str) -> IResult<&str, f64> {
     let (i, rate_str) = recognize(pair(
         opt(alt((char('+'), char('-')))),
         alt((
@@ -369,7 +674,13 @@ fn parse_percentage(i: &str) -> IResult<&str, AstNode> {
         )),
     ))(i)?;
     let (i, _) = char('%')(i)?;
-    let rate: f64 = rate_str.parse().unwrap();
+    Ok((i, rate_str.parse().unwrap()))
+}
+
+fn parse_percentage(i: &str) -> IResult<&str, AstNode> {
+    let (i, _) = tag_no_case("percentage")(i)?;
+    let (i, _) = ws(char('('))(i)?;
+    let (i, rate) = parse_rate(i)?;
 
     // Parse comma and field name
     let (i, _) = ws(char(','))(i)?;
@@ -390,6 +701,86 @@ fn parse_percentage(i: &str) -> IResult<&str, AstNode> {
     ))
 }
 
+/// `ramp(0% -> 100%, 2026-11-01 .. 2026-11-15, userId)`, with an optional
+/// salt after the field like `percentage`. A ramp that doesn't end after it
+/// starts is a hard failure at `ramp`.
+fn parse_ramp(input: &str) -> IResult<&str, AstNode> {
+    let (i, _) = tag_no_case("ramp")(input)?;
+    let (i, _) = ws(char('('))(i)?;
+    let (i, from) = ws(parse_rate)(i)?;
+    let (i, _) = ws(tag("->"))(i)?;
+    let (i, to) = ws(parse_rate)(i)?;
+    let (i, _) = ws(char(','))(i)?;
+    let (i, start) = ws(parse_instant)(i)?;
+    let (i, _) = ws(tag(".."))(i)?;
+    let (i, end) = ws(parse_instant)(i)?;
+    let (i, _) = ws(char(','))(i)?;
+    let (i, field) = ws(parse_variable_node)(i)?;
+    let (i, salt) = opt(parse_percentage_salt)(i)?;
+    let (i, _) = ws(char(')'))(i)?;
+
+    if end <= start {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((
+        i,
+        AstNode::Ramp {
+            from,
+            to,
+            start,
+            end,
+            field: Box::new(field),
+            salt,
+        },
+    ))
+}
+
+/// Field, salt and weighted arms of a `split(...) { ... }`.
+pub(crate) type SplitParts<V> = (AstNode, Option<String>, Vec<(V, f64)>);
+
+/// Weighted split header and arms: `split(userId) { a: 33%, b: 67% }`, with
+/// an optional salt after the field like `percentage`. Arms are separated by
+/// commas or newlines and their values are read by `value`. Weights that are
+/// negative or don't add up to 100% are a hard failure at `split`.
+pub(crate) fn parse_split<'a, V>(
+    input: &'a str,
+    mut value: impl FnMut(&'a str) -> IResult<&'a str, V>,
+) -> IResult<&'a str, SplitParts<V>> {
+    let (i, _) = tag_no_case("split")(input)?;
+    let (i, _) = ws(char('('))(i)?;
+    let (i, field) = ws(parse_variable_node)(i)?;
+    let (i, salt) = opt(parse_percentage_salt)(i)?;
+    let (i, _) = ws(char(')'))(i)?;
+    let (mut i, _) = ws(char('{'))(i)?;
+
+    let mut arms = Vec::new();
+    loop {
+        let close: IResult<&str, char> = ws(char('}'))(i);
+        if let Ok((rest, _)) = close {
+            i = rest;
+            break;
+        }
+        let (rest, v) = value(i)?;
+        let (rest, _) = ws(char(':'))(rest)?;
+        let (rest, weight) = ws(parse_rate)(rest)?;
+        let (rest, _) = opt(ws(char(',')))(rest)?;
+        arms.push((v, weight));
+        i = rest;
+    }
+
+    let total: f64 = arms.iter().map(|(_, w)| w).sum();
+    if arms.is_empty() || arms.iter().any(|(_, w)| *w < 0.0) || (total - 100.0).abs() > 1e-9 {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((i, (field, salt, arms)))
+}
+
 fn parse_parenthesized_expr(i: &str) -> IResult<&str, AstNode> {
     let parser = tuple((
         opt(alt((tag_no_case("not"), tag("!")))),
@@ -406,18 +797,23 @@ fn parse_expr(input: &str) -> IResult<&str, AstNode> {
     let (i, mut head) = alt((
         parse_parenthesized_expr,
         parse_percentage,
+        parse_ramp,
         parse_segment_call,
         parse_logic_expr,
         parse_compare_or_array_expr,
-        parse_constant,
+        // `fn:entitled(accountId, "sso") -> true`: a predicate on its own
+        parse_function_call,
+        parse_bare_condition,
     ))(input)?;
 
     let (i, tail) = many0(pair(
         ws(parse_logic_op),
         alt((
             parse_percentage,
+            parse_ramp,
             parse_segment_call,
             parse_compare_or_array_expr,
+            parse_function_call,
             parse_parenthesized_expr,
         )),
     ))(i)?;
@@ -493,6 +889,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float() {
         let (_, v) = parse_atom("3.14").unwrap();
         assert_eq!(v, Atom::Float(3.14));
@@ -598,7 +995,9 @@ mod tests {
         if let Ok((i, v)) = res {
             assert_eq!(i, "");
             let expected =
-                NaiveDateTime::parse_from_str("2025-06-15T09:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+                NaiveDateTime::parse_from_str("2025-06-15T09:00:00", "%Y-%m-%dT%H:%M:%S")
+                    .unwrap()
+                    .and_utc();
             assert_eq!(v, Atom::DateTime(expected));
         }
     }
@@ -613,6 +1012,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_datetime_without_seconds() {
+        let (i, v) = parse_datetime("2026-11-01T09:30Z").unwrap();
+        assert_eq!(i, "");
+        let expected = NaiveDateTime::parse_from_str("2026-11-01T09:30:00", "%Y-%m-%dT%H:%M:%S")
+            .unwrap()
+            .and_utc();
+        assert_eq!(v, Atom::DateTime(expected));
+    }
+
+    #[test]
+    fn test_parse_datetime_offsets_and_timezone() {
+        let utc = |s: &str| Atom::DateTime(s.parse().unwrap());
+        assert_eq!(
+            parse_datetime("2026-12-01T09:00+02:00").unwrap().1,
+            utc("2026-12-01T07:00:00Z")
+        );
+        assert_eq!(
+            parse_datetime("2026-12-01T01:00:00-05:30").unwrap().1,
+            utc("2026-12-01T06:30:00Z")
+        );
+        let vilnius: Tz = "Europe/Vilnius".parse().unwrap();
+        // Winter is UTC+2, summer UTC+3; an explicit offset wins over the zone.
+        with_timezone(vilnius, || {
+            assert_eq!(
+                parse_datetime("2026-12-01T00:00").unwrap().1,
+                utc("2026-11-30T22:00:00Z")
+            );
+            assert_eq!(
+                parse_datetime("2026-07-01T00:00").unwrap().1,
+                utc("2026-06-30T21:00:00Z")
+            );
+            assert_eq!(
+                parse_datetime("2026-07-01T00:00Z").unwrap().1,
+                utc("2026-07-01T00:00:00Z")
+            );
+            // 03:30 on 29 March does not exist; it is read an hour later.
+            assert_eq!(
+                parse_datetime("2026-03-29T03:30").unwrap().1,
+                utc("2026-03-29T01:30:00Z")
+            );
+        });
+        assert_eq!(
+            parse_datetime("2026-12-01T00:00").unwrap().1,
+            utc("2026-12-01T00:00:00Z")
+        );
+    }
+
     #[test]
     fn test_datetime_before_date_in_atom() {
         // DateTime should be parsed as DateTime, not Date
@@ -632,6 +1079,134 @@ mod tests {
         assert_eq!(i, "");
     }
 
+    #[test]
+    fn test_parse_duration() {
+        fn d(s: &str) -> IResult<&str, String> {
+            map(parse_duration, |atom| atom.to_string())(s)
+        }
+        assert_eq!(d("30d"), Ok(("", "30d".to_string())));
+        assert_eq!(d("1h30m rest"), Ok((" rest", "1h30m".to_string())));
+        assert_eq!(d("2w"), Ok(("", "14d".to_string())));
+        assert_eq!(d("90s"), Ok(("", "1m30s".to_string())));
+        assert_eq!(d("250ms"), Ok(("", "250ms".to_string())));
+        assert!(d("30days").is_err());
+        assert!(d("1.5h").is_err());
+        assert!(d("30").is_err());
+    }
+
+    #[test]
+    fn test_parse_arithmetic() {
+        let var = |v: &str| Box::new(AstNode::Variable(Atom::Variable(v.into())));
+        let num = |n: i64| Box::new(AstNode::Constant(Atom::Number(n)));
+        // `*` binds tighter than `+`, and both are left-associative
+        let (i, v) = parse_compare_expr("a + b * 2 - c > 1").unwrap();
+        assert_eq!(i, "");
+        let AstNode::Compare(lhs, ComparisonOp::More, _) = v else {
+            panic!("expected a comparison, got {v:?}");
+        };
+        let product = Box::new(AstNode::Arithmetic(var("b"), ArithOp::Mul, num(2)));
+        let sum = Box::new(AstNode::Arithmetic(var("a"), ArithOp::Add, product));
+        assert_eq!(*lhs, AstNode::Arithmetic(sum, ArithOp::Sub, var("c")));
+
+        let (i, v) = parse_compare_expr("(price - discount) * qty >= 100").unwrap();
+        assert_eq!(i, "");
+        assert!(matches!(
+            v,
+            AstNode::Compare(lhs, ..) if matches!(*lhs, AstNode::Arithmetic(_, ArithOp::Mul, _))
+        ));
+
+        let (i, v) = parse_compare_expr("NOW() - signupDate < 30d").unwrap();
+        assert_eq!(i, "");
+        let AstNode::Compare(lhs, _, rhs) = v else {
+            panic!("expected a comparison, got {v:?}");
+        };
+        assert!(matches!(*lhs, AstNode::Arithmetic(ref now, ArithOp::Sub, _)
+            if matches!(**now, AstNode::Function(FnCall::Now, _))));
+        assert_eq!(*rhs, AstNode::Constant(Atom::Duration(TimeDelta::days(30))));
+
+        // a bare word on the right is still text; var() reads the context
+        let (_, v) = parse_compare_expr("billing != shipping").unwrap();
+        assert_eq!(
+            v,
+            AstNode::Compare(
+                var("billing"),
+                ComparisonOp::NotEq,
+                Box::new(AstNode::Constant(Atom::Variable("shipping".into())))
+            )
+        );
+        let (_, v) = parse_compare_expr("billing != var(shipping)").unwrap();
+        assert_eq!(
+            v,
+            AstNode::Compare(var("billing"), ComparisonOp::NotEq, var("shipping"))
+        );
+        let (i, v) = parse_compare_expr("expires > created + 7d").unwrap();
+        assert_eq!(i, "");
+        assert!(
+            matches!(v, AstNode::Compare(_, _, rhs) if matches!(*rhs, AstNode::Arithmetic(..)))
+        );
+
+        // `-` needs a space before it, and `->` is never a subtraction
+        assert_eq!(parse_compare_expr("userId == user-123").unwrap().0, "-123");
+        assert_eq!(parse_compare_expr("userId == \"user-123\"").unwrap().0, "");
+        assert_eq!(
+            parse_compare_expr("qty == 2 -> true").unwrap().0,
+            " -> true"
+        );
+    }
+
+    #[test]
+    fn test_parse_function_call() {
+        let var = |n: &str| AstNode::Variable(Atom::Variable(n.into()));
+        let (i, v) = parse("fn:region(ip) == \"eu\"").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(
+            v,
+            AstNode::Compare(
+                Box::new(AstNode::Call("region".into(), vec![var("ip")])),
+                ComparisonOp::Eq,
+                Box::new(AstNode::Constant(Atom::String("eu".into())))
+            )
+        );
+
+        // a predicate on its own, with several arguments and no arguments
+        let (i, v) = parse("fn:entitled(account.id, \"sso\") and fn:business_hours()").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(
+            v,
+            AstNode::Logic(
+                Box::new(AstNode::Call(
+                    "entitled".into(),
+                    vec![
+                        var("account.id"),
+                        AstNode::Constant(Atom::String("sso".into()))
+                    ]
+                )),
+                LogicOp::And,
+                Box::new(AstNode::Call("business_hours".into(), vec![]))
+            )
+        );
+
+        // on the right, inside arithmetic, and as a list or match operand
+        assert!(parse("plan == fn:plan_of(accountId)").unwrap().0.is_empty());
+        assert!(parse("fn:score(userId) * 2 - 1 >= 10")
+            .unwrap()
+            .0
+            .is_empty());
+        assert!(parse("fn:region(ip) in (eu, uk)").unwrap().0.is_empty());
+        assert!(parse("fn:tier( user.id , cartTotal * 100 ) ~ gold")
+            .unwrap()
+            .0
+            .is_empty());
+        assert_eq!(
+            parse("fn:region(ip)").unwrap().1.function_calls(),
+            vec!["region"]
+        );
+
+        // the namespace is required, and so are the parentheses
+        assert!(parse_function_call("region(ip)").is_err());
+        assert!(parse_function_call("fn:region").is_err());
+    }
+
     #[test]
     fn test_datetime_range_expr() {
         let (i, _v) =
@@ -729,6 +1304,21 @@ mod tests {
         assert_eq!(i, "");
     }
 
+    #[test]
+    fn test_parse_match_invalid_regex_fails() {
+        assert!(matches!(
+            parse("name ~ /(unclosed/"),
+            Err(nom::Err::Failure(_))
+        ));
+        let long = format!("name ~ /{}/", "a".repeat(MAX_REGEX_LEN + 1));
+        assert!(matches!(parse(&long), Err(nom::Err::Failure(_))));
+        assert!(compile_regex(
+            "(((((((((((((((((((((((((((((((((a)))))))))))))))))))))))))))))))))"
+        )
+        .unwrap_err()
+        .contains("nest"));
+    }
+
     #[test]
     fn test_parse_match_not_regex() {
         let (i, _v) = parse("name !~ /.*ola.*/").unwrap();
@@ -892,14 +1482,66 @@ mod tests {
     fn test_parse_segment_call() {
         let (i, v) = parse("segment(beta_users)").unwrap();
         assert_eq!(i, "");
-        assert_eq!(v, AstNode::Segment("beta_users".to_string()));
+        assert_eq!(v, AstNode::Segment("beta_users".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_parse_nested_variable_path() {
+        let (i, v) = parse("user.company[\"tier\"] == gold and items[0] ~ x").unwrap();
+        assert_eq!(i, "");
+        let AstNode::Logic(lhs, _, rhs) = v else {
+            panic!("expected a logic node");
+        };
+        let AstNode::Compare(var, _, _) = *lhs else {
+            panic!("expected a comparison");
+        };
+        assert_eq!(var.as_str(), Some("user.company[\"tier\"]"));
+        let AstNode::Match(var, _, _) = *rhs else {
+            panic!("expected a match");
+        };
+        assert_eq!(var.as_str(), Some("items[0]"));
+
+        assert_eq!(
+            parse_path("user.company['tier']"),
+            Some((
+                "user",
+                vec![PathSegment::Key("company"), PathSegment::Key("tier")]
+            ))
+        );
+        assert_eq!(
+            parse_path("items[2]"),
+            Some(("items", vec![PathSegment::Index(2)]))
+        );
+        assert_eq!(parse_path("plain"), Some(("plain", vec![])));
+        assert_eq!(parse_path("user."), None);
+    }
+
+    #[test]
+    fn test_parse_segment_call_with_args() {
+        let (i, v) =
+            parse("segment(in-country, \"NL\") and segment(tier-at-least, premium , 10)").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(
+            v,
+            AstNode::Logic(
+                Box::new(AstNode::Segment(
+                    "in-country".to_string(),
+                    vec![Atom::String("NL".into())]
+                )),
+                LogicOp::And,
+                Box::new(AstNode::Segment(
+                    "tier-at-least".to_string(),
+                    vec![Atom::Variable("premium".into()), Atom::Number(10)]
+                )),
+            )
+        );
     }
 
     #[test]
     fn test_parse_segment_call_with_hyphens() {
         let (i, v) = parse("segment(premium-users)").unwrap();
         assert_eq!(i, "");
-        assert_eq!(v, AstNode::Segment("premium-users".to_string()));
+        assert_eq!(v, AstNode::Segment("premium-users".to_string(), vec![]));
     }
 
     #[test]
@@ -913,4 +1555,71 @@ mod tests {
         let (i, _v) = parse("country == US or segment(enterprise)").unwrap();
         assert_eq!(i, "");
     }
+
+    #[test]
+    fn test_parse_split() {
+        let (i, (field, salt, arms)) = parse_split(
+            "split(userId, exp1) {\n    a: 33%,\n    b: 33.5%\n    c: 33.5%\n}",
+            alpha1,
+        )
+        .unwrap();
+        assert_eq!(i, "");
+        assert_eq!(field, AstNode::Variable(Atom::Variable("userId".into())));
+        assert_eq!(salt.as_deref(), Some("exp1"));
+        assert_eq!(arms, vec![("a", 33.0), ("b", 33.5), ("c", 33.5)]);
+    }
+
+    #[test]
+    fn test_parse_split_weights_must_add_up() {
+        for bad in [
+            "split(userId) { a: 50%, b: 40% }",
+            "split(userId) { a: 120%, b: -20% }",
+            "split(userId) {}",
+        ] {
+            assert!(
+                matches!(parse_split(bad, alpha1), Err(nom::Err::Failure(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_ramp() {
+        let (i, v) =
+            parse("ramp(0% -> 100%, 2026-11-01T00:00Z .. 2026-11-15, userId, exp1)").unwrap();
+        assert_eq!(i, "");
+        let at = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+                .unwrap()
+                .and_utc()
+        };
+        assert_eq!(
+            v,
+            AstNode::Ramp {
+                from: 0.0,
+                to: 100.0,
+                start: at("2026-11-01T00:00"),
+                end: at("2026-11-15T00:00"),
+                field: Box::new(AstNode::Variable(Atom::Variable("userId".into()))),
+                salt: Some("exp1".into()),
+            }
+        );
+        assert!(matches!(
+            parse("ramp(0% -> 100%, 2026-11-15 .. 2026-11-01, userId)"),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_active_window() {
+        let (i, w) = parse_active_window("from 2026-11-01 until 2026-12-01T12:00Z").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(
+            w.to_string(),
+            "from 2026-11-01T00:00:00Z until 2026-12-01T12:00:00Z"
+        );
+        let (_, w) = parse_active_window("until 2026-12-01").unwrap();
+        assert_eq!(w.from, None);
+        assert!(parse_active_window("2026-12-01").is_err());
+    }
 }
@@ -0,0 +1,185 @@
+//! Syntax errors with enough context to point at the offending text: file,
+//! line, column, what the parser expected there and the source line itself.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+/// A Flagfile that failed to parse.
+///
+/// ```
+/// use flagfile_lib::parse_flagfile::parse_flagfile_with_segments;
+///
+/// let err = parse_flagfile_with_segments("FF-a {\n    plan == pro true\n}").unwrap_err();
+/// assert_eq!((err.line, err.column), (2, 17));
+/// assert_eq!(err.expected, ["`->`"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// File the error is in, when the content was read from one. Errors in
+    /// `@include`d content point at the included file.
+    pub file: Option<PathBuf>,
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    /// What would have been accepted at this point, e.g. `` `->` `` or
+    /// `a quoted string`.
+    pub expected: Vec<String>,
+    /// The line the error is on, without its line ending.
+    pub source_line: String,
+}
+
+impl ParseError {
+    /// An error at `rest`, a suffix of `content`.
+    pub(crate) fn at(content: &str, rest: &str, message: impl Into<String>) -> Self {
+        let (line, column) = line_column(content, rest);
+        let source_line = content.lines().nth(line - 1).unwrap_or("");
+        Self {
+            message: message.into(),
+            file: None,
+            line,
+            column,
+            expected: Vec::new(),
+            source_line: source_line.trim_end_matches('\r').to_string(),
+        }
+    }
+
+    pub(crate) fn expecting(mut self, expected: &[&str]) -> Self {
+        self.expected = expected.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    /// `expected `->`` or `expected one of `true`, `false``, if anything
+    /// was expected.
+    pub fn expected_hint(&self) -> Option<String> {
+        match self.expected.as_slice() {
+            [] => None,
+            [one] => Some(format!("expected {}", one)),
+            many => Some(format!("expected one of {}", many.join(", "))),
+        }
+    }
+
+    /// The source line with a caret under the error column:
+    ///
+    /// ```text
+    ///   |
+    /// 2 |     plan == pro true
+    ///   |                 ^
+    /// ```
+    pub fn snippet(&self) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // keep tabs so the caret lines up under them
+        let pad: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{gutter} |\n{number} | {}\n{gutter} | {pad}^",
+            self.source_line
+        )
+    }
+
+    /// Multi-line report for terminals, in the style of compiler errors.
+    pub fn render(&self) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let mut out = format!(
+            "error: {}\n{gutter}--> {}\n{}",
+            self.message,
+            self.position(),
+            self.snippet()
+        );
+        if let Some(hint) = self.expected_hint() {
+            out.push_str(&format!("\n{gutter} = {}", hint));
+        }
+        out
+    }
+
+    /// `{"message", "file", "line", "column", "expected", "sourceLine",
+    /// "snippet"}`, for tools that annotate the source.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "message": self.message,
+            "file": self.file.as_ref().map(|f| f.display().to_string()),
+            "line": self.line,
+            "column": self.column,
+            "expected": self.expected,
+            "sourceLine": self.source_line,
+            "snippet": self.snippet(),
+        })
+    }
+
+    /// `path:line:column`, or `line N, column M` without a file.
+    fn position(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}:{}", file.display(), self.line, self.column),
+            None => format!("line {}, column {}", self.line, self.column),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position(), self.message)?;
+        if let Some(hint) = self.expected_hint() {
+            write!(f, " ({})", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// 1-based line and column where `rest`, a suffix of `content`, begins.
+pub(crate) fn line_column(content: &str, rest: &str) -> (usize, usize) {
+    // nom hands back the unparsed suffix of the input
+    let offset = content.len().saturating_sub(rest.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_and_json() {
+        let content = "FF-a -> true\nFF-b {\n\tplan == pro true\n}\n";
+        let rest = &content[content.find("true\n}").unwrap()..];
+        let mut err =
+            ParseError::at(content, rest, "missing `->` after the condition").expecting(&["`->`"]);
+        assert_eq!((err.line, err.column), (3, 14));
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 14: missing `->` after the condition (expected `->`)"
+        );
+
+        err.file = Some(PathBuf::from("flags/Flagfile"));
+        assert_eq!(
+            err.render(),
+            "error: missing `->` after the condition\n --> flags/Flagfile:3:14\n  |\n3 | \tplan == pro true\n  | \t            ^\n  = expected `->`"
+        );
+        let value = err.to_json();
+        assert_eq!(value["file"], "flags/Flagfile");
+        assert_eq!(value["line"], 3);
+        assert_eq!(value["expected"], json!(["`->`"]));
+        assert_eq!(value["sourceLine"], "\tplan == pro true");
+    }
+
+    #[test]
+    fn test_expected_hint() {
+        let err = ParseError::at("x", "x", "bad").expecting(&["`true`", "`false`"]);
+        assert_eq!(
+            err.expected_hint().as_deref(),
+            Some("expected one of `true`, `false`")
+        );
+        assert_eq!(ParseError::at("x", "x", "bad").expected_hint(), None);
+    }
+}
@@ -1,21 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use chrono::NaiveDate;
+use chrono_tz::Tz;
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag, take_till, take_until},
     character::complete::{alphanumeric1, multispace0, space0},
-    combinator::{map, opt, recognize, value},
-    multi::{many0, many0_count, many1},
+    combinator::{map, map_res, opt, recognize, value},
+    multi::{many0, many0_count, many1, separated_list0},
     sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 use serde_json::Value;
 
 use crate::{
-    ast::{AstNode, Atom, FlagMetadata},
+    ast::{ActiveWindow, AstNode, Atom, FlagMetadata, LogicOp, SegmentDef, Span, ValueType},
     eval::Segments,
-    parse::{parse, parse_boolean, parse_segment_name, ws},
+    parse::{
+        parse, parse_active_window, parse_boolean, parse_float, parse_identifier,
+        parse_segment_name, parse_split, with_timezone, ws,
+    },
+    parse_error::ParseError,
 };
 
 // Dependency
@@ -26,13 +32,97 @@ pub enum FlagReturn {
     OnOff(bool),
     Json(Value),
     Integer(i64),
+    Float(f64),
     Str(String),
+    /// A named arm of the flag's `@variants` block together with its value.
+    Variant(String, Box<FlagReturn>),
+}
+
+impl FlagReturn {
+    /// Name of the `@variants` arm this value came from, if any.
+    pub fn variant_name(&self) -> Option<&str> {
+        match self {
+            FlagReturn::Variant(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The underlying value, looking through a named variant.
+    pub fn value(&self) -> &FlagReturn {
+        match self {
+            FlagReturn::Variant(_, inner) => inner.value(),
+            other => other,
+        }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            FlagReturn::OnOff(_) => ValueType::Bool,
+            FlagReturn::Integer(_) => ValueType::Int,
+            FlagReturn::Float(_) => ValueType::Float,
+            FlagReturn::Str(_) => ValueType::String,
+            FlagReturn::Json(_) => ValueType::Json,
+            FlagReturn::Variant(_, inner) => inner.value_type(),
+        }
+    }
+
+    /// The value as JSON, looking through a named variant. A non-finite
+    /// float has no JSON form and becomes `null`.
+    pub fn to_json(&self) -> Value {
+        match self {
+            FlagReturn::OnOff(b) => Value::Bool(*b),
+            FlagReturn::Integer(n) => Value::Number((*n).into()),
+            FlagReturn::Float(f) => serde_json::Number::from_f64(*f)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            FlagReturn::Str(s) => Value::String(s.clone()),
+            FlagReturn::Json(v) => v.clone(),
+            FlagReturn::Variant(_, inner) => inner.to_json(),
+        }
+    }
+}
+
+impl fmt::Display for FlagReturn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagReturn::OnOff(b) => write!(f, "{b}"),
+            FlagReturn::Integer(n) => write!(f, "{n}"),
+            // keep the decimal point so the value reads back as a float
+            FlagReturn::Float(n) if n.fract() == 0.0 => write!(f, "{n:.1}"),
+            FlagReturn::Float(n) => write!(f, "{n}"),
+            FlagReturn::Str(s) => write!(f, "\"{s}\""),
+            FlagReturn::Json(v) => write!(f, "json({v})"),
+            FlagReturn::Variant(name, _) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Reads a value the way it's written after `->`; the inverse of
+/// [`fmt::Display`]. A bare name is a variant reference.
+impl std::str::FromStr for FlagReturn {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_return_val(s) {
+            Ok((rest, value)) if rest.trim().is_empty() => Ok(value),
+            Ok((rest, _)) => Err(ParseError::at(s, rest, "unexpected text after the value")),
+            Err(_) => Err(ParseError::at(s, s, "not a flag value").expecting(&[
+                "`true`",
+                "`false`",
+                "a number",
+                "a quoted string",
+                "`json(...)`",
+                "a variant name",
+            ])),
+        }
+    }
 }
 
 impl From<FlagReturn> for bool {
     fn from(val: FlagReturn) -> Self {
         match val {
             FlagReturn::OnOff(b) => b,
+            FlagReturn::Variant(_, inner) => (*inner).into(),
             _ => panic!("cannot convert non-boolean FlagReturn to bool"),
         }
     }
@@ -43,27 +133,159 @@ impl From<FlagReturn> for String {
         match val {
             FlagReturn::OnOff(b) => b.to_string(),
             FlagReturn::Integer(n) => n.to_string(),
+            FlagReturn::Float(n) => n.to_string(),
             FlagReturn::Str(s) => s,
             FlagReturn::Json(v) => v.to_string(),
+            FlagReturn::Variant(_, inner) => (*inner).into(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Rule {
-    Value(FlagReturn),
-    BoolExpressionValue(AstNode, FlagReturn, Option<String>),
-    EnvRule { env: String, rules: Vec<Rule> },
+    Value(FlagReturn, Span),
+    BoolExpressionValue(AstNode, FlagReturn, Option<String>, Span),
+    EnvRule {
+        env: String,
+        rules: Vec<Rule>,
+        span: Span,
+    },
+    /// `split(userId) { a: 33%, b: 33%, c: 34% }`: one arm per context.
+    Split {
+        split: Split,
+        name: Option<String>,
+        span: Span,
+    },
+}
+
+/// A weighted split: each context lands in one arm, picked by the same
+/// SHA-1 bucket `percentage()` uses on `field` (and `salt`). Weights are
+/// percentages that add up to 100.
+#[derive(Debug, Clone)]
+pub struct Split {
+    pub field: AstNode,
+    pub salt: Option<String>,
+    pub arms: Vec<(FlagReturn, f64)>,
+}
+
+impl Split {
+    /// Each arm with the bucket it ends below, out of 100 000. Bounds are
+    /// cumulative, so the first arm covers the same buckets as
+    /// `percentage()` with its weight; the last arm takes what rounding
+    /// leaves.
+    pub fn bounds(&self) -> Vec<(u64, &FlagReturn)> {
+        let mut upto = 0.0;
+        let mut bounds: Vec<_> = self
+            .arms
+            .iter()
+            .map(|(value, weight)| {
+                upto += weight;
+                ((upto * 1000.0) as u64, value)
+            })
+            .collect();
+        if let Some(last) = bounds.last_mut() {
+            last.0 = 100_000;
+        }
+        bounds
+    }
+
+    /// The arm a bucket (`0..100_000`) falls in.
+    pub fn arm(&self, bucket: u64) -> &FlagReturn {
+        let bounds = self.bounds();
+        bounds
+            .iter()
+            .find(|(bound, _)| bucket < *bound)
+            .unwrap_or(&bounds[bounds.len() - 1])
+            .1
+    }
+}
+
+impl Rule {
+    /// Where the rule is: from its condition, bare value or `@env` to the
+    /// end of its return value or block.
+    pub fn span(&self) -> Span {
+        match self {
+            Rule::Value(_, span)
+            | Rule::BoolExpressionValue(_, _, _, span)
+            | Rule::EnvRule { span, .. }
+            | Rule::Split { span, .. } => *span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Rule::Value(_, span)
+            | Rule::BoolExpressionValue(_, _, _, span)
+            | Rule::EnvRule { span, .. }
+            | Rule::Split { span, .. } => span,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FlagDefinition {
     pub rules: Vec<Rule>,
     pub metadata: FlagMetadata,
+    /// Named values declared in the flag's `@variants` block, in declaration order.
+    pub variants: Vec<(String, FlagReturn)>,
+    /// From the flag name to the end of its value or closing brace.
+    pub span: Span,
 }
 
 pub type FlagValue<'a> = HashMap<&'a str, FlagDefinition>;
 
+/// A return value that disagrees with the flag's declared `@type`.
+#[derive(Debug, Clone)]
+pub struct TypeMismatch {
+    pub flag: String,
+    pub declared: ValueType,
+    pub found: FlagReturn,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: rule returns {} {} but flag is declared @type {}",
+            self.flag,
+            self.found.value_type(),
+            self.found.value(),
+            self.declared
+        )
+    }
+}
+
+impl FlagDefinition {
+    /// Check every return value (declared variants, rules and `@env` sub-rules)
+    /// against the flag's `@type` declaration. Flags without one always pass.
+    pub fn type_mismatches(&self, name: &str) -> Vec<TypeMismatch> {
+        let Some(declared) = self.metadata.value_type else {
+            return Vec::new();
+        };
+        let mut returns: Vec<&FlagReturn> = self.variants.iter().map(|(_, v)| v).collect();
+        collect_returns(&self.rules, &mut returns);
+        returns
+            .into_iter()
+            .filter(|ret| !declared.accepts(ret.value_type()))
+            .map(|ret| TypeMismatch {
+                flag: name.to_string(),
+                declared,
+                found: ret.clone(),
+            })
+            .collect()
+    }
+}
+
+fn collect_returns<'a>(rules: &'a [Rule], out: &mut Vec<&'a FlagReturn>) {
+    for rule in rules {
+        match rule {
+            Rule::Value(ret, _) | Rule::BoolExpressionValue(_, ret, _, _) => out.push(ret),
+            Rule::EnvRule { rules, .. } => collect_returns(rules, out),
+            Rule::Split { split, .. } => out.extend(split.arms.iter().map(|(ret, _)| ret)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct TestAnnotation {
     pub assertion: String,
@@ -161,11 +383,16 @@ fn multiline_comment(i: &str) -> IResult<&str, ()> {
     value((), delimited(tag("/*"), take_until("*/"), tag("*/")))(i)
 }
 
+/// Invalid JSON inside `json(...)` is a hard failure pointing at the JSON,
+/// like an invalid regex.
 fn parse_json(i: &str) -> IResult<&str, FlagReturn> {
-    let parser = delimited(ws(tag("json(")), take_until(")"), ws(tag(")")));
-    map(parser, |v| {
-        FlagReturn::Json(serde_json::from_str(v).unwrap())
-    })(i)
+    let (body, _) = ws(tag("json("))(i)?;
+    let (rest, v) = take_until(")")(body)?;
+    let json = serde_json::from_str(v).map_err(|_| {
+        nom::Err::Failure(nom::error::Error::new(body, nom::error::ErrorKind::Verify))
+    })?;
+    let (rest, _) = ws(tag(")"))(rest)?;
+    Ok((rest, FlagReturn::Json(json)))
 }
 
 fn parse_bool(i: &str) -> IResult<&str, FlagReturn> {
@@ -180,8 +407,13 @@ fn parse_integer_return(i: &str) -> IResult<&str, FlagReturn> {
         nom::combinator::opt(tag("-")),
         nom::character::complete::digit1,
     ));
-    map(parser, |num: &str| {
-        FlagReturn::Integer(num.parse().unwrap())
+    map_res(parser, |num: &str| num.parse().map(FlagReturn::Integer))(i)
+}
+
+fn parse_float_return(i: &str) -> IResult<&str, FlagReturn> {
+    map(parse_float, |v| match v {
+        Atom::Float(v) => FlagReturn::Float(v),
+        _ => unreachable!(),
     })(i)
 }
 
@@ -205,6 +437,7 @@ enum Annotation {
     Deprecated(String),
     Requires(String),
     Test(String),
+    Active(ActiveWindow),
 }
 
 fn parse_quoted_string(i: &str) -> IResult<&str, &str> {
@@ -305,6 +538,13 @@ fn parse_annotation_test(i: &str) -> IResult<&str, Annotation> {
     Ok((rest, Annotation::Test(assertion.to_string())))
 }
 
+fn parse_annotation_active(i: &str) -> IResult<&str, Annotation> {
+    let (rest, _) = ws(tag("@active"))(i)?;
+    let (rest, window) = parse_active_window(rest)?;
+    let (rest, _) = multispace0(rest)?;
+    Ok((rest, Annotation::Active(window)))
+}
+
 fn parse_annotation(i: &str) -> IResult<&str, Annotation> {
     alt((
         parse_annotation_owner,
@@ -315,6 +555,7 @@ fn parse_annotation(i: &str) -> IResult<&str, Annotation> {
         parse_annotation_deprecated,
         parse_annotation_requires,
         parse_annotation_test,
+        parse_annotation_active,
     ))(i)
 }
 
@@ -330,10 +571,14 @@ fn parse_metadata_block(i: &str) -> IResult<&str, FlagMetadata> {
             Annotation::Expires(v) => metadata.expires = Some(v),
             Annotation::Ticket(v) => metadata.ticket = Some(v),
             Annotation::Description(v) => metadata.description = Some(v),
-            Annotation::FlagType(v) => metadata.flag_type = Some(v),
+            Annotation::FlagType(v) => match ValueType::from_name(&v) {
+                Some(t) => metadata.value_type = Some(t),
+                None => metadata.flag_type = Some(v),
+            },
             Annotation::Deprecated(v) => metadata.deprecated = Some(v),
             Annotation::Requires(v) => metadata.requires.push(v),
             Annotation::Test(v) => metadata.tests.push(v),
+            Annotation::Active(v) => metadata.active = Some(v),
         }
     }
     Ok((rest, metadata))
@@ -363,31 +608,120 @@ fn parse_flag_name(i: &str) -> IResult<&str, &str> {
     ))(i)
 }
 
-fn parse_return_val(i: &str) -> IResult<&str, FlagReturn> {
+fn parse_literal_return_val(i: &str) -> IResult<&str, FlagReturn> {
     alt((
         ws(parse_bool),
         ws(parse_json),
         ws(parse_string_return),
+        ws(parse_float_return),
         ws(parse_integer_return),
     ))(i)
 }
 
+fn parse_variant_name(i: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((nom::character::complete::alpha1, tag("_"))),
+        many0_count(alt((alphanumeric1, hyphen_not_arrow, tag("_")))),
+    ))(i)
+}
+
+/// Bare identifier returned by a rule, e.g. `-> streamlined`. The value is a
+/// placeholder until `resolve_variant_refs` swaps in the declared one.
+fn parse_variant_ref(i: &str) -> IResult<&str, FlagReturn> {
+    map(parse_variant_name, |name: &str| {
+        FlagReturn::Variant(
+            name.to_string(),
+            Box::new(FlagReturn::Str(name.to_string())),
+        )
+    })(i)
+}
+
+fn parse_return_val(i: &str) -> IResult<&str, FlagReturn> {
+    alt((parse_literal_return_val, ws(parse_variant_ref)))(i)
+}
+
+fn parse_variant_decl(i: &str) -> IResult<&str, (String, FlagReturn)> {
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
+    let (rest, name) = ws(parse_variant_name)(rest)?;
+    let (rest, _) = ws(tag("->"))(rest)?;
+    let (rest, val) = parse_literal_return_val(rest)?;
+    Ok((rest, (name.to_string(), val)))
+}
+
+/// Parses `@variants { control -> json(...) streamlined -> ... }`.
+fn parse_variants_block(i: &str) -> IResult<&str, Vec<(String, FlagReturn)>> {
+    let (rest, _) = ws(tag("@variants"))(i)?;
+    let (rest, variants) = delimited(
+        ws(tag("{")),
+        many1(parse_variant_decl),
+        preceded(many0(alt((parse_comment, multiline_comment))), ws(tag("}"))),
+    )(rest)?;
+    let mut seen = std::collections::HashSet::new();
+    if !variants.iter().all(|(name, _)| seen.insert(name.as_str())) {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((rest, variants))
+}
+
+/// Replace every variant reference in `rules` with the value declared in
+/// `variants`. Returns the first name that has no declaration.
+fn resolve_variant_refs(
+    rules: &mut [Rule],
+    variants: &[(String, FlagReturn)],
+) -> Result<(), String> {
+    for rule in rules {
+        match rule {
+            Rule::Value(ret, _) | Rule::BoolExpressionValue(_, ret, _, _) => {
+                resolve_variant_ref(ret, variants)?
+            }
+            Rule::EnvRule { rules, .. } => resolve_variant_refs(rules, variants)?,
+            Rule::Split { split, .. } => {
+                for (ret, _) in &mut split.arms {
+                    resolve_variant_ref(ret, variants)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve_variant_ref(
+    ret: &mut FlagReturn,
+    variants: &[(String, FlagReturn)],
+) -> Result<(), String> {
+    if let FlagReturn::Variant(name, value) = ret {
+        let (_, declared) = variants
+            .iter()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| name.clone())?;
+        **value = declared.clone();
+    }
+    Ok(())
+}
+
 fn parse_anonymous_func(i: &str) -> IResult<&str, FlagValue<'_>> {
-    let parser = tuple((ws(parse_flag_name), ws(tag("->")), parse_return_val));
-    map(parser, |(n, _, v)| {
+    let (value, (n, _)) = pair(ws(parse_flag_name), ws(tag("->")))(i)?;
+    let (rest, v) = parse_return_val(value)?;
+    Ok((
+        rest,
         HashMap::from([(
             n,
             FlagDefinition {
-                rules: vec![Rule::Value(v)],
+                rules: vec![Rule::Value(v, span(value, rest))],
                 metadata: FlagMetadata::default(),
+                variants: Vec::new(),
+                span: span(i, rest),
             },
-        )])
-    })(i)
+        )]),
+    ))
 }
 
 fn parse_rule_expr(i: &str) -> IResult<&str, Rule> {
-    let parser = tuple((parse, ws(tag("->")), parse_return_val));
-    map(parser, |(e, _, v)| Rule::BoolExpressionValue(e, v, None))(i)
+    let (rest, (e, _, v)) = tuple((parse, ws(tag("->")), parse_return_val))(i)?;
+    Ok((rest, Rule::BoolExpressionValue(e, v, None, span(i, rest))))
 }
 
 /// Rule-level name annotation. Accepts both a bare quoted form
@@ -405,11 +739,25 @@ fn parse_rule_name(i: &str) -> IResult<&str, String> {
     ))(rest)
 }
 
-/// Consume any leading comments and at most one `@name` annotation preceding a rule,
-/// returning the last name seen. `parse_rule_name` is tried before `parse_comment` so
+/// Rule-level schedule, `@active from ... until ...` on its own line before a
+/// rule. Also returns where the annotation starts, to report a rule that
+/// can't carry one.
+fn parse_rule_active(i: &str) -> IResult<&str, (&str, ActiveWindow)> {
+    let (at, _) = multispace0(i)?;
+    let (rest, _) = tag("@active")(at)?;
+    let (rest, window) = parse_active_window(rest)?;
+    Ok((rest, (at, window)))
+}
+
+/// Name and `@active` window annotated on a rule.
+type RulePrefix<'a> = (Option<String>, Option<(&'a str, ActiveWindow)>);
+
+/// Consume any leading comments, `@name` and `@active` annotations preceding a rule,
+/// returning the last of each seen. `parse_rule_name` is tried before `parse_comment` so
 /// the `// @name ...` form is captured rather than swallowed as a plain comment.
-fn parse_rule_prefix(i: &str) -> IResult<&str, Option<String>> {
+fn parse_rule_prefix(i: &str) -> IResult<&str, RulePrefix<'_>> {
     let mut name = None;
+    let mut active = None;
     let mut input = i;
     loop {
         if let Ok((r, n)) = parse_rule_name(input) {
@@ -417,6 +765,11 @@ fn parse_rule_prefix(i: &str) -> IResult<&str, Option<String>> {
             input = r;
             continue;
         }
+        if let Ok((r, a)) = parse_rule_active(input) {
+            active = Some(a);
+            input = r;
+            continue;
+        }
         if let Ok((r, _)) = parse_comment(input) {
             input = r;
             continue;
@@ -427,156 +780,1396 @@ fn parse_rule_prefix(i: &str) -> IResult<&str, Option<String>> {
         }
         break;
     }
-    Ok((input, name))
+    Ok((input, (name, active)))
+}
+
+fn parse_rule_static(i: &str) -> IResult<&str, Rule> {
+    let (rest, v) = parse_return_val(i)?;
+    Ok((rest, Rule::Value(v, span(i, rest))))
+}
+
+fn parse_rule_split(i: &str) -> IResult<&str, Rule> {
+    let (start, _) = multispace0(i)?;
+    let (rest, (field, salt, arms)) = parse_split(start, parse_return_val)?;
+    Ok((
+        rest,
+        Rule::Split {
+            split: Split { field, salt, arms },
+            name: None,
+            span: span(i, rest),
+        },
+    ))
+}
+
+fn parse_env_name(i: &str) -> IResult<&str, &str> {
+    recognize(many1(alt((alphanumeric1, hyphen_not_arrow, tag("_")))))(i)
+}
+
+fn parse_env_rule_simple(i: &str) -> IResult<&str, Rule> {
+    let (rest, _) = ws(tag("@env"))(i)?;
+    let (rest, _) = multispace0(rest)?;
+    let (rest, env_name) = parse_env_name(rest)?;
+    let (value, _) = ws(tag("->"))(rest)?;
+    let (rest, val) = parse_return_val(value)?;
+    Ok((
+        rest,
+        Rule::EnvRule {
+            env: env_name.to_string(),
+            rules: vec![Rule::Value(val, span(value, rest))],
+            span: span(i, rest),
+        },
+    ))
+}
+
+fn parse_env_rule_block(i: &str) -> IResult<&str, Rule> {
+    let (rest, _) = ws(tag("@env"))(i)?;
+    let (rest, _) = multispace0(rest)?;
+    let (rest, env_name) = parse_env_name(rest)?;
+    let (rest, rules) = delimited(ws(tag("{")), parse_rules_list, ws(tag("}")))(rest)?;
+    Ok((
+        rest,
+        Rule::EnvRule {
+            env: env_name.to_string(),
+            rules,
+            span: span(i, rest),
+        },
+    ))
+}
+
+fn parse_env_rule(i: &str) -> IResult<&str, Rule> {
+    alt((parse_env_rule_block, parse_env_rule_simple))(i)
+}
+
+fn parse_rules(i: &str) -> IResult<&str, Rule> {
+    alt((
+        parse_env_rule,
+        parse_rule_split,
+        parse_rule_expr,
+        parse_rule_static,
+    ))(i)
+}
+
+fn parse_rules_or_comments(i: &str) -> IResult<&str, Rule> {
+    let (rest, (name, active)) = parse_rule_prefix(i)?;
+    let (rest, rule) = parse_rules(rest)?;
+    // An `@active` rule only matches inside its window: the window becomes
+    // part of the condition.
+    let rule = match (rule, active) {
+        (rule, None) => rule,
+        (Rule::BoolExpressionValue(e, v, n, at), Some((_, w))) => Rule::BoolExpressionValue(
+            AstNode::Logic(Box::new(AstNode::Active(w)), LogicOp::And, Box::new(e)),
+            v,
+            n,
+            at,
+        ),
+        (Rule::Value(v, at), Some((_, w))) => {
+            Rule::BoolExpressionValue(AstNode::Active(w), v, None, at)
+        }
+        (_, Some((at, _))) => {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                at,
+                nom::error::ErrorKind::Verify,
+            )))
+        }
+    };
+    let rule = match (rule, name) {
+        (Rule::BoolExpressionValue(e, v, _, at), Some(n)) => {
+            Rule::BoolExpressionValue(e, v, Some(n), at)
+        }
+        (Rule::Split { split, span, .. }, Some(n)) => Rule::Split {
+            split,
+            name: Some(n),
+            span,
+        },
+        (other, _) => other,
+    };
+    // NB: trailing comments are deliberately NOT consumed here — doing so would eat
+    // the next rule's leading `// @name` annotation as if it were a plain comment.
+    // Trailing comments before `}` are handled once at the end of `parse_rules_list`.
+    Ok((rest, rule))
+}
+
+fn parse_rules_list(i: &str) -> IResult<&str, Vec<Rule>> {
+    let (rest, rules) = many1(parse_rules_or_comments)(i)?;
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
+    Ok((rest, rules))
+}
+
+fn parse_function(i: &str) -> IResult<&str, FlagValue<'_>> {
+    let (rest, (flag_name, (variants, rules))) = pair(
+        ws(parse_flag_name),
+        delimited(
+            ws(tag("{")),
+            pair(
+                opt(preceded(
+                    many0(alt((parse_comment, multiline_comment))),
+                    parse_variants_block,
+                )),
+                parse_rules_list,
+            ),
+            ws(tag("}")),
+        ),
+    )(i)?;
+    Ok((
+        rest,
+        HashMap::from([(
+            flag_name,
+            FlagDefinition {
+                rules,
+                metadata: FlagMetadata::default(),
+                variants: variants.unwrap_or_default(),
+                span: span(i, rest),
+            },
+        )]),
+    ))
+}
+
+fn parse_flag_entry(i: &str) -> IResult<&str, FlagValue<'_>> {
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
+    let (rest, metadata) = parse_metadata_block(rest)?;
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
+    let (rest, mut fv) = alt((parse_anonymous_func, parse_function))(rest)?;
+    // Every variant a rule returns must be declared in the flag's @variants block
+    for def in fv.values_mut() {
+        if resolve_variant_refs(&mut def.rules, &def.variants).is_err() {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                i,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+    }
+    // Attach collected metadata to the flag definition
+    if metadata != FlagMetadata::default() {
+        for (_, def) in fv.iter_mut() {
+            def.metadata = metadata.clone();
+        }
+    }
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
+    Ok((rest, fv))
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedFlagfile<'a> {
+    pub flags: Vec<FlagValue<'a>>,
+    pub segments: Segments,
+}
+
+impl<'a> ParsedFlagfile<'a> {
+    /// Collect entries parsed from `content`, making their spans absolute.
+    fn from_entries(content: &str, entries: Vec<FlagfileEntry<'a>>) -> Self {
+        let anchor = Anchor::new(content);
+        let mut flags = Vec::new();
+        let mut segments = Segments::new();
+        for entry in entries {
+            match entry {
+                FlagfileEntry::Flag(mut fv) => {
+                    anchor.flags(&mut fv);
+                    flags.push(fv);
+                }
+                FlagfileEntry::Segment(name, mut def) => {
+                    def.span = anchor.span(def.span);
+                    segments.insert(name, def);
+                }
+            }
+        }
+        ParsedFlagfile { flags, segments }
+    }
+
+    /// All `@type` violations across the file, sorted by flag name.
+    pub fn type_mismatches(&self) -> Vec<TypeMismatch> {
+        let mut out: Vec<TypeMismatch> = self
+            .flags
+            .iter()
+            .flat_map(|fv| fv.iter())
+            .flat_map(|(name, def)| def.type_mismatches(name))
+            .collect();
+        out.sort_by(|a, b| a.flag.cmp(&b.flag));
+        out
+    }
+
+    /// The first segment cycle found, e.g. `a -> b -> a`, visiting segments
+    /// in name order so the result is stable.
+    pub fn segment_cycle(&self) -> Option<SegmentCycle> {
+        let mut names: Vec<&str> = self.segments.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        let mut done = HashSet::new();
+        let mut path = Vec::new();
+        names
+            .into_iter()
+            .find_map(|name| find_cycle(name, &self.segments, &mut done, &mut path))
+            .map(SegmentCycle)
+    }
+
+    /// The first `segment(...)` call with more or fewer arguments than the
+    /// segment has parameters: flags in file order, then segment bodies in
+    /// name order. Calls to undefined segments are left to lint.
+    pub fn segment_arity_mismatch(&self) -> Option<SegmentArityMismatch> {
+        let mut conditions = Vec::new();
+        for fv in &self.flags {
+            for def in fv.values() {
+                collect_conditions(&def.rules, &mut conditions);
+            }
+        }
+        let mut names: Vec<&String> = self.segments.keys().collect();
+        names.sort_unstable();
+        conditions.extend(names.into_iter().map(|name| {
+            let def = &self.segments[name];
+            (&def.body, def.span)
+        }));
+        conditions.into_iter().find_map(|(expr, span)| {
+            expr.segment_calls().into_iter().find_map(|(name, args)| {
+                let params = self.segments.get(name)?.params.len();
+                (params != args).then(|| SegmentArityMismatch {
+                    segment: name.to_string(),
+                    params,
+                    args,
+                    span,
+                })
+            })
+        })
+    }
+}
+
+/// Conditions of `rules` and their `@env` blocks, with the rule's span.
+fn collect_conditions<'a>(rules: &'a [Rule], out: &mut Vec<(&'a AstNode, Span)>) {
+    for rule in rules {
+        match rule {
+            Rule::BoolExpressionValue(expr, _, _, span) => out.push((expr, *span)),
+            Rule::EnvRule { rules, .. } => collect_conditions(rules, out),
+            Rule::Value(..) | Rule::Split { .. } => {}
+        }
+    }
+}
+
+/// A `segment(...)` call whose argument count doesn't match the segment's
+/// parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentArityMismatch {
+    pub segment: String,
+    pub params: usize,
+    pub args: usize,
+    /// The rule or `@segment` making the call.
+    pub span: Span,
+}
+
+impl fmt::Display for SegmentArityMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "segment '{}' takes {} argument{}, called with {} at line {}",
+            self.segment,
+            self.params,
+            if self.params == 1 { "" } else { "s" },
+            self.args,
+            self.span.line
+        )
+    }
+}
+
+/// Segments that reference each other in a loop. The first name is repeated
+/// at the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentCycle(pub Vec<String>);
+
+impl fmt::Display for SegmentCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circular segment dependency: {}", self.0.join(" -> "))
+    }
+}
+
+fn find_cycle<'a>(
+    name: &'a str,
+    segments: &'a Segments,
+    done: &mut HashSet<&'a str>,
+    path: &mut Vec<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|n| *n == name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    if done.contains(name) {
+        return None;
+    }
+    // Unknown segments are reported by lint and at evaluation time.
+    let def = segments.get(name)?;
+    path.push(name);
+    for dep in def.body.segment_refs() {
+        if let Some(cycle) = find_cycle(dep, segments, done, path) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(name);
+    None
+}
+
+/// `(country, tier)` after a segment name; a parameter may appear once.
+fn parse_segment_params(i: &str) -> IResult<&str, Vec<String>> {
+    let (rest, params) = delimited(
+        ws(tag("(")),
+        separated_list0(ws(tag(",")), parse_identifier),
+        ws(tag(")")),
+    )(i)?;
+    let params: Vec<String> = params.into_iter().map(str::to_string).collect();
+    if params
+        .iter()
+        .enumerate()
+        .any(|(n, p)| params[..n].contains(p))
+    {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((rest, params))
+}
+
+fn parse_segment_definition(i: &str) -> IResult<&str, (String, SegmentDef)> {
+    let (start, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
+    let (rest, _) = ws(tag("@segment"))(start)?;
+    let (rest, name) = ws(parse_segment_name)(rest)?;
+    let name = name.to_string();
+    let (rest, params) = opt(parse_segment_params)(rest)?;
+    let (end, body) = delimited(ws(tag("{")), parse, ws(tag("}")))(rest)?;
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(end)?;
+    let params = params.unwrap_or_default();
+    let span = span(start, end);
+    Ok((rest, (name, SegmentDef { params, body, span })))
+}
+
+enum FlagfileEntry<'a> {
+    Flag(FlagValue<'a>),
+    Segment(String, SegmentDef),
+}
+
+fn parse_flagfile_entry(i: &str) -> IResult<&str, FlagfileEntry<'_>> {
+    alt((
+        map(parse_segment_definition, |(name, def)| {
+            FlagfileEntry::Segment(name, def)
+        }),
+        map(parse_flag_entry, FlagfileEntry::Flag),
+    ))(i)
+}
+
+/// `@timezone Europe/Vilnius`: the IANA zone datetimes written without an
+/// offset are in. An unknown zone is a hard failure at its name.
+fn parse_timezone_directive(i: &str) -> IResult<&str, Tz> {
+    let (at, _) = ws(tag("@timezone"))(i)?;
+    let (rest, name) = alt((
+        parse_quoted_string,
+        recognize(many1(alt((
+            alphanumeric1,
+            tag("/"),
+            tag("_"),
+            tag("-"),
+            tag("+"),
+        )))),
+    ))(at)?;
+    let tz = name.parse::<Tz>().map_err(|_| {
+        nom::Err::Failure(nom::error::Error::new(at, nom::error::ErrorKind::Verify))
+    })?;
+    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
+    Ok((rest, tz))
+}
+
+/// The file's `@timezone` and what follows it. It has to come before the
+/// first flag or segment; without one the zone is UTC.
+fn parse_timezone_header(i: &str) -> IResult<&str, Tz> {
+    let (after_comments, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
+    match parse_timezone_directive(after_comments) {
+        Ok((rest, tz)) => Ok((rest, tz)),
+        Err(nom::Err::Error(_)) => Ok((i, Tz::UTC)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse a whole Flagfile. Anything left over that isn't whitespace or a
+/// comment is an error pointing at where the offending entry stopped parsing.
+pub fn parse_flagfile_with_segments(i: &str) -> Result<ParsedFlagfile<'_>, ParseError> {
+    let (body, tz) = parse_timezone_header(i).map_err(|e| hard_failure(i, e))?;
+    let (rest, entries) =
+        with_timezone(tz, || many0(parse_flagfile_entry)(body)).map_err(|e| hard_failure(i, e))?;
+    if !skip_trivia(rest).is_empty() {
+        let d = diagnose_entry(rest);
+        return Err(ParseError::at(i, d.at, d.message).expecting(d.expected));
+    }
+    Ok(ParsedFlagfile::from_entries(i, entries))
+}
+
+/// Parse a whole Flagfile, reporting every syntax error instead of stopping
+/// at the first. After an error, parsing resumes at the next line that
+/// starts a flag, a segment or an annotation; the flags and segments that
+/// did parse are returned alongside the errors, in source order.
+pub fn parse_flagfile_recovering(i: &str) -> (ParsedFlagfile<'_>, Vec<ParseError>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    let (mut rest, tz) = match parse_timezone_header(i) {
+        Ok(header) => header,
+        Err(e) => {
+            let at = match &e {
+                nom::Err::Error(e) | nom::Err::Failure(e) => e.input,
+                nom::Err::Incomplete(_) => i,
+            };
+            errors.push(hard_failure(i, e));
+            (next_entry(at), Tz::UTC)
+        }
+    };
+    with_timezone(tz, || loop {
+        let start = skip_trivia(rest);
+        if start.is_empty() {
+            break;
+        }
+        let at = match parse_flagfile_entry(rest) {
+            Ok((r, entry)) => {
+                entries.push(entry);
+                rest = r;
+                continue;
+            }
+            Err(nom::Err::Failure(e)) => {
+                let at = e.input;
+                errors.push(hard_failure(i, nom::Err::Failure(e)));
+                at
+            }
+            Err(_) => {
+                let d = diagnose_entry(rest);
+                errors.push(ParseError::at(i, d.at, d.message).expecting(d.expected));
+                d.at
+            }
+        };
+        // resume after the error, so annotations that went with the failed
+        // flag aren't read as the start of the next one
+        rest = next_entry(if at.len() < start.len() { at } else { start });
+    });
+    (ParsedFlagfile::from_entries(i, entries), errors)
+}
+
+/// The first line after the one `i` starts on that begins a top-level
+/// entry, or the empty end of `i`. `i` may start mid-line.
+fn next_entry(i: &str) -> &str {
+    let mut lines = i.split_inclusive('\n');
+    let mut offset = lines.next().map_or(0, str::len);
+    for line in lines {
+        let trimmed = line.trim_start();
+        let starts_entry = trimmed.starts_with("FF-")
+            || trimmed.starts_with("FF_")
+            || trimmed.starts_with("@segment")
+            || trimmed.starts_with("@include")
+            || trimmed.starts_with("@timezone")
+            || ANNOTATIONS
+                .iter()
+                .any(|a| trimmed.starts_with(a.trim_matches('`')));
+        if starts_entry {
+            return &i[offset..];
+        }
+        offset += line.len();
+    }
+    &i[i.len()..]
+}
+
+pub fn parse_flagfile(i: &str) -> IResult<&str, Vec<FlagValue<'_>>> {
+    let (body, tz) = parse_timezone_header(i)?;
+    let (rest, mut flags) = with_timezone(tz, || many0(parse_flag_entry)(body))?;
+    let anchor = Anchor::new(i);
+    flags.iter_mut().for_each(|fv| anchor.flags(fv));
+    Ok((rest, flags))
+}
+
+// ── Spans ────────────────────────────────────────────────────────
+//
+// A nom parser only sees the rest of the input, so spans are recorded as
+// distances from its end and made absolute once the whole content is known.
+
+/// The text from `start` up to `rest`, both suffixes of the same input.
+fn span(start: &str, rest: &str) -> Span {
+    Span {
+        start: start.len(),
+        end: rest.len(),
+        line: 0,
+        column: 0,
+    }
+}
+
+/// Turns spans recorded by [`span`] into offsets into `content`.
+struct Anchor<'a> {
+    content: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Anchor<'a> {
+    fn new(content: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(at, _)| at + 1))
+            .collect();
+        Anchor {
+            content,
+            line_starts,
+        }
+    }
+
+    /// The absolute span, without the whitespace the parsers consume
+    /// around tokens.
+    fn span(&self, recorded: Span) -> Span {
+        let len = self.content.len();
+        let (start, end) = (len - recorded.start, len - recorded.end);
+        let text = &self.content[start..end];
+        let start = start + (text.len() - text.trim_start().len());
+        let end = start + text.trim().len();
+        let line = self.line_starts.partition_point(|&at| at <= start);
+        let line_start = self.line_starts[line - 1];
+        Span {
+            start,
+            end,
+            line,
+            column: self.content[line_start..start].chars().count() + 1,
+        }
+    }
+
+    fn flags(&self, fv: &mut FlagValue<'_>) {
+        for def in fv.values_mut() {
+            def.span = self.span(def.span);
+            self.rules(&mut def.rules);
+        }
+    }
+
+    fn rules(&self, rules: &mut [Rule]) {
+        for rule in rules {
+            *rule.span_mut() = self.span(rule.span());
+            if let Rule::EnvRule { rules, .. } = rule {
+                self.rules(rules);
+            }
+        }
+    }
+}
+
+// ── Error diagnosis ──────────────────────────────────────────────
+//
+// The grammar is a set of backtracking nom parsers, so when an entry doesn't
+// parse all we learn is where the entry started. To say what went wrong, the
+// failed entry is walked again piece by piece with the same sub-parsers until
+// one of them fails; that point and what was expected there become the error.
+
+/// A failure that nom reports directly: an invalid regex or JSON literal, or
+/// another `cut` in the grammar.
+fn hard_failure(content: &str, err: nom::Err<nom::error::Error<&str>>) -> ParseError {
+    let e = match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        nom::Err::Incomplete(_) => return ParseError::at(content, "", "unexpected end of input"),
+    };
+    let before = content[..content.len() - e.input.len()].trim_end();
+    let message = if before.ends_with('/') && e.code == nom::error::ErrorKind::Verify {
+        let pattern = e.input.split('/').next().unwrap_or("");
+        match crate::parse::compile_regex(pattern) {
+            Err(msg) => {
+                // regex syntax errors span several lines: the pattern, a
+                // caret under the problem and then `error: <what>`.
+                let reason = msg.lines().last().unwrap_or_default().trim();
+                let reason = reason.strip_prefix("error: ").unwrap_or(reason);
+                format!("invalid regex /{}/: {}", pattern, reason)
+            }
+            Ok(_) => "invalid regex".to_string(),
+        }
+    } else if before.ends_with("json(") {
+        let json = e.input.split(')').next().unwrap_or("");
+        match serde_json::from_str::<Value>(json) {
+            Err(msg) => format!("invalid JSON in json(...): {}", msg),
+            Ok(_) => "invalid JSON in json(...)".to_string(),
+        }
+    } else if e.input.starts_with("split") && e.code == nom::error::ErrorKind::Verify {
+        "split weights must be between 0% and 100% and add up to 100%".to_string()
+    } else if before
+        .trim_end_matches(['"', '\'', ' '])
+        .ends_with("@timezone")
+        && e.code == nom::error::ErrorKind::Verify
+    {
+        let name = e.input.split_whitespace().next().unwrap_or("");
+        format!("unknown timezone `{}`", name.trim_matches(['"', '\'']))
+    } else if e.input.starts_with("ramp") && e.code == nom::error::ErrorKind::Verify {
+        "ramp must end after it starts".to_string()
+    } else if e.input.starts_with("@active") && e.code == nom::error::ErrorKind::Verify {
+        "`@active` is not supported on `@env` blocks or `split()` rules".to_string()
+    } else if e.input.trim_start().starts_with('(') && e.code == nom::error::ErrorKind::Verify {
+        "segment parameter declared more than once".to_string()
+    } else if e.code == nom::error::ErrorKind::Digit {
+        return ParseError::at(content, e.input, "missing exponent digits in number")
+            .expecting(&["a digit"]);
+    } else {
+        format!("unexpected {}", found(e.input))
+    };
+    ParseError::at(content, e.input, message)
+}
+
+/// Where and why an entry stopped parsing.
+struct Diagnosis<'a> {
+    at: &'a str,
+    message: String,
+    expected: &'static [&'static str],
+}
+
+fn diagnosis<'a>(
+    at: &'a str,
+    message: impl Into<String>,
+    expected: &'static [&'static str],
+) -> Diagnosis<'a> {
+    Diagnosis {
+        at,
+        message: message.into(),
+        expected,
+    }
+}
+
+/// `unexpected <found>` at `at`, after `context`.
+fn unexpected<'a>(at: &'a str, context: &str, expected: &'static [&'static str]) -> Diagnosis<'a> {
+    diagnosis(
+        at,
+        format!("unexpected {} {}", found(at), context),
+        expected,
+    )
+}
+
+const RETURN_VALUES: &[&str] = &[
+    "`true`",
+    "`false`",
+    "a number",
+    "a quoted string",
+    "`json(...)`",
+    "a variant name",
+];
+
+const ANNOTATIONS: &[&str] = &[
+    "`@owner`",
+    "`@expires`",
+    "`@ticket`",
+    "`@description`",
+    "`@type`",
+    "`@deprecated`",
+    "`@requires`",
+    "`@test`",
+    "`@active`",
+];
+
+const ACTIVE_WINDOW: &[&str] = &["`from` and/or `until` with a date or datetime"];
+
+/// How to name the text at `at` in a message.
+fn found(at: &str) -> String {
+    let at = at.trim_start_matches([' ', '\t']);
+    let token: String = at
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .chars()
+        .take(20)
+        .collect();
+    if at.is_empty() {
+        "end of file".to_string()
+    } else if at.starts_with(['\n', '\r']) {
+        "end of line".to_string()
+    } else {
+        format!("`{}`", token)
+    }
 }
 
-fn parse_rule_static(i: &str) -> IResult<&str, Rule> {
-    map(parse_return_val, Rule::Value)(i)
-}
+/// Skip whitespace and comments.
+fn skip_trivia(mut i: &str) -> &str {
+    loop {
+        let trimmed = i.trim_start();
+        match alt((parse_comment, multiline_comment))(trimmed) {
+            Ok((rest, _)) => i = rest,
+            Err(_) => return trimmed,
+        }
+    }
+}
+
+/// Where to report what should have followed the text `start` consumed up
+/// to `rest`: at `rest`, or right after the last token when `rest` is on a
+/// later line — the missing piece belongs on the line before.
+fn after<'a>(start: &'a str, rest: &'a str) -> &'a str {
+    let rest = rest.trim_start();
+    let consumed = &start[..start.len() - rest.len()];
+    let end = &start[consumed.trim_end().len()..];
+    if end[..end.len() - rest.len()].contains('\n') {
+        end
+    } else {
+        rest
+    }
+}
+
+fn diagnose_entry(i: &str) -> Diagnosis<'_> {
+    let i = skip_trivia(i);
+    if i.starts_with("@include") {
+        return diagnosis(
+            i,
+            "@include directives are only resolved when loading from a file path — use init().file(...) or the ff CLI",
+            &[],
+        );
+    }
+    if i.starts_with("@segment") {
+        return diagnose_segment(i);
+    }
+    if let Some(after_keyword) = i.strip_prefix("@timezone") {
+        if parse_timezone_directive(i).is_ok() {
+            return diagnosis(
+                i,
+                "`@timezone` must come before the first flag or segment",
+                &[],
+            );
+        }
+        return unexpected(
+            after(i, after_keyword),
+            "after `@timezone`",
+            &["an IANA timezone name such as `Europe/Vilnius`"],
+        );
+    }
+    let i = match parse_metadata_block(i) {
+        Ok((rest, _)) => skip_trivia(rest),
+        Err(_) => i,
+    };
+    if i.starts_with('@') {
+        return diagnose_annotation(i);
+    }
+    let Ok((after_name, _)) = parse_flag_name(i) else {
+        return unexpected(
+            i,
+            "where a flag was expected",
+            &[
+                "a flag name starting with `FF-`",
+                "`@segment`",
+                "an annotation",
+            ],
+        );
+    };
+    let rest = after_name.trim_start();
+    if let Some(value) = rest.strip_prefix("->") {
+        if let Some(d) = diagnose_return(rest, value) {
+            return d;
+        }
+    } else if let Some(body) = rest.strip_prefix('{') {
+        if let Some(d) = diagnose_block(rest, body) {
+            return d;
+        }
+    } else {
+        return unexpected(after(i, rest), "after the flag name", &["`->`", "`{`"]);
+    }
+    // The syntax is fine, so a rule must return an undeclared variant.
+    diagnose_variant_refs(i)
+}
+
+fn diagnose_annotation(i: &str) -> Diagnosis<'_> {
+    let keyword_len = 1 + i[1..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(i.len() - 1);
+    let keyword = &i[..keyword_len];
+    let expected: &'static [&'static str] = match keyword {
+        "@owner" | "@ticket" | "@deprecated" => &["a quoted string"],
+        "@expires" => &["a date such as `2025-12-31`"],
+        "@description" | "@test" => &["a value on the same line"],
+        "@type" => &["a type name"],
+        "@requires" => &["a flag name starting with `FF-`"],
+        "@active" => ACTIVE_WINDOW,
+        _ => return diagnosis(i, format!("unknown annotation `{}`", keyword), ANNOTATIONS),
+    };
+    let value = &i[keyword_len..];
+    let at = after(i, value.trim_start_matches([' ', '\t']));
+    diagnosis(at, format!("invalid `{}` annotation", keyword), expected)
+}
+
+fn diagnose_segment(i: &str) -> Diagnosis<'_> {
+    let after_keyword = &i["@segment".len()..];
+    let Ok((rest, _)) = ws(parse_segment_name)(after_keyword) else {
+        return unexpected(
+            after(i, after_keyword),
+            "after `@segment`",
+            &["a segment name"],
+        );
+    };
+    let rest = rest.trim_start();
+    if rest.starts_with('(') && parse_segment_params(rest).is_err() {
+        return diagnosis(
+            rest,
+            "invalid segment parameters",
+            &["`(name, ...)` with identifiers separated by commas"],
+        );
+    }
+    let rest = parse_segment_params(rest)
+        .map_or(rest, |(r, _)| r)
+        .trim_start();
+    let Some(body) = rest.strip_prefix('{') else {
+        return unexpected(after(i, rest), "after the segment name", &["`{`"]);
+    };
+    match parse(body) {
+        Ok((after_condition, _)) => unexpected(
+            after(body, after_condition),
+            "after the segment condition",
+            &["`}`"],
+        ),
+        Err(_) => unexpected(after(rest, body), "in the segment body", &["a condition"]),
+    }
+}
+
+/// `value` follows a `->` that `arrow` starts with.
+fn diagnose_return<'a>(arrow: &'a str, value: &'a str) -> Option<Diagnosis<'a>> {
+    if parse_return_val(value).is_ok() {
+        return None;
+    }
+    Some(unexpected(after(arrow, value), "after `->`", RETURN_VALUES))
+}
+
+/// `body` follows the `{` that `open` starts with. `None` if the block is
+/// fine as far as the syntax goes.
+fn diagnose_block<'a>(open: &'a str, body: &'a str) -> Option<Diagnosis<'a>> {
+    let mut rest = skip_trivia(body);
+    if rest.starts_with("@variants") {
+        match parse_variants_block(rest) {
+            Ok((r, _)) => rest = r,
+            Err(_) => return Some(diagnose_variants_block(rest)),
+        }
+    }
+    let mut rules = 0;
+    loop {
+        let next = skip_trivia(rest);
+        if next.starts_with('}') {
+            return (rules == 0)
+                .then(|| unexpected(next, "in an empty block", &["a rule", "a return value"]));
+        }
+        if next.is_empty() {
+            return Some(diagnosis(open, "unclosed `{`", &["`}`"]));
+        }
+        match parse_rules_or_comments(rest) {
+            // A bare word is a valid rule on its own (a variant name), so
+            // `plan == pro true` parses as `plan` followed by junk. Anything
+            // else on the same line means it was meant as a condition.
+            Ok((r, Rule::Value(..))) if continues_line(rest, r) => {
+                return Some(diagnose_rule(rest));
+            }
+            Ok((r, _)) => {
+                rest = r;
+                rules += 1;
+            }
+            Err(_) => return Some(diagnose_rule(rest)),
+        }
+    }
+}
+
+/// Whether `rest`, which follows text parsed from `start`, carries on with
+/// something other than a comment or `}` on the same line.
+fn continues_line(start: &str, rest: &str) -> bool {
+    let consumed = &start[..start.len() - rest.len()];
+    let trailing = &consumed[consumed.trim_end().len()..];
+    !trailing.contains('\n')
+        && !rest.is_empty()
+        && !rest.starts_with(['}', '\n', '\r'])
+        && !rest.starts_with("//")
+        && !rest.starts_with("/*")
+}
+
+fn diagnose_rule(i: &str) -> Diagnosis<'_> {
+    let i = match parse_rule_prefix(i) {
+        Ok((rest, _)) => rest.trim_start(),
+        Err(_) => i.trim_start(),
+    };
+    // A well-formed window was consumed with the prefix
+    if let Some(window) = i.strip_prefix("@active") {
+        let at = after(i, window.trim_start_matches([' ', '\t']));
+        return diagnosis(at, "invalid `@active` annotation", ACTIVE_WINDOW);
+    }
+    if let Some(after_keyword) = i.strip_prefix("@env") {
+        let Ok((after_name, _)) = parse_env_name(after_keyword.trim_start()) else {
+            return unexpected(
+                after(i, after_keyword),
+                "after `@env`",
+                &["an environment name"],
+            );
+        };
+        let rest = after_name.trim_start();
+        if let Some(value) = rest.strip_prefix("->") {
+            if let Some(d) = diagnose_return(rest, value) {
+                return d;
+            }
+        } else if let Some(body) = rest.strip_prefix('{') {
+            if let Some(d) = diagnose_block(rest, body) {
+                return d;
+            }
+        } else {
+            return unexpected(
+                after(i, rest),
+                "after the environment name",
+                &["`->`", "`{`"],
+            );
+        }
+    }
+    if let Ok((after_condition, _)) = parse(i) {
+        let rest = after_condition.trim_start();
+        match rest.strip_prefix("->") {
+            Some(value) => {
+                if let Some(d) = diagnose_return(rest, value) {
+                    return d;
+                }
+            }
+            None => {
+                return unexpected(after(i, rest), "after the condition", &["`->`"]);
+            }
+        }
+    }
+    unexpected(
+        i,
+        "where a rule was expected",
+        &[
+            "a condition followed by `->`",
+            "a return value",
+            "`@env`",
+            "`}`",
+        ],
+    )
+}
+
+fn diagnose_variants_block(i: &str) -> Diagnosis<'_> {
+    let after_keyword = &i["@variants".len()..];
+    let open = after_keyword.trim_start();
+    let Some(mut rest) = open.strip_prefix('{') else {
+        return unexpected(after(i, open), "after `@variants`", &["`{`"]);
+    };
+    let mut names = Vec::new();
+    loop {
+        let next = skip_trivia(rest);
+        if next.starts_with('}') {
+            return if names.is_empty() {
+                unexpected(next, "in an empty `@variants` block", &["a variant name"])
+            } else {
+                diagnosis(i, "invalid `@variants` block", &[])
+            };
+        }
+        if next.is_empty() {
+            return diagnosis(open, "unclosed `{`", &["`}`"]);
+        }
+        match parse_variant_decl(next) {
+            Ok((r, (name, _))) => {
+                if names.contains(&name) {
+                    return diagnosis(next, format!("variant `{}` is declared twice", name), &[]);
+                }
+                names.push(name);
+                rest = r;
+            }
+            Err(_) => {
+                let Ok((after_name, _)) = parse_variant_name(next) else {
+                    return unexpected(
+                        next,
+                        "in the `@variants` block",
+                        &["a variant name", "`}`"],
+                    );
+                };
+                let arrow = after_name.trim_start();
+                return match arrow.strip_prefix("->") {
+                    Some(value) => unexpected(
+                        after(arrow, value),
+                        "after `->`",
+                        &[
+                            "`true`",
+                            "`false`",
+                            "a number",
+                            "a quoted string",
+                            "`json(...)`",
+                        ],
+                    ),
+                    None => unexpected(after(next, arrow), "after the variant name", &["`->`"]),
+                };
+            }
+        }
+    }
+}
+
+/// Report the first rule in the flag at `i` that returns an undeclared
+/// variant.
+fn diagnose_variant_refs(i: &str) -> Diagnosis<'_> {
+    if let Ok((rest, fv)) = alt((parse_anonymous_func, parse_function))(i) {
+        let source = &i[..i.len() - rest.len()];
+        for (name, mut def) in fv {
+            if let Err(variant) = resolve_variant_refs(&mut def.rules, &def.variants) {
+                if let Some(offset) = find_returned(source, &variant) {
+                    let expected: &'static [&'static str] = if def.variants.is_empty() {
+                        RETURN_VALUES
+                    } else {
+                        &["a variant declared in `@variants`"]
+                    };
+                    return diagnosis(
+                        &i[offset..],
+                        format!(
+                            "{} returns `{}`, which is not a declared variant",
+                            name, variant
+                        ),
+                        expected,
+                    );
+                }
+                let at = find_arm(source, &variant).map_or(i, |offset| &i[offset..]);
+                return diagnosis(
+                    at,
+                    format!(
+                        "{} splits to `{}`, which is not a declared variant: bare arm names must be declared in `@variants` or quoted",
+                        name, variant
+                    ),
+                    &["a variant declared in `@variants`", "a quoted string"],
+                );
+            }
+        }
+    }
+    unexpected(i, "where a flag was expected", &[])
+}
+
+/// Byte offset of `name` where it's returned by a `->` in `source`.
+fn find_returned(source: &str, name: &str) -> Option<usize> {
+    source
+        .match_indices(name)
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            let end = offset + name.len();
+            source[..offset].trim_end().ends_with("->")
+                && !source[end..].starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+/// Byte offset of `name` where it's a `split(...)` arm in `source`.
+fn find_arm(source: &str, name: &str) -> Option<usize> {
+    source
+        .match_indices(name)
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            source[..offset].trim_end().ends_with(['{', ',', '%'])
+                && source[offset + name.len()..].trim_start().starts_with(':')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_regex_is_a_parse_error_with_location() {
+        let data = "FF-a -> true\nFF-b {\n    email ~ /[a-z/ -> true\n    false\n}\n";
+        let err = parse_flagfile_with_segments(data).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 14));
+        assert_eq!(
+            err.message,
+            "invalid regex /[a-z/: unclosed character class"
+        );
+        assert_eq!(err.source_line, "    email ~ /[a-z/ -> true");
+    }
+
+    /// `(line, column, message, expected)` for a Flagfile that fails to parse.
+    fn parse_failure(data: &str) -> (usize, usize, String, Vec<String>) {
+        let err = parse_flagfile_with_segments(data).unwrap_err();
+        (err.line, err.column, err.message, err.expected)
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_failure() {
+        let (line, column, message, expected) =
+            parse_failure("FF-a -> true\n\nFF-b {\n    plan == pro true\n    false\n}\n");
+        assert_eq!((line, column), (4, 17));
+        assert_eq!(message, "unexpected `true` after the condition");
+        assert_eq!(expected, ["`->`"]);
+
+        // a missing arrow at the end of a line is reported on that line
+        let (line, column, _, expected) = parse_failure("FF-b {\n    plan == pro\n    false\n}");
+        assert_eq!((line, column), (2, 16));
+        assert_eq!(expected, ["`->`"]);
+
+        let (line, column, message, expected) = parse_failure("FF-a -> \n");
+        assert_eq!((line, column), (1, 8));
+        assert_eq!(message, "unexpected end of line after `->`");
+        assert_eq!(expected.len(), RETURN_VALUES.len());
+
+        let (line, column, message, _) = parse_failure("FF-a {\n    true\n");
+        assert_eq!((line, column), (1, 6));
+        assert_eq!(message, "unclosed `{`");
+
+        let (line, column, message, _) = parse_failure("FF-a = true");
+        assert_eq!((line, column), (1, 6));
+        assert_eq!(message, "unexpected `=` after the flag name");
+
+        let (line, _, message, expected) = parse_failure("FF-a -> true\nfeature -> true");
+        assert_eq!(line, 2);
+        assert_eq!(message, "unexpected `feature` where a flag was expected");
+        assert_eq!(expected[0], "a flag name starting with `FF-`");
+
+        // words can't contain `-`; such values are quoted
+        for rule in ["id == user-123", "user-123 == id"] {
+            let (_, _, message, _) = parse_failure(&format!("FF-a {{\n    {rule} -> true\n}}"));
+            assert_eq!(message, "unexpected `-123` after the condition", "{rule}");
+        }
+        assert!(parse_flagfile_with_segments("FF-a {\n    id == \"user-123\" -> true\n}").is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors_in_annotations_segments_and_variants() {
+        let (line, column, message, expected) =
+            parse_failure("@owner \"team\"\n@expires 2024-13-45\nFF-a -> true");
+        assert_eq!((line, column), (2, 10));
+        assert_eq!(message, "invalid `@expires` annotation");
+        assert_eq!(expected, ["a date such as `2025-12-31`"]);
+
+        let (_, _, message, _) = parse_failure("@onwer \"team\"\nFF-a -> true");
+        assert_eq!(message, "unknown annotation `@onwer`");
+
+        let (line, column, message, expected) =
+            parse_failure("@segment eu {\n    country in (NL, DE)\n    plan\n}");
+        assert_eq!((line, column), (2, 24));
+        assert_eq!(
+            message,
+            "unexpected end of line after the segment condition"
+        );
+        assert_eq!(expected, ["`}`"]);
+
+        let (line, column, message, _) = parse_failure(
+            "FF-a {\n    @variants {\n        on -> true\n        on -> false\n    }\n    on\n}",
+        );
+        assert_eq!((line, column), (4, 9));
+        assert_eq!(message, "variant `on` is declared twice");
+
+        let (line, column, message, expected) = parse_failure(
+            "FF-a {\n    @variants {\n        on -> true\n    }\n    plan == pro -> off\n    on\n}",
+        );
+        assert_eq!((line, column), (5, 20));
+        assert_eq!(
+            message,
+            "FF-a returns `off`, which is not a declared variant"
+        );
+        assert_eq!(expected, ["a variant declared in `@variants`"]);
+
+        let (line, column, message, _) = parse_failure("FF-a {\n    @env prod { }\n    true\n}");
+        assert_eq!((line, column), (2, 17));
+        assert_eq!(message, "unexpected `}` in an empty block");
+
+        let (_, _, message, _) = parse_failure("FF-a -> json({\"a\": })");
+        assert!(
+            message.starts_with("invalid JSON in json(...):"),
+            "{message}"
+        );
+
+        let (_, _, message, _) = parse_failure("FF-ok -> true\n@include \"other.ff\"");
+        assert!(message.starts_with("@include directives are only resolved"));
+    }
+
+    #[test]
+    fn test_parse_split_rule() {
+        let data = "FF-layout {\n    @variants {\n        wide -> json({\"cols\": 3})\n    }\n    @env dev {\n        split(orgId) { wide: 100% }\n    }\n    // @name layout test\n    split(userId, exp1) {\n        wide: 40%\n        \"narrow\": 60%\n    }\n}";
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        let def = &parsed.flags[0]["FF-layout"];
+        let Rule::Split { split, name, span } = &def.rules[1] else {
+            panic!("expected a split, got {:?}", def.rules[1]);
+        };
+        assert_eq!(name.as_deref(), Some("layout test"));
+        assert_eq!(split.salt.as_deref(), Some("exp1"));
+        assert_eq!((span.line, span.column), (9, 5));
+        let arms: Vec<(String, f64)> = split
+            .arms
+            .iter()
+            .map(|(ret, weight)| (ret.value().to_string(), *weight))
+            .collect();
+        assert_eq!(
+            arms,
+            [
+                ("json({\"cols\":3})".to_string(), 40.0),
+                ("\"narrow\"".to_string(), 60.0)
+            ]
+        );
+        assert!(
+            matches!(&def.rules[0], Rule::EnvRule { rules, .. } if matches!(rules[0], Rule::Split { .. }))
+        );
+
+        let (line, column, message, expected) =
+            parse_failure("FF-a -> true\nFF-b {\n    split(userId) { \"a\": 50%, b: 50% }\n}");
+        assert_eq!((line, column), (3, 31));
+        assert_eq!(
+            message,
+            "FF-b splits to `b`, which is not a declared variant: bare arm names must be declared in `@variants` or quoted"
+        );
+        assert_eq!(
+            expected,
+            ["a variant declared in `@variants`", "a quoted string"]
+        );
+        let (line, column, _, _) = parse_failure(
+            "FF-b {\n    @variants {\n        a -> 1\n    }\n    split(userId) {\n        a: 50%\n        b: 50%\n    }\n}",
+        );
+        assert_eq!((line, column), (7, 9));
+
+        let (line, column, message, _) =
+            parse_failure("FF-a -> true\nFF-b {\n    split(userId) { a: 50%, b: 40% }\n}");
+        assert_eq!((line, column), (3, 5));
+        assert_eq!(
+            message,
+            "split weights must be between 0% and 100% and add up to 100%"
+        );
+    }
+
+    #[test]
+    fn test_parse_active_windows() {
+        let data = "@active from 2026-11-01 until 2026-12-01T12:00Z\nFF-launch -> true\n\nFF-promo {\n    // @name holiday\n    @active until 2026-12-26\n    country == NL -> true\n    @active from 2027-01-01\n    false\n    true\n}";
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        let launch = &parsed.flags[0]["FF-launch"];
+        assert_eq!(
+            launch.metadata.active.unwrap().to_string(),
+            "from 2026-11-01T00:00:00Z until 2026-12-01T12:00:00Z"
+        );
+        let promo = &parsed.flags[1]["FF-promo"];
+        let Rule::BoolExpressionValue(AstNode::Logic(window, LogicOp::And, _), _, name, span) =
+            &promo.rules[0]
+        else {
+            panic!("expected a windowed condition, got {:?}", promo.rules[0]);
+        };
+        assert!(matches!(**window, AstNode::Active(w) if w.from.is_none()));
+        assert_eq!(name.as_deref(), Some("holiday"));
+        assert_eq!((span.line, span.column), (7, 5));
+        assert!(matches!(
+            &promo.rules[1],
+            Rule::BoolExpressionValue(AstNode::Active(w), ..) if w.until.is_none()
+        ));
+        assert!(matches!(promo.rules[2], Rule::Value(..)));
 
-fn parse_env_name(i: &str) -> IResult<&str, &str> {
-    recognize(many1(alt((alphanumeric1, hyphen_not_arrow, tag("_")))))(i)
-}
+        let (line, column, message, _) = parse_failure(
+            "FF-b {\n    @active from 2026-11-01\n    split(userId) { a: 50%, b: 50% }\n}",
+        );
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(
+            message,
+            "`@active` is not supported on `@env` blocks or `split()` rules"
+        );
+        let (line, column, message, _) = parse_failure(
+            "FF-b {\n    ramp(0% -> 100%, 2026-11-15 .. 2026-11-01, userId) -> true\n    false\n}",
+        );
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(message, "ramp must end after it starts");
+    }
 
-fn parse_env_rule_simple(i: &str) -> IResult<&str, Rule> {
-    let (rest, _) = ws(tag("@env"))(i)?;
-    let (rest, _) = multispace0(rest)?;
-    let (rest, env_name) = parse_env_name(rest)?;
-    let (rest, _) = ws(tag("->"))(rest)?;
-    let (rest, val) = parse_return_val(rest)?;
-    Ok((
-        rest,
-        Rule::EnvRule {
-            env: env_name.to_string(),
-            rules: vec![Rule::Value(val)],
-        },
-    ))
-}
+    #[test]
+    fn test_timezone_directive() {
+        let data = "// promo schedule\n@timezone \"America/New_York\"\n\n@active from 2026-11-27\nFF-sale {\n    ramp(0% -> 100%, 2026-11-27T09:00 .. 2026-11-28T09:00-05:00, userId) -> true\n    false\n}";
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        let sale = &parsed.flags[0]["FF-sale"];
+        assert_eq!(
+            sale.metadata.active.unwrap().to_string(),
+            "from 2026-11-27T05:00:00Z"
+        );
+        let Rule::BoolExpressionValue(AstNode::Ramp { start, end, .. }, ..) = &sale.rules[0] else {
+            panic!("expected a ramp, got {:?}", sale.rules[0]);
+        };
+        assert_eq!(start.to_string(), "2026-11-27 14:00:00 UTC");
+        assert_eq!(end.to_string(), "2026-11-28 14:00:00 UTC");
+
+        let (line, column, message, _) = parse_failure("@timezone Mars/Olympus\nFF-a -> true");
+        assert_eq!((line, column), (1, 11));
+        assert_eq!(message, "unknown timezone `Mars/Olympus`");
+        let (line, _, message, _) =
+            parse_failure("FF-a -> true\n@timezone Europe/Vilnius\nFF-b -> true");
+        assert_eq!(line, 2);
+        assert_eq!(
+            message,
+            "`@timezone` must come before the first flag or segment"
+        );
+    }
 
-fn parse_env_rule_block(i: &str) -> IResult<&str, Rule> {
-    let (rest, _) = ws(tag("@env"))(i)?;
-    let (rest, _) = multispace0(rest)?;
-    let (rest, env_name) = parse_env_name(rest)?;
-    let (rest, rules) = delimited(ws(tag("{")), parse_rules_list, ws(tag("}")))(rest)?;
-    Ok((
-        rest,
-        Rule::EnvRule {
-            env: env_name.to_string(),
-            rules,
-        },
-    ))
-}
+    #[test]
+    fn test_recovering_parse_reports_every_error() {
+        let data = r#"FF-ok-1 -> true
 
-fn parse_env_rule(i: &str) -> IResult<&str, Rule> {
-    alt((parse_env_rule_block, parse_env_rule_simple))(i)
-}
+@owner "growth"
+@expires 2024-13-45
+FF-bad-annotation -> true
 
-fn parse_rules(i: &str) -> IResult<&str, Rule> {
-    alt((parse_env_rule, parse_rule_expr, parse_rule_static))(i)
+FF-bad-rule {
+    plan == pro true
+    false
 }
 
-fn parse_rules_or_comments(i: &str) -> IResult<&str, Rule> {
-    let (rest, name) = parse_rule_prefix(i)?;
-    let (rest, rule) = parse_rules(rest)?;
-    let rule = match (rule, name) {
-        (Rule::BoolExpressionValue(e, v, _), Some(n)) => Rule::BoolExpressionValue(e, v, Some(n)),
-        (other, _) => other,
-    };
-    // NB: trailing comments are deliberately NOT consumed here — doing so would eat
-    // the next rule's leading `// @name` annotation as if it were a plain comment.
-    // Trailing comments before `}` are handled once at the end of `parse_rules_list`.
-    Ok((rest, rule))
+@segment eu {
+    country in (NL, DE)
 }
 
-fn parse_rules_list(i: &str) -> IResult<&str, Vec<Rule>> {
-    let (rest, rules) = many1(parse_rules_or_comments)(i)?;
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
-    Ok((rest, rules))
+FF-ok-2 {
+    segment(eu) -> true
+    false
 }
 
-fn parse_function(i: &str) -> IResult<&str, FlagValue<'_>> {
-    let parser = pair(
-        ws(parse_flag_name),
-        delimited(ws(tag("{")), parse_rules_list, ws(tag("}"))),
-    );
-    map(parser, |(flag_name, rules)| {
-        HashMap::from([(
-            flag_name,
-            FlagDefinition {
-                rules,
-                metadata: FlagMetadata::default(),
-            },
-        )])
-    })(i)
-}
+FF-bad-return -> "unterminated
+FF-ok-3 -> 42
+"#;
+        let (parsed, errors) = parse_flagfile_recovering(data);
+        let positions: Vec<(usize, usize)> = errors.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(positions, [(4, 10), (8, 17), (21, 18)]);
+        let names: Vec<&str> = parsed
+            .flags
+            .iter()
+            .flat_map(|fv| fv.keys().copied())
+            .collect();
+        assert_eq!(
+            names,
+            ["FF-ok-1", "FF-bad-annotation", "FF-ok-2", "FF-ok-3"]
+        );
+        assert!(parsed.segments.contains_key("eu"));
 
-fn parse_flag_entry(i: &str) -> IResult<&str, FlagValue<'_>> {
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
-    let (rest, metadata) = parse_metadata_block(rest)?;
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
-    let (rest, mut fv) = alt((parse_anonymous_func, parse_function))(rest)?;
-    // Attach collected metadata to the flag definition
-    if metadata != FlagMetadata::default() {
-        for (_, def) in fv.iter_mut() {
-            def.metadata = metadata.clone();
-        }
-    }
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
-    Ok((rest, fv))
-}
+        // the first error is the one the strict parser reports
+        assert_eq!(parse_flagfile_with_segments(data).unwrap_err(), errors[0]);
 
-#[derive(Debug, Clone)]
-pub struct ParsedFlagfile<'a> {
-    pub flags: Vec<FlagValue<'a>>,
-    pub segments: Segments,
-}
+        let (parsed, errors) = parse_flagfile_recovering("FF-a -> true\n// done\n");
+        assert!(errors.is_empty());
+        assert_eq!(parsed.flags.len(), 1);
+    }
 
-fn parse_segment_definition(i: &str) -> IResult<&str, (String, AstNode)> {
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(i)?;
-    let (rest, _) = ws(tag("@segment"))(rest)?;
-    let (rest, name) = ws(parse_segment_name)(rest)?;
-    let name = name.to_string();
-    let (rest, expr) = delimited(ws(tag("{")), parse, ws(tag("}")))(rest)?;
-    let (rest, _) = many0(alt((parse_comment, multiline_comment)))(rest)?;
-    Ok((rest, (name, expr)))
-}
+    #[test]
+    fn test_recovering_parse_continues_after_hard_failures() {
+        let data =
+            "FF-a {\n    email ~ /[a-z/ -> true\n    false\n}\nFF-b -> json({)\nFF-c -> true\n";
+        let (parsed, errors) = parse_flagfile_recovering(data);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("invalid regex"));
+        assert!(messages[1].starts_with("invalid JSON"));
+        assert_eq!(parsed.flags.len(), 1);
+        assert!(parsed.flags[0].contains_key("FF-c"));
+    }
 
-enum FlagfileEntry<'a> {
-    Flag(FlagValue<'a>),
-    Segment(String, AstNode),
-}
+    #[test]
+    fn test_spans_point_at_flags_rules_and_segments() {
+        let data = r#"// checkout
+FF-a -> true
 
-fn parse_flagfile_entry(i: &str) -> IResult<&str, FlagfileEntry<'_>> {
-    alt((
-        map(parse_segment_definition, |(name, expr)| {
-            FlagfileEntry::Segment(name, expr)
-        }),
-        map(parse_flag_entry, FlagfileEntry::Flag),
-    ))(i)
+@segment eu {
+    country in (NL, DE)
 }
 
-pub fn parse_flagfile_with_segments(i: &str) -> IResult<&str, ParsedFlagfile<'_>> {
-    let (rest, entries) = many0(parse_flagfile_entry)(i)?;
-    let mut flags = Vec::new();
-    let mut segments = Segments::new();
-    for entry in entries {
-        match entry {
-            FlagfileEntry::Flag(fv) => flags.push(fv),
-            FlagfileEntry::Segment(name, expr) => {
-                segments.insert(name, expr);
-            }
-        }
+@owner "checkout"
+FF-b {
+    // @name premium
+    plan == premium -> true
+    @env prod {
+      segment(eu) -> true
     }
-    Ok((rest, ParsedFlagfile { flags, segments }))
-}
-
-pub fn parse_flagfile(i: &str) -> IResult<&str, Vec<FlagValue<'_>>> {
-    many0(parse_flag_entry)(i)
+    false
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+"#;
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        let text = |span: Span| &data[span.start..span.end];
+        let position = |span: Span| (span.line, span.column);
+
+        let a = &parsed.flags[0]["FF-a"];
+        assert_eq!(text(a.span), "FF-a -> true");
+        assert_eq!(position(a.rules[0].span()), (2, 9));
+
+        let eu = &parsed.segments["eu"];
+        assert_eq!(position(eu.span), (4, 1));
+        assert!(text(eu.span).starts_with("@segment eu {") && text(eu.span).ends_with('}'));
+
+        let b = &parsed.flags[1]["FF-b"];
+        assert_eq!(position(b.span), (9, 1));
+        assert!(text(b.span).starts_with("FF-b {") && text(b.span).ends_with('}'));
+        assert_eq!(text(b.rules[0].span()), "plan == premium -> true");
+        assert_eq!(position(b.rules[0].span()), (11, 5));
+        let Rule::EnvRule { rules, span, .. } = &b.rules[1] else {
+            panic!("expected an @env rule");
+        };
+        assert_eq!(position(*span), (12, 5));
+        assert_eq!(text(rules[0].span()), "segment(eu) -> true");
+        assert_eq!(position(rules[0].span()), (13, 7));
+        assert_eq!(position(b.rules[2].span()), (15, 5));
+    }
 
     #[test]
     fn test_parse_rules() {
@@ -613,7 +2206,43 @@ mod tests {
         assert_eq!(def.rules.len(), 1);
         assert!(matches!(
             &def.rules[0],
-            Rule::Value(FlagReturn::Integer(5000))
+            Rule::Value(FlagReturn::Integer(5000), _)
+        ));
+    }
+
+    #[test]
+    fn test_parse_float_return() {
+        let data = r#"@type float
+FF-sample-rate {
+    tier == enterprise -> 1
+    plan == premium -> 0.5
+    0.25
+}"#;
+        let (i, v) = parse_flagfile(data).unwrap();
+        assert_eq!(i.trim(), "");
+        let def = &v[0]["FF-sample-rate"];
+        assert!(matches!(
+            &def.rules[0],
+            Rule::BoolExpressionValue(_, FlagReturn::Integer(1), _, _)
+        ));
+        let Rule::Value(default, _) = &def.rules[2] else {
+            panic!("expected a default value");
+        };
+        assert!(matches!(default, FlagReturn::Float(f) if *f == 0.25));
+        assert_eq!(default.value_type(), ValueType::Float);
+        // whole numbers are valid floats
+        assert!(def.type_mismatches("FF-sample-rate").is_empty());
+        // Display keeps a decimal point so the value reads back as a float
+        assert_eq!(FlagReturn::Float(2.0).to_string(), "2.0");
+        assert_eq!(FlagReturn::Float(-0.125).to_string(), "-0.125");
+    }
+
+    #[test]
+    fn test_parse_large_integer_return() {
+        let (_, v) = parse_anonymous_func("FF-epoch -> 1735689600000").unwrap();
+        assert!(matches!(
+            &v["FF-epoch"].rules[0],
+            Rule::Value(FlagReturn::Integer(1_735_689_600_000), _)
         ));
     }
 
@@ -624,7 +2253,7 @@ mod tests {
         assert_eq!(i, "");
         let def = v.get("FF-log-level").unwrap();
         assert_eq!(def.rules.len(), 1);
-        assert!(matches!(&def.rules[0], Rule::Value(FlagReturn::Str(s)) if s == "debug"));
+        assert!(matches!(&def.rules[0], Rule::Value(FlagReturn::Str(s), _) if s == "debug"));
     }
 
     #[test]
@@ -639,7 +2268,7 @@ mod tests {
         assert_eq!(def.rules.len(), 2);
         assert!(matches!(
             &def.rules[1],
-            Rule::Value(FlagReturn::Integer(5000))
+            Rule::Value(FlagReturn::Integer(5000), _)
         ));
     }
 
@@ -647,10 +2276,8 @@ mod tests {
     fn full_flag_file_test() {
         let data = include_str!("../Flagfile.example");
 
-        let (i, v) = parse_flagfile_with_segments(data).unwrap();
-        dbg!(i, &v);
-        assert_eq!(true, v.flags.len() > 0);
-        assert_eq!(i.to_string().trim(), "");
+        let v = parse_flagfile_with_segments(data).unwrap();
+        assert!(!v.flags.is_empty());
     }
 
     #[test]
@@ -898,8 +2525,7 @@ FF-beta-feature {
     segment(beta_users) -> true
     false
 }"#;
-        let (i, parsed) = parse_flagfile_with_segments(data).unwrap();
-        assert_eq!(i.trim(), "");
+        let parsed = parse_flagfile_with_segments(data).unwrap();
         assert_eq!(parsed.segments.len(), 1);
         assert!(parsed.segments.contains_key("beta_users"));
         assert_eq!(parsed.flags.len(), 1);
@@ -920,8 +2546,7 @@ FF-us-premium {
     segment(premium) and segment(us_users) -> true
     false
 }"#;
-        let (i, parsed) = parse_flagfile_with_segments(data).unwrap();
-        assert_eq!(i.trim(), "");
+        let parsed = parse_flagfile_with_segments(data).unwrap();
         assert_eq!(parsed.segments.len(), 2);
         assert!(parsed.segments.contains_key("premium"));
         assert!(parsed.segments.contains_key("us_users"));
@@ -941,8 +2566,7 @@ FF-internal-feature {
 }
 
 FF-another -> false"#;
-        let (i, parsed) = parse_flagfile_with_segments(data).unwrap();
-        assert_eq!(i.trim(), "");
+        let parsed = parse_flagfile_with_segments(data).unwrap();
         assert_eq!(parsed.segments.len(), 1);
         assert_eq!(parsed.flags.len(), 3);
     }
@@ -950,12 +2574,114 @@ FF-another -> false"#;
     #[test]
     fn test_parse_no_segments_backward_compat() {
         let data = "FF-flag1 -> true\nFF-flag2 -> false";
-        let (i, parsed) = parse_flagfile_with_segments(data).unwrap();
-        assert_eq!(i.trim(), "");
+        let parsed = parse_flagfile_with_segments(data).unwrap();
         assert_eq!(parsed.segments.len(), 0);
         assert_eq!(parsed.flags.len(), 2);
     }
 
+    #[test]
+    fn test_parse_parameterized_segment() {
+        let data = r#"@segment in-country(c) {
+    countryCode == c
+}
+
+@segment eu-tier ( tier , min ) {
+    segment(in-country, NL) and plan == tier and seats >= min
+}
+
+FF-nl {
+    segment(eu-tier, "premium", 5) -> true
+    false
+}"#;
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        assert_eq!(parsed.segments["in-country"].params, vec!["c"]);
+        assert_eq!(parsed.segments["eu-tier"].params, vec!["tier", "min"]);
+        assert_eq!(
+            parsed.segments["eu-tier"].body.segment_refs(),
+            vec!["in-country"]
+        );
+        assert_eq!(parsed.segment_cycle(), None);
+
+        let err = parse_flagfile_with_segments("@segment s(a, a) {\n    x == a\n}").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 11));
+        assert_eq!(err.message, "segment parameter declared more than once");
+    }
+
+    #[test]
+    fn test_segment_cycle() {
+        let data = r#"@segment base {
+    plan == premium
+}
+@segment eu {
+    segment(base) and country in (NL, DE)
+}
+FF-x -> true"#;
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        assert_eq!(parsed.segment_cycle(), None);
+
+        let data = r#"@segment a {
+    segment(b)
+}
+@segment b {
+    plan == premium or segment(c)
+}
+@segment c {
+    segment(a)
+}
+@segment self_ref {
+    not (segment(self_ref))
+}
+FF-x -> true"#;
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        assert_eq!(
+            parsed.segment_cycle().map(|c| c.to_string()).as_deref(),
+            Some("circular segment dependency: a -> b -> c -> a")
+        );
+        let parsed = parse_flagfile_with_segments("@segment me {\n    segment(me)\n}").unwrap();
+        assert_eq!(
+            parsed.segment_cycle(),
+            Some(SegmentCycle(vec!["me".into(), "me".into()]))
+        );
+    }
+
+    #[test]
+    fn test_segment_arity_mismatch() {
+        let segments = r#"@segment in-country(c) {
+    country == c
+}
+@segment eu {
+    segment(in-country, NL) or segment(undefined)
+}
+"#;
+        let data = format!("{segments}FF-x {{\n    segment(eu) -> true\n    false\n}}");
+        let parsed = parse_flagfile_with_segments(&data).unwrap();
+        assert_eq!(parsed.segment_arity_mismatch(), None);
+
+        let data = format!(
+            "{segments}FF-x {{\n    @env prod {{\n        segment(in-country) -> true\n    }}\n    false\n}}"
+        );
+        let parsed = parse_flagfile_with_segments(&data).unwrap();
+        let mismatch = parsed.segment_arity_mismatch().unwrap();
+        assert_eq!(
+            (mismatch.params, mismatch.args, mismatch.span.line),
+            (1, 0, 9)
+        );
+        assert_eq!(
+            mismatch.to_string(),
+            "segment 'in-country' takes 1 argument, called with 0 at line 9"
+        );
+
+        let parsed = parse_flagfile_with_segments(
+            "@segment in-country(c) {\n    country == c\n}\n@segment eu {\n    segment(in-country, NL, DE)\n}",
+        )
+        .unwrap();
+        let mismatch = parsed.segment_arity_mismatch().unwrap();
+        assert_eq!(
+            (mismatch.params, mismatch.args, mismatch.span.line),
+            (1, 2, 4)
+        );
+    }
+
     // ── @env rule tests ─────────────────────────────────────────────
 
     #[test]
@@ -969,10 +2695,10 @@ FF-another -> false"#;
         let def = v[0].get("FF-debug").unwrap();
         assert_eq!(def.rules.len(), 2);
         assert!(
-            matches!(&def.rules[0], Rule::EnvRule { env, rules } if env == "dev" && rules.len() == 1)
+            matches!(&def.rules[0], Rule::EnvRule { env, rules, .. } if env == "dev" && rules.len() == 1)
         );
         assert!(
-            matches!(&def.rules[1], Rule::EnvRule { env, rules } if env == "prod" && rules.len() == 1)
+            matches!(&def.rules[1], Rule::EnvRule { env, rules, .. } if env == "prod" && rules.len() == 1)
         );
     }
 
@@ -990,11 +2716,11 @@ FF-another -> false"#;
         let def = v[0].get("FF-search").unwrap();
         assert_eq!(def.rules.len(), 2);
         assert!(
-            matches!(&def.rules[0], Rule::EnvRule { env, rules } if env == "prod" && rules.len() == 2)
+            matches!(&def.rules[0], Rule::EnvRule { env, rules, .. } if env == "prod" && rules.len() == 2)
         );
         assert!(matches!(
             &def.rules[1],
-            Rule::Value(FlagReturn::OnOff(true))
+            Rule::Value(FlagReturn::OnOff(true), _)
         ));
     }
 
@@ -1018,7 +2744,7 @@ FF-another -> false"#;
         assert!(matches!(&def.rules[2], Rule::EnvRule { env, .. } if env == "prod"));
         assert!(matches!(
             &def.rules[3],
-            Rule::Value(FlagReturn::OnOff(false))
+            Rule::Value(FlagReturn::OnOff(false), _)
         ));
     }
 
@@ -1047,7 +2773,7 @@ FF-logging {
         let def = v.get("FF-dep-root-new-checkout").unwrap();
         assert!(matches!(
             &def.rules[0],
-            Rule::Value(FlagReturn::OnOff(true))
+            Rule::Value(FlagReturn::OnOff(true), _)
         ));
     }
 
@@ -1084,7 +2810,7 @@ FF-logging {
         let def = v.get("FF_feature_flag").unwrap();
         assert!(matches!(
             &def.rules[0],
-            Rule::Value(FlagReturn::OnOff(false))
+            Rule::Value(FlagReturn::OnOff(false), _)
         ));
     }
 
@@ -1097,7 +2823,7 @@ FF-logging {
         assert_eq!(i, "");
         assert!(matches!(
             rule,
-            Rule::BoolExpressionValue(_, FlagReturn::OnOff(true), _)
+            Rule::BoolExpressionValue(_, FlagReturn::OnOff(true), _, _)
         ));
     }
 
@@ -1113,9 +2839,9 @@ FF-logging {
         let def = v.get("FF-checkout").unwrap();
         assert!(matches!(
             &def.rules[0],
-            Rule::BoolExpressionValue(_, _, Some(n)) if n == "EU rollout"
+            Rule::BoolExpressionValue(_, _, Some(n), _) if n == "EU rollout"
         ));
-        assert!(matches!(&def.rules[1], Rule::Value(_)));
+        assert!(matches!(&def.rules[1], Rule::Value(_, _)));
     }
 
     #[test]
@@ -1130,7 +2856,7 @@ FF-logging {
         let def = v.get("FF-checkout").unwrap();
         assert!(matches!(
             &def.rules[0],
-            Rule::BoolExpressionValue(_, _, Some(n)) if n == "beta cohort"
+            Rule::BoolExpressionValue(_, _, Some(n), _) if n == "beta cohort"
         ));
     }
 
@@ -1147,7 +2873,7 @@ FF-logging {
         // The name preceding a bare static value is parsed but not retained.
         assert!(matches!(
             &def.rules[1],
-            Rule::Value(FlagReturn::OnOff(false))
+            Rule::Value(FlagReturn::OnOff(false), _)
         ));
     }
 
@@ -1170,14 +2896,14 @@ FF-logging {
         assert_eq!(def.rules.len(), 3);
         assert!(matches!(
             &def.rules[0],
-            Rule::BoolExpressionValue(_, _, Some(n)) if n == "user switch"
+            Rule::BoolExpressionValue(_, _, Some(n), _) if n == "user switch"
         ));
         assert!(matches!(
             &def.rules[1],
-            Rule::BoolExpressionValue(_, _, Some(n)) if n == "country selection"
+            Rule::BoolExpressionValue(_, _, Some(n), _) if n == "country selection"
         ));
         // The third is a static fallthrough value; its name is intentionally dropped.
-        assert!(matches!(&def.rules[2], Rule::Value(_)));
+        assert!(matches!(&def.rules[2], Rule::Value(_, _)));
     }
 
     #[test]
@@ -1192,8 +2918,170 @@ FF-logging {
         let def = v.get("FF-checkout").unwrap();
         assert!(matches!(
             &def.rules[0],
-            Rule::BoolExpressionValue(_, _, None)
+            Rule::BoolExpressionValue(_, _, None, _)
+        ));
+    }
+
+    #[test]
+    fn test_parse_variants_block() {
+        let data = r#"FF-checkout-experiment {
+    @variants {
+        control -> json({"steps": 4})
+        // two-step flow
+        streamlined -> json({"steps": 2})
+    }
+
+    country == NL -> streamlined
+    control
+}"#;
+        let (i, v) = parse_flagfile(data).unwrap();
+        assert_eq!(i, "");
+        let def = v[0].get("FF-checkout-experiment").unwrap();
+        let names: Vec<&str> = def.variants.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["control", "streamlined"]);
+        assert!(matches!(
+            &def.rules[0],
+            Rule::BoolExpressionValue(_, FlagReturn::Variant(n, v), _, _)
+                if n == "streamlined" && matches!(v.as_ref(), FlagReturn::Json(j) if j["steps"] == 2)
+        ));
+        assert!(matches!(
+            &def.rules[1],
+            Rule::Value(FlagReturn::Variant(n, v), _)
+                if n == "control" && matches!(v.as_ref(), FlagReturn::Json(j) if j["steps"] == 4)
+        ));
+    }
+
+    #[test]
+    fn test_parse_variants_in_env_rules() {
+        let data = r#"FF-banner {
+    @variants {
+        off -> "none"
+        hero -> "hero-v2"
+    }
+    @env dev -> hero
+    @env prod {
+        beta == true -> hero
+        off
+    }
+    off
+}"#;
+        let (i, v) = parse_flagfile(data).unwrap();
+        assert_eq!(i, "");
+        let def = v[0].get("FF-banner").unwrap();
+        assert!(matches!(
+            &def.rules[0],
+            Rule::EnvRule { rules, .. }
+                if matches!(&rules[0], Rule::Value(r, _) if r.variant_name() == Some("hero"))
         ));
+        match &def.rules[1] {
+            Rule::EnvRule { rules, .. } => {
+                assert!(matches!(rules[1].clone(), Rule::Value(r, _)
+                    if matches!(r.value(), FlagReturn::Str(s) if s == "none")));
+            }
+            other => panic!("expected env rule, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_variants_unknown_reference_rejected() {
+        let data = r#"FF-checkout {
+    @variants {
+        control -> json({"steps": 4})
+    }
+    country == NL -> streamlined
+    control
+}"#;
+        let (i, v) = parse_flagfile(data).unwrap();
+        assert!(v.is_empty());
+        assert!(i.starts_with("FF-checkout"));
+    }
+
+    #[test]
+    fn test_parse_variant_reference_without_block_rejected() {
+        let (_, v) = parse_flagfile("FF-checkout -> control").unwrap();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_parse_value_type_annotation() {
+        let data = "@type int\nFF-retry-count -> 3\n@type release\nFF-new-ui -> true";
+        let (i, v) = parse_flagfile(data).unwrap();
+        assert_eq!(i.trim(), "");
+        let retry = &v[0]["FF-retry-count"];
+        assert_eq!(retry.metadata.value_type, Some(ValueType::Int));
+        assert_eq!(retry.metadata.flag_type, None);
+        assert!(retry.type_mismatches("FF-retry-count").is_empty());
+        let ui = &v[1]["FF-new-ui"];
+        assert_eq!(ui.metadata.value_type, None);
+        assert_eq!(ui.metadata.flag_type, Some("release".to_string()));
+    }
+
+    #[test]
+    fn test_type_mismatch_message() {
+        let data = r#"@type int
+FF-retry-count {
+    plan == free -> "three"
+    3
+}"#;
+        let parsed = parse_flagfile_with_segments(data).unwrap();
+        let mismatches = parsed.type_mismatches();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].to_string(),
+            r#"FF-retry-count: rule returns string "three" but flag is declared @type int"#
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch_in_env_rules_and_variants() {
+        let data = r#"@type json
+FF-theme {
+    @variants {
+        dark -> json({"dark": true})
+        light -> "light"
+    }
+    @env dev {
+        beta == true -> 1
+        dark
+    }
+    dark
+}"#;
+        let (_, v) = parse_flagfile(data).unwrap();
+        let mismatches = v[0]["FF-theme"].type_mismatches("FF-theme");
+        let found: Vec<ValueType> = mismatches.iter().map(|m| m.found.value_type()).collect();
+        assert_eq!(found, vec![ValueType::String, ValueType::Int]);
+    }
+
+    #[test]
+    fn test_parse_variants_duplicate_name_rejected() {
+        let data = r#"FF-checkout {
+    @variants {
+        control -> 1
+        control -> 2
+    }
+    control
+}"#;
+        let (_, v) = parse_flagfile(data).unwrap();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_flag_return_from_str_round_trips() {
+        for text in [
+            "true",
+            "42",
+            "2.0",
+            "0.25",
+            "\"dark\"",
+            "json({\"a\":1})",
+            "express",
+        ] {
+            let value: FlagReturn = text.parse().unwrap();
+            assert_eq!(value.to_string(), text);
+        }
+        let err = "true false".parse::<FlagReturn>().unwrap_err();
+        assert_eq!(err.column, 6);
+        assert!("-> true".parse::<FlagReturn>().is_err());
     }
 }
 
@@ -0,0 +1,1174 @@
+//! Precompiled evaluation plans.
+//!
+//! [`crate::eval`] walks the parsed AST on every call: it looks variables up
+//! by name and clones them, re-finds segments by name and formats SHA-1 hex
+//! strings for `percentage()`. A [`Plan`] does that work once per Flagfile:
+//!
+//! - every variable a flag reads gets a slot, looked up at most once per
+//!   evaluation and borrowed from the context instead of cloned;
+//! - `segment(...)` references point straight at the compiled segment body;
+//! - `percentage()`, `ramp()` and `split()` keep a SHA-1 state with the `flag.salt.`
+//!   prefix already hashed;
+//! - constant `true`/`false` conditions and double negations are folded away.
+//!
+//! Results are identical to [`crate::evaluation::evaluate_flag`], including
+//! reasons, rule indexes and errors.
+
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use sha1::{Digest, Sha1};
+
+use crate::ast::{
+    ActiveWindow, ArithOp, ArrayOp, AstNode, Atom, ComparisonOp, FlagMetadata, FnCall, LogicOp,
+    MatchOp, Span,
+};
+use crate::eval::{
+    arithmetic, compare, describe_operand, ramp_rate, resolve, Clock, Context, EvalError,
+    EvalOptions, Functions, Segments,
+};
+use crate::evaluation::{EvaluationDetail, Reason};
+use crate::parse_flagfile::{FlagReturn, Rule};
+
+/// Compiled form of every flag in a Flagfile.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    flags: HashMap<String, FlagPlan>,
+}
+
+impl Plan {
+    pub fn compile<K>(
+        flags: &HashMap<K, Vec<Rule>>,
+        metadata: &HashMap<K, FlagMetadata>,
+        segments: &Segments,
+    ) -> Self
+    where
+        K: Borrow<str> + Eq + Hash,
+    {
+        let flags = flags
+            .iter()
+            .map(|(name, rules)| {
+                let name = name.borrow();
+                let mut plan = FlagPlan::compile(name, rules, segments);
+                if let Some(meta) = metadata.get(name) {
+                    plan.requires = meta.requires.clone();
+                    plan.active = meta.active;
+                }
+                (name.to_string(), plan)
+            })
+            .collect();
+        Plan { flags }
+    }
+
+    /// Evaluate a flag by name, checking its `@requires` prerequisites first.
+    pub fn evaluate(
+        &self,
+        flag_name: &str,
+        context: &Context,
+        env: Option<&str>,
+        options: &EvalOptions,
+    ) -> EvaluationDetail {
+        let Some(flag) = self.flags.get(flag_name) else {
+            return EvaluationDetail::no_value(Reason::FlagNotFound);
+        };
+        let now = options.clock.now();
+        if !flag.is_active(now) {
+            return EvaluationDetail::no_value(Reason::Disabled);
+        }
+        for req in &flag.requires {
+            let satisfied = self.flags.get(req.as_str()).is_some_and(|req_plan| {
+                req_plan.is_active(now)
+                    && matches!(
+                        req_plan
+                            .evaluate(context, env, options)
+                            .value
+                            .as_ref()
+                            .map(FlagReturn::value),
+                        Some(FlagReturn::OnOff(true))
+                    )
+            });
+            if !satisfied {
+                return EvaluationDetail::no_value(Reason::PrerequisiteFailed);
+            }
+        }
+        flag.evaluate(context, env, options)
+    }
+
+    pub fn flag(&self, flag_name: &str) -> Option<&FlagPlan> {
+        self.flags.get(flag_name)
+    }
+}
+
+/// Compiled rules of a single flag.
+#[derive(Debug, Clone)]
+pub struct FlagPlan {
+    /// Variable names by slot.
+    vars: Vec<String>,
+    /// Bodies of the segment calls this flag makes, directly or through
+    /// other segments, with arguments bound.
+    segments: Vec<Cond>,
+    rules: Vec<PlanRule>,
+    requires: Vec<String>,
+    active: Option<ActiveWindow>,
+}
+
+#[derive(Debug, Clone)]
+enum PlanRule {
+    When {
+        cond: Cond,
+        value: FlagReturn,
+        name: Option<String>,
+        span: Span,
+    },
+    Value(FlagReturn, Span),
+    Env {
+        env: String,
+        rules: Vec<PlanRule>,
+    },
+    Split {
+        operand: Operand,
+        label: String,
+        prefix: Sha1,
+        /// Each arm with the bucket it ends below, from
+        /// [`Split::bounds`](crate::parse_flagfile::Split::bounds)..
+        arms: Vec<(u64, FlagReturn)>,
+        name: Option<String>,
+        span: Span,
+    },
+}
+
+/// Where a compared value comes from. `label` fields next to an operand
+/// name it in error messages.
+#[derive(Debug, Clone)]
+enum Operand {
+    Var(usize),
+    Upper(Box<Operand>),
+    Lower(Box<Operand>),
+    Now,
+    /// First argument that resolves; constants always do.
+    Coalesce(Vec<Operand>),
+    Const(Atom),
+    /// `lhs op rhs`; only read through [`Frame::lookup`], which reports why
+    /// it could not be computed.
+    Arith {
+        lhs: Box<Operand>,
+        lhs_label: String,
+        op: ArithOp,
+        rhs: Box<Operand>,
+        rhs_label: String,
+    },
+    /// `fn:name(args...)`; like `Arith`, only read through [`Frame::lookup`].
+    Call {
+        name: String,
+        args: Vec<(Operand, String)>,
+    },
+    /// An expression that never yields a value.
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Regex(Regex),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+enum Cond {
+    Const(bool),
+    /// A bare variable used as a condition.
+    Bool {
+        slot: usize,
+        label: String,
+    },
+    Compare {
+        operand: Operand,
+        label: String,
+        op: ComparisonOp,
+        value: Operand,
+        value_label: String,
+    },
+    InList {
+        operand: Operand,
+        label: String,
+        items: Vec<Atom>,
+        negate: bool,
+    },
+    /// `needle in variable`, where the variable holds a list.
+    InVar {
+        needle: Operand,
+        needle_label: String,
+        list: Operand,
+        list_label: String,
+        negate: bool,
+    },
+    Match {
+        operand: Operand,
+        label: String,
+        op: MatchOp,
+        pattern: Pattern,
+    },
+    NullCheck {
+        operand: Operand,
+        is_null: bool,
+    },
+    Percentage {
+        operand: Operand,
+        label: String,
+        /// SHA-1 state after hashing `flag.` or `flag.salt.`.
+        prefix: Sha1,
+        threshold: u64,
+    },
+    Ramp {
+        operand: Operand,
+        label: String,
+        prefix: Sha1,
+        from: f64,
+        to: f64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    Active(ActiveWindow),
+    /// A `fn:` call used as a condition.
+    Call {
+        operand: Operand,
+        label: String,
+    },
+    /// Index into [`FlagPlan::segments`].
+    Segment(usize),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+    /// Fails every time it is evaluated, e.g. an unknown segment.
+    Error(EvalError),
+}
+
+struct Compiler<'s> {
+    flag_name: &'s str,
+    segment_defs: &'s Segments,
+    vars: Vec<String>,
+    var_slots: HashMap<String, usize>,
+    segments: Vec<Cond>,
+    segment_slots: HashMap<String, usize>,
+}
+
+impl Compiler<'_> {
+    fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.var_slots.get(name) {
+            return slot;
+        }
+        let slot = self.vars.len();
+        self.vars.push(name.to_string());
+        self.var_slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    fn rules(&mut self, rules: &[Rule]) -> Vec<PlanRule> {
+        rules
+            .iter()
+            .map(|rule| match rule {
+                Rule::BoolExpressionValue(expr, value, name, span) => PlanRule::When {
+                    cond: self.cond(expr),
+                    value: value.clone(),
+                    name: name.clone(),
+                    span: *span,
+                },
+                Rule::Value(value, span) => PlanRule::Value(value.clone(), *span),
+                Rule::EnvRule { env, rules, .. } => PlanRule::Env {
+                    env: env.clone(),
+                    rules: self.rules(rules),
+                },
+                Rule::Split { split, name, span } => PlanRule::Split {
+                    operand: self.operand(&split.field),
+                    label: describe_operand(&split.field),
+                    prefix: self.bucket_prefix(split.salt.as_deref()),
+                    arms: split
+                        .bounds()
+                        .into_iter()
+                        .map(|(bound, value)| (bound, value.clone()))
+                        .collect(),
+                    name: name.clone(),
+                    span: *span,
+                },
+            })
+            .collect()
+    }
+
+    /// SHA-1 state after hashing `flag.` or `flag.salt.`.
+    fn bucket_prefix(&self, salt: Option<&str>) -> Sha1 {
+        let mut prefix = Sha1::new();
+        match salt {
+            Some(s) => prefix.update(format!("{}.{}.", self.flag_name, s)),
+            None => prefix.update(format!("{}.", self.flag_name)),
+        }
+        prefix
+    }
+
+    fn operand(&mut self, node: &AstNode) -> Operand {
+        match node {
+            AstNode::Variable(Atom::Variable(v)) | AstNode::Constant(Atom::Variable(v)) => {
+                Operand::Var(self.slot(v))
+            }
+            AstNode::Function(FnCall::Now, _) => Operand::Now,
+            AstNode::Function(FnCall::Upper, inner) => {
+                Operand::Upper(Box::new(self.operand(inner)))
+            }
+            AstNode::Function(FnCall::Lower, inner) => {
+                Operand::Lower(Box::new(self.operand(inner)))
+            }
+            AstNode::Coalesce(args) => Operand::Coalesce(
+                args.iter()
+                    .filter_map(|arg| match arg {
+                        AstNode::Variable(Atom::Variable(v)) => Some(Operand::Var(self.slot(v))),
+                        AstNode::Constant(atom) => Some(Operand::Const(atom.clone())),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            AstNode::Call(name, args) => Operand::Call {
+                name: name.clone(),
+                args: args
+                    .iter()
+                    .map(|arg| (self.value_operand(arg), describe_operand(arg)))
+                    .collect(),
+            },
+            _ => Operand::Missing,
+        }
+    }
+
+    /// One side of a comparison: literals as written, where a bare word is
+    /// text, and computed values.
+    fn value_operand(&mut self, node: &AstNode) -> Operand {
+        match node {
+            AstNode::Constant(atom) => Operand::Const(atom.clone()),
+            AstNode::Arithmetic(lhs, op, rhs) => Operand::Arith {
+                lhs: Box::new(self.value_operand(lhs)),
+                lhs_label: describe_operand(lhs),
+                op: *op,
+                rhs: Box::new(self.value_operand(rhs)),
+                rhs_label: describe_operand(rhs),
+            },
+            other => self.operand(other),
+        }
+    }
+
+    /// Each distinct `segment(name, args...)` call is compiled once.
+    fn segment(&mut self, name: &str, args: &[Atom]) -> Cond {
+        let key = format!("{name}{args:?}");
+        if let Some(&idx) = self.segment_slots.get(&key) {
+            return Cond::Segment(idx);
+        }
+        let Some(def) = self.segment_defs.get(name) else {
+            return Cond::Error(EvalError::UnknownSegment(name.to_string()));
+        };
+        let body = match def.instantiate(name, args) {
+            Ok(body) => body,
+            Err(msg) => return Cond::Error(EvalError::InvalidExpression(msg)),
+        };
+        // Reserve the slot first so self-references compile to a jump.
+        let idx = self.segments.len();
+        self.segments.push(Cond::Const(false));
+        self.segment_slots.insert(key, idx);
+        self.segments[idx] = self.cond(&body);
+        Cond::Segment(idx)
+    }
+
+    fn cond(&mut self, expr: &AstNode) -> Cond {
+        match expr {
+            AstNode::Constant(Atom::Boolean(b)) => Cond::Const(*b),
+            AstNode::Constant(Atom::Variable(v)) => Cond::Bool {
+                slot: self.slot(v),
+                label: describe_operand(expr),
+            },
+            AstNode::Constant(other) => {
+                Cond::Error(EvalError::TypeMismatch(format!("{other} is not a boolean")))
+            }
+            AstNode::Compare(lhs, op, rhs) => Cond::Compare {
+                operand: self.value_operand(lhs),
+                label: describe_operand(lhs),
+                op: op.clone(),
+                value: self.value_operand(rhs),
+                value_label: describe_operand(rhs),
+            },
+            AstNode::Array(left, op, right) => {
+                let negate = matches!(op, ArrayOp::NotIn);
+                match right.as_ref() {
+                    AstNode::List(items) => Cond::InList {
+                        operand: self.operand(left),
+                        label: describe_operand(left),
+                        items: items.clone(),
+                        negate,
+                    },
+                    _ => Cond::InVar {
+                        needle: match left.as_ref() {
+                            AstNode::Constant(atom) if !matches!(atom, Atom::Variable(_)) => {
+                                Operand::Const(atom.clone())
+                            }
+                            _ => self.operand(left),
+                        },
+                        needle_label: describe_operand(left),
+                        list: self.operand(right),
+                        list_label: describe_operand(right),
+                        negate,
+                    },
+                }
+            }
+            AstNode::Match(var, op, rhs) => match rhs.as_ref() {
+                AstNode::Constant(atom) => Cond::Match {
+                    operand: self.operand(var),
+                    label: describe_operand(var),
+                    op: op.clone(),
+                    pattern: match atom {
+                        Atom::Regex(re) => Pattern::Regex(re.clone()),
+                        other => Pattern::Text(other.to_string()),
+                    },
+                },
+                other => Cond::Error(EvalError::InvalidExpression(format!(
+                    "right side of match must be a constant, got {:?}",
+                    other
+                ))),
+            },
+            AstNode::Logic(lhs, op, rhs) => {
+                let lhs = self.cond(lhs);
+                let rhs = self.cond(rhs);
+                match (op, lhs) {
+                    (LogicOp::And, Cond::Const(false)) => Cond::Const(false),
+                    (LogicOp::Or, Cond::Const(true)) => Cond::Const(true),
+                    (LogicOp::And, Cond::Const(true)) | (LogicOp::Or, Cond::Const(false)) => rhs,
+                    (LogicOp::And, lhs) => Cond::And(Box::new(lhs), Box::new(rhs)),
+                    (LogicOp::Or, lhs) => Cond::Or(Box::new(lhs), Box::new(rhs)),
+                }
+            }
+            AstNode::Scope { expr, negate } => {
+                let inner = self.cond(expr);
+                match (negate, inner) {
+                    (false, inner) => inner,
+                    (true, Cond::Const(b)) => Cond::Const(!b),
+                    (true, Cond::Not(inner)) => *inner,
+                    (true, inner) => Cond::Not(Box::new(inner)),
+                }
+            }
+            AstNode::Segment(name, args) => self.segment(name, args),
+            AstNode::NullCheck { variable, is_null } => Cond::NullCheck {
+                operand: self.operand(variable),
+                is_null: *is_null,
+            },
+            AstNode::Percentage { rate, field, salt } => Cond::Percentage {
+                operand: self.operand(field),
+                label: describe_operand(field),
+                prefix: self.bucket_prefix(salt.as_deref()),
+                threshold: (rate * 1000.0) as u64,
+            },
+            AstNode::Ramp {
+                from,
+                to,
+                start,
+                end,
+                field,
+                salt,
+            } => Cond::Ramp {
+                operand: self.operand(field),
+                label: describe_operand(field),
+                prefix: self.bucket_prefix(salt.as_deref()),
+                from: *from,
+                to: *to,
+                start: *start,
+                end: *end,
+            },
+            AstNode::Active(window) => Cond::Active(*window),
+            AstNode::Call(..) => Cond::Call {
+                operand: self.operand(expr),
+                label: describe_operand(expr),
+            },
+            other => Cond::Error(EvalError::InvalidExpression(format!(
+                "{:?} is not a condition",
+                other
+            ))),
+        }
+    }
+}
+
+/// Per-evaluation state: variable values, looked up on first use.
+struct Frame<'a> {
+    context: &'a Context<'a>,
+    vars: &'a [String],
+    slots: Vec<Option<Option<&'a Atom>>>,
+    clock: Clock,
+    functions: &'a Functions,
+}
+
+impl<'a> Frame<'a> {
+    fn var(&mut self, slot: usize) -> Option<&'a Atom> {
+        let (context, vars) = (self.context, self.vars);
+        *self.slots[slot].get_or_insert_with(|| resolve(context, &vars[slot]))
+    }
+
+    fn resolve(&mut self, operand: &'a Operand) -> Option<Cow<'a, Atom>> {
+        match operand {
+            Operand::Var(slot) => self.var(*slot).map(Cow::Borrowed),
+            Operand::Upper(inner) => self
+                .resolve(inner)
+                .map(|v| Cow::Owned(Atom::String(text(&v).to_uppercase()))),
+            Operand::Lower(inner) => self
+                .resolve(inner)
+                .map(|v| Cow::Owned(Atom::String(text(&v).to_lowercase()))),
+            Operand::Now => Some(Cow::Owned(Atom::DateTime(self.clock.now()))),
+            Operand::Coalesce(args) => args.iter().find_map(|arg| self.resolve(arg)),
+            Operand::Const(atom) => Some(Cow::Borrowed(atom)),
+            Operand::Arith { .. } | Operand::Call { .. } | Operand::Missing => None,
+        }
+    }
+
+    fn lookup(&mut self, operand: &'a Operand, label: &str) -> Result<Cow<'a, Atom>, EvalError> {
+        if let Operand::Arith {
+            lhs,
+            lhs_label,
+            op,
+            rhs,
+            rhs_label,
+        } = operand
+        {
+            let lhs = self.lookup(lhs, lhs_label)?;
+            let rhs = self.lookup(rhs, rhs_label)?;
+            return arithmetic(&lhs, *op, &rhs).map(Cow::Owned);
+        }
+        if let Operand::Call { name, args } = operand {
+            let functions = self.functions;
+            let f = functions
+                .get(name)
+                .ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+            let args = args
+                .iter()
+                .map(|(arg, label)| self.lookup(arg, label).map(Cow::into_owned))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Cow::Owned(f(&args)));
+        }
+        self.resolve(operand)
+            .ok_or_else(|| EvalError::MissingVariable(label.to_string()))
+    }
+}
+
+/// The text of an atom, borrowed when it is already a string.
+fn text(atom: &Atom) -> Cow<'_, str> {
+    match atom {
+        Atom::String(s) => Cow::Borrowed(s),
+        other => Cow::Owned(other.to_string()),
+    }
+}
+
+impl FlagPlan {
+    /// Compile a flag's rules. `flag_name` seeds `percentage()` bucketing.
+    pub fn compile(flag_name: &str, rules: &[Rule], segments: &Segments) -> Self {
+        let mut compiler = Compiler {
+            flag_name,
+            segment_defs: segments,
+            vars: Vec::new(),
+            var_slots: HashMap::new(),
+            segments: Vec::new(),
+            segment_slots: HashMap::new(),
+        };
+        let rules = compiler.rules(rules);
+        FlagPlan {
+            vars: compiler.vars,
+            segments: compiler.segments,
+            rules,
+            requires: Vec::new(),
+            active: None,
+        }
+    }
+
+    /// Whether `at` is inside the flag's `@active` window, if it has one.
+    fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.active.is_none_or(|w| w.contains(at))
+    }
+
+    /// Evaluate the rules top to bottom; prerequisites are not checked.
+    pub fn evaluate(
+        &self,
+        context: &Context,
+        env: Option<&str>,
+        options: &EvalOptions,
+    ) -> EvaluationDetail {
+        let mut frame = Frame {
+            context,
+            vars: &self.vars,
+            slots: vec![None; self.vars.len()],
+            clock: options.clock,
+            functions: &options.functions,
+        };
+        let mut error = None;
+        if let Some(detail) = self.block(&self.rules, &mut frame, env, None, options, &mut error) {
+            return detail;
+        }
+        let reason = if error.is_some() {
+            Reason::Error
+        } else {
+            Reason::NoRuleMatched
+        };
+        EvaluationDetail {
+            error,
+            ..EvaluationDetail::no_value(reason)
+        }
+    }
+
+    fn block<'a>(
+        &'a self,
+        rules: &'a [PlanRule],
+        frame: &mut Frame<'a>,
+        env: Option<&str>,
+        block_env: Option<&str>,
+        options: &EvalOptions,
+        error: &mut Option<EvalError>,
+    ) -> Option<EvaluationDetail> {
+        let matched = |idx: usize,
+                       value: &FlagReturn,
+                       reason: Reason,
+                       name: Option<&String>,
+                       span: &Span,
+                       error: &Option<EvalError>| {
+            EvaluationDetail {
+                value: Some(value.clone()),
+                reason: if block_env.is_some() {
+                    Reason::EnvMatch
+                } else {
+                    reason
+                },
+                rule_index: Some(idx),
+                env: block_env.map(str::to_string),
+                rule_name: name.cloned(),
+                rule_span: Some(*span),
+                error: error.clone(),
+            }
+        };
+
+        for (idx, rule) in rules.iter().enumerate() {
+            match rule {
+                PlanRule::When {
+                    cond,
+                    value,
+                    name,
+                    span,
+                } => match self.eval(cond, frame, options, error, 0) {
+                    Ok(true) => {
+                        return Some(matched(
+                            idx,
+                            value,
+                            Reason::TargetingMatch,
+                            name.as_ref(),
+                            span,
+                            error,
+                        ))
+                    }
+                    Ok(false) => {}
+                    Err(e) if options.strict => {
+                        return Some(EvaluationDetail {
+                            rule_index: Some(idx),
+                            env: block_env.map(str::to_string),
+                            rule_name: name.clone(),
+                            rule_span: Some(*span),
+                            error: Some(e),
+                            ..EvaluationDetail::no_value(Reason::Error)
+                        });
+                    }
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                },
+                PlanRule::Value(value, span) => {
+                    return Some(matched(idx, value, Reason::Default, None, span, error));
+                }
+                PlanRule::Split {
+                    operand,
+                    label,
+                    prefix,
+                    arms,
+                    name,
+                    span,
+                } => match frame.lookup(operand, label) {
+                    Ok(key) => {
+                        let bucket = bucket(prefix, &key);
+                        let (_, value) = arms
+                            .iter()
+                            .find(|(bound, _)| bucket < *bound)
+                            .unwrap_or(&arms[arms.len() - 1]);
+                        return Some(matched(
+                            idx,
+                            value,
+                            Reason::Split,
+                            name.as_ref(),
+                            span,
+                            error,
+                        ));
+                    }
+                    Err(e) if options.strict => {
+                        return Some(EvaluationDetail {
+                            rule_index: Some(idx),
+                            env: block_env.map(str::to_string),
+                            rule_name: name.clone(),
+                            rule_span: Some(*span),
+                            error: Some(e),
+                            ..EvaluationDetail::no_value(Reason::Error)
+                        });
+                    }
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                },
+                PlanRule::Env {
+                    env: rule_env,
+                    rules: sub_rules,
+                } => {
+                    if env == Some(rule_env.as_str()) {
+                        let result =
+                            self.block(sub_rules, frame, env, Some(rule_env), options, error);
+                        if result.is_some() {
+                            return result;
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn eval<'a>(
+        &'a self,
+        cond: &'a Cond,
+        frame: &mut Frame<'a>,
+        options: &EvalOptions,
+        skipped: &mut Option<EvalError>,
+        depth: usize,
+    ) -> Result<bool, EvalError> {
+        match cond {
+            Cond::Const(b) => Ok(*b),
+            Cond::And(lhs, rhs) => Ok(self.eval(lhs, frame, options, skipped, depth)?
+                && self.eval(rhs, frame, options, skipped, depth)?),
+            Cond::Or(lhs, rhs) => Ok(self.eval(lhs, frame, options, skipped, depth)?
+                || self.eval(rhs, frame, options, skipped, depth)?),
+            Cond::Not(inner) => Ok(!self.eval(inner, frame, options, skipped, depth)?),
+            Cond::Segment(idx) => {
+                if depth >= options.max_segment_depth {
+                    return Err(EvalError::RecursionLimit(options.max_segment_depth));
+                }
+                self.eval(&self.segments[*idx], frame, options, skipped, depth + 1)
+            }
+            // Same lenient rule as `eval`: a condition that can't be
+            // evaluated is false, and the first such error is kept.
+            leaf => match eval_leaf(leaf, frame) {
+                Err(e) if !options.strict => {
+                    skipped.get_or_insert(e);
+                    Ok(false)
+                }
+                other => other,
+            },
+        }
+    }
+}
+
+fn eval_leaf<'a>(cond: &'a Cond, frame: &mut Frame<'a>) -> Result<bool, EvalError> {
+    match cond {
+        Cond::Bool { slot, label } => match frame.var(*slot) {
+            Some(Atom::Boolean(b)) => Ok(*b),
+            Some(other) => Err(EvalError::TypeMismatch(format!(
+                "{label} is {other}, not a boolean"
+            ))),
+            None => Err(EvalError::MissingVariable(label.clone())),
+        },
+        Cond::Compare {
+            operand,
+            label,
+            op,
+            value,
+            value_label,
+        } => {
+            let lhs = frame.lookup(operand, label)?;
+            compare(lhs.as_ref(), op, frame.lookup(value, value_label)?.as_ref())
+        }
+        Cond::InList {
+            operand,
+            label,
+            items,
+            negate,
+        } => {
+            let search = frame.lookup(operand, label)?;
+            Ok(items.iter().any(|i| search.as_ref() == i) != *negate)
+        }
+        Cond::InVar {
+            needle,
+            needle_label,
+            list,
+            list_label,
+            negate,
+        } => {
+            let needle = frame.lookup(needle, needle_label)?;
+            match frame.lookup(list, list_label)?.as_ref() {
+                Atom::List(items) => Ok(items.iter().any(|i| needle.as_ref() == i) != *negate),
+                other => Err(EvalError::TypeMismatch(format!(
+                    "{list_label} is {other}, not a list"
+                ))),
+            }
+        }
+        Cond::Match {
+            operand,
+            label,
+            op,
+            pattern,
+        } => {
+            let value = frame.lookup(operand, label)?;
+            let haystack = text(&value);
+            match pattern {
+                Pattern::Regex(re) => match op {
+                    MatchOp::Contains => Ok(re.is_match(&haystack)),
+                    MatchOp::NotContains => Ok(!re.is_match(&haystack)),
+                    other => Err(EvalError::TypeMismatch(format!(
+                        "regex /{}/ only supports ~ and !~, not {other}",
+                        re.as_str()
+                    ))),
+                },
+                Pattern::Text(needle) => Ok(match op {
+                    MatchOp::Contains => haystack.contains(needle.as_str()),
+                    MatchOp::NotContains => !haystack.contains(needle.as_str()),
+                    MatchOp::StartsWith => haystack.starts_with(needle.as_str()),
+                    MatchOp::NotStartsWith => !haystack.starts_with(needle.as_str()),
+                    MatchOp::EndsWith => haystack.ends_with(needle.as_str()),
+                    MatchOp::NotEndsWith => !haystack.ends_with(needle.as_str()),
+                }),
+            }
+        }
+        Cond::NullCheck { operand, is_null } => match frame.lookup(operand, "") {
+            Ok(_) => Ok(!*is_null),
+            Err(EvalError::MissingVariable(_)) => Ok(*is_null),
+            Err(e) => Err(e),
+        },
+        Cond::Percentage {
+            operand,
+            label,
+            prefix,
+            threshold,
+        } => {
+            let key = frame.lookup(operand, label)?;
+            Ok(bucket(prefix, &key) < *threshold)
+        }
+        Cond::Ramp {
+            operand,
+            label,
+            prefix,
+            from,
+            to,
+            start,
+            end,
+        } => {
+            let key = frame.lookup(operand, label)?;
+            let rate = ramp_rate(*from, *to, *start, *end, frame.clock.now());
+            Ok(bucket(prefix, &key) < (rate * 1000.0) as u64)
+        }
+        Cond::Active(window) => Ok(window.contains(frame.clock.now())),
+        Cond::Call { operand, label } => match frame.lookup(operand, label)?.as_ref() {
+            Atom::Boolean(b) => Ok(*b),
+            other => Err(EvalError::TypeMismatch(format!(
+                "{label} is {other}, not a boolean"
+            ))),
+        },
+        Cond::Error(e) => Err(e.clone()),
+        Cond::Const(_) | Cond::Segment(_) | Cond::And(..) | Cond::Or(..) | Cond::Not(_) => {
+            unreachable!("handled by FlagPlan::eval")
+        }
+    }
+}
+
+/// Same bucket as `eval`: the first 15 hex digits of the digest.
+fn bucket(prefix: &Sha1, key: &Atom) -> u64 {
+    let mut hasher = prefix.clone();
+    hasher.update(text(key).as_bytes());
+    let hash = hasher.finalize();
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&hash[..8]);
+    (u64::from_be_bytes(head) >> 4) % 100_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::{evaluate_flag, evaluate_rules_with_options};
+    use crate::parse_flagfile::parse_flagfile_with_segments;
+
+    fn load(
+        content: &str,
+    ) -> (
+        HashMap<String, Vec<Rule>>,
+        HashMap<String, FlagMetadata>,
+        Segments,
+    ) {
+        let parsed = parse_flagfile_with_segments(content).unwrap();
+        let mut flags = HashMap::new();
+        let mut metadata = HashMap::new();
+        for fv in parsed.flags {
+            for (name, def) in fv {
+                flags.insert(name.to_string(), def.rules);
+                metadata.insert(name.to_string(), def.metadata);
+            }
+        }
+        (flags, metadata, parsed.segments)
+    }
+
+    fn assert_same(a: &EvaluationDetail, b: &EvaluationDetail, what: &str) {
+        assert_eq!(format!("{:?}", a.value), format!("{:?}", b.value), "{what}");
+        assert_eq!(a.reason, b.reason, "{what}");
+        assert_eq!(a.rule_index, b.rule_index, "{what}");
+        assert_eq!(a.env, b.env, "{what}");
+        assert_eq!(a.rule_name, b.rule_name, "{what}");
+        assert_eq!(a.rule_span, b.rule_span, "{what}");
+        assert_eq!(a.error, b.error, "{what}");
+    }
+
+    #[test]
+    fn test_plan_matches_interpreter_on_example() {
+        let content = std::fs::read_to_string("Flagfile.example").unwrap();
+        let (flags, metadata, segments) = load(&content);
+        let plan = Plan::compile(&flags, &metadata, &segments);
+
+        let contexts: Vec<Context> = vec![
+            Context::new(),
+            HashMap::from([
+                ("countryCode", Atom::from("NL")),
+                ("userId", Atom::from("user-42")),
+                ("plan", Atom::from("premium")),
+                ("tier", Atom::from("enterprise")),
+                ("seats", Atom::Number(120)),
+                ("name", Atom::from("Nikola")),
+                ("appVersion", Atom::from("5.3.4")),
+                ("email", Atom::from("dev@company.com")),
+            ]),
+            HashMap::from([
+                ("countryCode", Atom::from("us")),
+                ("userId", Atom::Number(7)),
+                ("age", Atom::from("abc")),
+                ("beta", Atom::Boolean(true)),
+                ("created", Atom::from("2024-01-01")),
+            ]),
+        ];
+        for ctx in &contexts {
+            for options in [EvalOptions::default(), EvalOptions::strict()] {
+                for env in [None, Some("prod"), Some("dev")] {
+                    for name in flags.keys() {
+                        let expected = crate::evaluation::evaluate_flag_with_options(
+                            name, ctx, &flags, &metadata, &segments, env, &options,
+                        );
+                        let actual = plan.evaluate(name, ctx, env, &options);
+                        assert_same(&actual, &expected, name);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_percentage_buckets_match() {
+        let (flags, metadata, segments) =
+            load("FF-roll {\n    percentage(50%, userId, beta) -> true\n    false\n}");
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        for id in 0..200 {
+            let ctx: Context = HashMap::from([("userId", Atom::Number(id))]);
+            let expected = evaluate_flag("FF-roll", &ctx, &flags, &metadata, &segments, None);
+            let actual = plan.evaluate("FF-roll", &ctx, None, &EvalOptions::default());
+            assert_same(&actual, &expected, &format!("userId={id}"));
+        }
+    }
+
+    #[test]
+    fn test_plan_split_buckets_match() {
+        let (flags, metadata, segments) = load(
+            "FF-split {\n    @env dev -> \"a\"\n    // @name layout test\n    split(userId, exp1) { \"a\": 20%, \"b\": 30.5%, \"c\": 49.5% }\n    \"none\"\n}",
+        );
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        for id in 0..200 {
+            let ctx: Context = HashMap::from([("userId", Atom::Number(id))]);
+            let expected = evaluate_flag("FF-split", &ctx, &flags, &metadata, &segments, None);
+            let actual = plan.evaluate("FF-split", &ctx, None, &EvalOptions::default());
+            assert_same(&actual, &expected, &format!("userId={id}"));
+        }
+        let strict = EvalOptions {
+            strict: true,
+            ..EvalOptions::default()
+        };
+        for options in [EvalOptions::default(), strict] {
+            let expected = evaluate_rules_with_options(
+                &flags["FF-split"],
+                &Context::new(),
+                Some("FF-split"),
+                &segments,
+                None,
+                &options,
+            );
+            let actual = plan.evaluate("FF-split", &Context::new(), None, &options);
+            assert_same(&actual, &expected, "no userId");
+        }
+    }
+
+    #[test]
+    fn test_plan_ramp_and_active_match() {
+        let (flags, metadata, segments) = load(
+            "@active until 2020-01-01\nFF-expired -> true\n\n@requires FF-expired\nFF-dependent -> true\n\nFF-ramp {\n    @active until 2020-01-01\n    country == NL -> \"closed\"\n    ramp(0% -> 40%, 2020-01-01 .. 2020-02-01, userId, exp1) -> \"ramped\"\n    ramp(100% -> 0%, 2999-01-01 .. 2999-02-01, userId) -> \"pending\"\n    \"none\"\n}",
+        );
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        for name in ["FF-expired", "FF-dependent", "FF-ramp"] {
+            for id in 0..100 {
+                let ctx: Context = HashMap::from([
+                    ("userId", Atom::Number(id)),
+                    ("country", Atom::String("NL".into())),
+                ]);
+                let expected = evaluate_flag(name, &ctx, &flags, &metadata, &segments, None);
+                let actual = plan.evaluate(name, &ctx, None, &EvalOptions::default());
+                assert_same(&actual, &expected, &format!("{name} userId={id}"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_arithmetic_match() {
+        let (flags, metadata, segments) = load(
+            "@segment spend(price) {\n    seats * price >= 100\n}\n\
+             FF-a {\n    NOW() - signupDate < 30d -> \"new\"\n    billing != var(shipping) -> \"abroad\"\n    segment(spend, 12) -> \"big\"\n    seats / 0 > 1 -> \"never\"\n    \"other\"\n}",
+        );
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        let contexts = [
+            vec![("signupDate", Atom::from("2026-11-20"))],
+            vec![
+                ("signupDate", Atom::from("2025-01-01")),
+                ("billing", Atom::from("NL")),
+                ("shipping", Atom::from("DE")),
+            ],
+            vec![
+                ("billing", Atom::from("NL")),
+                ("shipping", Atom::from("NL")),
+                ("seats", Atom::Number(9)),
+            ],
+            vec![("seats", Atom::Number(5))],
+            vec![
+                ("signupDate", Atom::from("soon")),
+                ("seats", Atom::from("many")),
+            ],
+        ];
+        let options = EvalOptions {
+            clock: Clock::Fixed("2026-12-01T00:00:00Z".parse().unwrap()),
+            ..EvalOptions::default()
+        };
+        for ctx in contexts {
+            let label = format!("{ctx:?}");
+            let ctx: Context = ctx.into_iter().collect();
+            for options in [
+                options.clone(),
+                EvalOptions {
+                    strict: true,
+                    ..options.clone()
+                },
+            ] {
+                let expected = crate::evaluation::evaluate_flag_with_options(
+                    "FF-a", &ctx, &flags, &metadata, &segments, None, &options,
+                );
+                let actual = plan.evaluate("FF-a", &ctx, None, &options);
+                assert_same(&actual, &expected, &label);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_functions_match() {
+        let (flags, metadata, segments) = load(
+            "@segment eu {\n    fn:region(ip) == eu\n}\n\
+             FF-a {\n    fn:beta(userId) and segment(eu) -> \"beta-eu\"\n    fn:score(userId) * 2 > 50 -> \"high\"\n    fn:missing(userId) -> \"never\"\n    \"other\"\n}",
+        );
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        let mut functions = crate::eval::Functions::new();
+        functions
+            .register("region", |args: &[Atom]| match args {
+                [Atom::String(ip)] if ip.starts_with("81.") => Atom::from("eu"),
+                _ => Atom::from("us"),
+            })
+            .register("beta", |args: &[Atom]| {
+                Atom::Boolean(matches!(args, [Atom::Number(id)] if id % 2 == 0))
+            })
+            .register("score", |args: &[Atom]| match args {
+                [Atom::Number(id)] => Atom::Number(id % 100),
+                _ => Atom::from("none"),
+            });
+        let contexts = [
+            vec![
+                ("userId", Atom::Number(42)),
+                ("ip", Atom::from("81.2.69.160")),
+            ],
+            vec![("userId", Atom::Number(42)), ("ip", Atom::from("8.8.8.8"))],
+            vec![
+                ("userId", Atom::Number(7)),
+                ("ip", Atom::from("81.2.69.160")),
+            ],
+            vec![("userId", Atom::from("abc"))],
+            vec![],
+        ];
+        for ctx in contexts {
+            let label = format!("{ctx:?}");
+            let ctx: Context = ctx.into_iter().collect();
+            for strict in [false, true] {
+                let options = EvalOptions {
+                    strict,
+                    functions: functions.clone(),
+                    ..EvalOptions::default()
+                };
+                let expected = crate::evaluation::evaluate_flag_with_options(
+                    "FF-a", &ctx, &flags, &metadata, &segments, None, &options,
+                );
+                let actual = plan.evaluate("FF-a", &ctx, None, &options);
+                assert_same(&actual, &expected, &label);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_parameterized_segments() {
+        let (flags, metadata, segments) = load(
+            "@segment in-country(c) {\n    countryCode == c\n}\n\
+             @segment eu(min) {\n    (segment(in-country, NL) or segment(in-country, DE)) and seats >= min\n}\n\
+             FF-a {\n    segment(eu, 10) and not (segment(in-country, NL)) -> \"de\"\n    segment(eu, 10) -> \"nl\"\n    segment(in-country) -> \"arity\"\n    \"other\"\n}",
+        );
+        let plan = Plan::compile(&flags, &metadata, &segments);
+        // one slot per distinct call: eu(10), in-country(NL), in-country(DE)
+        assert_eq!(plan.flag("FF-a").unwrap().segments.len(), 3);
+        for (country, seats) in [("NL", 20), ("DE", 20), ("DE", 5), ("US", 20)] {
+            let ctx: Context = HashMap::from([
+                ("countryCode", Atom::from(country)),
+                ("seats", Atom::Number(seats)),
+            ]);
+            for options in [EvalOptions::default(), EvalOptions::strict()] {
+                let expected = crate::evaluation::evaluate_flag_with_options(
+                    "FF-a", &ctx, &flags, &metadata, &segments, None, &options,
+                );
+                let actual = plan.evaluate("FF-a", &ctx, None, &options);
+                assert_same(&actual, &expected, &format!("{country}/{seats}"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_folds_constants_and_resolves_segments() {
+        let (flags, _, segments) = load(
+            "@segment loop {\n    segment(loop)\n}\n\
+             FF-a {\n    not (not (true)) and segment(loop) -> true\n    false\n}",
+        );
+        let plan = FlagPlan::compile("FF-a", &flags["FF-a"], &segments);
+        let PlanRule::When { cond, .. } = &plan.rules[0] else {
+            panic!("expected a condition");
+        };
+        assert!(matches!(cond, Cond::Segment(0)));
+        assert_eq!(plan.segments.len(), 1);
+        let detail = plan.evaluate(&Context::new(), None, &EvalOptions::strict());
+        assert_eq!(detail.reason, Reason::Error);
+        assert_eq!(
+            detail.error,
+            Some(EvalError::RecursionLimit(
+                crate::eval::DEFAULT_MAX_SEGMENT_DEPTH
+            ))
+        );
+    }
+}
@@ -17,12 +17,14 @@
 //! LD UI survive the next merge. See `RuleTarget` / `LdEnvironment`.
 
 use std::collections::BTreeMap;
+use std::fmt;
 
 use serde::Serialize;
 use serde_json::Value;
 
 use crate::ast::{ArrayOp, AstNode, Atom, ComparisonOp, FlagMetadata, FnCall, LogicOp, MatchOp};
 use crate::eval::Segments;
+use crate::parse::{parse_path, PathSegment};
 use crate::parse_flagfile::{FlagDefinition, FlagReturn, ParsedFlagfile, Rule};
 
 // ──────────────────────────── configuration ────────────────────────────
@@ -57,6 +59,9 @@ pub struct LdFlag {
 #[derive(Debug, Serialize, PartialEq)]
 pub struct LdVariation {
     pub value: Value,
+    /// Variant name from the flag's `@variants` block, when the value has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Serialize, PartialEq)]
@@ -92,7 +97,7 @@ pub struct LdClause {
 }
 
 /// What a rule (or fallthrough) serves: a fixed variation, or a percentage
-/// rollout (from a `percentage(...)` term).
+/// rollout (from a `percentage(...)` term or a `split(...)` rule).
 #[derive(Debug, Serialize, PartialEq)]
 #[serde(untagged)]
 pub enum RuleTarget {
@@ -137,6 +142,63 @@ pub enum TranspileError {
     UnresolvedPrerequisite { flag: String, requires: String },
     /// More than one `percentage(...)` in a single conjunction.
     MultiplePercentage { flag: String },
+    /// Rules after a bare value or split, which always serves a value.
+    UnreachableRule { flag: String },
+}
+
+impl TranspileError {
+    /// The flag that could not be transpiled, for locating it in the source.
+    pub fn flag(&self) -> &str {
+        match self {
+            TranspileError::MixedVariationKinds { flag }
+            | TranspileError::TimeRelative { flag }
+            | TranspileError::UnsupportedConstruct { flag, .. }
+            | TranspileError::UnsupportedClauseShape { flag }
+            | TranspileError::UnresolvedPrerequisite { flag, .. }
+            | TranspileError::MultiplePercentage { flag }
+            | TranspileError::UnreachableRule { flag } => flag,
+        }
+    }
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranspileError::MixedVariationKinds { flag } => {
+                write!(f, "{flag}: returns values of more than one kind")
+            }
+            TranspileError::TimeRelative { flag } => {
+                write!(
+                    f,
+                    "{flag}: time-relative conditions have no LaunchDarkly clause"
+                )
+            }
+            TranspileError::UnsupportedConstruct { flag, what } => {
+                write!(f, "{flag}: {what} is not supported by LaunchDarkly")
+            }
+            TranspileError::UnsupportedClauseShape { flag } => {
+                write!(
+                    f,
+                    "{flag}: condition cannot be expressed as a LaunchDarkly clause"
+                )
+            }
+            TranspileError::UnresolvedPrerequisite { flag, requires } => {
+                write!(
+                    f,
+                    "{flag}: @requires {requires} does not resolve to a variation"
+                )
+            }
+            TranspileError::MultiplePercentage { flag } => {
+                write!(f, "{flag}: more than one percentage() in a single rule")
+            }
+            TranspileError::UnreachableRule { flag } => {
+                write!(
+                    f,
+                    "{flag}: rules after the default value or split are unreachable"
+                )
+            }
+        }
+    }
 }
 
 // ──────────────────────────── entry point ───────────────────────────────
@@ -172,23 +234,26 @@ fn transpile_flag(
 ) -> Result<LdFlag, Vec<TranspileError>> {
     let mut errors = Vec::new();
 
+    // LD schedules flag changes outside the flag itself.
+    if def.metadata.active.is_some() {
+        errors.push(TranspileError::TimeRelative { flag: name.into() });
+    }
+
     // 1. Synthesize variations from every distinct return value in the flag.
     let (variations, index_of) = match synthesize_variations(name, def) {
         Ok(v) => v,
         Err(e) => return Err(vec![e]),
     };
 
-    // 2. Split rules by environment. Non-@env rules become the "base" applied
-    //    to every configured env; @env blocks override/extend per env.
-    let (base_rules, env_rules) = partition_env_rules(&def.rules);
+    if has_unreachable_rule(&def.rules) {
+        errors.push(TranspileError::UnreachableRule { flag: name.into() });
+    }
 
+    // 2. Split rules by environment. Non-@env rules apply to every configured
+    //    env; @env blocks apply where they sit, in their env only.
     let mut environments = BTreeMap::new();
     for (env_name, env_key) in &cfg.env_keys {
-        let rules_for_env: Vec<&Rule> = base_rules
-            .iter()
-            .copied()
-            .chain(env_rules.get(env_name).into_iter().flatten().copied())
-            .collect();
+        let rules_for_env = rules_for_env(&def.rules, env_name);
 
         match lower_environment(name, &rules_for_env, &index_of, &variations, segments, cfg) {
             Ok(env) => {
@@ -236,7 +301,7 @@ fn synthesize_variations(
     let mut kind: Option<LdKind> = None;
 
     let mut record = |ret: &FlagReturn| -> Result<(), TranspileError> {
-        let value = flag_return_to_value(ret);
+        let value = ret.to_json();
         let k = LdKind::of(&value);
         match &kind {
             Some(prev) if *prev != k => {
@@ -246,11 +311,19 @@ fn synthesize_variations(
             _ => {}
         }
         if !variations.iter().any(|v| v.value == value) {
-            variations.push(LdVariation { value });
+            variations.push(LdVariation {
+                value,
+                name: ret.variant_name().map(str::to_string),
+            });
         }
         Ok(())
     };
 
+    // Declared variants come first so every named arm is exposed, even ones
+    // no rule currently serves.
+    for (name, ret) in &def.variants {
+        record(&FlagReturn::Variant(name.clone(), Box::new(ret.clone())))?;
+    }
     walk_returns(&def.rules, &mut record)?;
 
     // LD requires every flag to expose at least two variations. A Flagfile that
@@ -260,7 +333,7 @@ fn synthesize_variations(
     // (LD rejects `null` values for typed/JSON variations).
     let push_unique = |variations: &mut Vec<LdVariation>, value: Value| {
         if !variations.iter().any(|v| v.value == value) {
-            variations.push(LdVariation { value });
+            variations.push(LdVariation { value, name: None });
         }
     };
     match kind {
@@ -311,9 +384,14 @@ fn walk_returns(
 ) -> Result<(), TranspileError> {
     for r in rules {
         match r {
-            Rule::Value(ret) => f(ret)?,
-            Rule::BoolExpressionValue(_, ret, _) => f(ret)?,
+            Rule::Value(ret, _) => f(ret)?,
+            Rule::BoolExpressionValue(_, ret, _, _) => f(ret)?,
             Rule::EnvRule { rules, .. } => walk_returns(rules, f)?,
+            Rule::Split { split, .. } => {
+                for (ret, _) in &split.arms {
+                    f(ret)?;
+                }
+            }
         }
     }
     Ok(())
@@ -325,7 +403,7 @@ struct ReturnIndex {
 }
 impl ReturnIndex {
     fn index_of(&self, ret: &FlagReturn) -> usize {
-        let v = flag_return_to_value(ret);
+        let v = ret.to_json();
         self.variations.iter().position(|x| *x == v).unwrap_or(0)
     }
     fn bool_index(&self, want: bool) -> Option<usize> {
@@ -333,15 +411,6 @@ impl ReturnIndex {
     }
 }
 
-fn flag_return_to_value(ret: &FlagReturn) -> Value {
-    match ret {
-        FlagReturn::OnOff(b) => Value::Bool(*b),
-        FlagReturn::Integer(n) => Value::Number((*n).into()),
-        FlagReturn::Str(s) => Value::String(s.clone()),
-        FlagReturn::Json(v) => v.clone(),
-    }
-}
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum LdKind {
     Boolean,
@@ -364,18 +433,35 @@ impl LdKind {
 
 type IndexMap = ReturnIndex;
 
-fn partition_env_rules(rules: &[Rule]) -> (Vec<&Rule>, BTreeMap<String, Vec<&Rule>>) {
-    let mut base = Vec::new();
-    let mut by_env: BTreeMap<String, Vec<&Rule>> = BTreeMap::new();
+/// The rules evaluated in `env`, in source order: matching `@env` blocks are
+/// inlined where they sit and the others dropped.
+fn rules_for_env<'a>(rules: &'a [Rule], env: &str) -> Vec<&'a Rule> {
+    let mut out = Vec::new();
     for r in rules {
         match r {
-            Rule::EnvRule { env, rules } => {
-                by_env.entry(env.clone()).or_default().extend(rules.iter());
+            Rule::EnvRule {
+                env: rule_env,
+                rules,
+                ..
+            } => {
+                if rule_env == env {
+                    out.extend(rules_for_env(rules, env));
+                }
             }
-            other => base.push(other),
+            other => out.push(other),
         }
     }
-    (base, by_env)
+    out
+}
+
+/// Whether a block, or an `@env` block inside it, has rules after its bare
+/// value or split.
+fn has_unreachable_rule(rules: &[Rule]) -> bool {
+    rules.iter().enumerate().any(|(i, rule)| match rule {
+        Rule::Value(..) | Rule::Split { .. } => i + 1 < rules.len(),
+        Rule::EnvRule { rules, .. } => has_unreachable_rule(rules),
+        Rule::BoolExpressionValue(..) => false,
+    })
 }
 
 // ──────────────────────────── environment lowering ──────────────────────
@@ -392,15 +478,30 @@ fn lower_environment(
     let mut ld_rules = Vec::new();
     let mut fallthrough: Option<RuleTarget> = None;
 
+    // Rules after the fallthrough are never reached, e.g. the base default
+    // after an `@env` block that ends in its own.
     for rule in rules {
         match rule {
             // A bare value is the block default -> fallthrough.
-            Rule::Value(ret) => {
+            Rule::Value(ret, _) => {
                 fallthrough = Some(RuleTarget::Variation {
                     variation: index_of.index_of(ret),
                 });
+                break;
             }
-            Rule::BoolExpressionValue(cond, ret, name) => {
+            // A split always serves one of its arms, so like a bare value it
+            // becomes the fallthrough.
+            Rule::Split { split, .. } => {
+                let arms: Vec<_> = split
+                    .arms
+                    .iter()
+                    .map(|(ret, weight)| (index_of.index_of(ret), *weight))
+                    .collect();
+                // salt is dropped: LD owns the bucketing seed.
+                fallthrough = Some(build_rollout(&arms, split.field.as_str().unwrap_or("key")));
+                break;
+            }
+            Rule::BoolExpressionValue(cond, ret, name, _) => {
                 match lower_condition(flag, cond, ret, name.as_deref(), index_of, segments, cfg) {
                     Ok(mut produced) => ld_rules.append(&mut produced),
                     Err(mut e) => errors.append(&mut e),
@@ -478,10 +579,19 @@ fn lower_condition(
             None => RuleTarget::Variation {
                 variation: index_of.index_of(ret),
             },
-            Some(p) => build_rollout(flag, p, ret, index_of).unwrap_or_else(|e| {
-                errors.push(e);
-                RuleTarget::Variation { variation: 0 }
-            }),
+            Some(p) => {
+                // percentage(rate, field) -> ret ⇒ rate% to the matched
+                // return, rest off.
+                let off_idx = index_of
+                    .bool_index(false)
+                    .or_else(|| index_of.bool_index(true).map(|_| 0))
+                    .unwrap_or(0);
+                let rate = p.rate.clamp(0.0, 100.0);
+                build_rollout(
+                    &[(index_of.index_of(ret), rate), (off_idx, 100.0 - rate)],
+                    &p.field,
+                )
+            }
         };
         out.push(LdRule {
             description: name.map(|s| s.to_string()),
@@ -501,10 +611,12 @@ fn lower_condition(
 // LD only accepts `segmentMatch` against segments that already exist in the
 // project. A Flagfile `@segment` is just a named boolean expression, so we
 // substitute each `segment(name)` reference with its defining expression before
-// lowering. A `segment(...)` that isn't defined in the Flagfile is left intact
-// (assumed to be a natively-managed LD segment) and still lowers to a
-// `segmentMatch` clause. Substitution is recursive (segments may reference other
-// segments) with cycle detection.
+// lowering; arguments of a parameterized segment are bound first. A
+// `segment(...)` that isn't defined in the Flagfile is left intact (assumed to
+// be a natively-managed LD segment) and still lowers to a `segmentMatch`
+// clause, unless it passes arguments, which LD segments cannot take.
+// Substitution is recursive (segments may reference other segments) with cycle
+// detection.
 
 fn inline_segments(
     flag: &str,
@@ -513,19 +625,29 @@ fn inline_segments(
     seen: &mut Vec<String>,
 ) -> Result<AstNode, TranspileError> {
     match node {
-        AstNode::Segment(name) => match segments.get(name) {
-            Some(expr) => {
+        AstNode::Segment(name, args) => match segments.get(name) {
+            Some(def) => {
                 if seen.iter().any(|n| n == name) {
                     return Err(TranspileError::UnsupportedConstruct {
                         flag: flag.into(),
                         what: format!("recursive segment \"{name}\""),
                     });
                 }
+                let expr = def.instantiate(name, args).map_err(|what| {
+                    TranspileError::UnsupportedConstruct {
+                        flag: flag.into(),
+                        what,
+                    }
+                })?;
                 seen.push(name.clone());
-                let inlined = inline_segments(flag, expr, segments, seen)?;
+                let inlined = inline_segments(flag, &expr, segments, seen)?;
                 seen.pop();
                 Ok(inlined)
             }
+            None if !args.is_empty() => Err(TranspileError::UnsupportedConstruct {
+                flag: flag.into(),
+                what: format!("arguments to undefined segment \"{name}\""),
+            }),
             // Not a Flagfile segment — keep it; lower_leaf emits a segmentMatch.
             None => Ok(node.clone()),
         },
@@ -631,7 +753,7 @@ fn to_dnf<'a>(
             flag: flag.into(),
             what: "null check".into(),
         }),
-        AstNode::Function(FnCall::Now, _) => {
+        AstNode::Function(FnCall::Now, _) | AstNode::Ramp { .. } | AstNode::Active(_) => {
             Err(TranspileError::TimeRelative { flag: flag.into() })
         }
         AstNode::Function(_, _) => Err(TranspileError::UnsupportedConstruct {
@@ -639,7 +761,8 @@ fn to_dnf<'a>(
             what: "attribute function (upper/lower)".into(),
         }),
 
-        // Anything else is a leaf predicate (Compare / Match / Array / Segment).
+        // Anything else is a leaf predicate (Compare / Match / Array / Segment
+        // / fn: call).
         leaf => Ok(vec![vec![Literal::Pred {
             node: leaf,
             negate: negated,
@@ -649,6 +772,29 @@ fn to_dnf<'a>(
 
 // ──────────────────────────── leaf -> clause ────────────────────────────
 
+/// LD attribute for the left side of a clause. A nested path becomes an LD
+/// attribute reference: `user.company["tier"]` -> `/user/company/tier`.
+fn attribute_ref(flag: &str, lhs: &AstNode) -> Result<String, TranspileError> {
+    let name = lhs
+        .as_str()
+        .ok_or(TranspileError::UnsupportedClauseShape { flag: flag.into() })?;
+    let Some((root, path)) = parse_path(name).filter(|(_, path)| !path.is_empty()) else {
+        return Ok(name.to_string());
+    };
+    let mut out = String::new();
+    for step in std::iter::once(PathSegment::Key(root)).chain(path) {
+        let PathSegment::Key(key) = step else {
+            return Err(TranspileError::UnsupportedConstruct {
+                flag: flag.into(),
+                what: format!("list index in attribute \"{name}\""),
+            });
+        };
+        out.push('/');
+        out.push_str(&key.replace('~', "~0").replace('/', "~1"));
+    }
+    Ok(out)
+}
+
 fn lower_leaf(
     flag: &str,
     node: &AstNode,
@@ -656,8 +802,15 @@ fn lower_leaf(
     _segments: &Segments,
     cfg: &TranspileConfig,
 ) -> Result<LdClause, TranspileError> {
+    // Host functions only exist in the application that registers them.
+    if let Some(name) = node.function_calls().first() {
+        return Err(TranspileError::UnsupportedConstruct {
+            flag: flag.into(),
+            what: format!("custom function fn:{name}()"),
+        });
+    }
     match node {
-        AstNode::Segment(name) => Ok(LdClause {
+        AstNode::Segment(name, _) => Ok(LdClause {
             context_kind: cfg.default_context_kind.clone(),
             attribute: "key".into(),
             op: "segmentMatch".into(),
@@ -666,10 +819,22 @@ fn lower_leaf(
         }),
 
         AstNode::Compare(lhs, op, rhs) => {
-            let attribute = lhs
-                .as_str()
-                .ok_or(TranspileError::UnsupportedClauseShape { flag: flag.into() })?
-                .to_string();
+            if reads_clock(lhs) || reads_clock(rhs) {
+                return Err(TranspileError::TimeRelative { flag: flag.into() });
+            }
+            let unsupported = match (lhs.as_ref(), rhs.as_ref()) {
+                (AstNode::Arithmetic(..), _) | (_, AstNode::Arithmetic(..)) => Some("arithmetic"),
+                (_, AstNode::Variable(_)) => Some("comparison between two attributes"),
+                (_, AstNode::Constant(Atom::Duration(_))) => Some("duration"),
+                _ => None,
+            };
+            if let Some(what) = unsupported {
+                return Err(TranspileError::UnsupportedConstruct {
+                    flag: flag.into(),
+                    what: what.into(),
+                });
+            }
+            let attribute = attribute_ref(flag, lhs)?;
             // NOW() / function on either side -> not representable.
             if matches!(**lhs, AstNode::Function(..)) || matches!(**rhs, AstNode::Function(..)) {
                 return Err(TranspileError::TimeRelative { flag: flag.into() });
@@ -686,10 +851,7 @@ fn lower_leaf(
         }
 
         AstNode::Match(lhs, op, rhs) => {
-            let attribute = lhs
-                .as_str()
-                .ok_or(TranspileError::UnsupportedClauseShape { flag: flag.into() })?
-                .to_string();
+            let attribute = attribute_ref(flag, lhs)?;
             let value = constant_value(flag, rhs)?;
             let (ld_op, op_negate) = map_match(op);
             Ok(LdClause {
@@ -702,10 +864,7 @@ fn lower_leaf(
         }
 
         AstNode::Array(lhs, op, rhs) => {
-            let attribute = lhs
-                .as_str()
-                .ok_or(TranspileError::UnsupportedClauseShape { flag: flag.into() })?
-                .to_string();
+            let attribute = attribute_ref(flag, lhs)?;
             let values = list_values(flag, rhs)?;
             let op_negate = matches!(op, ArrayOp::NotIn);
             Ok(LdClause {
@@ -721,6 +880,15 @@ fn lower_leaf(
     }
 }
 
+/// Whether `NOW()` is read, alone or inside arithmetic.
+fn reads_clock(node: &AstNode) -> bool {
+    match node {
+        AstNode::Function(FnCall::Now, _) => true,
+        AstNode::Arithmetic(l, _, r) => reads_clock(l) || reads_clock(r),
+        _ => false,
+    }
+}
+
 /// (LD op, whether it implies negate). Date/semver-aware.
 fn map_comparison(op: &ComparisonOp, value: &Value) -> (&'static str, bool) {
     let is_date = matches!(value, Value::String(s) if looks_like_date(s));
@@ -754,35 +922,31 @@ fn map_match(op: &MatchOp) -> (&'static str, bool) {
 
 // ──────────────────────────── rollout ───────────────────────────────────
 
-fn build_rollout(
-    flag: &str,
-    p: &PercentageLit,
-    on_ret: &FlagReturn,
-    index_of: &IndexMap,
-) -> Result<RuleTarget, TranspileError> {
-    // percentage(rate, field) -> true ⇒ rate% to the matched return, rest off.
-    let on_idx = index_of.index_of(on_ret);
-    let off_idx = index_of
-        .bool_index(false)
-        .or_else(|| index_of.bool_index(true).map(|_| 0))
-        .unwrap_or(0);
-    let on_weight = (p.rate.clamp(0.0, 100.0) * 1000.0).round() as u32;
-    let _ = flag;
-    Ok(RuleTarget::Rollout {
+/// Weighted variations, in percent, -> one LD rollout. The running total is
+/// rounded to thousandths, so the weights always sum to 100_000.
+fn build_rollout(arms: &[(usize, f64)], bucket_by: &str) -> RuleTarget {
+    let mut upto = 0.0;
+    let mut bound = 0;
+    let mut variations = Vec::with_capacity(arms.len());
+    for (i, (variation, weight)) in arms.iter().enumerate() {
+        upto += weight;
+        let next = if i + 1 == arms.len() {
+            100_000
+        } else {
+            ((upto * 1000.0).round() as u32).min(100_000)
+        };
+        variations.push(LdWeightedVariation {
+            variation: *variation,
+            weight: next - bound,
+        });
+        bound = next;
+    }
+    RuleTarget::Rollout {
         rollout: LdRollout {
-            variations: vec![
-                LdWeightedVariation {
-                    variation: on_idx,
-                    weight: on_weight,
-                },
-                LdWeightedVariation {
-                    variation: off_idx,
-                    weight: 100_000 - on_weight,
-                },
-            ],
-            bucket_by: Some(p.field.clone()),
+            variations,
+            bucket_by: Some(bucket_by.to_string()),
         },
-    })
+    }
 }
 
 // ──────────────────────────── prerequisites / metadata ──────────────────
@@ -855,8 +1019,15 @@ fn atom_to_value(a: &Atom) -> Value {
         Atom::Date(d) => Value::String(d.to_string()),
         Atom::DateTime(dt) => Value::String(dt.to_string()),
         Atom::Semver(a, b, c) => Value::String(format!("{a}.{b}.{c}")),
-        Atom::Regex(r) => Value::String(r.clone()),
+        Atom::Duration(d) => Value::String(Atom::Duration(*d).to_string()),
+        Atom::Regex(r) => Value::String(r.as_str().to_string()),
         Atom::List(items) => Value::Array(items.iter().map(atom_to_value).collect()),
+        Atom::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), atom_to_value(v)))
+                .collect(),
+        ),
     }
 }
 
@@ -874,14 +1045,6 @@ fn looks_like_semver(s: &str) -> bool {
 mod tests {
     use super::*;
 
-    fn cfg() -> TranspileConfig {
-        TranspileConfig {
-            project_key: "default".into(),
-            env_keys: BTreeMap::from([("prod".into(), "production".into())]),
-            default_context_kind: "user".into(),
-        }
-    }
-
     // (A or B) and C  ->  two LD rules: {A,C} and {B,C}
     #[test]
     fn or_expands_to_two_rules() {
@@ -961,7 +1124,7 @@ mod e2e {
     use crate::parse_flagfile::parse_flagfile_with_segments;
 
     fn transpile_one(src: &str) -> LdFlag {
-        let (_, parsed) = parse_flagfile_with_segments(src).expect("parse");
+        let parsed = parse_flagfile_with_segments(src).expect("parse");
         let cfg = TranspileConfig {
             project_key: "default".into(),
             env_keys: BTreeMap::from([("_".into(), "production".into())]),
@@ -971,6 +1134,212 @@ mod e2e {
         flags.into_iter().next().expect("one flag")
     }
 
+    // Declared variants become named LD variations in declaration order, even
+    // when no rule serves them yet.
+    #[test]
+    fn variants_become_named_variations() {
+        let flag = transpile_one(
+            r#"FF-checkout {
+    @variants {
+        control -> "classic"
+        streamlined -> "single-page"
+        express -> "one-click"
+    }
+    plan == premium -> express
+    control
+}
+"#,
+        );
+        let named: Vec<(Option<&str>, &Value)> = flag
+            .variations
+            .iter()
+            .map(|v| (v.name.as_deref(), &v.value))
+            .collect();
+        assert_eq!(
+            named,
+            vec![
+                (Some("control"), &serde_json::json!("classic")),
+                (Some("streamlined"), &serde_json::json!("single-page")),
+                (Some("express"), &serde_json::json!("one-click")),
+            ]
+        );
+    }
+
+    // A split is one multi-variation rollout; thousandths that don't divide
+    // evenly go to the last arm so the weights still sum to 100_000.
+    #[test]
+    fn split_becomes_weighted_fallthrough() {
+        let flag = transpile_one(
+            "FF-layout {\n    plan == free -> \"a\"\n    split(userId, exp1) { \"a\": 33.3333%, \"b\": 33.3333%, \"c\": 33.3334% }\n}\n",
+        );
+        let env = &flag.environments["production"];
+        assert_eq!(env.rules.len(), 1);
+        assert_eq!(
+            env.fallthrough,
+            RuleTarget::Rollout {
+                rollout: LdRollout {
+                    variations: vec![
+                        LdWeightedVariation {
+                            variation: 0,
+                            weight: 33_333,
+                        },
+                        LdWeightedVariation {
+                            variation: 1,
+                            weight: 33_334,
+                        },
+                        LdWeightedVariation {
+                            variation: 2,
+                            weight: 33_333,
+                        },
+                    ],
+                    bucket_by: Some("userId".into()),
+                },
+            }
+        );
+    }
+
+    // Evaluation stops at a split, so nothing after it can be lowered: a
+    // later default would replace the rollout and a later rule would run
+    // before it.
+    #[test]
+    fn rules_after_split_are_unreachable() {
+        let split = r#"split(userId) { "a": 50%, "b": 50% }"#;
+        for rest in ["\"none\"", "plan == free -> \"b\"\n    \"none\""] {
+            let src = format!("FF-x {{\n    {split}\n    {rest}\n}}\n");
+            let parsed = parse_flagfile_with_segments(&src).expect("parse");
+            let cfg = TranspileConfig {
+                project_key: "default".into(),
+                env_keys: BTreeMap::from([("_".into(), "production".into())]),
+                default_context_kind: "user".into(),
+            };
+            assert_eq!(
+                transpile(&parsed, &cfg).unwrap_err(),
+                vec![TranspileError::UnreachableRule {
+                    flag: "FF-x".into()
+                }],
+                "{rest}"
+            );
+        }
+    }
+
+    // An `@env` block applies where it sits, and one that ends in a split or
+    // value hides the rules after it in that environment only.
+    #[test]
+    fn env_block_applies_in_place() {
+        let parsed = parse_flagfile_with_segments(
+            r#"FF-x {
+    @env prod {
+        split(userId) { "a": 50%, "b": 50% }
+    }
+    plan == free -> "b"
+    "none"
+}
+"#,
+        )
+        .expect("parse");
+        let cfg = TranspileConfig {
+            project_key: "default".into(),
+            env_keys: BTreeMap::from([
+                ("prod".into(), "production".into()),
+                ("dev".into(), "test".into()),
+            ]),
+            default_context_kind: "user".into(),
+        };
+        let flag = transpile(&parsed, &cfg).expect("transpile").remove(0);
+        let prod = &flag.environments["production"];
+        assert!(prod.rules.is_empty());
+        assert!(matches!(prod.fallthrough, RuleTarget::Rollout { .. }));
+        let dev = &flag.environments["test"];
+        assert_eq!(dev.rules.len(), 1);
+        assert!(matches!(dev.fallthrough, RuleTarget::Variation { .. }));
+    }
+
+    // Schedules live outside LD flags, so ramps and `@active` windows, on the
+    // flag or on a rule, can't be carried over.
+    #[test]
+    fn ramp_and_active_are_time_relative() {
+        let cfg = TranspileConfig {
+            project_key: "default".into(),
+            env_keys: BTreeMap::from([("_".into(), "production".into())]),
+            default_context_kind: "user".into(),
+        };
+        for src in [
+            "@active from 2026-11-01\nFF-launch -> true\n",
+            "FF-launch {\n    @active until 2026-12-01\n    true\n}\n",
+            "FF-launch {\n    ramp(0% -> 100%, 2026-11-01 .. 2026-11-15, userId) -> true\n    false\n}\n",
+        ] {
+            let parsed = parse_flagfile_with_segments(src).expect("parse");
+            let errors = transpile(&parsed, &cfg).unwrap_err();
+            assert!(
+                matches!(errors[..], [TranspileError::TimeRelative { .. }]),
+                "{src}: {errors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_and_attribute_comparisons_are_rejected() {
+        let cfg = TranspileConfig {
+            project_key: "default".into(),
+            env_keys: BTreeMap::from([("_".into(), "production".into())]),
+            default_context_kind: "user".into(),
+        };
+        let cases = [
+            ("NOW() - signupDate < 30d", None),
+            ("cartTotal * 100 >= 5000", Some("arithmetic")),
+            (
+                "billingCountry != var(shippingCountry)",
+                Some("comparison between two attributes"),
+            ),
+            ("age < 30d", Some("duration")),
+        ];
+        for (cond, what) in cases {
+            let src = format!("FF-x {{\n    {cond} -> true\n    false\n}}\n");
+            let parsed = parse_flagfile_with_segments(&src).expect("parse");
+            let errors = transpile(&parsed, &cfg).unwrap_err();
+            match what {
+                None => assert!(
+                    matches!(errors[..], [TranspileError::TimeRelative { .. }]),
+                    "{cond}: {errors:?}"
+                ),
+                Some(what) => assert_eq!(
+                    errors,
+                    vec![TranspileError::UnsupportedConstruct {
+                        flag: "FF-x".into(),
+                        what: what.into(),
+                    }],
+                    "{cond}"
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_functions_are_rejected() {
+        let cfg = TranspileConfig {
+            project_key: "default".into(),
+            env_keys: BTreeMap::from([("_".into(), "production".into())]),
+            default_context_kind: "user".into(),
+        };
+        for (cond, name) in [
+            ("fn:region(ip) == \"eu\"", "region"),
+            ("fn:entitled(accountId, sso)", "entitled"),
+            ("plan == pro and not (fn:region(ip) in (eu, uk))", "region"),
+        ] {
+            let src = format!("FF-x {{\n    {cond} -> true\n    false\n}}\n");
+            let parsed = parse_flagfile_with_segments(&src).expect("parse");
+            let errors = transpile(&parsed, &cfg).unwrap_err();
+            assert_eq!(
+                errors,
+                vec![TranspileError::UnsupportedConstruct {
+                    flag: "FF-x".into(),
+                    what: format!("custom function fn:{name}()"),
+                }],
+                "{cond}"
+            );
+        }
+    }
+
     // LD rejects flags with < 2 variations; a constant boolean flag must still
     // produce both true and false, with off serving false.
     #[test]
@@ -990,6 +1359,17 @@ mod e2e {
         assert_eq!(flag.environments["production"].off_variation, false_idx);
     }
 
+    // Float returns are numeric variations, alongside whole numbers.
+    #[test]
+    fn float_returns_become_number_variations() {
+        let flag = transpile_one("FF-sample-rate {\n    tier == gold -> 1\n    0.25\n}\n");
+        let values: Vec<&Value> = flag.variations.iter().map(|v| &v.value).collect();
+        assert_eq!(
+            values,
+            vec![&serde_json::json!(1), &serde_json::json!(0.25)]
+        );
+    }
+
     // A single-value non-boolean flag is padded with a non-null, kind-appropriate
     // "disabled" variation (LD rejects null for typed/JSON variations).
     #[test]
@@ -1085,6 +1465,27 @@ mod e2e {
             .all(|r| r.clauses.iter().all(|c| c.op != "segmentMatch")));
     }
 
+    // Nested context paths become LD attribute references.
+    #[test]
+    fn nested_attribute_becomes_reference() {
+        let flag =
+            transpile_one("FF-x {\n    user.company[\"tier\"] == gold -> true\n    false\n}\n");
+        let rules = &flag.environments["production"].rules;
+        assert_eq!(rules[0].clauses[0].attribute, "/user/company/tier");
+    }
+
+    // Arguments of a parameterized segment are bound before inlining.
+    #[test]
+    fn parameterized_segment_is_inlined_with_arguments() {
+        let flag = transpile_one(
+            "@segment in-country(c) {\n    country == c\n}\n\nFF-nl {\n    segment(in-country, \"NL\") -> true\n    false\n}\n",
+        );
+        let rules = &flag.environments["production"].rules;
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].clauses[0].attribute, "country");
+        assert_eq!(rules[0].clauses[0].values, vec![Value::String("NL".into())]);
+    }
+
     // A segment(...) not defined in the Flagfile is assumed to be a native LD
     // segment and still lowers to a segmentMatch clause.
     #[test]
@@ -1102,8 +1503,7 @@ mod e2e {
     #[test]
     fn transpile_real_example_file() {
         let data = include_str!("../../Flagfile.example");
-        let (rest, parsed) = parse_flagfile_with_segments(data).expect("parse example");
-        assert_eq!(rest.trim(), "", "parser should consume the whole file");
+        let parsed = parse_flagfile_with_segments(data).expect("parse example");
 
         let cfg = TranspileConfig {
             project_key: "default".into(),
@@ -0,0 +1,193 @@
+//! JavaScript API for browsers and edge workers, built with
+//! `wasm-pack build --target web` (or `--target nodejs`).
+//!
+//! ```js
+//! import init, { Flagfile } from "flagfile-lib";
+//!
+//! await init();
+//! const flags = new Flagfile(source, "prod");
+//! const { value, reason } = flags.evaluate("FF-new-ui", { userId: "42" });
+//! ```
+//!
+//! Values cross the boundary as JSON, so the conversions below are plain
+//! Rust and tested natively; only the `#[wasm_bindgen]` shims touch JS.
+
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::flagfile::{Flagfile, LoadError};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = JSON, js_name = stringify, catch)]
+    fn json_stringify(value: &JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_namespace = JSON, js_name = parse, catch)]
+    fn json_parse(text: &str) -> Result<JsValue, JsValue>;
+}
+
+/// A parsed Flagfile. Exported to JS as `Flagfile`.
+#[wasm_bindgen(js_name = Flagfile)]
+pub struct WasmFlagfile {
+    inner: Flagfile,
+}
+
+#[wasm_bindgen(js_class = Flagfile)]
+impl WasmFlagfile {
+    /// Parse Flagfile source, optionally selecting the environment for
+    /// `@env` rules. Throws `{ message, line, column }` on failure; `line`
+    /// and `column` are `null` for errors that aren't tied to a position,
+    /// such as a `@type` mismatch.
+    #[wasm_bindgen(constructor)]
+    pub fn new(content: &str, env: Option<String>) -> Result<WasmFlagfile, JsValue> {
+        load(content, env)
+            .map(|inner| WasmFlagfile { inner })
+            .map_err(|e| to_js(&e))
+    }
+
+    /// Evaluate a flag against a context object. Returns
+    /// `{ value, variant, reason, ruleIndex, ruleName, env, error }`.
+    pub fn evaluate(&self, flag: &str, context: JsValue) -> Result<JsValue, JsValue> {
+        let context = if context.is_undefined() || context.is_null() {
+            Value::Null
+        } else {
+            let text = json_stringify(&context)?
+                .as_string()
+                .ok_or_else(|| JsValue::from_str("context is not JSON-serializable"))?;
+            serde_json::from_str(&text).map_err(|e| JsValue::from_str(&e.to_string()))?
+        };
+        let detail = self
+            .inner
+            .eval_json(flag, &context)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(to_js(&detail))
+    }
+
+    /// Flag names in source order.
+    #[wasm_bindgen(js_name = listFlags)]
+    pub fn list_flags(&self) -> Vec<String> {
+        self.inner.flags().map(str::to_string).collect()
+    }
+
+    /// The flag's annotations, or `null` if it doesn't exist.
+    pub fn metadata(&self, flag: &str) -> JsValue {
+        match self.inner.metadata(flag) {
+            Some(meta) => to_js(&meta.to_json()),
+            None => JsValue::NULL,
+        }
+    }
+}
+
+/// Parse an expression and return its AST in debug form.
+#[wasm_bindgen]
+pub fn parse_wasm(i: &str) -> String {
+    match crate::parse::parse(i) {
+        Ok((_i, tree)) => format!("{:?}", tree),
+        Err(e) => format!("parse error: {}", e),
+    }
+}
+
+fn to_js(value: &Value) -> JsValue {
+    json_parse(&value.to_string()).unwrap_or(JsValue::NULL)
+}
+
+fn load(content: &str, env: Option<String>) -> Result<Flagfile, Value> {
+    let flagfile = Flagfile::parse_located(content).map_err(|e| load_error_to_json(&e))?;
+    Ok(match env {
+        Some(env) => flagfile.with_env(env),
+        None => flagfile,
+    })
+}
+
+fn load_error_to_json(err: &LoadError) -> Value {
+    serde_json::json!({
+        "message": err.message,
+        "line": err.location.map(|(line, _)| line),
+        "column": err.location.map(|(_, column)| column),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CONTENT: &str = r#"
+@owner "growth"
+@expires 2026-12-31
+FF-button {
+    @variants {
+        control -> "blue"
+        treatment -> "green"
+    }
+    @env prod -> control
+    user.plan == premium -> treatment
+    control
+}
+
+FF-sample-rate -> 0.25
+"#;
+
+    #[test]
+    fn test_evaluate_with_json_context() {
+        let ff = load(CONTENT, None).unwrap();
+        let detail = ff
+            .eval_json("FF-button", &json!({"user": {"plan": "premium"}}))
+            .unwrap();
+        assert_eq!(
+            detail,
+            json!({
+                "value": "green",
+                "variant": "treatment",
+                "reason": "TARGETING_MATCH",
+                "ruleIndex": 1,
+                "ruleName": null,
+                "env": null,
+                "error": null,
+            })
+        );
+        let detail = ff.eval_json("FF-sample-rate", &Value::Null).unwrap();
+        assert_eq!(detail["value"], json!(0.25));
+        assert_eq!(detail["reason"], "DEFAULT");
+
+        let missing = ff.eval_json("FF-missing", &json!({})).unwrap();
+        assert_eq!(missing["value"], Value::Null);
+        assert_eq!(missing["reason"], "FLAG_NOT_FOUND");
+        assert!(ff.eval_json("FF-button", &json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_env_and_metadata() {
+        let ff = load(CONTENT, Some("prod".into())).unwrap();
+        let detail = ff
+            .eval_json("FF-button", &json!({"user": {"plan": "premium"}}))
+            .unwrap();
+        assert_eq!(detail["value"], "blue");
+        assert_eq!(detail["reason"], "ENV_MATCH");
+        assert_eq!(detail["env"], "prod");
+
+        let meta = ff.metadata("FF-button").unwrap().to_json();
+        assert_eq!(meta["owner"], "growth");
+        assert_eq!(meta["expires"], "2026-12-31");
+        assert_eq!(meta["requires"], json!([]));
+    }
+
+    #[test]
+    fn test_structured_load_errors() {
+        let err = load(
+            "FF-a -> true\nFF-b {\n    email ~ /[a-z/ -> true\n}\n",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err["line"], 3);
+        assert_eq!(err["column"], 14);
+        assert!(err["message"].as_str().unwrap().contains("invalid regex"));
+
+        let err = load("@type int\nFF-x -> true", None).unwrap_err();
+        assert_eq!(err["line"], Value::Null);
+        assert!(err["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Flagfile type check failed"));
+    }
+}
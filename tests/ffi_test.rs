@@ -0,0 +1,76 @@
+//! Compiles `tests/fixtures/ffi/smoke.c` against `include/flagfile.h` and the
+//! `cdylib`, runs it and checks what it printed.
+#![cfg(unix)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `target/<profile>/deps`, where cargo builds the cdylib alongside this
+/// test binary.
+fn deps_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn test_c_abi_end_to_end() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping: no C compiler on PATH");
+        return;
+    }
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = deps_dir();
+    let exe = lib_dir.join("ffi_smoke");
+
+    let status = Command::new("cc")
+        .arg(root.join("tests/fixtures/ffi/smoke.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lflagfile_lib")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling smoke.c failed");
+
+    let output = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(lines[0], format!("version: {}", env!("CARGO_PKG_VERSION")));
+    assert!(
+        lines[1].starts_with("broken: Flagfile type check failed"),
+        "{}",
+        lines[1]
+    );
+
+    let detail = |line: &str| -> serde_json::Value { serde_json::from_str(line).unwrap() };
+    let prod = detail(lines[2]);
+    assert_eq!(prod["value"], false);
+    assert_eq!(prod["reason"], "ENV_MATCH");
+    assert_eq!(prod["env"], "prod");
+    let rate = detail(lines[3]);
+    assert_eq!(rate["value"], 0.25);
+    assert_eq!(rate["reason"], "DEFAULT");
+    assert_eq!(detail(lines[4])["reason"], "FLAG_NOT_FOUND");
+    assert!(
+        lines[5].starts_with("error: invalid context JSON"),
+        "{}",
+        lines[5]
+    );
+    let matched = detail(lines[6]);
+    assert_eq!(matched["value"], true);
+    assert_eq!(matched["reason"], "TARGETING_MATCH");
+    assert_eq!(matched["ruleIndex"], 0);
+}
@@ -0,0 +1,45 @@
+/* Drives the C ABI the way an embedding runtime would. Prints one line per
+ * call; tests/ffi_test.rs compares the output. */
+#include <stdio.h>
+
+#include "flagfile.h"
+
+static void evaluate(const Flagfile *flags, const char *flag, const char *context) {
+    char *detail = ff_flagfile_evaluate(flags, flag, context);
+    if (detail == NULL) {
+        printf("error: %s\n", ff_last_error());
+        return;
+    }
+    printf("%s\n", detail);
+    ff_string_free(detail);
+}
+
+int main(void) {
+    printf("version: %s\n", ff_version());
+
+    Flagfile *broken = ff_flagfile_new("@type int\nFF-x -> true", NULL);
+    printf("broken: %s\n", broken == NULL ? ff_last_error() : "loaded");
+
+    Flagfile *flags = ff_flagfile_new(
+        "FF-checkout {\n"
+        "    @env prod -> false\n"
+        "    user.plan == premium -> true\n"
+        "    false\n"
+        "}\n"
+        "FF-sample-rate -> 0.25\n",
+        "prod");
+    if (flags == NULL) {
+        printf("load failed: %s\n", ff_last_error());
+        return 1;
+    }
+    evaluate(flags, "FF-checkout", "{\"user\": {\"plan\": \"premium\"}}");
+    evaluate(flags, "FF-sample-rate", NULL);
+    evaluate(flags, "FF-missing", "{}");
+    evaluate(flags, "FF-checkout", "{not json");
+    ff_flagfile_free(flags);
+
+    flags = ff_flagfile_new("FF-checkout {\n    user.plan == premium -> true\n    false\n}\n", NULL);
+    evaluate(flags, "FF-checkout", "{\"user\": {\"plan\": \"premium\"}}");
+    ff_flagfile_free(flags);
+    return 0;
+}
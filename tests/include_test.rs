@@ -30,8 +30,7 @@ fn test_basic_include_merges_flags() {
     assert_eq!(resolved.includes.len(), 1);
     assert!(resolved.includes[0].path.ends_with("Flagfile.demo"));
 
-    let (rest, parsed) = parse_flagfile_with_segments(&resolved.content).unwrap();
-    assert_eq!(rest.trim(), "");
+    let parsed = parse_flagfile_with_segments(&resolved.content).unwrap();
     let names = flag_names(&parsed);
     assert!(names.contains(&"FF-basic-local"));
     assert!(names.contains(&"FF-basic-included"));
@@ -46,8 +45,7 @@ fn test_nested_includes_resolve_relative_to_own_dir() {
     assert!(resolved.includes[0].path.ends_with("sub/Flagfile"));
     assert!(resolved.includes[1].path.ends_with("sub/deep/more.ff"));
 
-    let (rest, parsed) = parse_flagfile_with_segments(&resolved.content).unwrap();
-    assert_eq!(rest.trim(), "");
+    let parsed = parse_flagfile_with_segments(&resolved.content).unwrap();
     let names = flag_names(&parsed);
     assert!(names.contains(&"FF-nested-root"));
     assert!(names.contains(&"FF-nested-sub"));
@@ -59,8 +57,7 @@ fn test_include_directives_in_comments_are_ignored() {
     let resolved = resolve_includes_from_path(&fixture("comments/Flagfile")).unwrap();
 
     assert!(resolved.includes.is_empty());
-    let (rest, parsed) = parse_flagfile_with_segments(&resolved.content).unwrap();
-    assert_eq!(rest.trim(), "");
+    let parsed = parse_flagfile_with_segments(&resolved.content).unwrap();
     assert!(flag_names(&parsed).contains(&"FF-comments-only"));
 }
 
@@ -107,6 +104,43 @@ fn test_self_include_detected() {
     assert!(err.contains("cycle"), "error should mention cycle: {err}");
 }
 
+#[test]
+fn test_parse_error_points_into_included_file() {
+    let resolved = resolve_includes_from_path(&fixture("syntax/Flagfile")).unwrap();
+    let err = parse_flagfile_with_segments(&resolved.content).unwrap_err();
+    assert_eq!(err.line, 5, "line in the merged content");
+
+    let err = resolved.locate(err);
+    assert!(err.file.as_ref().unwrap().ends_with("syntax/broken.ff"));
+    assert_eq!((err.line, err.column), (2, 21));
+    assert_eq!(err.source_line, "    plan == premium true");
+
+    // lines outside the include map back to the root file
+    let (file, line) = resolved.origin(9);
+    assert!(file.unwrap().ends_with("syntax/Flagfile"));
+    assert_eq!(line, 6);
+}
+
+#[test]
+fn test_spans_map_back_to_included_file() {
+    let resolved = resolve_includes_from_path(&fixture("withtests/Flagfile")).unwrap();
+    let parsed = parse_flagfile_with_segments(&resolved.content).unwrap();
+    let def = parsed
+        .flags
+        .iter()
+        .find_map(|fv| fv.get("FF-included-feature"))
+        .unwrap();
+
+    let flag = resolved.position(def.span);
+    assert!(flag.ends_with("withtests/sub/Flagfile:2:1"), "{flag}");
+    let rule = resolved.position(def.rules[0].span());
+    assert!(rule.ends_with("withtests/sub/Flagfile:3:5"), "{rule}");
+
+    let root = parsed.flags[0]["FF-root-feature"].span;
+    let root = resolved.position(root);
+    assert!(root.ends_with("withtests/Flagfile:1:1"), "{root}");
+}
+
 // ── Evaluation & tests discovery ───────────────────────────
 
 // The only test in this binary that touches the global FLAGS state.
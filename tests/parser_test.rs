@@ -8,7 +8,7 @@ use flagfile_lib::{self, eval::eval, parse::parse};
 #[test]
 fn test_hashmap_into() {
     let out: Context = HashMap::from([("demo", "demo".into())]);
-    assert_eq!(true, out.get("demo").is_some());
+    assert!(out.contains_key("demo"));
     assert_eq!(Atom::String("demo".into()), *out.get("demo").unwrap());
 }
 
@@ -49,7 +49,7 @@ accountRole in (Admin,admin,"Admin/Order Manager")
         ("user_id", Atom::Number(2032312)),
     ]);
 
-    let (i, expr) = parse(&rule).unwrap();
+    let (i, expr) = parse(rule).unwrap();
     let val = eval(&expr, &context, None).unwrap();
     assert_eq!(val, true);
     assert_eq!(i, ""); // empty remainder of parsed string
@@ -74,7 +74,7 @@ fn scoped_test_case() {
         ("user_id", Atom::Number(2032312)),
     ]);
 
-    let (i, expr) = parse(&rule).unwrap();
+    let (i, expr) = parse(rule).unwrap();
     let val = eval(&expr, &context, None).unwrap();
     assert_eq!(val, true);
     assert_eq!(i, ""); // empty remainder of parsed string
@@ -361,7 +361,6 @@ fn starts_with_and_ends_with_test() {
 #[test]
 fn flagfile_with_semver_parses() {
     let data = include_str!("../Flagfile.example");
-    let (i, v) = parse_flagfile_with_segments(data).unwrap();
-    assert!(v.flags.len() > 0);
-    assert_eq!(i.to_string().trim(), "");
+    let v = parse_flagfile_with_segments(data).unwrap();
+    assert!(!v.flags.is_empty());
 }